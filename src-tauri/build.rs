@@ -1,3 +1,42 @@
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::path::Path;
+
+// Same as frontendDist of tauri.conf.json
+const FRONTEND_DIST_PATH: &str = "../build";
+
+// "<sha256> <asset key>" lines of the frontend assets that are embedded into
+// the app, checked at startup by integrity.rs
+fn hash_assets(dir: &Path, root: &Path, hashes: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            hash_assets(&path, root, hashes);
+        } else if let Ok(contents) = fs::read(&path) {
+            let key = path.strip_prefix(root).unwrap().to_string_lossy();
+            hashes.push(format!(
+                "{:x} /{}",
+                Sha256::digest(contents),
+                key.replace('\\', "/")
+            ));
+        }
+    }
+}
+
 fn main() {
+    let mut hashes = Vec::new();
+    let dist = Path::new(FRONTEND_DIST_PATH);
+    hash_assets(dist, dist, &mut hashes);
+    hashes.sort();
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("asset_hashes.txt"),
+        hashes.join("\n"),
+    )
+    .unwrap();
+    println!("cargo:rerun-if-changed={}", FRONTEND_DIST_PATH);
+
     tauri_build::build()
 }
@@ -0,0 +1,180 @@
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::consts;
+use crate::scheduler::SyncScheduler;
+use crate::tray;
+use crate::utils;
+
+// Accounts with a schedule are only synced and notified about in their
+// active hours, like a work account that sleeps on weekends. Accounts
+// without one are never paused. Times are local, a schedule that ends
+// before it starts spans midnight and belongs to the day it starts.
+pub const PAUSED_ACCOUNTS_CHANGED_EVENT: &str = "paused-accounts-changed";
+const CHECK_INTERVAL_SEC: u64 = 60;
+const TIME_FORMAT: &str = "%H:%M";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountSchedule {
+    pub account: String,
+    // Active days, 0 is Monday.
+    pub days: Vec<u8>,
+    // "HH:MM", the end is exclusive. The same start and end is the whole
+    // day.
+    pub start: String,
+    pub end: String,
+}
+
+impl AccountSchedule {
+    fn is_active_at(&self, now: NaiveDateTime) -> bool {
+        let (Ok(start), Ok(end)) = (
+            NaiveTime::parse_from_str(&self.start, TIME_FORMAT),
+            NaiveTime::parse_from_str(&self.end, TIME_FORMAT),
+        ) else {
+            return true;
+        };
+        let time = now.time();
+        let today = now.weekday().num_days_from_monday() as u8;
+        let yesterday = (today + 6) % 7;
+        if start == end {
+            self.days.contains(&today)
+        } else if start < end {
+            self.days.contains(&today) && start <= time && time < end
+        } else {
+            (self.days.contains(&today) && time >= start)
+                || (self.days.contains(&yesterday) && time < end)
+        }
+    }
+}
+
+fn validate(schedules: &[AccountSchedule]) -> Result<(), String> {
+    let mut accounts = HashSet::new();
+    for schedule in schedules {
+        if !accounts.insert(schedule.account.as_str()) {
+            return Err(format!(
+                "Account has more than one schedule: {}",
+                schedule.account
+            ));
+        }
+        if let Some(day) = schedule.days.iter().find(|day| **day > 6) {
+            return Err(format!("Invalid day: {}", day));
+        }
+        for time in [&schedule.start, &schedule.end] {
+            NaiveTime::parse_from_str(time, TIME_FORMAT)
+                .map_err(|_| format!("Invalid time: {}", time))?;
+        }
+    }
+    Ok(())
+}
+
+pub struct AccountSchedules {
+    schedules: Mutex<Vec<AccountSchedule>>,
+    paused: Mutex<Vec<String>>,
+    wakeup: Condvar,
+}
+
+impl AccountSchedules {
+    pub fn load() -> Self {
+        let schedules = AccountSchedules {
+            schedules: Mutex::new(
+                utils::read_json_file(consts::ACCOUNT_SCHEDULES_FILE_PATH).unwrap_or_default(),
+            ),
+            paused: Mutex::new(Vec::new()),
+            wakeup: Condvar::new(),
+        };
+        *schedules.paused.lock().unwrap() = schedules.compute_paused();
+        schedules
+    }
+
+    pub fn get_schedules(&self) -> Vec<AccountSchedule> {
+        self.schedules.lock().unwrap().clone()
+    }
+
+    pub fn set_schedules(&self, schedules: Vec<AccountSchedule>) -> Result<(), String> {
+        validate(&schedules)?;
+        utils::write_json_file(consts::ACCOUNT_SCHEDULES_FILE_PATH, &schedules)?;
+        *self.schedules.lock().unwrap() = schedules;
+        self.wakeup.notify_all();
+        Ok(())
+    }
+
+    fn compute_paused(&self) -> Vec<String> {
+        let now = Local::now().naive_local();
+        self.schedules
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|schedule| !schedule.is_active_at(now))
+            .map(|schedule| schedule.account.clone())
+            .collect()
+    }
+
+    pub fn get_paused(&self) -> Vec<String> {
+        self.paused.lock().unwrap().clone()
+    }
+
+    pub fn is_paused(&self, account: &str) -> bool {
+        self.paused
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|paused| paused.eq_ignore_ascii_case(account))
+    }
+
+    fn wait(&self, interval: Duration) {
+        let schedules = self.schedules.lock().unwrap();
+        let _ = self.wakeup.wait_timeout(schedules, interval).unwrap();
+    }
+}
+
+// For the places that only have the app, like the notifications.
+pub fn is_paused(app: &AppHandle, account: &str) -> bool {
+    app.try_state::<Arc<AccountSchedules>>()
+        .is_some_and(|schedules| schedules.is_paused(account))
+}
+
+pub fn start_worker(
+    app: AppHandle,
+    schedules: Arc<AccountSchedules>,
+    scheduler: Arc<SyncScheduler>,
+) {
+    tray::set_paused_accounts(&app, &schedules.get_paused());
+    thread::spawn(move || loop {
+        schedules.wait(Duration::from_secs(CHECK_INTERVAL_SEC));
+        let paused = schedules.compute_paused();
+        let previous = std::mem::replace(&mut *schedules.paused.lock().unwrap(), paused.clone());
+        if paused == previous {
+            continue;
+        }
+
+        tray::set_paused_accounts(&app, &paused);
+        app.emit(PAUSED_ACCOUNTS_CHANGED_EVENT, &paused).ok();
+        // Resumed accounts catch up right away.
+        if previous.iter().any(|account| !paused.contains(account)) {
+            scheduler.request();
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_account_schedules(schedules: State<'_, Arc<AccountSchedules>>) -> Vec<AccountSchedule> {
+    schedules.get_schedules()
+}
+
+#[tauri::command]
+pub fn set_account_schedules(
+    account_schedules: State<'_, Arc<AccountSchedules>>,
+    schedules: Vec<AccountSchedule>,
+) -> Result<(), String> {
+    account_schedules.set_schedules(schedules)
+}
+
+#[tauri::command]
+pub fn get_paused_accounts(schedules: State<'_, Arc<AccountSchedules>>) -> Vec<String> {
+    schedules.get_paused()
+}
@@ -0,0 +1,126 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::api;
+use crate::utils;
+
+// Accounts are moved to another install as a QR code that holds the account
+// encrypted by the server with a key derived from a one time transfer code.
+// The code is shown next to the QR code and typed on the other install, so
+// the QR code alone is useless, and both expire after a few minutes.
+const TRANSFER_TTL_SEC: i64 = 10 * 60;
+const TRANSFER_PREFIX: &str = "openmail-account";
+const TRANSFER_VERSION: &str = "1";
+const TRANSFER_CODE_LENGTH: usize = 8;
+// Without 0/O and 1/I/L so the code is easy to read and type.
+const TRANSFER_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKMNPQRSTUVWXYZ23456789";
+const SALT_LENGTH: usize = 16;
+const QR_CODE_SIZE: u32 = 320;
+
+#[derive(Debug, Serialize)]
+pub struct AccountQrExport {
+    pub qr_svg: String,
+    pub payload: String,
+    pub code: String,
+    pub expires_at: i64,
+}
+
+fn generate_code() -> String {
+    (0..TRANSFER_CODE_LENGTH)
+        .map(|_| {
+            let index = OsRng.next_u32() as usize % TRANSFER_CODE_ALPHABET.len();
+            TRANSFER_CODE_ALPHABET[index] as char
+        })
+        .collect()
+}
+
+fn transfer_key(code: &str, salt: &[u8]) -> String {
+    BASE64.encode(utils::derive_key(&code.trim().to_uppercase(), salt))
+}
+
+pub fn export(account: &str) -> Result<AccountQrExport, String> {
+    let code = generate_code();
+    let mut salt = [0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let expires_at = Utc::now().timestamp() + TRANSFER_TTL_SEC;
+
+    let response = api::post_json(
+        "/export-account",
+        json!({
+            "account": account,
+            "transfer_key": transfer_key(&code, &salt),
+            "expires_at": expires_at,
+        }),
+    )?;
+    if !response.success {
+        return Err(response.message);
+    }
+    let encrypted = response
+        .data
+        .as_ref()
+        .and_then(|data| data.get("payload"))
+        .and_then(Value::as_str)
+        .ok_or(format!("No transfer payload found for {}", account))?;
+
+    let payload = format!(
+        "{}:{}:{}:{}",
+        TRANSFER_PREFIX,
+        TRANSFER_VERSION,
+        BASE64.encode(salt),
+        encrypted
+    );
+    let qr_svg = QrCode::new(payload.as_bytes())
+        .map_err(|err| format!("Failed to create QR code: {}", err))?
+        .render::<svg::Color>()
+        .min_dimensions(QR_CODE_SIZE, QR_CODE_SIZE)
+        .build();
+
+    Ok(AccountQrExport {
+        qr_svg,
+        payload,
+        code,
+        expires_at,
+    })
+}
+
+pub fn import(payload: &str, code: &str) -> Result<(), String> {
+    let parts: Vec<&str> = payload.trim().splitn(4, ':').collect();
+    let [prefix, version, salt, encrypted] = parts[..] else {
+        return Err("Invalid account transfer payload".to_string());
+    };
+    if prefix != TRANSFER_PREFIX || version != TRANSFER_VERSION {
+        return Err("Unsupported account transfer payload".to_string());
+    }
+    let salt = BASE64
+        .decode(salt)
+        .map_err(|err| format!("Failed to decode account transfer payload: {}", err))?;
+
+    let response = api::post_json(
+        "/import-account",
+        json!({
+            "payload": encrypted,
+            "transfer_key": transfer_key(code, &salt),
+        }),
+    )?;
+    if !response.success {
+        return Err(response.message);
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn export_account_qr(account: String) -> Result<AccountQrExport, String> {
+    export(&account)
+}
+
+#[tauri::command]
+pub async fn import_account_qr(payload: String, code: String) -> Result<(), String> {
+    import(&payload, &code)
+}
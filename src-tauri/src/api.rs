@@ -0,0 +1,105 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::net::TcpStream;
+use std::time::Duration;
+use tungstenite::stream::MaybeTlsStream;
+
+use crate::fault_injection;
+
+// Client of the python server, mirrors the `Response` model of the
+// server so the results can be handled the same way as in ApiService.ts
+const REQUEST_TIMEOUT_SEC: u64 = 60;
+
+pub type WebSocket = tungstenite::WebSocket<MaybeTlsStream<TcpStream>>;
+
+#[derive(Debug, Deserialize)]
+pub struct ApiResponse {
+    pub success: bool,
+    pub message: String,
+    pub data: Option<Value>,
+}
+
+fn build_url(route: &str) -> Result<String, String> {
+    fault_injection::check_request(route)?;
+    let info = crate::read_uvicorn_info_file()?;
+    Ok(format!("{}{}", info.url.trim_end_matches('/'), route))
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SEC))
+        .build()
+}
+
+// Prefixes of the errors where the request was sent but no (readable)
+// response came back, like a timeout. The server may have handled it.
+const NO_RESPONSE_ERROR: &str = "No response from server";
+const PARSE_RESPONSE_ERROR: &str = "Failed to parse server response";
+const UNREACHABLE_ERROR: &str = "Failed to reach server";
+
+fn parse_response(response: Result<ureq::Response, ureq::Error>) -> Result<ApiResponse, String> {
+    let response = match response {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(ureq::Error::Transport(err)) => {
+            return Err(match err.kind() {
+                ureq::ErrorKind::Io | ureq::ErrorKind::BadStatus | ureq::ErrorKind::BadHeader => {
+                    format!("{}: {}", NO_RESPONSE_ERROR, err)
+                }
+                _ => format!("{}: {}", UNREACHABLE_ERROR, err),
+            })
+        }
+    };
+
+    response
+        .into_json::<ApiResponse>()
+        .map_err(|err| format!("{}: {}", PARSE_RESPONSE_ERROR, err))
+}
+
+// Whether the request that failed with `err` may still have been handled,
+// so it shouldn't be sent again blindly.
+pub fn may_have_been_handled(err: &str) -> bool {
+    err.starts_with(NO_RESPONSE_ERROR) || err.starts_with(PARSE_RESPONSE_ERROR)
+}
+
+// Whether the request that failed with `err` didn't get an answer from the
+// server at all, rather than being refused by it.
+pub fn is_unreachable(err: &str) -> bool {
+    err.starts_with(NO_RESPONSE_ERROR) || err.starts_with(UNREACHABLE_ERROR)
+}
+
+pub fn get(route: &str, query: &[(&str, &str)]) -> Result<ApiResponse, String> {
+    let mut request = agent().get(&build_url(route)?);
+    for (key, value) in query {
+        request = request.query(key, value);
+    }
+    parse_response(request.call())
+}
+
+pub fn post_json(route: &str, body: Value) -> Result<ApiResponse, String> {
+    parse_response(agent().post(&build_url(route)?).send_json(body))
+}
+
+pub fn post_form(route: &str, form: &[(&str, &str)]) -> Result<ApiResponse, String> {
+    parse_response(agent().post(&build_url(route)?).send_form(form))
+}
+
+pub fn connect_websocket(route: &str) -> Result<WebSocket, String> {
+    let url = build_url(route)?.replacen("http", "ws", 1);
+    tungstenite::connect(url.as_str())
+        .map(|(socket, _)| socket)
+        .map_err(|err| format!("{}: {}", UNREACHABLE_ERROR, err))
+}
+
+// Most of the routes return their results keyed by the account
+// like `{"data": {"a@b.com": ...}}`, see `OpenmailTaskResults`.
+pub fn get_account_data(response: ApiResponse, account: &str) -> Result<Value, String> {
+    if !response.success {
+        return Err(response.message);
+    }
+
+    response
+        .data
+        .and_then(|mut data| data.get_mut(account).map(Value::take))
+        .ok_or(format!("No data found for {}", account))
+}
@@ -0,0 +1,295 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::utils;
+use crate::worker::{self, Job};
+
+// Password protected zips can't be scanned by mail servers, which makes
+// them a common way to deliver malware. They are extracted by the parser
+// worker into a directory only the user can read, and the extracted files
+// are scanned with ClamAV (if it is installed) before they are given to the
+// UI. Infected files are removed right away.
+const MAX_EXTRACTED_SIZE: u64 = 1024 * 1024 * 1024;
+const MAX_EXTRACTED_FILES: usize = 10000;
+// Types that run code when they are opened.
+const RISKY_EXTENSIONS: [&str; 16] = [
+    "exe", "scr", "com", "bat", "cmd", "msi", "ps1", "vbs", "vbe", "js", "jse", "wsf", "hta",
+    "lnk", "jar", "iso",
+];
+// Name of the archive for the password dialog of Windows.
+const PASSWORD_NAME_ENV: &str = "OPENMAIL_NAME";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanStatus {
+    Clean,
+    Infected,
+    // No scanner is installed.
+    NotScanned,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExtractedFile {
+    pub name: String,
+    pub size: u64,
+    pub path: String,
+    pub risky: bool,
+    pub scan: Option<ScanStatus>,
+    pub threat: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedArchive {
+    pub dir: String,
+    pub files: Vec<ExtractedFile>,
+}
+
+pub fn is_archive(name: &str, mime_type: &str) -> bool {
+    name.to_lowercase().ends_with(".zip")
+        || mime_type == "application/zip"
+        || mime_type == "application/x-zip-compressed"
+}
+
+fn open_archive(path: &str) -> Result<zip::ZipArchive<fs::File>, String> {
+    let file = fs::File::open(path).map_err(|err| format!("Failed to open {}: {}", path, err))?;
+    zip::ZipArchive::new(file).map_err(|err| format!("Invalid archive {}: {}", path, err))
+}
+
+// Runs in the worker process.
+pub fn is_encrypted_archive(path: &str) -> Result<bool, String> {
+    let mut archive = open_archive(path)?;
+    for index in 0..archive.len() {
+        let entry = archive
+            .by_index_raw(index)
+            .map_err(|err| format!("Invalid archive {}: {}", path, err))?;
+        if entry.encrypted() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Runs in the worker process.
+pub fn extract_archive(
+    path: &str,
+    dir: &str,
+    password: &str,
+) -> Result<Vec<ExtractedFile>, String> {
+    let mut archive = open_archive(path)?;
+    if archive.len() > MAX_EXTRACTED_FILES {
+        return Err(format!("Archive has too many files: {}", archive.len()));
+    }
+
+    let mut extracted = Vec::new();
+    let mut total_size = 0u64;
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index_decrypt(index, password.as_bytes())
+            .map_err(|err| match err {
+                zip::result::ZipError::InvalidPassword => "Wrong password".to_string(),
+                err => format!("Failed to extract {}: {}", path, err),
+            })?;
+        // Entries like "../../.bashrc" are skipped.
+        let Some(name) = entry.enclosed_name() else {
+            continue;
+        };
+        let target = Path::new(dir).join(&name);
+        if entry.is_dir() {
+            fs::create_dir_all(&target)
+                .map_err(|err| format!("Failed to create {}: {}", target.display(), err))?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("Failed to create {}: {}", parent.display(), err))?;
+        }
+
+        // The sizes in the archive can't be trusted, so the limit is
+        // checked against what is actually written.
+        let mut file = fs::File::create(&target)
+            .map_err(|err| format!("Failed to create {}: {}", target.display(), err))?;
+        let written = io::copy(
+            &mut io::Read::take(&mut entry, MAX_EXTRACTED_SIZE - total_size + 1),
+            &mut file,
+        )
+        .map_err(|err| format!("Failed to extract {}: {}", name.display(), err))?;
+        total_size += written;
+        if total_size > MAX_EXTRACTED_SIZE {
+            return Err("Archive is too large to extract".to_string());
+        }
+
+        let name = name.to_string_lossy().to_string();
+        extracted.push(ExtractedFile {
+            risky: name.rsplit_once('.').is_some_and(|(_, extension)| {
+                RISKY_EXTENSIONS.contains(&extension.to_lowercase().as_str())
+            }),
+            name,
+            size: written,
+            path: target.display().to_string(),
+            scan: None,
+            threat: None,
+        });
+    }
+    Ok(extracted)
+}
+
+// Readable only by the user, under the temp dir of the app.
+fn create_secure_temp_dir() -> Result<String, String> {
+    let dir = utils::build_temp_path(&format!("archive-{}", utils::generate_random_id()))?;
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(&dir)
+        .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+    Ok(dir.display().to_string())
+}
+
+// clamdscan is preferred since it doesn't have to load the signatures on
+// every run.
+fn scan(dir: &str, files: &mut [ExtractedFile]) {
+    let output = ["clamdscan", "clamscan"].iter().find_map(|scanner| {
+        let mut command = Command::new(scanner);
+        if *scanner == "clamdscan" {
+            command.arg("--fdpass");
+        } else {
+            command.arg("--recursive");
+        }
+        command
+            .arg("--infected")
+            .arg("--no-summary")
+            .arg(dir)
+            .stderr(Stdio::null())
+            .output()
+            .ok()
+            // 0 is clean, 1 is infected and anything else is an error.
+            .filter(|output| matches!(output.status.code(), Some(0) | Some(1)))
+    });
+    let Some(output) = output else {
+        files
+            .iter_mut()
+            .for_each(|file| file.scan = Some(ScanStatus::NotScanned));
+        return;
+    };
+
+    // Infected files are reported as "<path>: <threat> FOUND".
+    let report = String::from_utf8_lossy(&output.stdout);
+    for file in files.iter_mut() {
+        file.threat = report.lines().find_map(|line| {
+            line.strip_suffix(" FOUND")?
+                .strip_prefix(&file.path)?
+                .strip_prefix(": ")
+                .map(str::to_string)
+        });
+        file.scan = Some(if file.threat.is_some() {
+            fs::remove_file(&file.path).ok();
+            ScanStatus::Infected
+        } else {
+            ScanStatus::Clean
+        });
+    }
+}
+
+// The password dialog of the OS, None if the user cancelled it.
+fn prompt_password(name: &str) -> Result<Option<String>, String> {
+    let message = format!("Enter the password of {}", name);
+    let mut command = if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(format!(
+            "text returned of (display dialog \"{}\" default answer \"\" with hidden answer with title \"Openmail\")",
+            message.replace(['"', '\\'], "")
+        ));
+        command
+    } else if cfg!(target_os = "windows") {
+        // The name is given as an environment variable, so it is never
+        // parsed as a part of the script.
+        let mut command = Command::new("powershell");
+        command.env(PASSWORD_NAME_ENV, name).arg("-NoProfile").arg("-Command").arg(format!(
+            "$credential = Get-Credential -UserName $env:{} -Message 'Openmail'; if ($credential) {{ $credential.GetNetworkCredential().Password }} else {{ exit 1 }}",
+            PASSWORD_NAME_ENV
+        ));
+        command
+    } else {
+        let mut command = Command::new("zenity");
+        command.arg("--password").arg("--title").arg(message);
+        command
+    };
+
+    let output = command
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|err| format!("Failed to open password dialog: {}", err))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let password = String::from_utf8_lossy(&output.stdout);
+    Ok(Some(password.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+fn run_extract(path: &str, dir: &str, password: &str) -> Result<Vec<ExtractedFile>, String> {
+    let value = worker::run(&Job::ExtractArchive {
+        path: path.to_string(),
+        dir: dir.to_string(),
+        password: password.to_string(),
+    })?;
+    serde_json::from_value(value).map_err(|err| format!("Invalid extracted files: {}", err))
+}
+
+#[tauri::command]
+pub async fn is_encrypted_attachment(path: String) -> Result<bool, String> {
+    match worker::run(&Job::CheckArchive { path })? {
+        serde_json::Value::Bool(encrypted) => Ok(encrypted),
+        _ => Err("Invalid worker result".to_string()),
+    }
+}
+
+// The password is asked with the native dialog unless it is given.
+#[tauri::command]
+pub async fn extract_encrypted_attachment(
+    path: String,
+    password: Option<String>,
+) -> Result<Option<ExtractedArchive>, String> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            let name = Path::new(&path)
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            match prompt_password(&name)? {
+                Some(password) => password,
+                None => return Ok(None),
+            }
+        }
+    };
+
+    let dir = create_secure_temp_dir()?;
+    let mut files = match run_extract(&path, &dir, &password) {
+        Ok(files) => files,
+        Err(err) => {
+            fs::remove_dir_all(&dir).ok();
+            return Err(err);
+        }
+    };
+    scan(&dir, &mut files);
+    Ok(Some(ExtractedArchive { dir, files }))
+}
+
+#[tauri::command]
+pub fn remove_extracted_attachment(dir: String) -> Result<(), String> {
+    // Only the directories created by extract_encrypted_attachment.
+    let is_extracted_dir = Path::new(&dir).parent() == utils::build_temp_dir().ok().as_deref()
+        && Path::new(&dir)
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("archive-"));
+    if !is_extracted_dir {
+        return Err(format!("Not an extracted attachment: {}", dir));
+    }
+    fs::remove_dir_all(&dir).map_err(|err| format!("Failed to remove {}: {}", dir, err))
+}
@@ -0,0 +1,52 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+use crate::consts;
+use crate::utils;
+
+// Record of the things the app did on its own on behalf of the user (like
+// resolving a conflict), so they can be reviewed later. Oldest entries are
+// dropped once the limit is reached.
+const MAX_AUDIT_ENTRIES: usize = 5000;
+
+// Entries are recorded from the worker threads.
+static AUDIT_LOG_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: i64,
+    pub account: String,
+    pub action: String,
+    pub details: String,
+}
+
+pub fn record(account: &str, action: &str, details: String) -> Result<(), String> {
+    let _lock = AUDIT_LOG_LOCK.lock().unwrap();
+    let mut entries: Vec<AuditEntry> = utils::read_json_file(consts::AUDIT_LOG_FILE_PATH)?;
+    entries.push(AuditEntry {
+        timestamp: Utc::now().timestamp(),
+        account: account.to_string(),
+        action: action.to_string(),
+        details,
+    });
+    if entries.len() > MAX_AUDIT_ENTRIES {
+        entries.drain(..entries.len() - MAX_AUDIT_ENTRIES);
+    }
+    utils::write_json_file(consts::AUDIT_LOG_FILE_PATH, &entries)
+}
+
+#[tauri::command]
+pub fn get_audit_log(account: Option<String>) -> Result<Vec<AuditEntry>, String> {
+    let _lock = AUDIT_LOG_LOCK.lock().unwrap();
+    let entries: Vec<AuditEntry> = utils::read_json_file(consts::AUDIT_LOG_FILE_PATH)?;
+    Ok(entries
+        .into_iter()
+        .rev()
+        .filter(|entry| {
+            account
+                .as_ref()
+                .is_none_or(|account| &entry.account == account)
+        })
+        .collect())
+}
@@ -0,0 +1,211 @@
+use chrono::{Duration as ChronoDuration, Local, NaiveDateTime, Timelike};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::cache::Cache;
+use crate::consts;
+use crate::events::{BatchedEvent, EventBus};
+use crate::notifications::{self, HeldNotification, NotificationHistory};
+use crate::scheduler::EMAIL_ADDED_EVENT;
+use crate::utils;
+
+// Batched delivery holds the new emails back and releases them at set
+// times, every `interval_min` minutes from midnight (so 60 is on the hour).
+// Syncs go on as usual, only the "email added" events of the event bus and
+// the notifications are held, so the lists of the UI don't change and
+// nothing pops up in between. Releasing sends the held events with the next
+// batch and shows the held notifications, the UI is told with
+// MAIL_RELEASED_EVENT so it can refresh what it froze.
+pub const MAIL_RELEASED_EVENT: &str = "mail-released";
+const MIN_INTERVAL_MIN: u32 = 15;
+const MAX_INTERVAL_MIN: u32 = 24 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchDeliverySettings {
+    pub enabled: bool,
+    pub interval_min: u32,
+}
+
+impl Default for BatchDeliverySettings {
+    fn default() -> Self {
+        BatchDeliverySettings {
+            enabled: false,
+            interval_min: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchDeliveryStatus {
+    #[serde(flatten)]
+    pub settings: BatchDeliverySettings,
+    pub held_notifications: usize,
+    // Local time, None if batched delivery is off.
+    pub next_release_at: Option<String>,
+}
+
+fn next_release(now: NaiveDateTime, interval_min: u32) -> NaiveDateTime {
+    let minutes = now.hour() * 60 + now.minute();
+    let next = (minutes / interval_min + 1) * interval_min;
+    let midnight = now.date().and_hms_opt(0, 0, 0).unwrap_or(now);
+    midnight + ChronoDuration::minutes(next.min(MAX_INTERVAL_MIN) as i64)
+}
+
+#[derive(Clone, Serialize)]
+struct ReleasedPayload {
+    emails: usize,
+    notifications: usize,
+}
+
+pub struct BatchDelivery {
+    cache: Arc<Cache>,
+    events: Arc<EventBus>,
+    settings: Mutex<BatchDeliverySettings>,
+    held: Mutex<Vec<HeldNotification>>,
+    wakeup: Condvar,
+}
+
+impl BatchDelivery {
+    pub fn load(cache: Arc<Cache>, events: Arc<EventBus>) -> Self {
+        let settings: BatchDeliverySettings =
+            utils::read_json_file(consts::BATCH_DELIVERY_FILE_PATH).unwrap_or_default();
+        if settings.enabled {
+            events.hold(&[EMAIL_ADDED_EVENT]);
+        }
+        BatchDelivery {
+            cache,
+            events,
+            settings: Mutex::new(settings),
+            held: Mutex::new(Vec::new()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    pub fn get_status(&self) -> BatchDeliveryStatus {
+        let settings = self.settings.lock().unwrap().clone();
+        BatchDeliveryStatus {
+            held_notifications: self.held.lock().unwrap().len(),
+            next_release_at: settings.enabled.then(|| {
+                next_release(Local::now().naive_local(), settings.interval_min)
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            }),
+            settings,
+        }
+    }
+
+    pub fn set_settings(
+        &self,
+        app: &AppHandle,
+        settings: BatchDeliverySettings,
+    ) -> Result<(), String> {
+        if !(MIN_INTERVAL_MIN..=MAX_INTERVAL_MIN).contains(&settings.interval_min) {
+            return Err(format!(
+                "Interval must be between {} and {} minutes",
+                MIN_INTERVAL_MIN, MAX_INTERVAL_MIN
+            ));
+        }
+        utils::write_json_file(consts::BATCH_DELIVERY_FILE_PATH, &settings)?;
+        let enabled = settings.enabled;
+        *self.settings.lock().unwrap() = settings;
+        if enabled {
+            self.events.hold(&[EMAIL_ADDED_EVENT]);
+        } else {
+            // Whatever was held is delivered right away.
+            self.release(app);
+            self.events.hold(&[]);
+        }
+        self.wakeup.notify_all();
+        Ok(())
+    }
+
+    // Gives the notification back if it is not held.
+    fn hold(&self, notification: HeldNotification) -> Option<HeldNotification> {
+        if !self.settings.lock().unwrap().enabled {
+            return Some(notification);
+        }
+        self.held.lock().unwrap().push(notification);
+        None
+    }
+
+    pub fn release(&self, app: &AppHandle) {
+        // Emails that were removed while they were held are not added.
+        let is_cached = |event: &BatchedEvent| {
+            let field = |name: &str| event.payload[name].as_str().unwrap_or_default();
+            event.event != EMAIL_ADDED_EVENT
+                || self
+                    .cache
+                    .contains(field("account"), field("folder"), field("uid"))
+        };
+        let emails = self.events.release(is_cached);
+        let held = std::mem::take(&mut *self.held.lock().unwrap());
+        let payload = ReleasedPayload {
+            emails,
+            notifications: held.len(),
+        };
+        if let Some(history) = app.try_state::<Arc<NotificationHistory>>() {
+            notifications::show_released(app, &history, held);
+        }
+        app.emit(MAIL_RELEASED_EVENT, payload).ok();
+    }
+
+    fn wait(&self, interval: Duration) {
+        let settings = self.settings.lock().unwrap();
+        let _ = self.wakeup.wait_timeout(settings, interval).unwrap();
+    }
+}
+
+// For the places that only have the app, like the notifications.
+pub fn hold_notification(
+    app: &AppHandle,
+    notification: HeldNotification,
+) -> Option<HeldNotification> {
+    match app.try_state::<Arc<BatchDelivery>>() {
+        Some(batch_delivery) => batch_delivery.hold(notification),
+        None => Some(notification),
+    }
+}
+
+pub fn start_worker(app: AppHandle, batch_delivery: Arc<BatchDelivery>) {
+    thread::spawn(move || loop {
+        let settings = batch_delivery.settings.lock().unwrap().clone();
+        if !settings.enabled {
+            batch_delivery.wait(Duration::from_secs(MAX_INTERVAL_MIN as u64 * 60));
+            continue;
+        }
+
+        let now = Local::now().naive_local();
+        let release_at = next_release(now, settings.interval_min);
+        batch_delivery.wait((release_at - now).to_std().unwrap_or_default());
+        // Woken up early by a change of the settings, the release time is
+        // computed again.
+        let is_enabled = batch_delivery.settings.lock().unwrap().enabled;
+        if is_enabled && Local::now().naive_local() >= release_at {
+            batch_delivery.release(&app);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_batch_delivery(batch_delivery: State<'_, Arc<BatchDelivery>>) -> BatchDeliveryStatus {
+    batch_delivery.get_status()
+}
+
+#[tauri::command]
+pub fn set_batch_delivery(
+    app: AppHandle,
+    batch_delivery: State<'_, Arc<BatchDelivery>>,
+    settings: BatchDeliverySettings,
+) -> Result<(), String> {
+    batch_delivery.set_settings(&app, settings)
+}
+
+// Releases the held emails before their time.
+#[tauri::command]
+pub fn release_held_mail(app: AppHandle, batch_delivery: State<'_, Arc<BatchDelivery>>) {
+    batch_delivery.release(&app);
+}
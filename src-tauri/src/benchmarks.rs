@@ -0,0 +1,284 @@
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::json;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::api;
+use crate::cache::Cache;
+use crate::consts;
+use crate::scheduler;
+use crate::search::SearchIndex;
+use crate::utils;
+
+// Timings of the core paths on the machine of the user, so releases can be
+// compared with each other. Only available in the debug builds and the ones
+// built with the "benchmarks" feature. Every report is also saved as JSON
+// to BENCHMARKS_DIR_PATH, named after the version and the time.
+// Envelope paging is timed against the server with the Inbox of the first
+// connected account, search with the local index of it. The sanitizer of
+// the email bodies runs on the server, so it is timed there. IPC round-trip
+// is an event to the UI that is answered with `ack_benchmark_ping`, it is
+// skipped if the UI doesn't answer.
+pub const BENCHMARK_PING_EVENT: &str = "benchmark-ping";
+const PAGE_SIZE: usize = 50;
+const PAGING_PAGES: usize = 5;
+const SEARCH_QUERIES: usize = 20;
+const SANITIZER_ROUNDS: usize = 20;
+const IPC_PINGS: usize = 50;
+const IPC_TIMEOUT_MS: u64 = 2000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Measurement {
+    pub name: String,
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub max_ms: f64,
+    // Like "MB/s" for the sanitizer, None for the ones that are only timed.
+    pub throughput: Option<f64>,
+    pub throughput_unit: Option<String>,
+    // Why it was skipped or stopped.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchmarkReport {
+    pub version: String,
+    pub os: String,
+    pub ran_at: String,
+    pub measurements: Vec<Measurement>,
+}
+
+fn is_enabled() -> bool {
+    cfg!(any(debug_assertions, feature = "benchmarks"))
+}
+
+fn to_ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn measurement(name: &str, mut samples: Vec<Duration>, error: Option<String>) -> Measurement {
+    samples.sort();
+    let percentile = |percent: usize| {
+        samples
+            .get((samples.len() * percent / 100).min(samples.len().saturating_sub(1)))
+            .map_or(0.0, |sample| to_ms(*sample))
+    };
+    Measurement {
+        name: name.to_string(),
+        samples: samples.len(),
+        p50_ms: percentile(50),
+        p95_ms: percentile(95),
+        max_ms: samples.last().map_or(0.0, |sample| to_ms(*sample)),
+        throughput: None,
+        throughput_unit: None,
+        error,
+    }
+}
+
+fn skipped(name: &str, error: String) -> Measurement {
+    measurement(name, Vec::new(), Some(error))
+}
+
+fn bench_paging(account: &str) -> Measurement {
+    let mut samples = Vec::new();
+    for page in 0..PAGING_PAGES {
+        let start = Instant::now();
+        match scheduler::fetch_page(account, "Inbox", page * PAGE_SIZE, (page + 1) * PAGE_SIZE) {
+            Ok(fetched) => {
+                samples.push(start.elapsed());
+                if fetched.emails.len() < PAGE_SIZE {
+                    break;
+                }
+            }
+            Err(err) => return measurement("envelope_paging", samples, Some(err)),
+        }
+    }
+    measurement("envelope_paging", samples, None)
+}
+
+// Queries are words of the cached subjects, so they have results.
+fn bench_search(cache: &Cache, index: &SearchIndex, account: &str) -> Measurement {
+    let mut queries: Vec<String> = Vec::new();
+    for email in cache.get_emails(account, "Inbox") {
+        for word in email.subject.split_whitespace() {
+            let word: String = word.chars().filter(|c| c.is_alphanumeric()).collect();
+            if word.chars().count() >= 4 && !queries.contains(&word) {
+                queries.push(word);
+            }
+        }
+        if queries.len() >= SEARCH_QUERIES {
+            break;
+        }
+    }
+    if queries.is_empty() {
+        return skipped("search", format!("Nothing is cached for {}", account));
+    }
+
+    // The first search builds the index, it is not counted.
+    index.search(account, &queries[0]).ok();
+    let mut samples = Vec::new();
+    for query in &queries {
+        let start = Instant::now();
+        if let Err(err) = index.search(account, query) {
+            return measurement("search", samples, Some(err));
+        }
+        samples.push(start.elapsed());
+    }
+    measurement("search", samples, None)
+}
+
+// A newsletter-like document with the markup the sanitizer has to skip.
+fn sample_html() -> String {
+    let paragraph = "<div class=\"row\"><p style=\"color:#333\">Lorem ipsum &amp; dolor \
+                     <a href=\"https://example.com/?a=1&amp;b=2\">sit amet</a>, consectetur \
+                     <b>adipiscing</b> elit&nbsp;&mdash; sed do eiusmod.</p><br/></div>\n";
+    let mut html = String::from("<html><head><style>p { margin: 0 }</style></head><body>");
+    html.push_str("<script>var tracking = 1;</script>");
+    while html.len() < 1024 * 1024 {
+        html.push_str(paragraph);
+    }
+    html.push_str("</body></html>");
+    html
+}
+
+fn fetch_sanitizer_timings(html: &str) -> Result<Vec<Duration>, String> {
+    let response = api::post_json(
+        "/benchmark-sanitizer",
+        json!({ "html": html, "rounds": SANITIZER_ROUNDS }),
+    )?;
+    if !response.success {
+        return Err(response.message);
+    }
+    let seconds: Vec<f64> = serde_json::from_value(response.data.unwrap_or_default())
+        .map_err(|err| format!("Failed to parse sanitizer timings: {}", err))?;
+    Ok(seconds.into_iter().map(Duration::from_secs_f64).collect())
+}
+
+fn bench_sanitizer() -> Measurement {
+    let html = sample_html();
+    let samples = match fetch_sanitizer_timings(&html) {
+        Ok(samples) => samples,
+        Err(err) => return skipped("sanitizer", err),
+    };
+    let total: Duration = samples.iter().sum();
+    let megabytes = (html.len() * samples.len()) as f64 / (1024.0 * 1024.0);
+    let mut measurement = measurement("sanitizer", samples, None);
+    measurement.throughput = Some(megabytes / total.as_secs_f64().max(f64::EPSILON));
+    measurement.throughput_unit = Some("MB/s".to_string());
+    measurement
+}
+
+#[derive(Default)]
+pub struct Benchmarks {
+    acked: Mutex<u64>,
+    ack: Condvar,
+    running: Mutex<bool>,
+}
+
+impl Benchmarks {
+    fn bench_ipc(&self, app: &AppHandle) -> Measurement {
+        let mut samples = Vec::new();
+        for seq in 1..=IPC_PINGS as u64 {
+            let start = Instant::now();
+            let acked = self.acked.lock().unwrap();
+            if app.emit(BENCHMARK_PING_EVENT, seq).is_err() {
+                return skipped("ipc_round_trip", "Failed to reach the UI".to_string());
+            }
+            let (acked, timeout) = self
+                .ack
+                .wait_timeout_while(acked, Duration::from_millis(IPC_TIMEOUT_MS), |acked| {
+                    *acked < seq
+                })
+                .unwrap();
+            drop(acked);
+            if timeout.timed_out() {
+                return measurement(
+                    "ipc_round_trip",
+                    samples,
+                    Some("UI didn't answer the ping".to_string()),
+                );
+            }
+            samples.push(start.elapsed());
+        }
+        measurement("ipc_round_trip", samples, None)
+    }
+
+    pub fn ack(&self, seq: u64) {
+        let mut acked = self.acked.lock().unwrap();
+        *acked = (*acked).max(seq);
+        self.ack.notify_all();
+    }
+
+    pub fn run(
+        &self,
+        app: &AppHandle,
+        cache: &Cache,
+        index: &SearchIndex,
+    ) -> Result<BenchmarkReport, String> {
+        if !is_enabled() {
+            return Err("Benchmarks are not available in this build".to_string());
+        }
+        {
+            let mut running = self.running.lock().unwrap();
+            if *running {
+                return Err("Benchmarks are already running".to_string());
+            }
+            *running = true;
+        }
+        *self.acked.lock().unwrap() = 0;
+
+        let mut measurements = Vec::new();
+        match scheduler::get_connected_accounts() {
+            Ok(accounts) if !accounts.is_empty() => {
+                measurements.push(bench_paging(&accounts[0]));
+                measurements.push(bench_search(cache, index, &accounts[0]));
+            }
+            Ok(_) => {
+                let error = "No connected account".to_string();
+                measurements.push(skipped("envelope_paging", error.clone()));
+                measurements.push(skipped("search", error));
+            }
+            Err(err) => {
+                measurements.push(skipped("envelope_paging", err.clone()));
+                measurements.push(skipped("search", err));
+            }
+        }
+        measurements.push(bench_sanitizer());
+        measurements.push(self.bench_ipc(app));
+        *self.running.lock().unwrap() = false;
+
+        let now = Utc::now();
+        let report = BenchmarkReport {
+            version: app.package_info().version.to_string(),
+            os: std::env::consts::OS.to_string(),
+            ran_at: now.to_rfc3339(),
+            measurements,
+        };
+        let file = format!(
+            "{}/{}-{}.json",
+            consts::BENCHMARKS_DIR_PATH,
+            report.version,
+            now.format("%Y%m%d%H%M%S")
+        );
+        utils::write_json_file(&file, &report)?;
+        Ok(report)
+    }
+}
+
+#[tauri::command]
+pub async fn run_benchmarks(
+    app: AppHandle,
+    benchmarks: State<'_, Arc<Benchmarks>>,
+    cache: State<'_, Arc<Cache>>,
+    index: State<'_, Arc<SearchIndex>>,
+) -> Result<BenchmarkReport, String> {
+    benchmarks.run(&app, &cache, &index)
+}
+
+#[tauri::command]
+pub fn ack_benchmark_ping(benchmarks: State<'_, Arc<Benchmarks>>, seq: u64) {
+    benchmarks.ack(seq);
+}
@@ -0,0 +1,208 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde_json::{json, Map, Value};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpStream};
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[path = "../session.rs"]
+mod session;
+
+// Command line companion of the app, sends the command to the running app
+// over its local IPC server (see ipc.rs) and prints the result.
+//
+//     openmail-cli compose --to x@y [--cc ..] [--bcc ..] [--subject ..] [--body ..]
+//     openmail-cli unread [--account x@y]
+//     openmail-cli sync
+//     openmail-cli send [--account x@y] < message.eml
+//
+// Same as consts::RUNTIME_DIR_PATH and consts::IPC_INFO_FILE_NAME of the
+// app, the app of the current login session is used (see session.rs). If
+// the session can't be told, like over SSH, the app that runs in the only
+// live session is used.
+const RUNTIME_DIR_PATH: &str = "/.openmail/run";
+const IPC_INFO_FILE_NAME: &str = "ipc.info";
+const TIMEOUT_SEC: u64 = 60;
+const LIVE_CHECK_TIMEOUT_MS: u64 = 500;
+const USAGE: &str = "Usage:
+    openmail-cli compose --to <address> [--cc <addresses>] [--bcc <addresses>] [--subject <subject>] [--body <body>]
+    openmail-cli unread [--account <address>]
+    openmail-cli sync
+    openmail-cli send [--account <address>] < message.eml";
+
+struct IpcInfo {
+    port: u16,
+    token: String,
+}
+
+fn read_ipc_info() -> Result<IpcInfo, String> {
+    let home = env::var("HOME").map_err(|_| "HOME is not set".to_string())?;
+    let run_dir = format!("{}/{}", home, RUNTIME_DIR_PATH);
+    let path = format!(
+        "{}/{}/{}",
+        run_dir,
+        session::session_id(),
+        IPC_INFO_FILE_NAME
+    );
+    match fs::read_to_string(path) {
+        Ok(info) => parse_ipc_info(&info),
+        // The session was chosen, there is nothing to fall back to.
+        Err(_) if env::var(session::SESSION_ENV).is_ok_and(|value| !value.is_empty()) => {
+            Err("Openmail is not running".to_string())
+        }
+        Err(_) => find_live_ipc_info(&run_dir),
+    }
+}
+
+// Info files are left behind by the apps that didn't quit cleanly, only
+// the ones whose app still listens count.
+fn find_live_ipc_info(run_dir: &str) -> Result<IpcInfo, String> {
+    let entries = fs::read_dir(run_dir).map_err(|_| "Openmail is not running".to_string())?;
+    let mut live: Vec<IpcInfo> = entries
+        .flatten()
+        .filter_map(|entry| fs::read_to_string(entry.path().join(IPC_INFO_FILE_NAME)).ok())
+        .filter_map(|info| parse_ipc_info(&info).ok())
+        .filter(|info| {
+            TcpStream::connect_timeout(
+                &SocketAddr::from((Ipv4Addr::LOCALHOST, info.port)),
+                Duration::from_millis(LIVE_CHECK_TIMEOUT_MS),
+            )
+            .is_ok()
+        })
+        .collect();
+    match live.len() {
+        0 => Err("Openmail is not running".to_string()),
+        1 => Ok(live.remove(0)),
+        _ => Err(format!(
+            "Openmail is running in more than one session, set {} to one of the sessions in {}",
+            session::SESSION_ENV,
+            run_dir
+        )),
+    }
+}
+
+fn parse_ipc_info(info: &str) -> Result<IpcInfo, String> {
+    let mut port = None;
+    let mut token = None;
+    for (key, value) in info.lines().filter_map(|line| line.split_once('=')) {
+        match key {
+            "port" => port = value.parse::<u16>().ok(),
+            "token" => token = Some(value.to_string()),
+            _ => {}
+        }
+    }
+    match (port, token) {
+        (Some(port), Some(token)) => Ok(IpcInfo { port, token }),
+        _ => Err("Invalid IPC info file, is Openmail running?".to_string()),
+    }
+}
+
+// "--to x@y --subject Hi" to {"to": "x@y", "subject": "Hi"}, only the given
+// options are allowed.
+fn parse_options(args: &[String], allowed: &[&str]) -> Result<Map<String, Value>, String> {
+    let mut options = Map::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = arg
+            .strip_prefix("--")
+            .filter(|name| allowed.contains(name))
+            .ok_or(format!("Unknown option: {}", arg))?;
+        let value = args.next().ok_or(format!("Missing value of {}", arg))?;
+        options.insert(name.to_string(), json!(value));
+    }
+    Ok(options)
+}
+
+fn request(command: &str, args: Value) -> Result<Value, String> {
+    let info = read_ipc_info()?;
+    let stream = TcpStream::connect(("127.0.0.1", info.port))
+        .map_err(|_| "Openmail is not running".to_string())?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(TIMEOUT_SEC)))
+        .map_err(|err| format!("Failed to set timeout: {}", err))?;
+
+    let mut request = json!({
+        "token": info.token,
+        "command": command,
+        "args": args,
+    })
+    .to_string();
+    request.push('\n');
+    (&stream)
+        .write_all(request.as_bytes())
+        .map_err(|err| format!("Failed to send command: {}", err))?;
+
+    let mut line = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut line)
+        .map_err(|err| format!("Failed to read response: {}", err))?;
+    let response: Value =
+        serde_json::from_str(&line).map_err(|err| format!("Invalid response: {}", err))?;
+    if response["success"] != json!(true) {
+        return Err(response["message"]
+            .as_str()
+            .unwrap_or("Unknown error")
+            .to_string());
+    }
+    Ok(response["data"].clone())
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let Some((command, options)) = args.split_first() else {
+        return Err(USAGE.to_string());
+    };
+
+    match command.as_str() {
+        "compose" => {
+            let options = parse_options(options, &["to", "cc", "bcc", "subject", "body"])?;
+            if !options.contains_key("to") {
+                return Err("Missing --to".to_string());
+            }
+            request("compose", Value::Object(options))?;
+        }
+        "unread" => {
+            let options = parse_options(options, &["account"])?;
+            let unread = request("unread", Value::Object(options))?;
+            for (account, count) in unread.as_object().into_iter().flatten() {
+                println!("{}\t{}", account, count);
+            }
+        }
+        "sync" => {
+            parse_options(options, &[])?;
+            request("sync", json!({}))?;
+            println!("Sync requested");
+        }
+        // Like sendmail, the message is read from stdin and queued to the
+        // outbox of the app, the account defaults to the sender of it.
+        "send" => {
+            let mut options = parse_options(options, &["account"])?;
+            let mut message = Vec::new();
+            io::stdin()
+                .read_to_end(&mut message)
+                .map_err(|err| format!("Failed to read message: {}", err))?;
+            if message.is_empty() {
+                return Err("Message is empty".to_string());
+            }
+            options.insert("message".to_string(), json!(BASE64.encode(message)));
+            let queued = request("send", Value::Object(options))?;
+            println!("Queued {}", queued["id"].as_str().unwrap_or_default());
+        }
+        "help" | "--help" | "-h" => println!("{}", USAGE),
+        _ => return Err(format!("Unknown command: {}\n{}", command, USAGE)),
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
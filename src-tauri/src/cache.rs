@@ -0,0 +1,491 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::consts;
+use crate::utils;
+
+// Local copy of the envelopes (and body previews) returned by the server,
+// one file per account. Folders are keyed by the standard folder names
+// that were requested from the server (see `Folder` enum of the server).
+// Each account has its own lock. Pages of a sync are saved at most every
+// `SYNC_SAVE_INTERVAL_SEC` and once the folder is synced (see `flush`), a
+// lost write only loses pages that are fetched again, the local folders
+// and the flags are saved right away.
+const SYNC_SAVE_INTERVAL_SEC: u64 = 10;
+// Only the latest emails of the synced folders are kept.
+pub const MAX_SYNCED_EMAILS: usize = 1000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CachedAttachment {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub mime_type: String,
+    pub cid: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CachedEmail {
+    pub message_id: String,
+    pub uid: String,
+    pub sender: String,
+    pub receivers: String,
+    pub date: String,
+    pub subject: String,
+    pub body: String,
+    pub cc: Option<String>,
+    pub bcc: Option<String>,
+    pub flags: Vec<String>,
+    pub attachments: Vec<CachedAttachment>,
+    pub in_reply_to: Option<String>,
+    pub references: Option<String>,
+}
+
+impl CachedEmail {
+    pub fn is_seen(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "\\Seen")
+    }
+
+    pub fn is_flagged(&self) -> bool {
+        self.flags.iter().any(|flag| flag == "\\Flagged")
+    }
+
+    pub fn uid_number(&self) -> u64 {
+        self.uid.parse().unwrap_or(0)
+    }
+}
+
+// Progress of a sync that has to go deeper than the already cached emails
+// (the first sync of a folder), so it can be resumed if it is interrupted.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SyncCheckpoint {
+    // Offset of the next page and the total of the folder at that time,
+    // new emails shift the offsets.
+    pub next_offset: usize,
+    pub total: usize,
+    // Everything from the newest email down to this one is synced.
+    pub oldest_uid: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FolderCache {
+    // Newest first, same as the order of the server.
+    pub emails: Vec<CachedEmail>,
+    pub total: usize,
+    pub synced_at: Option<i64>,
+    pub checkpoint: Option<SyncCheckpoint>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountCache {
+    pub folders: HashMap<String, FolderCache>,
+    // Folders that only exist on this computer, see local_folder.rs
+    pub local_folders: HashMap<String, Vec<CachedEmail>>,
+}
+
+// What an update changed in a folder, published to the UI during syncs.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "change", rename_all = "snake_case")]
+pub enum EmailChange {
+    Added { uid: String },
+    Removed { uid: String },
+    FlagsChanged { uid: String, flags: Vec<String> },
+}
+
+impl EmailChange {
+    pub fn uid(&self) -> &str {
+        match self {
+            EmailChange::Added { uid }
+            | EmailChange::Removed { uid }
+            | EmailChange::FlagsChanged { uid, .. } => uid,
+        }
+    }
+}
+
+#[derive(Default)]
+struct LoadedAccount {
+    cache: AccountCache,
+    // Changed since it was saved.
+    dirty: bool,
+    saved_at: Option<Instant>,
+}
+
+impl LoadedAccount {
+    fn save(&mut self, account: &str) -> Result<(), String> {
+        utils::write_json_file(&account_file(account), &self.cache)?;
+        self.dirty = false;
+        self.saved_at = Some(Instant::now());
+        Ok(())
+    }
+
+    // For the pages of a sync, see `flush`.
+    fn save_later(&mut self, account: &str) -> Result<(), String> {
+        self.dirty = true;
+        let is_due = self.saved_at.is_none_or(|saved_at| {
+            saved_at.elapsed() >= Duration::from_secs(SYNC_SAVE_INTERVAL_SEC)
+        });
+        if is_due {
+            self.save(account)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Cache {
+    accounts: Mutex<HashMap<String, Arc<Mutex<LoadedAccount>>>>,
+}
+
+fn account_file(account: &str) -> String {
+    format!("{}/{}.json", consts::CACHE_DIR_PATH, account)
+}
+
+impl Cache {
+    fn get_account(&self, account: &str) -> Arc<Mutex<LoadedAccount>> {
+        self.accounts
+            .lock()
+            .unwrap()
+            .entry(account.to_string())
+            .or_insert_with(|| {
+                Arc::new(Mutex::new(LoadedAccount {
+                    cache: utils::read_json_file(&account_file(account)).unwrap_or_default(),
+                    ..Default::default()
+                }))
+            })
+            .clone()
+    }
+
+    fn with_account<R>(&self, account: &str, f: impl FnOnce(&mut AccountCache) -> R) -> R {
+        f(&mut self.get_account(account).lock().unwrap().cache)
+    }
+
+    // Changes the account and saves it right away.
+    fn update_account<R>(
+        &self,
+        account: &str,
+        f: impl FnOnce(&mut AccountCache) -> Result<R, String>,
+    ) -> Result<R, String> {
+        let loaded = self.get_account(account);
+        let mut loaded = loaded.lock().unwrap();
+        let result = f(&mut loaded.cache)?;
+        loaded.save(account)?;
+        Ok(result)
+    }
+
+    // Saves the pages of a sync that were not saved yet.
+    pub fn flush(&self, account: &str) -> Result<(), String> {
+        let loaded = self.get_account(account);
+        let mut loaded = loaded.lock().unwrap();
+        if loaded.dirty {
+            loaded.save(account)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_folder(&self, account: &str, folder: &str) -> FolderCache {
+        self.with_account(account, |cache| {
+            cache.folders.get(folder).cloned().unwrap_or_default()
+        })
+    }
+
+    // Synced folders, the local ones are listed by `get_local_folders`.
+    pub fn get_folder_names(&self, account: &str) -> Vec<String> {
+        self.with_account(account, |cache| cache.folders.keys().cloned().collect())
+    }
+
+    pub fn get_emails(&self, account: &str, folder: &str) -> Vec<CachedEmail> {
+        self.get_folder(account, folder).emails
+    }
+
+    pub fn contains(&self, account: &str, folder: &str, uid: &str) -> bool {
+        self.with_account(account, |cache| {
+            cache
+                .folders
+                .get(folder)
+                .is_some_and(|folder| folder.emails.iter().any(|email| email.uid == uid))
+        })
+    }
+
+    pub fn set_checkpoint(
+        &self,
+        account: &str,
+        folder: &str,
+        checkpoint: Option<SyncCheckpoint>,
+    ) -> Result<(), String> {
+        let loaded = self.get_account(account);
+        let mut loaded = loaded.lock().unwrap();
+        loaded
+            .cache
+            .folders
+            .entry(folder.to_string())
+            .or_default()
+            .checkpoint = checkpoint;
+        loaded.save_later(account)
+    }
+
+    // Called once every page of a sync is fetched, a folder only counts as
+    // synced after that, see scheduler.rs
+    pub fn finish_sync(&self, account: &str, folder: &str, synced_at: i64) -> Result<(), String> {
+        let loaded = self.get_account(account);
+        let mut loaded = loaded.lock().unwrap();
+        let folder_cache = loaded.cache.folders.entry(folder.to_string()).or_default();
+        folder_cache.checkpoint = None;
+        folder_cache.synced_at = Some(synced_at);
+        loaded.save_later(account)
+    }
+
+    pub fn remove_emails(
+        &self,
+        account: &str,
+        folder: &str,
+        uids: &[String],
+    ) -> Result<Vec<EmailChange>, String> {
+        self.update_account(account, |cache| {
+            let Some(folder_cache) = cache.folders.get_mut(folder) else {
+                return Ok(Vec::new());
+            };
+
+            let mut changes = Vec::new();
+            folder_cache.emails.retain(|email| {
+                let keep = !uids.contains(&email.uid);
+                if !keep {
+                    changes.push(EmailChange::Removed {
+                        uid: email.uid.clone(),
+                    });
+                }
+                keep
+            });
+            folder_cache.total = folder_cache.total.saturating_sub(changes.len());
+            Ok(changes)
+        })
+    }
+
+    pub fn get_local_folders(&self, account: &str) -> Vec<String> {
+        self.with_account(account, |cache| {
+            let mut folders: Vec<String> = cache.local_folders.keys().cloned().collect();
+            folders.sort();
+            folders
+        })
+    }
+
+    pub fn get_local_emails(&self, account: &str, folder: &str) -> Vec<CachedEmail> {
+        self.with_account(account, |cache| {
+            cache.local_folders.get(folder).cloned().unwrap_or_default()
+        })
+    }
+
+    pub fn create_local_folder(&self, account: &str, folder: &str) -> Result<(), String> {
+        self.update_account(account, |cache| {
+            if cache.local_folders.contains_key(folder) {
+                return Err(format!("Local folder already exists: {}", folder));
+            }
+            cache.local_folders.insert(folder.to_string(), Vec::new());
+            Ok(())
+        })
+    }
+
+    // Returns the emails of the removed folder.
+    pub fn delete_local_folder(
+        &self,
+        account: &str,
+        folder: &str,
+    ) -> Result<Vec<CachedEmail>, String> {
+        self.update_account(account, |cache| {
+            cache
+                .local_folders
+                .remove(folder)
+                .ok_or(format!("Local folder not found: {}", folder))
+        })
+    }
+
+    pub fn add_local_email(
+        &self,
+        account: &str,
+        folder: &str,
+        email: CachedEmail,
+    ) -> Result<(), String> {
+        self.update_account(account, |cache| {
+            cache
+                .local_folders
+                .get_mut(folder)
+                .ok_or(format!("Local folder not found: {}", folder))?
+                .insert(0, email);
+            Ok(())
+        })
+    }
+
+    // Adds or removes a flag of a cached email without waiting for the
+    // server, returns the flags of the email before the change.
+    pub fn set_flag(
+        &self,
+        account: &str,
+        folder: &str,
+        uid: &str,
+        flag: &str,
+        set: bool,
+    ) -> Result<Option<Vec<String>>, String> {
+        self.update_account(account, |cache| {
+            let Some(email) = cache
+                .folders
+                .get_mut(folder)
+                .and_then(|folder| folder.emails.iter_mut().find(|email| email.uid == uid))
+            else {
+                return Ok(None);
+            };
+
+            let flags = email.flags.clone();
+            email.flags.retain(|current| current != flag);
+            if set {
+                email.flags.push(flag.to_string());
+            }
+            Ok(Some(flags))
+        })
+    }
+
+    // Adds the emails (oldest first) that are not in the folder yet, by
+    // their Message-ID, for the folders that are not synced with a server
+    // like the feeds. They get the next uids of the folder and only the
+    // newest `max` emails are kept. Flags of the cached ones are kept.
+    pub fn add_emails(
+        &self,
+        account: &str,
+        folder: &str,
+        emails: Vec<CachedEmail>,
+        max: usize,
+        synced_at: i64,
+    ) -> Result<Vec<EmailChange>, String> {
+        self.update_account(account, |cache| {
+            let folder_cache = cache.folders.entry(folder.to_string()).or_default();
+            let mut message_ids: HashSet<String> = folder_cache
+                .emails
+                .iter()
+                .map(|email| email.message_id.clone())
+                .collect();
+            let mut next_uid = folder_cache
+                .emails
+                .iter()
+                .map(CachedEmail::uid_number)
+                .max()
+                .unwrap_or_default()
+                + 1;
+            let mut changes = Vec::new();
+            for mut email in emails {
+                if !message_ids.insert(email.message_id.clone()) {
+                    continue;
+                }
+                email.uid = next_uid.to_string();
+                next_uid += 1;
+                changes.push(EmailChange::Added {
+                    uid: email.uid.clone(),
+                });
+                folder_cache.emails.insert(0, email);
+            }
+            for email in folder_cache
+                .emails
+                .drain(max.min(folder_cache.emails.len())..)
+            {
+                changes.push(EmailChange::Removed { uid: email.uid });
+            }
+            folder_cache.total = folder_cache.emails.len();
+            folder_cache.synced_at = Some(synced_at);
+            Ok(changes)
+        })
+    }
+
+    pub fn remove_folder(&self, account: &str, folder: &str) -> Result<(), String> {
+        self.update_account(account, |cache| {
+            cache.folders.remove(folder);
+            Ok(())
+        })
+    }
+
+    // Emails and sync state of the folder move with it, nothing is reported.
+    pub fn rename_folder(&self, account: &str, folder: &str, name: &str) -> Result<(), String> {
+        self.update_account(account, |cache| {
+            if let Some(cached) = cache.folders.remove(folder) {
+                cache.folders.insert(name.to_string(), cached);
+            }
+            Ok(())
+        })
+    }
+
+    // Merges a page of emails fetched from the server (newest first) into
+    // the folder. Since a page is a continuous range of the folder, cached
+    // emails that fall into the range of the page but are not in it were
+    // deleted or moved on the server. Only the newest `MAX_SYNCED_EMAILS`
+    // are kept, the older ones are still on the server so they are not
+    // reported as removed.
+    pub fn update_folder(
+        &self,
+        account: &str,
+        folder: &str,
+        page: Vec<CachedEmail>,
+        is_first_page: bool,
+        total: usize,
+    ) -> Result<Vec<EmailChange>, String> {
+        let loaded = self.get_account(account);
+        let mut loaded = loaded.lock().unwrap();
+        let changes = {
+            let folder_cache = loaded.cache.folders.entry(folder.to_string()).or_default();
+            let mut changes = Vec::new();
+            let page_uids: HashSet<u64> = page.iter().map(CachedEmail::uid_number).collect();
+            if let (Some(newest), Some(oldest)) = (page_uids.iter().max(), page_uids.iter().min()) {
+                folder_cache.emails.retain(|email| {
+                    let uid = email.uid_number();
+                    let in_range = uid >= *oldest && (is_first_page || uid <= *newest);
+                    let keep = !in_range || page_uids.contains(&uid);
+                    if !keep {
+                        changes.push(EmailChange::Removed {
+                            uid: email.uid.clone(),
+                        });
+                    }
+                    keep
+                });
+            } else if is_first_page {
+                changes.extend(
+                    folder_cache
+                        .emails
+                        .drain(..)
+                        .map(|email| EmailChange::Removed { uid: email.uid }),
+                );
+            }
+
+            for email in page {
+                match folder_cache
+                    .emails
+                    .iter_mut()
+                    .find(|cached| cached.uid == email.uid)
+                {
+                    Some(cached) => {
+                        if cached.flags != email.flags {
+                            changes.push(EmailChange::FlagsChanged {
+                                uid: email.uid.clone(),
+                                flags: email.flags.clone(),
+                            });
+                        }
+                        *cached = email;
+                    }
+                    None => {
+                        changes.push(EmailChange::Added {
+                            uid: email.uid.clone(),
+                        });
+                        folder_cache.emails.push(email);
+                    }
+                }
+            }
+            folder_cache
+                .emails
+                .sort_by_key(|email| std::cmp::Reverse(email.uid_number()));
+            folder_cache.emails.truncate(MAX_SYNCED_EMAILS);
+            folder_cache.total = total;
+            changes
+        };
+        loaded.save_later(account)?;
+        Ok(changes)
+    }
+}
@@ -0,0 +1,867 @@
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, Local, LocalResult, Months, NaiveDate,
+    NaiveDateTime, NaiveTime, TimeZone, Utc, Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tauri::State;
+
+use crate::consts;
+use crate::utils;
+
+// There is no calendar in the app, the busy times come from the local copy
+// of the CalDAV calendars that a sync tool like vdirsyncer keeps: folders of
+// .ics files, one folder per calendar. Free slots are the gaps between the
+// events in the working hours of the working days, at least as long as the
+// meeting. Recurring events are expanded for the common rules (FREQ with
+// INTERVAL, COUNT, UNTIL, BYDAY and EXDATE), the others are taken as single
+// events.
+const TIME_FORMAT: &str = "%H:%M";
+const MAX_WINDOW_DAYS: i64 = 31;
+const MAX_SLOTS: usize = 10;
+const MAX_OCCURRENCES: usize = 5000;
+// Slots start on a quarter hour.
+const SLOT_STEP_MIN: i64 = 15;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CalendarSettings {
+    // Folders of the calendars, or a folder of them.
+    pub dirs: Vec<String>,
+    // Working days, 0 is Monday.
+    pub work_days: Vec<u8>,
+    // "HH:MM", local.
+    pub work_start: String,
+    pub work_end: String,
+}
+
+impl Default for CalendarSettings {
+    fn default() -> Self {
+        CalendarSettings {
+            dirs: Vec::new(),
+            work_days: vec![0, 1, 2, 3, 4],
+            work_start: "09:00".to_string(),
+            work_end: "17:00".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FreeSlot {
+    // RFC 3339, local.
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FreeSlots {
+    pub slots: Vec<FreeSlot>,
+    // The slots as a list, to insert into the body.
+    pub text: String,
+}
+
+type Busy = (DateTime<Utc>, DateTime<Utc>);
+
+#[derive(Debug, Default)]
+struct Property {
+    params: HashMap<String, String>,
+    value: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum EventTime {
+    Utc(NaiveDateTime),
+    Zoned(NaiveDateTime, Tz),
+    Floating(NaiveDateTime),
+    Date(NaiveDate),
+}
+
+impl EventTime {
+    fn naive(&self) -> NaiveDateTime {
+        match self {
+            EventTime::Utc(time) | EventTime::Zoned(time, _) | EventTime::Floating(time) => *time,
+            EventTime::Date(date) => date.and_time(NaiveTime::MIN),
+        }
+    }
+
+    // Same kind of time at another naive time, for the occurrences.
+    fn with_naive(&self, time: NaiveDateTime) -> EventTime {
+        match self {
+            EventTime::Utc(_) => EventTime::Utc(time),
+            EventTime::Zoned(_, tz) => EventTime::Zoned(time, *tz),
+            EventTime::Floating(_) => EventTime::Floating(time),
+            EventTime::Date(_) => EventTime::Date(time.date()),
+        }
+    }
+
+    fn to_utc(self) -> Option<DateTime<Utc>> {
+        match self {
+            EventTime::Utc(time) => Some(time.and_utc()),
+            EventTime::Zoned(time, tz) => to_zone(&tz, time).map(|time| time.with_timezone(&Utc)),
+            EventTime::Floating(time) => to_zone(&Local, time).map(|time| time.with_timezone(&Utc)),
+            EventTime::Date(date) => {
+                to_zone(&Local, date.and_time(NaiveTime::MIN)).map(|time| time.with_timezone(&Utc))
+            }
+        }
+    }
+}
+
+// Times that are skipped by a DST change are moved forward by the change,
+// like RFC 5545 asks, and repeated ones are the first of the two.
+fn to_zone<T: TimeZone>(tz: &T, time: NaiveDateTime) -> Option<DateTime<T>> {
+    match tz.from_local_datetime(&time) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => Some(time),
+        LocalResult::None => tz
+            .from_local_datetime(&(time + ChronoDuration::hours(1)))
+            .earliest(),
+    }
+}
+
+fn parse_time(property: &Property) -> Option<EventTime> {
+    let value = property.value.trim();
+    if property
+        .params
+        .get("VALUE")
+        .is_some_and(|kind| kind == "DATE")
+        || value.len() == 8
+    {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .map(EventTime::Date);
+    }
+    let (value, is_utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    if is_utc {
+        return Some(EventTime::Utc(time));
+    }
+    // Outlook writes Windows zone names, those are taken as local.
+    Some(
+        match property
+            .params
+            .get("TZID")
+            .and_then(|tzid| tzid.trim_matches('"').parse::<Tz>().ok())
+        {
+            Some(tz) => EventTime::Zoned(time, tz),
+            None => EventTime::Floating(time),
+        },
+    )
+}
+
+// Only the time part of the ISO 8601 durations, like PT1H30M or P1D.
+fn parse_duration(value: &str) -> Option<ChronoDuration> {
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let value = value.strip_prefix('P')?;
+    let mut total = ChronoDuration::zero();
+    let mut number = String::new();
+    for c in value.chars() {
+        match c {
+            'T' => {}
+            c if c.is_ascii_digit() => number.push(c),
+            unit => {
+                let amount: i64 = std::mem::take(&mut number).parse().ok()?;
+                total += match unit {
+                    'W' => ChronoDuration::weeks(amount),
+                    'D' => ChronoDuration::days(amount),
+                    'H' => ChronoDuration::hours(amount),
+                    'M' => ChronoDuration::minutes(amount),
+                    'S' => ChronoDuration::seconds(amount),
+                    _ => return None,
+                };
+            }
+        }
+    }
+    Some(if negative { -total } else { total })
+}
+
+// Properties of the VEVENTs of the calendar, with the folded lines joined.
+fn parse_events(content: &str) -> Vec<Vec<(String, Property)>> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in content.lines() {
+        match line.strip_prefix([' ', '\t']) {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<Vec<(String, Property)>> = None;
+    // Alarms and the like have their own properties.
+    let mut depth = 0;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let mut params = name.split(';');
+        let name = params.next().unwrap_or_default().to_uppercase();
+        match (name.as_str(), value.trim()) {
+            ("BEGIN", "VEVENT") => {
+                current = Some(Vec::new());
+                depth = 0;
+            }
+            ("END", "VEVENT") => events.extend(current.take()),
+            ("BEGIN", _) if current.is_some() => depth += 1,
+            ("END", _) if current.is_some() => depth -= 1,
+            _ if depth == 0 => {
+                if let Some(event) = current.as_mut() {
+                    let params = params
+                        .filter_map(|param| param.split_once('='))
+                        .map(|(key, value)| (key.to_uppercase(), value.to_string()))
+                        .collect();
+                    event.push((
+                        name,
+                        Property {
+                            params,
+                            value: value.to_string(),
+                        },
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    events
+}
+
+fn weekday(day: &str) -> Option<Weekday> {
+    // Like 2TU or -1FR, the position is ignored.
+    let day = day.trim_start_matches(|c: char| c == '-' || c == '+' || c.is_ascii_digit());
+    Some(match day {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+// UNTIL is in UTC, or a date or a local time like DTSTART. The whole day of
+// a date is included.
+fn parse_until(start: EventTime, value: &str) -> Option<DateTime<Utc>> {
+    if let Some(value) = value.strip_suffix('Z') {
+        return NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|time| time.and_utc());
+    }
+    match NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S") {
+        Ok(time) => start.with_naive(time).to_utc(),
+        Err(_) => {
+            let next_day =
+                NaiveDate::parse_from_str(value, "%Y%m%d").ok()? + ChronoDuration::days(1);
+            let end = start
+                .with_naive(next_day.and_time(NaiveTime::MIN))
+                .to_utc()?;
+            Some(end - ChronoDuration::seconds(1))
+        }
+    }
+}
+
+// Occurrences of the event that start before `until`, in the time of the
+// event and in UTC. The rule is followed in the time of the event, so a
+// weekly meeting at 9:00 stays at 9:00 after a DST change, while UNTIL and
+// `until` are compared in UTC.
+fn occurrences(
+    start: EventTime,
+    rule: Option<&str>,
+    until: DateTime<Utc>,
+) -> Vec<(NaiveDateTime, DateTime<Utc>)> {
+    let first = start.naive();
+    let Some(rule) = rule else {
+        return start
+            .to_utc()
+            .map(|time| (first, time))
+            .into_iter()
+            .collect();
+    };
+    let parts: HashMap<&str, &str> = rule
+        .split(';')
+        .filter_map(|part| part.split_once('='))
+        .collect();
+    let interval: u32 = parts
+        .get("INTERVAL")
+        .and_then(|interval| interval.parse().ok())
+        .unwrap_or(1)
+        .max(1);
+    let count: Option<usize> = parts.get("COUNT").and_then(|count| count.parse().ok());
+    let until = match parts
+        .get("UNTIL")
+        .and_then(|value| parse_until(start, value))
+    {
+        Some(rule_until) => rule_until.min(until),
+        None => until,
+    };
+    let by_day: Vec<Weekday> = parts
+        .get("BYDAY")
+        .map(|days| days.split(',').filter_map(weekday).collect())
+        .unwrap_or_default();
+    let is_weekly_by_day = parts.get("FREQ") == Some(&"WEEKLY") && !by_day.is_empty();
+    let is_after_until = |time: NaiveDateTime| {
+        start
+            .with_naive(time)
+            .to_utc()
+            .is_none_or(|time| time > until)
+    };
+
+    let mut times = Vec::new();
+    let push = |time: NaiveDateTime, times: &mut Vec<(NaiveDateTime, DateTime<Utc>)>| {
+        if time < first || count.is_some_and(|count| times.len() >= count) {
+            return;
+        }
+        if let Some(utc) = start.with_naive(time).to_utc().filter(|utc| *utc <= until) {
+            times.push((time, utc));
+        }
+    };
+    for step in 0..MAX_OCCURRENCES {
+        let step = step as u32 * interval;
+        let base = match parts.get("FREQ").copied() {
+            Some("DAILY") => first + ChronoDuration::days(step as i64),
+            Some("WEEKLY") => first + ChronoDuration::weeks(step as i64),
+            Some("MONTHLY") => match first.checked_add_months(Months::new(step)) {
+                Some(base) => base,
+                None => break,
+            },
+            Some("YEARLY") => match first.checked_add_months(Months::new(step * 12)) {
+                Some(base) => base,
+                None => break,
+            },
+            _ => {
+                return start
+                    .to_utc()
+                    .map(|time| (first, time))
+                    .into_iter()
+                    .collect()
+            }
+        };
+        // The days of a week can be before the day of DTSTART.
+        let period_start = if is_weekly_by_day {
+            base - ChronoDuration::days(base.weekday().num_days_from_monday() as i64)
+        } else {
+            base
+        };
+        if is_after_until(period_start) || count.is_some_and(|count| times.len() >= count) {
+            break;
+        }
+        if is_weekly_by_day {
+            let mut days = by_day.clone();
+            days.sort_by_key(|day| day.num_days_from_monday());
+            for day in days {
+                push(
+                    period_start + ChronoDuration::days(day.num_days_from_monday() as i64),
+                    &mut times,
+                );
+            }
+        } else {
+            push(base, &mut times);
+        }
+    }
+    times
+}
+
+// Busy times of the event in the range.
+fn event_busy(event: &[(String, Property)], range: Busy) -> Vec<Busy> {
+    let get = |name: &str| {
+        event
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, property)| property)
+    };
+    let value = |name: &str| get(name).map(|property| property.value.trim().to_uppercase());
+    if value("STATUS").as_deref() == Some("CANCELLED")
+        || value("TRANSP").as_deref() == Some("TRANSPARENT")
+    {
+        return Vec::new();
+    }
+    let Some(start) = get("DTSTART").and_then(parse_time) else {
+        return Vec::new();
+    };
+    let Some(first) = start.to_utc() else {
+        return Vec::new();
+    };
+    // DTEND can be in another zone than DTSTART.
+    let duration = match get("DTEND")
+        .and_then(parse_time)
+        .and_then(EventTime::to_utc)
+    {
+        Some(end) => end - first,
+        None => match get("DURATION").and_then(|duration| parse_duration(duration.value.trim())) {
+            Some(duration) => duration,
+            None if matches!(start, EventTime::Date(_)) => ChronoDuration::days(1),
+            None => ChronoDuration::zero(),
+        },
+    };
+    let excluded: Vec<EventTime> = event
+        .iter()
+        .filter(|(key, _)| key == "EXDATE")
+        .flat_map(|(_, property)| {
+            property.value.split(',').filter_map(|value| {
+                parse_time(&Property {
+                    params: property.params.clone(),
+                    value: value.to_string(),
+                })
+            })
+        })
+        .collect();
+    // Dates exclude the occurrences on that day, the others the ones that
+    // start at the same time, in whatever zone they are given.
+    let is_excluded = |(time, utc): &(NaiveDateTime, DateTime<Utc>)| {
+        excluded.iter().any(|excluded| match excluded {
+            EventTime::Date(date) => time.date() == *date,
+            excluded => excluded.to_utc() == Some(*utc),
+        })
+    };
+
+    occurrences(start, value("RRULE").as_deref(), range.1)
+        .into_iter()
+        .filter(|occurrence| !is_excluded(occurrence))
+        .map(|(_, start)| (start, start + duration))
+        .filter(|(start, end)| *start < range.1 && *end > range.0)
+        .collect()
+}
+
+fn ics_files(dir: &Path, files: &mut Vec<String>, depth: usize) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() && depth > 0 {
+            ics_files(&path, files, depth - 1);
+        } else if path.extension().is_some_and(|extension| extension == "ics") {
+            files.push(path.display().to_string());
+        }
+    }
+}
+
+fn busy_times(dirs: &[String], range: Busy) -> Vec<Busy> {
+    let mut files = Vec::new();
+    for dir in dirs {
+        ics_files(Path::new(dir), &mut files, 1);
+    }
+    let mut busy: Vec<Busy> = files
+        .iter()
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .flat_map(|content| {
+            parse_events(&content)
+                .iter()
+                .flat_map(|event| event_busy(event, range))
+                .collect::<Vec<Busy>>()
+        })
+        .collect();
+    busy.sort();
+    busy
+}
+
+// Rounded up to the next quarter hour.
+fn round_up(time: DateTime<Utc>) -> DateTime<Utc> {
+    let step = SLOT_STEP_MIN * 60;
+    let timestamp = time.timestamp();
+    let rounded = (timestamp + step - 1).div_euclid(step) * step;
+    DateTime::from_timestamp(rounded, 0).unwrap_or(time)
+}
+
+// Gaps of at least `duration` between the busy times, in the working hours
+// of the days (in the zone of the user) and from `now` on.
+fn find_slots<T: TimeZone>(
+    tz: &T,
+    work_days: &[u8],
+    (work_start, work_end): (NaiveTime, NaiveTime),
+    (first_day, last_day): (NaiveDate, NaiveDate),
+    duration: ChronoDuration,
+    busy: &[Busy],
+    now: DateTime<Utc>,
+) -> Vec<Busy> {
+    let mut slots = Vec::new();
+    let mut day = first_day;
+    while day <= last_day && slots.len() < MAX_SLOTS {
+        let is_work_day = work_days.contains(&(day.weekday().num_days_from_monday() as u8));
+        let hours = to_zone(tz, day.and_time(work_start)).zip(to_zone(tz, day.and_time(work_end)));
+        if let (true, Some((start, end))) = (is_work_day, hours) {
+            let end = end.with_timezone(&Utc);
+            let mut free_from = round_up(start.with_timezone(&Utc).max(now));
+            for (busy_start, busy_end) in busy.iter().chain([(end, end)].iter()) {
+                if *busy_end <= free_from {
+                    continue;
+                }
+                let free_until = (*busy_start).min(end);
+                if free_until - free_from >= duration && slots.len() < MAX_SLOTS {
+                    slots.push((free_from, free_until));
+                }
+                free_from = round_up(free_from.max(*busy_end));
+                if free_from >= end {
+                    break;
+                }
+            }
+        }
+        day += ChronoDuration::days(1);
+    }
+    slots
+}
+
+fn format_slots(slots: &[(DateTime<Local>, DateTime<Local>)]) -> String {
+    slots
+        .iter()
+        .map(|(start, end)| {
+            format!(
+                "- {} {}–{} ({})",
+                start.format("%a, %b %-d"),
+                start.format(TIME_FORMAT),
+                end.format(TIME_FORMAT),
+                start.format("%Z")
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn validate(settings: &CalendarSettings) -> Result<(), String> {
+    for dir in &settings.dirs {
+        if !Path::new(dir).is_dir() {
+            return Err(format!("Not a directory: {}", dir));
+        }
+    }
+    if settings.work_days.iter().any(|day| *day > 6) {
+        return Err("Days must be between 0 (Monday) and 6 (Sunday)".to_string());
+    }
+    let start = NaiveTime::parse_from_str(&settings.work_start, TIME_FORMAT)
+        .map_err(|_| format!("Invalid time: {}", settings.work_start))?;
+    let end = NaiveTime::parse_from_str(&settings.work_end, TIME_FORMAT)
+        .map_err(|_| format!("Invalid time: {}", settings.work_end))?;
+    if start >= end {
+        return Err("Working hours must end after they start".to_string());
+    }
+    Ok(())
+}
+
+pub struct Calendar {
+    settings: Mutex<CalendarSettings>,
+}
+
+impl Calendar {
+    pub fn load() -> Self {
+        Calendar {
+            settings: Mutex::new(
+                utils::read_json_file(consts::CALENDAR_SETTINGS_FILE_PATH).unwrap_or_default(),
+            ),
+        }
+    }
+
+    pub fn get_settings(&self) -> CalendarSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: CalendarSettings) -> Result<(), String> {
+        validate(&settings)?;
+        utils::write_json_file(consts::CALENDAR_SETTINGS_FILE_PATH, &settings)?;
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+
+    // Gaps of at least `duration_min` in the working hours of the days from
+    // `first_day` to `last_day` (YYYY-MM-DD, inclusive), from now on.
+    pub fn suggest_free_slots(
+        &self,
+        duration_min: u32,
+        first_day: &str,
+        last_day: &str,
+    ) -> Result<FreeSlots, String> {
+        let settings = self.get_settings();
+        if settings.dirs.is_empty() {
+            return Err("No calendar is set".to_string());
+        }
+        let parse_day = |day: &str| {
+            NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .map_err(|_| format!("Invalid date: {}, expected YYYY-MM-DD", day))
+        };
+        let (first_day, last_day) = (parse_day(first_day)?, parse_day(last_day)?);
+        if last_day < first_day || (last_day - first_day).num_days() >= MAX_WINDOW_DAYS {
+            return Err(format!("Window must be 1 to {} days", MAX_WINDOW_DAYS));
+        }
+        if duration_min == 0 {
+            return Err("Duration can not be 0".to_string());
+        }
+        let duration = ChronoDuration::minutes(duration_min as i64);
+        let (work_start, work_end) = (
+            NaiveTime::parse_from_str(&settings.work_start, TIME_FORMAT)
+                .map_err(|_| format!("Invalid time: {}", settings.work_start))?,
+            NaiveTime::parse_from_str(&settings.work_end, TIME_FORMAT)
+                .map_err(|_| format!("Invalid time: {}", settings.work_end))?,
+        );
+
+        let range = (
+            to_zone(&Local, first_day.and_time(NaiveTime::MIN))
+                .ok_or("Invalid window".to_string())?
+                .with_timezone(&Utc),
+            to_zone(
+                &Local,
+                (last_day + ChronoDuration::days(1)).and_time(NaiveTime::MIN),
+            )
+            .ok_or("Invalid window".to_string())?
+            .with_timezone(&Utc),
+        );
+        let busy = busy_times(&settings.dirs, range);
+        let slots = find_slots(
+            &Local,
+            &settings.work_days,
+            (work_start, work_end),
+            (first_day, last_day),
+            duration,
+            &busy,
+            Utc::now(),
+        );
+
+        let slots: Vec<(DateTime<Local>, DateTime<Local>)> = slots
+            .into_iter()
+            .map(|(start, end)| (start.with_timezone(&Local), end.with_timezone(&Local)))
+            .collect();
+        Ok(FreeSlots {
+            text: format_slots(&slots),
+            slots: slots
+                .iter()
+                .map(|(start, end)| FreeSlot {
+                    start: start.to_rfc3339(),
+                    end: end.to_rfc3339(),
+                })
+                .collect(),
+        })
+    }
+}
+
+#[tauri::command]
+pub fn get_calendar_settings(calendar: State<'_, Arc<Calendar>>) -> CalendarSettings {
+    calendar.get_settings()
+}
+
+#[tauri::command]
+pub fn set_calendar_settings(
+    calendar: State<'_, Arc<Calendar>>,
+    settings: CalendarSettings,
+) -> Result<(), String> {
+    calendar.set_settings(settings)
+}
+
+#[tauri::command]
+pub async fn suggest_free_slots(
+    calendar: State<'_, Arc<Calendar>>,
+    duration_min: u32,
+    first_day: String,
+    last_day: String,
+) -> Result<FreeSlots, String> {
+    calendar.suggest_free_slots(duration_min, &first_day, &last_day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_event(lines: &str) -> Vec<(String, Property)> {
+        let content = format!(
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\n{}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+            lines.trim().replace('\n', "\r\n")
+        );
+        parse_events(&content).remove(0)
+    }
+
+    fn utc(time: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M")
+            .unwrap()
+            .and_utc()
+    }
+
+    fn local(date: &str) -> DateTime<Utc> {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        to_zone(&Local, date.and_time(NaiveTime::MIN))
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn starts(event: &[(String, Property)]) -> Vec<DateTime<Utc>> {
+        let range = (utc("2024-01-01 00:00"), utc("2025-01-01 00:00"));
+        event_busy(event, range)
+            .into_iter()
+            .map(|(start, _)| start)
+            .collect()
+    }
+
+    #[test]
+    fn unfolds_lines_and_skips_alarms() {
+        let event = parse_event(
+            "SUMMARY:Long\n  title\nDTSTART:20240304T090000Z\nBEGIN:VALARM\nDTSTART:20240101T000000Z\nEND:VALARM",
+        );
+        let summary = event.iter().find(|(name, _)| name == "SUMMARY").unwrap();
+        assert_eq!(summary.1.value, "Long title");
+        assert_eq!(
+            event.iter().filter(|(name, _)| name == "DTSTART").count(),
+            1
+        );
+    }
+
+    #[test]
+    fn expands_weekly_by_day_with_count() {
+        let event = parse_event(
+            "DTSTART;TZID=Europe/Berlin:20240304T090000\n\
+             DTEND;TZID=Europe/Berlin:20240304T100000\n\
+             RRULE:FREQ=WEEKLY;BYDAY=WE,MO;COUNT=3",
+        );
+        let range = (utc("2024-01-01 00:00"), utc("2025-01-01 00:00"));
+        assert_eq!(
+            event_busy(&event, range),
+            vec![
+                (utc("2024-03-04 08:00"), utc("2024-03-04 09:00")),
+                (utc("2024-03-06 08:00"), utc("2024-03-06 09:00")),
+                (utc("2024-03-11 08:00"), utc("2024-03-11 09:00")),
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_the_local_time_of_zoned_events_across_dst() {
+        let event = parse_event(
+            "DTSTART;TZID=Europe/Berlin:20240325T090000\nRRULE:FREQ=WEEKLY;INTERVAL=1;COUNT=2",
+        );
+        assert_eq!(
+            starts(&event),
+            vec![utc("2024-03-25 08:00"), utc("2024-04-01 07:00")]
+        );
+    }
+
+    #[test]
+    fn compares_until_and_exdate_in_utc() {
+        // 09:00 in New York is 14:00 UTC in March before DST.
+        let event = parse_event(
+            "DTSTART;TZID=America/New_York:20240301T090000\n\
+             RRULE:FREQ=DAILY;UNTIL=20240304T133000Z\n\
+             EXDATE:20240302T140000Z",
+        );
+        assert_eq!(
+            starts(&event),
+            vec![utc("2024-03-01 14:00"), utc("2024-03-03 14:00")]
+        );
+
+        let event = parse_event(
+            "DTSTART;TZID=America/New_York:20240301T090000\n\
+             RRULE:FREQ=DAILY;UNTIL=20240303\n\
+             EXDATE;TZID=Europe/London:20240301T140000,20240302T140000",
+        );
+        assert_eq!(starts(&event), vec![utc("2024-03-03 14:00")]);
+    }
+
+    #[test]
+    fn excludes_whole_days_of_date_exdates() {
+        let event = parse_event(
+            "DTSTART:20240301T090000Z\nRRULE:FREQ=DAILY;COUNT=3\nEXDATE;VALUE=DATE:20240302",
+        );
+        assert_eq!(
+            starts(&event),
+            vec![utc("2024-03-01 09:00"), utc("2024-03-03 09:00")]
+        );
+    }
+
+    #[test]
+    fn blocks_whole_days_of_all_day_events() {
+        let range = (utc("2024-01-01 00:00"), utc("2025-01-01 00:00"));
+        let event = parse_event("DTSTART;VALUE=DATE:20240305");
+        assert_eq!(
+            event_busy(&event, range),
+            vec![(
+                local("2024-03-05"),
+                local("2024-03-05") + ChronoDuration::days(1)
+            )]
+        );
+
+        let event = parse_event("DTSTART;VALUE=DATE:20240305\nDTEND;VALUE=DATE:20240307");
+        assert_eq!(
+            event_busy(&event, range),
+            vec![(local("2024-03-05"), local("2024-03-07"))]
+        );
+    }
+
+    #[test]
+    fn takes_the_duration_across_zones() {
+        let range = (utc("2024-01-01 00:00"), utc("2025-01-01 00:00"));
+        let event = parse_event(
+            "DTSTART;TZID=Europe/Berlin:20240305T100000\nDTEND;TZID=Europe/London:20240305T100000",
+        );
+        assert_eq!(
+            event_busy(&event, range),
+            vec![(utc("2024-03-05 09:00"), utc("2024-03-05 10:00"))]
+        );
+
+        let event = parse_event("DTSTART:20240305T100000Z\nDURATION:PT1H30M");
+        assert_eq!(
+            event_busy(&event, range),
+            vec![(utc("2024-03-05 10:00"), utc("2024-03-05 11:30"))]
+        );
+    }
+
+    #[test]
+    fn skips_cancelled_and_free_events() {
+        let range = (utc("2024-01-01 00:00"), utc("2025-01-01 00:00"));
+        let cancelled = parse_event("DTSTART:20240305T100000Z\nDURATION:PT1H\nSTATUS:CANCELLED");
+        let free = parse_event("DTSTART:20240305T100000Z\nDURATION:PT1H\nTRANSP:TRANSPARENT");
+        assert!(event_busy(&cancelled, range).is_empty());
+        assert!(event_busy(&free, range).is_empty());
+    }
+
+    #[test]
+    fn finds_free_slots_between_busy_times() {
+        let hours = (
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+        );
+        // Friday to Monday.
+        let days = (
+            NaiveDate::from_ymd_opt(2024, 3, 8).unwrap(),
+            NaiveDate::from_ymd_opt(2024, 3, 11).unwrap(),
+        );
+        let busy = vec![
+            (utc("2024-03-08 10:00"), utc("2024-03-08 11:00")),
+            (utc("2024-03-08 10:30"), utc("2024-03-08 12:30")),
+            (utc("2024-03-08 16:30"), utc("2024-03-08 18:00")),
+        ];
+        let now = utc("2024-03-08 09:07");
+
+        assert_eq!(
+            find_slots(
+                &Utc,
+                &[0, 1, 2, 3, 4],
+                hours,
+                days,
+                ChronoDuration::minutes(60),
+                &busy,
+                now
+            ),
+            vec![
+                (utc("2024-03-08 12:30"), utc("2024-03-08 16:30")),
+                (utc("2024-03-11 09:00"), utc("2024-03-11 17:00")),
+            ]
+        );
+        // Starts on the next quarter hour from now.
+        assert_eq!(
+            find_slots(
+                &Utc,
+                &[4],
+                hours,
+                days,
+                ChronoDuration::minutes(30),
+                &busy,
+                now
+            ),
+            vec![
+                (utc("2024-03-08 09:15"), utc("2024-03-08 10:00")),
+                (utc("2024-03-08 12:30"), utc("2024-03-08 16:30")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,472 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Listener, State};
+
+use crate::consts;
+use crate::outbox::{self, Outbox, OutboxItem, OutboxStatus, OutgoingEmail};
+use crate::template;
+use crate::utils;
+
+// Recipients are handed to the outbox a few at a time instead of all at
+// once, so pausing or cancelling a campaign takes effect right away
+// while the outbox throttle still decides the actual sending pace.
+const QUEUE_WINDOW: usize = 5;
+const EMAIL_COLUMN: &str = "email";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CampaignStatus {
+    Draft,
+    Running,
+    Paused,
+    Cancelled,
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecipientStatus {
+    Pending,
+    Queued,
+    Sent,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CampaignRecipient {
+    pub email: String,
+    pub fields: HashMap<String, String>,
+    pub status: RecipientStatus,
+    pub outbox_id: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Campaign {
+    pub id: String,
+    pub name: String,
+    pub sender: String,
+    pub subject: String,
+    pub body: String,
+    pub status: CampaignStatus,
+    pub recipients: Vec<CampaignRecipient>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CampaignProgress {
+    pub total: usize,
+    pub pending: usize,
+    pub queued: usize,
+    pub sent: usize,
+    pub failed: usize,
+    pub cancelled: usize,
+}
+
+impl Campaign {
+    fn progress(&self) -> CampaignProgress {
+        let count = |status: RecipientStatus| {
+            self.recipients
+                .iter()
+                .filter(|recipient| recipient.status == status)
+                .count()
+        };
+        CampaignProgress {
+            total: self.recipients.len(),
+            pending: count(RecipientStatus::Pending),
+            queued: count(RecipientStatus::Queued),
+            sent: count(RecipientStatus::Sent),
+            failed: count(RecipientStatus::Failed),
+            cancelled: count(RecipientStatus::Cancelled),
+        }
+    }
+}
+
+fn parse_csv(content: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = vec![];
+    let mut row = vec![];
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = content.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' if !in_quotes => row.push(std::mem::take(&mut field)),
+            '\r' if !in_quotes => {}
+            '\n' if !in_quotes => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err("Invalid CSV: unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+
+    rows.retain(|row| row.iter().any(|field| !field.trim().is_empty()));
+    Ok(rows)
+}
+
+fn parse_recipients(csv: &str) -> Result<Vec<CampaignRecipient>, String> {
+    let mut rows = parse_csv(csv)?.into_iter();
+    let header: Vec<String> = rows
+        .next()
+        .ok_or("CSV file is empty")?
+        .into_iter()
+        .map(|column| column.trim().to_string())
+        .collect();
+    let email_index = header
+        .iter()
+        .position(|column| column.eq_ignore_ascii_case(EMAIL_COLUMN))
+        .ok_or(format!("CSV file must have an `{}` column", EMAIL_COLUMN))?;
+
+    let mut recipients = vec![];
+    for (line, row) in rows.enumerate() {
+        if row.len() != header.len() {
+            return Err(format!(
+                "Row {} has {} columns, expected {}",
+                line + 2,
+                row.len(),
+                header.len()
+            ));
+        }
+
+        let email = row[email_index].trim().to_string();
+        if !email.contains('@') {
+            return Err(format!("Row {} has an invalid email: {}", line + 2, email));
+        }
+
+        recipients.push(CampaignRecipient {
+            email,
+            fields: header.iter().cloned().zip(row).collect(),
+            status: RecipientStatus::Pending,
+            outbox_id: None,
+            error: None,
+        });
+    }
+
+    if recipients.is_empty() {
+        return Err("CSV file has no recipients".to_string());
+    }
+    Ok(recipients)
+}
+
+fn render(template: &str, fields: &HashMap<String, String>) -> String {
+    // Placeholders are validated when the campaign is created, empty
+    // fields are left empty.
+    template::render(template, |name| {
+        Some(fields.get(name).cloned().unwrap_or_default())
+    })
+}
+
+pub struct Campaigns {
+    campaigns: Mutex<Vec<Campaign>>,
+    outbox: Arc<Outbox>,
+}
+
+impl Campaigns {
+    pub fn load(outbox: Arc<Outbox>) -> Self {
+        let campaigns = Campaigns {
+            campaigns: Mutex::new(
+                utils::read_json_file(consts::CAMPAIGNS_FILE_PATH).unwrap_or_default(),
+            ),
+            outbox,
+        };
+
+        // Items may have been sent right before the app was closed,
+        // before the campaign got notified about them.
+        for item in campaigns.outbox.get_items() {
+            campaigns.handle_outbox_update(&item);
+        }
+        let mut list = campaigns.campaigns.lock().unwrap();
+        campaigns.feed(&mut list);
+        campaigns.save(&list).ok();
+        drop(list);
+
+        campaigns
+    }
+
+    fn save(&self, campaigns: &[Campaign]) -> Result<(), String> {
+        utils::write_json_file(consts::CAMPAIGNS_FILE_PATH, &campaigns)
+    }
+
+    pub fn create(
+        &self,
+        name: String,
+        sender: String,
+        subject: String,
+        body: String,
+        csv: &str,
+    ) -> Result<Campaign, String> {
+        if utils::extract_email_address(&sender).is_empty() {
+            return Err(format!("Invalid sender: {}", sender));
+        }
+
+        let recipients = parse_recipients(csv)?;
+        let columns: HashSet<&String> = recipients[0].fields.keys().collect();
+        for placeholder in template::find_placeholders(&subject)
+            .iter()
+            .chain(template::find_placeholders(&body).iter())
+        {
+            if !columns.contains(placeholder) {
+                return Err(format!("Unknown placeholder: {{{{{}}}}}", placeholder));
+            }
+        }
+
+        let campaign = Campaign {
+            id: utils::generate_random_id(),
+            name,
+            sender,
+            subject,
+            body,
+            status: CampaignStatus::Draft,
+            recipients,
+            created_at: Utc::now().timestamp(),
+        };
+        let mut campaigns = self.campaigns.lock().unwrap();
+        campaigns.push(campaign.clone());
+        self.save(&campaigns)?;
+        Ok(campaign)
+    }
+
+    pub fn get_campaigns(&self) -> Vec<Campaign> {
+        self.campaigns.lock().unwrap().clone()
+    }
+
+    pub fn get_progress(&self, id: &str) -> Result<CampaignProgress, String> {
+        let campaigns = self.campaigns.lock().unwrap();
+        campaigns
+            .iter()
+            .find(|campaign| campaign.id == id)
+            .map(Campaign::progress)
+            .ok_or(format!("Campaign not found: {}", id))
+    }
+
+    fn set_status(&self, id: &str, status: CampaignStatus) -> Result<(), String> {
+        let mut campaigns = self.campaigns.lock().unwrap();
+        let campaign = campaigns
+            .iter_mut()
+            .find(|campaign| campaign.id == id)
+            .ok_or(format!("Campaign not found: {}", id))?;
+        if matches!(
+            campaign.status,
+            CampaignStatus::Cancelled | CampaignStatus::Completed
+        ) {
+            return Err(format!("Campaign is already finished: {}", id));
+        }
+
+        campaign.status = status;
+        if matches!(status, CampaignStatus::Paused | CampaignStatus::Cancelled) {
+            for recipient in campaign.recipients.iter_mut() {
+                match recipient.status {
+                    RecipientStatus::Pending if status == CampaignStatus::Cancelled => {
+                        recipient.status = RecipientStatus::Cancelled
+                    }
+                    // Queued ones are taken back from the outbox, paused
+                    // ones are queued again once it is resumed. Already
+                    // sending ones can not be taken back.
+                    RecipientStatus::Queued => {
+                        let removed = recipient
+                            .outbox_id
+                            .as_ref()
+                            .is_some_and(|outbox_id| self.outbox.remove(outbox_id).is_ok());
+                        if removed {
+                            recipient.outbox_id = None;
+                            recipient.status = if status == CampaignStatus::Paused {
+                                RecipientStatus::Pending
+                            } else {
+                                RecipientStatus::Cancelled
+                            };
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self.feed(&mut campaigns);
+        self.save(&campaigns)
+    }
+
+    pub fn start(&self, id: &str) -> Result<(), String> {
+        self.set_status(id, CampaignStatus::Running)
+    }
+
+    pub fn pause(&self, id: &str) -> Result<(), String> {
+        self.set_status(id, CampaignStatus::Paused)
+    }
+
+    pub fn cancel(&self, id: &str) -> Result<(), String> {
+        self.set_status(id, CampaignStatus::Cancelled)
+    }
+
+    // Queues pending recipients of running campaigns until each campaign
+    // has `QUEUE_WINDOW` emails waiting in the outbox.
+    fn feed(&self, campaigns: &mut [Campaign]) {
+        for campaign in campaigns.iter_mut() {
+            if campaign.status != CampaignStatus::Running {
+                continue;
+            }
+
+            let mut queued = campaign.progress().queued;
+            for recipient in campaign.recipients.iter_mut() {
+                if queued >= QUEUE_WINDOW {
+                    break;
+                }
+                if recipient.status != RecipientStatus::Pending {
+                    continue;
+                }
+
+                let email = OutgoingEmail {
+                    sender: campaign.sender.clone(),
+                    receivers: recipient.email.clone(),
+                    subject: render(&campaign.subject, &recipient.fields),
+                    body: render(&campaign.body, &recipient.fields),
+                    cc: None,
+                    bcc: None,
+                    raw: None,
+                    keep_image_metadata: false,
+                };
+                match self.outbox.queue(email) {
+                    Ok(outbox_id) => {
+                        recipient.status = RecipientStatus::Queued;
+                        recipient.outbox_id = Some(outbox_id);
+                        queued += 1;
+                    }
+                    Err(err) => {
+                        recipient.status = RecipientStatus::Failed;
+                        recipient.error = Some(err);
+                    }
+                }
+            }
+
+            let progress = campaign.progress();
+            if progress.pending == 0 && progress.queued == 0 {
+                campaign.status = CampaignStatus::Completed;
+            }
+        }
+    }
+
+    pub fn handle_outbox_update(&self, item: &OutboxItem) {
+        let mut campaigns = self.campaigns.lock().unwrap();
+        let recipient = campaigns
+            .iter_mut()
+            .flat_map(|campaign| campaign.recipients.iter_mut())
+            .find(|recipient| recipient.outbox_id.as_deref() == Some(item.id.as_str()));
+        let Some(recipient) = recipient else {
+            return;
+        };
+
+        match item.status {
+            OutboxStatus::Sent => recipient.status = RecipientStatus::Sent,
+            OutboxStatus::Failed | OutboxStatus::Unknown => {
+                recipient.status = RecipientStatus::Failed;
+                recipient.error = item.error.clone();
+            }
+            _ => return,
+        }
+
+        self.feed(&mut campaigns);
+        self.save(&campaigns).ok();
+    }
+}
+
+pub fn listen_outbox(app: &AppHandle, campaigns: Arc<Campaigns>) {
+    app.listen(outbox::OUTBOX_ITEM_UPDATED_EVENT, move |event| {
+        if let Ok(item) = serde_json::from_str::<OutboxItem>(event.payload()) {
+            campaigns.handle_outbox_update(&item);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn create_campaign(
+    campaigns: State<'_, Arc<Campaigns>>,
+    name: String,
+    sender: String,
+    subject: String,
+    body: String,
+    csv: String,
+) -> Result<Campaign, String> {
+    campaigns.create(name, sender, subject, body, &csv)
+}
+
+#[tauri::command]
+pub fn get_campaigns(campaigns: State<'_, Arc<Campaigns>>) -> Vec<Campaign> {
+    campaigns.get_campaigns()
+}
+
+#[tauri::command]
+pub fn get_campaign_progress(
+    campaigns: State<'_, Arc<Campaigns>>,
+    id: String,
+) -> Result<CampaignProgress, String> {
+    campaigns.get_progress(&id)
+}
+
+#[tauri::command]
+pub fn start_campaign(campaigns: State<'_, Arc<Campaigns>>, id: String) -> Result<(), String> {
+    campaigns.start(&id)
+}
+
+#[tauri::command]
+pub fn pause_campaign(campaigns: State<'_, Arc<Campaigns>>, id: String) -> Result<(), String> {
+    campaigns.pause(&id)
+}
+
+#[tauri::command]
+pub fn cancel_campaign(campaigns: State<'_, Arc<Campaigns>>, id: String) -> Result<(), String> {
+    campaigns.cancel(&id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_quoted_fields() {
+        let rows = parse_csv("email,note\r\na@b.com,\"x, \"\"y\"\"\"\n\n").unwrap();
+        assert_eq!(
+            rows,
+            vec![vec!["email", "note"], vec!["a@b.com", "x, \"y\""]]
+        );
+        assert!(parse_csv("email\n\"a@b.com").is_err());
+    }
+
+    #[test]
+    fn validates_recipients() {
+        let recipients = parse_recipients(" Email ,name\na@b.com,Ada\n").unwrap();
+        assert_eq!(recipients.len(), 1);
+        assert_eq!(recipients[0].email, "a@b.com");
+        assert_eq!(recipients[0].fields["name"], "Ada");
+
+        assert!(parse_recipients("name\nAda\n").is_err());
+        assert!(parse_recipients("email,name\na@b.com\n").is_err());
+        assert!(parse_recipients("email\nnot-an-email\n").is_err());
+        assert!(parse_recipients("email\n").is_err());
+    }
+
+    #[test]
+    fn renders_fields_once() {
+        let fields = HashMap::from([
+            ("name".to_string(), "{{company}}".to_string()),
+            ("company".to_string(), "Acme".to_string()),
+        ]);
+        assert_eq!(
+            render("{{name }} at {{ company}}{{missing}}", &fields),
+            "{{company}} at Acme"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tauri::State;
+
+use crate::cache::{Cache, CachedEmail};
+use crate::consts;
+use crate::local_folder;
+use crate::template;
+use crate::threads;
+use crate::utils;
+use crate::worker;
+
+// "Clip to notes" writes an email as a Markdown note into a folder of a
+// notes app, like an Obsidian vault, with its attachments next to it in
+// "attachments/<note>/". The note starts with a front matter made from the
+// template of the settings, its placeholders are replaced with the values
+// of the email escaped for double quoted YAML strings. Attachments are
+// saved by the parser worker like the downloaded ones, see downloads.rs
+const DEFAULT_FRONT_MATTER: &str = "title: \"{{subject}}\"
+from: \"{{from}}\"
+to: \"{{to}}\"
+date: \"{{date}}\"
+message_id: \"{{message_id}}\"
+thread_id: \"{{thread_id}}\"
+tags: [email]";
+const ATTACHMENTS_DIR: &str = "attachments";
+const MAX_NOTE_NAME_CHARS: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClipSettings {
+    // Vault of the notes app, or any folder.
+    pub vault: Option<String>,
+    // Folder of the notes in the vault, relative to it.
+    pub folder: String,
+    // {{subject}}, {{from}}, {{to}}, {{cc}}, {{date}}, {{message_id}},
+    // {{thread_id}} and {{account}} are replaced.
+    pub front_matter: String,
+    pub attachments: bool,
+}
+
+impl Default for ClipSettings {
+    fn default() -> Self {
+        ClipSettings {
+            vault: None,
+            folder: String::new(),
+            front_matter: DEFAULT_FRONT_MATTER.to_string(),
+            attachments: true,
+        }
+    }
+}
+
+fn validate(settings: &ClipSettings) -> Result<(), String> {
+    if let Some(vault) = settings.vault.as_ref() {
+        if !Path::new(vault).is_absolute() || !Path::new(vault).is_dir() {
+            return Err(format!("Not a directory: {}", vault));
+        }
+    }
+    if !Path::new(&settings.folder)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)))
+    {
+        return Err(format!("Invalid folder: {}", settings.folder));
+    }
+    Ok(())
+}
+
+fn escape_yaml(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', " ")
+}
+
+// Values are never rendered again, so a subject with "{{to}}" stays as it is.
+fn render_front_matter(front_matter: &str, values: &[(&str, &str)]) -> String {
+    template::render(front_matter, |name| {
+        values
+            .iter()
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| escape_yaml(value))
+    })
+}
+
+// "<date> <subject>", with a number if a note with the name exists.
+fn note_name(dir: &Path, email: &CachedEmail) -> String {
+    let date = utils::parse_email_date(&email.date)
+        .map(|date| date.format("%Y-%m-%d ").to_string())
+        .unwrap_or_default();
+    let subject: String = utils::sanitize_file_name(&email.subject)
+        .chars()
+        .take(MAX_NOTE_NAME_CHARS)
+        .collect();
+    let name = format!("{}{}", date, subject).trim().to_string();
+    let name = if name.is_empty() {
+        "Email".to_string()
+    } else {
+        name
+    };
+    let mut unique = name.clone();
+    let mut number = 2;
+    while dir.join(format!("{}.md", unique)).exists() {
+        unique = format!("{} ({})", name, number);
+        number += 1;
+    }
+    unique
+}
+
+// Source of the email, the local ones are stored on the disk already.
+fn source_file(account: &str, folder: &str, uid: &str, local: bool) -> Result<PathBuf, String> {
+    if local {
+        return Ok(local_folder::email_file(account, folder, uid));
+    }
+    let source = local_folder::fetch_source(account, folder, uid)?;
+    let path = utils::build_temp_path(&format!("{}.eml", utils::generate_random_id()))?;
+    fs::write(&path, source).map_err(|err| format!("Failed to write message: {}", err))?;
+    Ok(path)
+}
+
+pub struct Clipper {
+    cache: Arc<Cache>,
+    settings: Mutex<ClipSettings>,
+}
+
+impl Clipper {
+    pub fn load(cache: Arc<Cache>) -> Self {
+        Clipper {
+            cache,
+            settings: Mutex::new(
+                utils::read_json_file(consts::CLIP_SETTINGS_FILE_PATH).unwrap_or_default(),
+            ),
+        }
+    }
+
+    pub fn get_settings(&self) -> ClipSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: ClipSettings) -> Result<(), String> {
+        validate(&settings)?;
+        utils::write_json_file(consts::CLIP_SETTINGS_FILE_PATH, &settings)?;
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+
+    fn find_email(
+        &self,
+        account: &str,
+        folder: &str,
+        uid: &str,
+        local: bool,
+    ) -> Option<CachedEmail> {
+        let emails = if local {
+            self.cache.get_local_emails(account, folder)
+        } else {
+            self.cache.get_emails(account, folder)
+        };
+        emails.into_iter().find(|email| email.uid == uid)
+    }
+
+    // Returns the path of the note.
+    pub fn clip(
+        &self,
+        account: &str,
+        folder: &str,
+        uid: &str,
+        local: bool,
+    ) -> Result<String, String> {
+        let settings = self.get_settings();
+        let vault = settings
+            .vault
+            .as_ref()
+            .ok_or("Notes folder is not set".to_string())?;
+        let email = self
+            .find_email(account, folder, uid, local)
+            .ok_or(format!("Email {} not found in {}", uid, folder))?;
+
+        let dir = Path::new(vault).join(&settings.folder);
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+        let name = note_name(&dir, &email);
+
+        let mut attachments = Vec::new();
+        if settings.attachments && !email.attachments.is_empty() {
+            let source = source_file(account, folder, uid, local)?;
+            let saved = worker::save_attachments(
+                &source.to_string_lossy(),
+                &dir.join(ATTACHMENTS_DIR).join(&name).to_string_lossy(),
+            );
+            if !local {
+                fs::remove_file(&source).ok();
+            }
+            attachments = saved?;
+        }
+
+        let emails = threads::get_account_emails(&self.cache, account);
+        let thread_id = threads::thread_id(&email, &threads::by_message_id(&emails));
+        let date = utils::parse_email_date(&email.date)
+            .map(|date| date.to_rfc3339())
+            .unwrap_or_else(|| email.date.clone());
+        let front_matter = render_front_matter(
+            &settings.front_matter,
+            &[
+                ("subject", &email.subject),
+                ("from", &email.sender),
+                ("to", &email.receivers),
+                ("cc", email.cc.as_deref().unwrap_or_default()),
+                ("date", &date),
+                ("message_id", &email.message_id),
+                ("thread_id", &thread_id),
+                ("account", account),
+            ],
+        );
+
+        let mut note = format!(
+            "---\n{}\n---\n\n# {}\n\n{}\n",
+            front_matter.trim(),
+            email.subject,
+            email.body.trim()
+        );
+        if !attachments.is_empty() {
+            note.push_str("\n## Attachments\n\n");
+            for attachment in attachments {
+                let file_name = Path::new(&attachment.path)
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                note.push_str(&format!(
+                    "- [{}](<{}/{}/{}>)\n",
+                    attachment.name, ATTACHMENTS_DIR, name, file_name
+                ));
+            }
+        }
+
+        let path = dir.join(format!("{}.md", name));
+        fs::write(&path, note)
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+        Ok(path.display().to_string())
+    }
+}
+
+#[tauri::command]
+pub fn get_clip_settings(clipper: State<'_, Arc<Clipper>>) -> ClipSettings {
+    clipper.get_settings()
+}
+
+#[tauri::command]
+pub fn set_clip_settings(
+    clipper: State<'_, Arc<Clipper>>,
+    settings: ClipSettings,
+) -> Result<(), String> {
+    clipper.set_settings(settings)
+}
+
+#[tauri::command]
+pub async fn clip_to_notes(
+    clipper: State<'_, Arc<Clipper>>,
+    account: String,
+    folder: String,
+    uid: String,
+    local: bool,
+) -> Result<String, String> {
+    clipper.clip(&account, &folder, &uid, local)
+}
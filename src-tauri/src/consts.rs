@@ -5,4 +5,50 @@ pub const UVICORN_START_SCRIPT_PATH: &str = if IS_WINDOWS {
     "./linux/start_uvicorn.sh"
 };
 pub const UVICORN_LOG_FILE_PATH: &str = "/.openmail/server/logs/uvicorn.log";
-pub const UVICORN_INFO_FILE_PATH: &str = "/.openmail/server/uvicorn.info";
+// Runtime files are kept per login session, see utils::build_runtime_path
+pub const RUNTIME_DIR_PATH: &str = "/.openmail/run";
+pub const UVICORN_INFO_FILE_NAME: &str = "uvicorn.info";
+pub const INBOX_PROGRESS_FILE_PATH: &str = "/.openmail/app/inbox_progress.json";
+pub const OUTBOX_FILE_PATH: &str = "/.openmail/app/outbox.json";
+pub const CACHE_DIR_PATH: &str = "/.openmail/app/cache";
+pub const CAMPAIGNS_FILE_PATH: &str = "/.openmail/app/campaigns.json";
+pub const SEND_LIMITS_FILE_PATH: &str = "/.openmail/app/send_limits.json";
+pub const DEFAULT_SENDS_PER_MINUTE: u32 = 20;
+pub const DEFAULT_SENDS_PER_HOUR: u32 = 200;
+pub const PREFERENCES_FILE_PATH: &str = "/.openmail/client/preferences.json";
+pub const SETTINGS_SYNC_FILE_PATH: &str = "/.openmail/app/settings_sync.json";
+pub const SETTINGS_SYNC_FOLDER: &str = "Openmail Settings";
+pub const KEYRING_SERVICE: &str = "Openmail";
+pub const AUDIT_LOG_FILE_PATH: &str = "/.openmail/app/audit_log.json";
+pub const OFFLINE_CHANGES_FILE_PATH: &str = "/.openmail/app/offline_changes.json";
+pub const SHARED_MAILBOXES_FILE_PATH: &str = "/.openmail/app/shared_mailboxes.json";
+pub const LOCAL_FOLDERS_DIR_PATH: &str = "/.openmail/app/local";
+pub const DIAGNOSTICS_DIR_PATH: &str = "/.openmail/app/diagnostics";
+pub const BENCHMARKS_DIR_PATH: &str = "/.openmail/app/benchmarks";
+pub const IPC_INFO_FILE_NAME: &str = "ipc.info";
+pub const NOTIFICATION_HISTORY_FILE_PATH: &str = "/.openmail/app/notification_history.json";
+pub const DOWNLOAD_RULES_FILE_PATH: &str = "/.openmail/app/download_rules.json";
+pub const ATTACHMENTS_DIR_PATH: &str = "/.openmail/app/attachments";
+pub const ACCOUNT_SCHEDULES_FILE_PATH: &str = "/.openmail/app/account_schedules.json";
+pub const STATUS_EXPORT_SETTINGS_FILE_PATH: &str = "/.openmail/app/status_export.json";
+pub const STATUS_FILE_PATH: &str = "/.openmail/app/status.json";
+pub const SEARCH_SETTINGS_FILE_PATH: &str = "/.openmail/app/search_settings.json";
+pub const SEARCH_STUBS_SETTINGS_FILE_PATH: &str = "/.openmail/app/search_stubs.json";
+// Has to be somewhere the search of the OS indexes, hidden directories like
+// .openmail are skipped.
+pub const SEARCH_STUBS_DIR_PATH: &str = if IS_WINDOWS {
+    "/Openmail Messages"
+} else {
+    "/Library/Caches/Metadata/Openmail"
+};
+pub const DROP_FOLDER_SETTINGS_FILE_PATH: &str = "/.openmail/app/drop_folder.json";
+pub const CLIP_SETTINGS_FILE_PATH: &str = "/.openmail/app/clip_settings.json";
+pub const FEEDS_FILE_PATH: &str = "/.openmail/app/feeds.json";
+pub const NEWSGROUPS_FILE_PATH: &str = "/.openmail/app/newsgroups.json";
+pub const SHARE_TARGETS_FILE_PATH: &str = "/.openmail/app/share_targets.json";
+pub const BATCH_DELIVERY_FILE_PATH: &str = "/.openmail/app/batch_delivery.json";
+pub const THREAD_SUBSCRIPTIONS_FILE_PATH: &str = "/.openmail/app/thread_subscriptions.json";
+pub const CALENDAR_SETTINGS_FILE_PATH: &str = "/.openmail/app/calendar.json";
+pub const SEND_TIME_SETTINGS_FILE_PATH: &str = "/.openmail/app/send_time.json";
+pub const PINNED_FILE_PATH: &str = "/.openmail/app/pinned.json";
+pub const AUTH_FAILURES_FILE_PATH: &str = "/.openmail/app/auth_failures.json";
@@ -0,0 +1,135 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::deep_link;
+use crate::utils;
+
+// "Send with Openmail" in the context menu of the files, in Explorer on
+// Windows and in Nautilus and Dolphin on Linux. It starts the app with
+// `--attach <path>...`, which opens the composer with the files attached,
+// see deep_link.rs. Finder can't have such an entry without an extension,
+// the same can be done with openmail://attach?path=... on macOS (the user
+// confirms the files of a link first).
+const MENU_TITLE: &str = "Send with Openmail";
+const WINDOWS_KEY: &str = r"HKCU\Software\Classes\*\shell\Openmail";
+const NAUTILUS_SCRIPT_PATH: &str = "/.local/share/nautilus/scripts/Send with Openmail";
+const DOLPHIN_SERVICE_MENU_PATH: &str = "/.local/share/kio/servicemenus/openmail-send.desktop";
+const SUPPORTED: bool = cfg!(any(target_os = "windows", target_os = "linux"));
+
+fn executable() -> Result<String, String> {
+    env::current_exe()
+        .map(|path| path.display().to_string())
+        .map_err(|err| format!("Failed to find the executable: {}", err))
+}
+
+fn reg(args: &[&str]) -> Result<bool, String> {
+    Command::new("reg")
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map(|status| status.success())
+        .map_err(|err| format!("Failed to run reg: {}", err))
+}
+
+fn write_executable(path: &str, content: &str) -> Result<(), String> {
+    let path = utils::build_home_path(path);
+    if let Some(parent) = Path::new(&path).parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| format!("Failed to create {}: {}", parent.display(), err))?;
+    }
+    fs::write(&path, content).map_err(|err| format!("Failed to write {}: {}", path, err))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+            .map_err(|err| format!("Failed to make {} executable: {}", path, err))?;
+    }
+    Ok(())
+}
+
+fn register() -> Result<(), String> {
+    let executable = executable()?;
+    if cfg!(target_os = "windows") {
+        let command = format!("\"{}\" {} \"%1\"", executable, deep_link::ATTACH_ARG);
+        let command_key = format!(r"{}\command", WINDOWS_KEY);
+        let registered = reg(&["add", WINDOWS_KEY, "/ve", "/d", MENU_TITLE, "/f"])?
+            && reg(&["add", WINDOWS_KEY, "/v", "Icon", "/d", &executable, "/f"])?
+            && reg(&["add", &command_key, "/ve", "/d", &command, "/f"])?;
+        if !registered {
+            return Err("Failed to add the context menu to the registry".to_string());
+        }
+        return Ok(());
+    }
+
+    // Selected files are the arguments of Nautilus scripts.
+    let quoted = format!("'{}'", executable.replace('\'', r"'\''"));
+    write_executable(
+        NAUTILUS_SCRIPT_PATH,
+        &format!(
+            "#!/bin/sh\nexec {} {} \"$@\"\n",
+            quoted,
+            deep_link::ATTACH_ARG
+        ),
+    )?;
+    write_executable(
+        DOLPHIN_SERVICE_MENU_PATH,
+        &format!(
+            "[Desktop Entry]\n\
+             Type=Service\n\
+             MimeType=all/allfiles;\n\
+             X-KDE-ServiceTypes=KonqPopupMenu/Plugin\n\
+             Actions=sendWithOpenmail\n\n\
+             [Desktop Action sendWithOpenmail]\n\
+             Name={}\n\
+             Icon=mail-send\n\
+             Exec=\"{}\" {} %F\n",
+            MENU_TITLE,
+            executable.replace('"', "\\\""),
+            deep_link::ATTACH_ARG
+        ),
+    )
+}
+
+fn unregister() -> Result<(), String> {
+    if cfg!(target_os = "windows") {
+        if is_registered() && !reg(&["delete", WINDOWS_KEY, "/f"])? {
+            return Err("Failed to remove the context menu from the registry".to_string());
+        }
+        return Ok(());
+    }
+    for path in [NAUTILUS_SCRIPT_PATH, DOLPHIN_SERVICE_MENU_PATH] {
+        let path = utils::build_home_path(path);
+        if Path::new(&path).exists() {
+            fs::remove_file(&path).map_err(|err| format!("Failed to remove {}: {}", path, err))?;
+        }
+    }
+    Ok(())
+}
+
+fn is_registered() -> bool {
+    if cfg!(target_os = "windows") {
+        reg(&["query", WINDOWS_KEY]).unwrap_or(false)
+    } else {
+        Path::new(&utils::build_home_path(NAUTILUS_SCRIPT_PATH)).exists()
+    }
+}
+
+#[tauri::command]
+pub fn get_context_menu() -> bool {
+    SUPPORTED && is_registered()
+}
+
+#[tauri::command]
+pub fn set_context_menu(enabled: bool) -> Result<(), String> {
+    if !SUPPORTED {
+        return Err("Context menu is only supported on Windows and Linux".to_string());
+    }
+    if enabled {
+        register()
+    } else {
+        unregister()
+    }
+}
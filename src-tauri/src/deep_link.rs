@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_deep_link::DeepLinkExt;
+use url::Url;
+
+use crate::ipc::{self, ComposeArgs, ComposeAttachments};
+
+// Links of the app:
+//     openmail://message?account=...&folder=...&uid=...
+//         opens the email, used by the search stubs of the OS (see
+//         search_stubs.rs).
+//     openmail://attach?path=...[&path=...]
+//         opens the composer and asks the user to attach the files,
+//         nothing is attached or sent without the user.
+// The context menu of the file managers (see context_menu.rs) starts the
+// app with `--attach <path>...` instead, since paths don't have to be
+// encoded that way. The OS starts a new instance for a link when the app is
+// already running, single instance plugin forwards it to the running one.
+// The link that started the app is kept until the UI is ready to take it.
+pub const SCHEME: &str = "openmail";
+pub const ATTACH_ARG: &str = "--attach";
+pub const OPEN_MESSAGE_EVENT: &str = "open-message";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageLink {
+    pub account: String,
+    pub folder: String,
+    pub uid: String,
+}
+
+impl MessageLink {
+    pub fn to_url(&self) -> String {
+        Url::parse_with_params(
+            &format!("{}://message", SCHEME),
+            [
+                ("account", &self.account),
+                ("folder", &self.folder),
+                ("uid", &self.uid),
+            ],
+        )
+        .map(String::from)
+        .unwrap_or_default()
+    }
+
+    pub fn parse(url: &Url) -> Option<Self> {
+        if url.scheme() != SCHEME || url.host_str() != Some("message") {
+            return None;
+        }
+        let param = |key: &str| {
+            url.query_pairs()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.to_string())
+        };
+        Some(MessageLink {
+            account: param("account")?,
+            folder: param("folder")?,
+            uid: param("uid")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OpenedLink {
+    Message(MessageLink),
+    Compose(ComposeArgs),
+}
+
+#[derive(Default)]
+pub struct PendingLink(Mutex<Option<OpenedLink>>);
+
+// Files of the app (like the cache and the keys) and hidden files (like the
+// ones in ~/.ssh) are never attached this way. Paths are resolved first, so
+// links and ".." can't lead to them.
+fn attachable_path(app: &AppHandle, path: &str) -> Option<String> {
+    if !Path::new(path).is_absolute() {
+        return None;
+    }
+    let path = Path::new(path).canonicalize().ok()?;
+    if !path.is_file()
+        || path
+            .components()
+            .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+    {
+        return None;
+    }
+    let paths = app.path();
+    let app_dirs = [
+        paths.app_data_dir(),
+        paths.app_local_data_dir(),
+        paths.app_config_dir(),
+        paths.app_cache_dir(),
+    ];
+    if app_dirs
+        .into_iter()
+        .flatten()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| path.starts_with(dir))
+    {
+        return None;
+    }
+    Some(path.to_string_lossy().to_string())
+}
+
+// Only the files that exist and can be attached.
+fn compose_with(app: &AppHandle, paths: Vec<String>, confirm: bool) -> Option<OpenedLink> {
+    let attachments: Vec<String> = paths
+        .iter()
+        .filter_map(|path| attachable_path(app, path))
+        .collect();
+    if attachments.is_empty() {
+        return None;
+    }
+    Some(OpenedLink::Compose(ComposeArgs {
+        attachments,
+        confirm,
+        ..Default::default()
+    }))
+}
+
+fn parse_link(app: &AppHandle, url: &Url) -> Option<OpenedLink> {
+    if let Some(link) = MessageLink::parse(url) {
+        return Some(OpenedLink::Message(link));
+    }
+    if url.scheme() != SCHEME || url.host_str() != Some("attach") {
+        return None;
+    }
+    // Any website can open a link, unlike the arguments.
+    compose_with(
+        app,
+        url.query_pairs()
+            .filter(|(name, _)| name == "path")
+            .map(|(_, value)| value.to_string())
+            .collect(),
+        true,
+    )
+}
+
+fn parse_args(app: &AppHandle, args: &[String]) -> Option<OpenedLink> {
+    let position = args.iter().position(|arg| arg == ATTACH_ARG)?;
+    compose_with(app, args[position + 1..].to_vec(), false)
+}
+
+// Also used by the pinned emails of the tray menu.
+pub fn open_message(app: &AppHandle, link: MessageLink) {
+    if let Some(window) = app.get_webview_window("main") {
+        window.unminimize().ok();
+        window.set_focus().ok();
+    }
+    app.emit(OPEN_MESSAGE_EVENT, link).ok();
+}
+
+fn open(app: &AppHandle, link: OpenedLink) {
+    match link {
+        OpenedLink::Message(link) => open_message(app, link),
+        OpenedLink::Compose(args) => {
+            if let Err(err) = ipc::compose(app, args) {
+                println!("{}", err);
+            }
+        }
+    }
+}
+
+// Arguments of a new instance, forwarded by the single instance plugin.
+pub fn open_args(app: &AppHandle, args: &[String]) {
+    if let Some(link) = parse_args(app, args) {
+        open(app, link);
+    }
+}
+
+pub fn listen(app: &AppHandle) {
+    let pending = PendingLink::default();
+    let started_with = match app.deep_link().get_current() {
+        Ok(Some(urls)) => urls
+            .iter()
+            .filter_map(|url| parse_link(app, url))
+            .next_back(),
+        _ => parse_args(app, &std::env::args().collect::<Vec<String>>()),
+    };
+    *pending.0.lock().unwrap() = started_with;
+    app.manage(pending);
+
+    let handle = app.clone();
+    app.deep_link().on_open_url(move |event| {
+        if let Some(link) = event
+            .urls()
+            .iter()
+            .filter_map(|url| parse_link(&handle, url))
+            .next_back()
+        {
+            open(&handle, link);
+        }
+    });
+}
+
+// The link that started the app, once.
+#[tauri::command]
+pub fn take_opened_link(
+    pending: State<'_, PendingLink>,
+    attachments: State<'_, ComposeAttachments>,
+) -> Option<OpenedLink> {
+    let link = pending.0.lock().unwrap().take();
+    if let Some(OpenedLink::Compose(args)) = link.as_ref() {
+        attachments.expect(args);
+    }
+    link
+}
@@ -0,0 +1,300 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use tauri::ipc::Response;
+use tauri::{AppHandle, Emitter, Listener, State};
+
+use crate::api;
+use crate::cache::{Cache, CachedEmail};
+use crate::consts;
+use crate::settings_sync;
+use crate::utils;
+use crate::worker::{self, SavedAttachment};
+
+// Attachments are downloaded from the source of the email and saved by the
+// parser worker under ATTACHMENTS_DIR_PATH. Users can always download them
+// by hand, the rules only decide which ones are downloaded as soon as they
+// are synced: the first rule that matches the sender (an address, or a
+// domain like "@company.com") wins, addresses before domains. Senders
+// without a rule fall back to `known_senders` if the user has sent them an
+// email before and to `unknown_senders` otherwise.
+pub const ATTACHMENTS_DOWNLOADED_EVENT: &str = "attachments-downloaded";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadAction {
+    Always,
+    #[default]
+    Never,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadRule {
+    pub sender: String,
+    pub action: DownloadAction,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadRules {
+    pub rules: Vec<DownloadRule>,
+    pub known_senders: DownloadAction,
+    pub unknown_senders: DownloadAction,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadedAttachments {
+    pub account: String,
+    pub folder: String,
+    pub uid: String,
+    pub attachments: Vec<SavedAttachment>,
+}
+
+struct DownloadJob {
+    // Account the email is fetched with and the mailbox it is cached as,
+    // see `SyncScheduler::sync_folder`.
+    account: String,
+    mailbox: String,
+    folder: String,
+    uid: String,
+}
+
+pub struct Downloads {
+    cache: Arc<Cache>,
+    rules: Mutex<DownloadRules>,
+    queue: Mutex<VecDeque<DownloadJob>>,
+    wakeup: Condvar,
+}
+
+fn validate(rules: &DownloadRules) -> Result<(), String> {
+    for rule in rules.rules.iter() {
+        let is_valid = match rule.sender.strip_prefix('@') {
+            Some(domain) => !domain.is_empty() && !domain.contains('@'),
+            None => utils::extract_email_address(&rule.sender) == rule.sender,
+        };
+        if !is_valid || rule.sender.trim() != rule.sender {
+            return Err(format!("Invalid sender of rule: {}", rule.sender));
+        }
+    }
+    Ok(())
+}
+
+fn path_component(name: &str) -> String {
+    name.replace(['/', '\\', ':'], "_")
+}
+
+fn attachments_dir(mailbox: &str, folder: &str, uid: &str) -> String {
+    utils::build_home_path(&format!(
+        "{}/{}/{}/{}",
+        consts::ATTACHMENTS_DIR_PATH,
+        path_component(mailbox),
+        path_component(folder),
+        path_component(uid)
+    ))
+}
+
+impl Downloads {
+    pub fn load(cache: Arc<Cache>) -> Self {
+        Downloads {
+            cache,
+            rules: Mutex::new(
+                utils::read_json_file(consts::DOWNLOAD_RULES_FILE_PATH).unwrap_or_default(),
+            ),
+            queue: Mutex::new(VecDeque::new()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    pub fn get_rules(&self) -> DownloadRules {
+        self.rules.lock().unwrap().clone()
+    }
+
+    pub fn set_rules(&self, rules: DownloadRules) -> Result<(), String> {
+        validate(&rules)?;
+        utils::write_json_file(consts::DOWNLOAD_RULES_FILE_PATH, &rules)?;
+        *self.rules.lock().unwrap() = rules;
+        Ok(())
+    }
+
+    // Rules can be replaced on disk by the settings sync.
+    pub fn reload_rules(&self) {
+        *self.rules.lock().unwrap() =
+            utils::read_json_file(consts::DOWNLOAD_RULES_FILE_PATH).unwrap_or_default();
+    }
+
+    fn is_known_sender(&self, mailbox: &str, sender: &str) -> bool {
+        self.cache.get_emails(mailbox, "Sent").iter().any(|email| {
+            [
+                Some(&email.receivers),
+                email.cc.as_ref(),
+                email.bcc.as_ref(),
+            ]
+            .into_iter()
+            .flatten()
+            .flat_map(|addresses| addresses.split(','))
+            .any(|address| utils::extract_email_address(address).eq_ignore_ascii_case(sender))
+        })
+    }
+
+    pub fn action_for(&self, mailbox: &str, sender: &str) -> DownloadAction {
+        let sender = utils::extract_email_address(sender).to_lowercase();
+        let rules = self.get_rules();
+        let matching = |rule: &&DownloadRule| {
+            let pattern = rule.sender.to_lowercase();
+            if pattern.starts_with('@') {
+                sender.ends_with(&pattern)
+            } else {
+                sender == pattern
+            }
+        };
+        let rule = rules
+            .rules
+            .iter()
+            .filter(|rule| !rule.sender.starts_with('@'))
+            .find(matching)
+            .or_else(|| rules.rules.iter().find(matching));
+        match rule {
+            Some(rule) => rule.action,
+            None if sender.is_empty() => rules.unknown_senders,
+            None if self.is_known_sender(mailbox, &sender) => rules.known_senders,
+            None => rules.unknown_senders,
+        }
+    }
+
+    // Called by the sync scheduler with the newly synced emails.
+    pub fn auto_download(
+        &self,
+        account: &str,
+        mailbox: &str,
+        folder: &str,
+        emails: &[CachedEmail],
+    ) {
+        let jobs: Vec<DownloadJob> = emails
+            .iter()
+            .filter(|email| !email.attachments.is_empty())
+            .filter(|email| self.action_for(mailbox, &email.sender) == DownloadAction::Always)
+            .map(|email| DownloadJob {
+                account: account.to_string(),
+                mailbox: mailbox.to_string(),
+                folder: folder.to_string(),
+                uid: email.uid.clone(),
+            })
+            .collect();
+        if jobs.is_empty() {
+            return;
+        }
+        self.queue.lock().unwrap().extend(jobs);
+        self.wakeup.notify_all();
+    }
+
+    fn next_job(&self) -> DownloadJob {
+        let queue = self.queue.lock().unwrap();
+        let mut queue = self
+            .wakeup
+            .wait_while(queue, |queue| queue.is_empty())
+            .unwrap();
+        queue.pop_front().unwrap()
+    }
+}
+
+pub fn download(
+    account: &str,
+    mailbox: &str,
+    folder: &str,
+    uid: &str,
+) -> Result<DownloadedAttachments, String> {
+    let response = api::get(
+        &format!("/get-email-source/{}/{}", account, uid),
+        &[("folder", folder)],
+    )?;
+    let source = api::get_account_data(response, account)?;
+    let source = BASE64
+        .decode(
+            source
+                .as_str()
+                .ok_or(format!("Email {} not found in {}", uid, folder))?,
+        )
+        .map_err(|err| format!("Failed to decode source of {}: {}", uid, err))?;
+
+    let path = utils::build_temp_path(&format!("{}.eml", utils::generate_random_id()))?;
+    fs::write(&path, source).map_err(|err| format!("Failed to write message: {}", err))?;
+    let attachments = worker::save_attachments(
+        &path.to_string_lossy(),
+        &attachments_dir(mailbox, folder, uid),
+    );
+    fs::remove_file(&path).ok();
+
+    Ok(DownloadedAttachments {
+        account: mailbox.to_string(),
+        folder: folder.to_string(),
+        uid: uid.to_string(),
+        attachments: attachments?,
+    })
+}
+
+pub fn listen_settings_sync(app: &AppHandle, downloads: Arc<Downloads>) {
+    app.listen(settings_sync::SETTINGS_SYNCED_EVENT, move |_| {
+        downloads.reload_rules();
+    });
+}
+
+pub fn start_worker(app: AppHandle, downloads: Arc<Downloads>) {
+    thread::spawn(move || loop {
+        let job = downloads.next_job();
+        match download(&job.account, &job.mailbox, &job.folder, &job.uid) {
+            Ok(downloaded) => {
+                app.emit(ATTACHMENTS_DOWNLOADED_EVENT, downloaded).ok();
+            }
+            Err(err) => println!(
+                "Failed to download attachments of {} in {}: {}",
+                job.uid, job.folder, err
+            ),
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_download_rules(downloads: State<'_, Arc<Downloads>>) -> DownloadRules {
+    downloads.get_rules()
+}
+
+#[tauri::command]
+pub fn set_download_rules(
+    downloads: State<'_, Arc<Downloads>>,
+    rules: DownloadRules,
+) -> Result<(), String> {
+    downloads.set_rules(rules)
+}
+
+#[tauri::command]
+pub async fn download_attachments(
+    account: String,
+    folder: String,
+    uid: String,
+) -> Result<DownloadedAttachments, String> {
+    download(&account, &account, &folder, &uid)
+}
+
+// Saved attachments for the reader, which lists them instead of the ones of
+// the server when they differ (like winmail.dat, see mime.rs). Only files
+// under ATTACHMENTS_DIR_PATH are read.
+#[tauri::command]
+pub async fn read_downloaded_attachment(path: String) -> Result<Response, String> {
+    let dir = Path::new(&utils::build_home_path(consts::ATTACHMENTS_DIR_PATH))
+        .canonicalize()
+        .map_err(|err| format!("Failed to resolve attachments directory: {}", err))?;
+    let file = Path::new(&path)
+        .canonicalize()
+        .map_err(|err| format!("Failed to resolve {}: {}", path, err))?;
+    if !file.starts_with(&dir) {
+        return Err(format!("Not a downloaded attachment: {}", path));
+    }
+    fs::read(&file)
+        .map(Response::new)
+        .map_err(|err| format!("Failed to read {}: {}", path, err))
+}
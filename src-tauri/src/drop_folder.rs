@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Listener, State};
+
+use crate::consts;
+use crate::outbox::{self, Outbox, OutboxStatus};
+use crate::utils;
+
+// Optional directory that other programs can send emails through by
+// dropping .eml files into it. Each file is first moved to "queued/", so it
+// is never queued twice (even if the app stops right after), then validated
+// and queued through the outbox like the messages of openmail-cli and named
+// with the id of the outbox item until it is sent, and finally moved to
+// "sent/" or "failed/". Failed files get a "<name>.error.txt" next to them
+// with the reason.
+const POLL_INTERVAL_SEC: u64 = 5;
+// Files that were modified more recently may still be being written.
+const MIN_FILE_AGE_SEC: u64 = 2;
+const QUEUED_DIR: &str = "queued";
+const SENT_DIR: &str = "sent";
+const FAILED_DIR: &str = "failed";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DropFolderSettings {
+    pub path: Option<String>,
+}
+
+pub struct DropFolder {
+    outbox: Arc<Outbox>,
+    settings: Mutex<DropFolderSettings>,
+    wakeup: Condvar,
+}
+
+fn is_eml(path: &Path) -> bool {
+    path.is_file()
+        && path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("eml"))
+}
+
+fn is_settled(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age >= Duration::from_secs(MIN_FILE_AGE_SEC))
+}
+
+// Moves the file into the subdirectory, a file with the same name there is
+// kept by prefixing the new one with a random id.
+fn move_to(dir: &Path, subdir: &str, file: &Path, name: &str) -> Result<PathBuf, String> {
+    let target_dir = dir.join(subdir);
+    fs::create_dir_all(&target_dir)
+        .map_err(|err| format!("Failed to create {}: {}", target_dir.display(), err))?;
+    let mut target = target_dir.join(name);
+    if target.exists() {
+        target = target_dir.join(format!("{} {}", utils::generate_random_id(), name));
+    }
+    fs::rename(file, &target)
+        .map_err(|err| format!("Failed to move {}: {}", file.display(), err))?;
+    Ok(target)
+}
+
+fn move_to_failed(dir: &Path, file: &Path, name: &str, err: &str) -> Result<(), String> {
+    let target = move_to(dir, FAILED_DIR, file, name)?;
+    let report = format!("{}.error.txt", target.display());
+    fs::write(&report, err).map_err(|err| format!("Failed to write {}: {}", report, err))
+}
+
+impl DropFolder {
+    pub fn load(outbox: Arc<Outbox>) -> Self {
+        DropFolder {
+            outbox,
+            settings: Mutex::new(
+                utils::read_json_file(consts::DROP_FOLDER_SETTINGS_FILE_PATH).unwrap_or_default(),
+            ),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    pub fn get_settings(&self) -> DropFolderSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set_path(&self, path: Option<String>) -> Result<(), String> {
+        if let Some(path) = path.as_ref() {
+            if !Path::new(path).is_absolute() || !Path::new(path).is_dir() {
+                return Err(format!("Not a directory: {}", path));
+            }
+        }
+        let settings = DropFolderSettings { path };
+        utils::write_json_file(consts::DROP_FOLDER_SETTINGS_FILE_PATH, &settings)?;
+        *self.settings.lock().unwrap() = settings;
+        self.wakeup.notify_all();
+        Ok(())
+    }
+
+    fn queue_new_files(&self, dir: &Path) -> Result<(), String> {
+        let entries = fs::read_dir(dir)
+            .map_err(|err| format!("Failed to read {}: {}", dir.display(), err))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if !is_eml(&path) || !is_settled(&path) {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Until it is renamed, the id is not one of the outbox items, so
+            // a file that was claimed but not queued ends up in "failed/".
+            let claimed = move_to(
+                dir,
+                QUEUED_DIR,
+                &path,
+                &format!("{} {}", utils::generate_random_id(), name),
+            )?;
+            match self
+                .outbox
+                .queue_message_file(&claimed.to_string_lossy(), None)
+            {
+                Ok(id) => {
+                    let queued = dir.join(QUEUED_DIR).join(format!("{} {}", id, name));
+                    fs::rename(&claimed, &queued)
+                        .map_err(|err| format!("Failed to move {}: {}", claimed.display(), err))?;
+                }
+                Err(err) => move_to_failed(dir, &claimed, &name, &err)?,
+            }
+        }
+        Ok(())
+    }
+
+    // Moves the queued files whose outbox items are done.
+    fn move_finished_files(&self, dir: &Path) -> Result<(), String> {
+        let queued_dir = dir.join(QUEUED_DIR);
+        let Ok(entries) = fs::read_dir(&queued_dir) else {
+            return Ok(());
+        };
+        let items = self.outbox.get_items();
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_string();
+            let Some((id, name)) = file_name.split_once(' ') else {
+                continue;
+            };
+            let item = items.iter().find(|item| item.id == id);
+            match item.map(|item| (item.status, item.error.as_deref())) {
+                Some((OutboxStatus::Sent, _)) => {
+                    move_to(dir, SENT_DIR, &entry.path(), name)?;
+                }
+                Some((OutboxStatus::Failed | OutboxStatus::Unknown, error)) => {
+                    move_to_failed(dir, &entry.path(), name, error.unwrap_or("Failed to send"))?
+                }
+                Some(_) => {}
+                None => move_to_failed(dir, &entry.path(), name, "Removed from the outbox")?,
+            }
+        }
+        Ok(())
+    }
+
+    fn poll(&self) -> Result<(), String> {
+        let Some(path) = self.settings.lock().unwrap().path.clone() else {
+            return Ok(());
+        };
+        let dir = Path::new(&path);
+        self.queue_new_files(dir)?;
+        self.move_finished_files(dir)
+    }
+
+    fn wait(&self, interval: Duration) {
+        let settings = self.settings.lock().unwrap();
+        let _ = self.wakeup.wait_timeout(settings, interval).unwrap();
+    }
+}
+
+pub fn listen_outbox(app: &AppHandle, drop_folder: Arc<DropFolder>) {
+    app.listen(outbox::OUTBOX_ITEM_UPDATED_EVENT, move |_| {
+        drop_folder.wakeup.notify_all();
+    });
+}
+
+pub fn start_worker(drop_folder: Arc<DropFolder>) {
+    thread::spawn(move || loop {
+        if let Err(err) = drop_folder.poll() {
+            println!("Failed to check the drop folder: {}", err);
+        }
+        drop_folder.wait(Duration::from_secs(POLL_INTERVAL_SEC));
+    });
+}
+
+#[tauri::command]
+pub fn get_drop_folder(drop_folder: State<'_, Arc<DropFolder>>) -> DropFolderSettings {
+    drop_folder.get_settings()
+}
+
+#[tauri::command]
+pub fn set_drop_folder(
+    drop_folder: State<'_, Arc<DropFolder>>,
+    path: Option<String>,
+) -> Result<(), String> {
+    drop_folder.set_path(path)
+}
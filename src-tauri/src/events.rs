@@ -0,0 +1,165 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
+
+// High frequency events (like an update per email during a big sync) are
+// not emitted one by one, they are collected here and sent to the UI as
+// batches. Events that are published with the same key replace each other
+// until they are sent. A new batch is only sent when the UI acknowledged
+// the previous one (or didn't in time), so a busy webview is not flooded.
+// Events can also be held back until they are released, for the batched
+// delivery of the new emails, see batch_delivery.rs
+pub const EVENT_BATCH_EVENT: &str = "event-batch";
+const FLUSH_INTERVAL_MS: u64 = 250;
+const ACK_TIMEOUT_MS: u64 = 5000;
+// Once exceeded, new events are only counted, the UI is expected to reload
+// what it shows when a batch has dropped events.
+const MAX_PENDING_EVENTS: usize = 5000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchedEvent {
+    pub event: String,
+    pub payload: Value,
+    // How many events were merged into this one.
+    pub coalesced: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EventBatch {
+    pub seq: u64,
+    pub events: Vec<BatchedEvent>,
+    pub dropped: usize,
+}
+
+#[derive(Default)]
+struct PendingEvents {
+    events: Vec<BatchedEvent>,
+    keys: HashMap<(String, String), usize>,
+    dropped: usize,
+    last_seq: u64,
+    in_flight: Option<(u64, Instant)>,
+    held_events: HashSet<String>,
+    held: Vec<BatchedEvent>,
+}
+
+#[derive(Default)]
+pub struct EventBus {
+    pending: Mutex<PendingEvents>,
+}
+
+impl EventBus {
+    pub fn publish<T: Serialize>(&self, event: &str, key: Option<&str>, payload: T) {
+        let payload = serde_json::to_value(payload).unwrap_or(Value::Null);
+        let mut pending = self.pending.lock().unwrap();
+        if pending.held_events.contains(event) {
+            if pending.held.len() >= MAX_PENDING_EVENTS {
+                pending.dropped += 1;
+                return;
+            }
+            pending.held.push(BatchedEvent {
+                event: event.to_string(),
+                payload,
+                coalesced: 1,
+            });
+            return;
+        }
+        let key = key.map(|key| (event.to_string(), key.to_string()));
+        if let Some(index) = key.as_ref().and_then(|key| pending.keys.get(key)).copied() {
+            let existing = &mut pending.events[index];
+            existing.payload = payload;
+            existing.coalesced += 1;
+            return;
+        }
+
+        if pending.events.len() >= MAX_PENDING_EVENTS {
+            pending.dropped += 1;
+            return;
+        }
+        if let Some(key) = key {
+            let index = pending.events.len();
+            pending.keys.insert(key, index);
+        }
+        pending.events.push(BatchedEvent {
+            event: event.to_string(),
+            payload,
+            coalesced: 1,
+        });
+    }
+
+    // Holds the given events from now on, the ones that are not given
+    // anymore are released.
+    pub fn hold(&self, events: &[&str]) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.held_events = events.iter().map(|event| event.to_string()).collect();
+        let held = std::mem::take(&mut pending.held);
+        let (held, released): (Vec<BatchedEvent>, Vec<BatchedEvent>) = held
+            .into_iter()
+            .partition(|held| pending.held_events.contains(&held.event));
+        pending.held = held;
+        pending.events.extend(released);
+    }
+
+    // Sends the held events that are still `wanted` with the next batch,
+    // returns how many of them were released.
+    pub fn release(&self, wanted: impl Fn(&BatchedEvent) -> bool) -> usize {
+        let mut pending = self.pending.lock().unwrap();
+        let released: Vec<BatchedEvent> = std::mem::take(&mut pending.held)
+            .into_iter()
+            .filter(wanted)
+            .collect();
+        let count = released.len();
+        pending.events.extend(released);
+        count
+    }
+
+    pub fn ack(&self, seq: u64) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending
+            .in_flight
+            .is_some_and(|(in_flight, _)| in_flight <= seq)
+        {
+            pending.in_flight = None;
+        }
+    }
+
+    fn take_batch(&self) -> Option<EventBatch> {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.events.is_empty() && pending.dropped == 0 {
+            return None;
+        }
+        if pending
+            .in_flight
+            .is_some_and(|(_, sent_at)| sent_at.elapsed() < Duration::from_millis(ACK_TIMEOUT_MS))
+        {
+            return None;
+        }
+
+        pending.last_seq += 1;
+        let seq = pending.last_seq;
+        pending.in_flight = Some((seq, Instant::now()));
+        pending.keys.clear();
+        Some(EventBatch {
+            seq,
+            events: std::mem::take(&mut pending.events),
+            dropped: std::mem::take(&mut pending.dropped),
+        })
+    }
+}
+
+pub fn start_worker(app: AppHandle, events: Arc<EventBus>) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_millis(FLUSH_INTERVAL_MS));
+        if let Some(batch) = events.take_batch() {
+            app.emit(EVENT_BATCH_EVENT, batch).ok();
+        }
+    });
+}
+
+#[tauri::command]
+pub fn ack_event_batch(events: State<'_, Arc<EventBus>>, seq: u64) {
+    events.ack(seq);
+}
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+// Failures that can be turned on in the debug builds, so the retries, the
+// offline queue and the rest of the recovery paths can be tried without
+// breaking the network or the server by hand. Backend requests (including
+// the websockets) are dropped before they are sent, IDLE reports are
+// delivered late and keychain reads fail. Nothing is persisted, a restart
+// turns everything off. Release builds ignore all of it.
+const MAX_IDLE_DELAY_MS: u64 = 10 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FaultInjection {
+    pub drop_request_percent: u8,
+    pub idle_delay_ms: u64,
+    pub fail_keychain_reads: bool,
+}
+
+static FAULTS: Mutex<FaultInjection> = Mutex::new(FaultInjection {
+    drop_request_percent: 0,
+    idle_delay_ms: 0,
+    fail_keychain_reads: false,
+});
+
+fn get() -> FaultInjection {
+    if cfg!(debug_assertions) {
+        *FAULTS.lock().unwrap()
+    } else {
+        FaultInjection::default()
+    }
+}
+
+// 0..100, a new random state is seeded for each call.
+fn roll() -> u8 {
+    (RandomState::new().build_hasher().finish() % 100) as u8
+}
+
+pub fn check_request(route: &str) -> Result<(), String> {
+    let percent = get().drop_request_percent;
+    if percent > 0 && roll() < percent {
+        return Err(format!(
+            "Failed to reach server: {} dropped by fault injection",
+            route
+        ));
+    }
+    Ok(())
+}
+
+pub fn delay_idle_event() {
+    let delay = get().idle_delay_ms;
+    if delay > 0 {
+        thread::sleep(Duration::from_millis(delay));
+    }
+}
+
+pub fn check_keychain_read() -> Result<(), String> {
+    if get().fail_keychain_reads {
+        return Err("Failed to access keychain: read failed by fault injection".to_string());
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_fault_injection() -> FaultInjection {
+    get()
+}
+
+#[tauri::command]
+pub fn set_fault_injection(faults: FaultInjection) -> Result<(), String> {
+    if !cfg!(debug_assertions) {
+        return Err("Fault injection is only available in debug builds".to_string());
+    }
+    if faults.drop_request_percent > 100 {
+        return Err("Drop percent must be between 0 and 100".to_string());
+    }
+    if faults.idle_delay_ms > MAX_IDLE_DELAY_MS {
+        return Err(format!(
+            "IDLE delay can be at most {} ms",
+            MAX_IDLE_DELAY_MS
+        ));
+    }
+    *FAULTS.lock().unwrap() = faults;
+    Ok(())
+}
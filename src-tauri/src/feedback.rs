@@ -0,0 +1,188 @@
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::sync::Arc;
+use tauri::{AppHandle, State};
+
+use crate::api;
+use crate::consts;
+use crate::offline::OfflineChanges;
+use crate::outbox::{Outbox, OutboxStatus};
+use crate::utils;
+
+// Feedback is composed here but sent by the user, either as an email from
+// the composer (with the diagnostics bundle as attachment) or as a GitHub
+// issue. Diagnostics never contain email addresses or the home directory,
+// and can be previewed with `get_diagnostics` before they are attached.
+const FEEDBACK_EMAIL: &str = "berkaykayaforbusiness@outlook.com";
+const NEW_ISSUE_URL: &str = "https://github.com/bberkay/open-mail/issues/new";
+const FEEDBACK_SUBJECT: &str = "Openmail feedback";
+const MAX_LOG_LINES: usize = 200;
+
+#[derive(Debug, Serialize)]
+pub struct Diagnostics {
+    pub app_version: String,
+    pub os: String,
+    pub os_version: String,
+    pub arch: String,
+    pub created_at: i64,
+    pub server_reachable: bool,
+    pub outbox: HashMap<String, usize>,
+    pub offline_changes: usize,
+    pub offline_conflicts: usize,
+    pub server_log: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeedbackDraft {
+    pub receivers: String,
+    pub subject: String,
+    pub body: String,
+    pub attachment_path: Option<String>,
+    pub issue_url: String,
+}
+
+fn is_address_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "._%+-@".contains(c)
+}
+
+// Replaces email addresses (also url encoded ones, like in the request
+// logs) and the home directory, which contains the user name.
+fn sanitize(line: &str) -> String {
+    let mut line = line.to_string();
+    if let Ok(home) = env::var("HOME") {
+        if !home.is_empty() {
+            line = line.replace(&home, "~");
+        }
+    }
+
+    let mut sanitized = String::with_capacity(line.len());
+    let mut word = String::new();
+    for c in line.chars().chain(std::iter::once(' ')) {
+        if is_address_char(c) {
+            word.push(c);
+            continue;
+        }
+
+        let unquoted = word.replace("%40", "@");
+        let is_address = unquoted
+            .split_once('@')
+            .is_some_and(|(name, domain)| !name.is_empty() && !domain.is_empty());
+        sanitized.push_str(if is_address { "<email>" } else { &word });
+        word.clear();
+        sanitized.push(c);
+    }
+    sanitized.pop();
+    sanitized
+}
+
+fn read_server_log() -> Vec<String> {
+    let Ok(log) = fs::read_to_string(utils::build_home_path(consts::UVICORN_LOG_FILE_PATH)) else {
+        return Vec::new();
+    };
+    let lines: Vec<&str> = log.lines().collect();
+    lines[lines.len().saturating_sub(MAX_LOG_LINES)..]
+        .iter()
+        .map(|line| sanitize(line))
+        .collect()
+}
+
+pub fn collect(app: &AppHandle, outbox: &Outbox, offline: &OfflineChanges) -> Diagnostics {
+    let mut outbox_counts = HashMap::new();
+    for item in outbox.get_items() {
+        let status = match item.status {
+            OutboxStatus::Queued => "queued",
+            OutboxStatus::Sending => "sending",
+            OutboxStatus::Sent => "sent",
+            OutboxStatus::Failed => "failed",
+            OutboxStatus::Unknown => "unknown",
+        };
+        *outbox_counts.entry(status.to_string()).or_insert(0) += 1;
+    }
+    let offline_changes = offline.get_changes();
+
+    Diagnostics {
+        app_version: app.package_info().version.to_string(),
+        os: env::consts::OS.to_string(),
+        os_version: tauri_plugin_os::version().to_string(),
+        arch: env::consts::ARCH.to_string(),
+        created_at: Utc::now().timestamp(),
+        server_reachable: api::get("/get-public-key", &[]).is_ok_and(|response| response.success),
+        outbox: outbox_counts,
+        offline_changes: offline_changes.len(),
+        offline_conflicts: offline_changes
+            .iter()
+            .filter(|change| change.conflict.is_some())
+            .count(),
+        server_log: read_server_log(),
+    }
+}
+
+fn write_bundle(diagnostics: &Diagnostics) -> Result<String, String> {
+    let file = format!(
+        "{}/diagnostics-{}.json",
+        consts::DIAGNOSTICS_DIR_PATH,
+        diagnostics.created_at
+    );
+    utils::write_json_file(&file, diagnostics)?;
+    Ok(utils::build_home_path(&file))
+}
+
+pub fn compose(
+    app: &AppHandle,
+    outbox: &Outbox,
+    offline: &OfflineChanges,
+    text: &str,
+    include_diagnostics: bool,
+) -> Result<FeedbackDraft, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("Feedback can not be empty".to_string());
+    }
+
+    let mut body = text.to_string();
+    let mut attachment_path = None;
+    if include_diagnostics {
+        let diagnostics = collect(app, outbox, offline);
+        // Issues can't have attachments through the url, only the summary
+        // is added to the body, the bundle can be dropped into the issue.
+        body.push_str(&format!(
+            "\n\n---\nOpenmail {} on {} {} ({})",
+            diagnostics.app_version, diagnostics.os, diagnostics.os_version, diagnostics.arch
+        ));
+        attachment_path = Some(write_bundle(&diagnostics)?);
+    }
+
+    let issue_url = url::Url::parse_with_params(NEW_ISSUE_URL, &[("body", body.as_str())])
+        .map_err(|err| format!("Failed to create issue url: {}", err))?
+        .to_string();
+    Ok(FeedbackDraft {
+        receivers: FEEDBACK_EMAIL.to_string(),
+        subject: FEEDBACK_SUBJECT.to_string(),
+        body,
+        attachment_path,
+        issue_url,
+    })
+}
+
+#[tauri::command]
+pub async fn get_diagnostics(
+    app: AppHandle,
+    outbox: State<'_, Arc<Outbox>>,
+    offline: State<'_, Arc<OfflineChanges>>,
+) -> Result<Diagnostics, String> {
+    Ok(collect(&app, &outbox, &offline))
+}
+
+#[tauri::command]
+pub async fn send_feedback(
+    app: AppHandle,
+    outbox: State<'_, Arc<Outbox>>,
+    offline: State<'_, Arc<OfflineChanges>>,
+    text: String,
+    include_diagnostics: bool,
+) -> Result<FeedbackDraft, String> {
+    compose(&app, &outbox, &offline, &text, include_diagnostics)
+}
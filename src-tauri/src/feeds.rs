@@ -0,0 +1,492 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+use url::Url;
+
+use crate::cache::{Cache, CachedEmail, EmailChange};
+use crate::consts;
+use crate::events::EventBus;
+use crate::notifications::{self, NotificationHistory, NotificationReference};
+use crate::scheduler;
+use crate::utils;
+use crate::worker::{self, Job};
+
+// RSS and Atom feeds are read like a mailbox: every feed is a folder of the
+// "Feeds" account, which only exists in the cache, and its entries are
+// emails with the feed (or the author) as the sender and the link of the
+// entry at the end of the body. So they are searched, marked read and
+// notified about like the emails. Feeds are fetched on their own interval
+// like the shared mailboxes, and parsed in the parser worker since they are
+// untrusted content.
+pub const FEEDS_ACCOUNT: &str = "Feeds";
+const MIN_SYNC_INTERVAL_SEC: u64 = 300;
+const RETRY_INTERVAL_SEC: u64 = 300;
+const IDLE_INTERVAL_SEC: u64 = 3600;
+const REQUEST_TIMEOUT_SEC: u64 = 30;
+const MAX_FEED_BYTES: u64 = 10 * 1024 * 1024;
+const MAX_FEED_ENTRIES: usize = 500;
+// More new entries than this are notified as a single summary.
+const MAX_ENTRY_NOTIFICATIONS: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Feed {
+    pub url: String,
+    // Name of the folder of the feed.
+    pub name: String,
+    pub sync_interval_sec: u64,
+    pub notify: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeedEntry {
+    pub id: String,
+    pub title: String,
+    pub link: Option<String>,
+    pub author: Option<String>,
+    // RFC 2822
+    pub date: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParsedFeed {
+    pub title: Option<String>,
+    // Oldest first.
+    pub entries: Vec<FeedEntry>,
+}
+
+fn validate(feed: &Feed) -> Result<(), String> {
+    let url = Url::parse(&feed.url).map_err(|err| format!("Invalid url {}: {}", feed.url, err))?;
+    if !["http", "https"].contains(&url.scheme()) {
+        return Err(format!("Invalid url: {}", feed.url));
+    }
+    if feed.name.trim().is_empty() || feed.name.contains('/') {
+        return Err(format!("Invalid feed name: {}", feed.name));
+    }
+    if feed.sync_interval_sec < MIN_SYNC_INTERVAL_SEC {
+        return Err(format!(
+            "Sync interval can not be less than {} seconds",
+            MIN_SYNC_INTERVAL_SEC
+        ));
+    }
+    Ok(())
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some(' '),
+        _ => {
+            let number = entity.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+// Feeds mostly have html in their entries, it is read as text like the
+// bodies of the emails.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut rest = html;
+    while let Some(c) = rest.chars().next() {
+        match c {
+            '<' => {
+                let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+                let tag = rest[1..end].trim_end_matches('>').to_lowercase();
+                let name = tag
+                    .trim_start_matches('/')
+                    .split(|c: char| c.is_whitespace() || c == '/')
+                    .next()
+                    .unwrap_or_default();
+                if ["script", "style"].contains(&name) && !tag.starts_with('/') {
+                    let close = format!("</{}", name);
+                    rest = rest[end..]
+                        .to_ascii_lowercase()
+                        .find(&close)
+                        .map_or("", |index| &rest[end + index..]);
+                    continue;
+                }
+                if [
+                    "br", "p", "div", "li", "tr", "h1", "h2", "h3", "h4", "h5", "h6",
+                ]
+                .contains(&name)
+                {
+                    text.push('\n');
+                }
+                rest = &rest[end..];
+            }
+            '&' => {
+                let decoded = rest[1..]
+                    .find(';')
+                    .filter(|end| *end <= 10)
+                    .and_then(|end| Some((decode_entity(&rest[1..end + 1])?, end + 2)));
+                match decoded {
+                    Some((c, length)) => {
+                        text.push(c);
+                        rest = &rest[length..];
+                    }
+                    None => {
+                        text.push('&');
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            c => {
+                text.push(c);
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+    }
+
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| line.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .collect();
+    let mut collapsed: Vec<String> = Vec::new();
+    for line in lines {
+        if line.is_empty() && collapsed.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        collapsed.push(line);
+    }
+    collapsed.join("\n").trim().to_string()
+}
+
+// Runs in the worker process.
+pub fn parse_feed(content: &[u8]) -> Result<ParsedFeed, String> {
+    let feed = feed_rs::parser::parse(content).map_err(|err| format!("Invalid feed: {}", err))?;
+    let mut entries: Vec<(Option<i64>, FeedEntry)> = feed
+        .entries
+        .into_iter()
+        .map(|entry| {
+            let date = entry.published.or(entry.updated);
+            let html = entry
+                .content
+                .and_then(|content| content.body)
+                .or(entry.summary.map(|summary| summary.content))
+                .unwrap_or_default();
+            let entry = FeedEntry {
+                id: entry.id,
+                title: entry
+                    .title
+                    .map(|title| html_to_text(&title.content))
+                    .unwrap_or_default(),
+                link: entry.links.into_iter().next().map(|link| link.href),
+                author: entry.authors.into_iter().next().map(|author| author.name),
+                date: date.map(|date| date.to_rfc2822()).unwrap_or_default(),
+                text: html_to_text(&html),
+            };
+            (date.map(|date| date.timestamp()), entry)
+        })
+        .collect();
+    // Feeds are usually newest first, the dates decide if all entries have
+    // one.
+    entries.reverse();
+    if entries.iter().all(|(date, _)| date.is_some()) {
+        entries.sort_by_key(|(date, _)| *date);
+    }
+    Ok(ParsedFeed {
+        title: feed.title.map(|title| html_to_text(&title.content)),
+        entries: entries.into_iter().map(|(_, entry)| entry).collect(),
+    })
+}
+
+fn fetch(url: &str) -> Result<ParsedFeed, String> {
+    let response = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SEC))
+        .build()
+        .get(url)
+        .call()
+        .map_err(|err| format!("Failed to fetch {}: {}", url, err))?;
+    let mut content = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_FEED_BYTES)
+        .read_to_end(&mut content)
+        .map_err(|err| format!("Failed to read {}: {}", url, err))?;
+
+    let path = utils::build_temp_path(&format!("feed-{}.xml", utils::generate_random_id()))?;
+    fs::write(&path, content).map_err(|err| format!("Failed to write feed: {}", err))?;
+    let value = worker::run(&Job::ParseFeed {
+        path: path.to_string_lossy().to_string(),
+    });
+    fs::remove_file(&path).ok();
+    serde_json::from_value(value?).map_err(|err| format!("Invalid parsed feed: {}", err))
+}
+
+fn to_email(feed: &Feed, title: Option<&str>, entry: FeedEntry) -> CachedEmail {
+    let source = title.unwrap_or(&feed.name);
+    let mut body = entry.text;
+    if let Some(link) = entry.link.as_ref() {
+        body = format!("{}\n\n{}", body, link).trim().to_string();
+    }
+    CachedEmail {
+        message_id: entry.id,
+        sender: match entry.author {
+            Some(author) if !author.trim().is_empty() => format!("{} ({})", author.trim(), source),
+            _ => source.to_string(),
+        },
+        date: entry.date,
+        subject: entry.title,
+        body,
+        ..Default::default()
+    }
+}
+
+// Marks the emails of a cache-only account read or unread, there is no
+// server to sync it to.
+pub fn set_seen(
+    cache: &Cache,
+    events: &EventBus,
+    account: &str,
+    folder: &str,
+    uids: &[String],
+    seen: bool,
+) -> Result<(), String> {
+    for uid in uids {
+        if cache
+            .set_flag(account, folder, uid, "\\Seen", seen)?
+            .is_none()
+        {
+            return Err(format!("Email {} not found in {}", uid, folder));
+        }
+    }
+    let changes: Vec<EmailChange> = cache
+        .get_emails(account, folder)
+        .into_iter()
+        .filter(|email| uids.contains(&email.uid))
+        .map(|email| EmailChange::FlagsChanged {
+            uid: email.uid,
+            flags: email.flags,
+        })
+        .collect();
+    scheduler::publish_changes(events, account, folder, &changes);
+    Ok(())
+}
+
+pub struct Feeds {
+    cache: Arc<Cache>,
+    events: Arc<EventBus>,
+    feeds: Mutex<Vec<Feed>>,
+    next_sync_at: Mutex<HashMap<String, Instant>>,
+    wakeup: Condvar,
+}
+
+impl Feeds {
+    pub fn load(cache: Arc<Cache>, events: Arc<EventBus>) -> Self {
+        Feeds {
+            cache,
+            events,
+            feeds: Mutex::new(utils::read_json_file(consts::FEEDS_FILE_PATH).unwrap_or_default()),
+            next_sync_at: Mutex::new(HashMap::new()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    fn save(&self, feeds: &[Feed]) -> Result<(), String> {
+        utils::write_json_file(consts::FEEDS_FILE_PATH, &feeds)
+    }
+
+    pub fn get_feeds(&self) -> Vec<Feed> {
+        self.feeds.lock().unwrap().clone()
+    }
+
+    // Adds the feed or replaces the one with the same url.
+    pub fn set(&self, mut feed: Feed) -> Result<(), String> {
+        feed.url = feed.url.trim().to_string();
+        feed.name = feed.name.trim().to_string();
+        validate(&feed)?;
+
+        let mut feeds = self.feeds.lock().unwrap();
+        if feeds
+            .iter()
+            .any(|other| other.url != feed.url && other.name == feed.name)
+        {
+            return Err(format!("There is another feed named {}", feed.name));
+        }
+        self.next_sync_at.lock().unwrap().remove(&feed.url);
+        match feeds.iter_mut().find(|other| other.url == feed.url) {
+            Some(other) => {
+                // Entries move with the feed to its new name.
+                if other.name != feed.name {
+                    self.cache
+                        .rename_folder(FEEDS_ACCOUNT, &other.name, &feed.name)?;
+                }
+                *other = feed;
+            }
+            None => feeds.push(feed),
+        }
+        self.save(&feeds)?;
+        self.wakeup.notify_all();
+        Ok(())
+    }
+
+    pub fn remove(&self, url: &str) -> Result<(), String> {
+        let mut feeds = self.feeds.lock().unwrap();
+        let position = feeds
+            .iter()
+            .position(|feed| feed.url == url)
+            .ok_or(format!("Feed not found: {}", url))?;
+        let feed = feeds.remove(position);
+        self.save(&feeds)?;
+        self.cache.remove_folder(FEEDS_ACCOUNT, &feed.name)
+    }
+
+    pub fn set_seen(&self, name: &str, uids: &[String], seen: bool) -> Result<(), String> {
+        set_seen(&self.cache, &self.events, FEEDS_ACCOUNT, name, uids, seen)
+    }
+
+    // Returns the new entries.
+    fn sync_feed(&self, feed: &Feed) -> Result<Vec<CachedEmail>, String> {
+        let parsed = fetch(&feed.url)?;
+        let skipped = parsed.entries.len().saturating_sub(MAX_FEED_ENTRIES);
+        let emails: Vec<CachedEmail> = parsed
+            .entries
+            .into_iter()
+            .skip(skipped)
+            .map(|entry| to_email(feed, parsed.title.as_deref(), entry))
+            .collect();
+        let changes = self.cache.add_emails(
+            FEEDS_ACCOUNT,
+            &feed.name,
+            emails,
+            MAX_FEED_ENTRIES,
+            Utc::now().timestamp(),
+        )?;
+        scheduler::publish_changes(&self.events, FEEDS_ACCOUNT, &feed.name, &changes);
+
+        let added: Vec<&str> = changes
+            .iter()
+            .filter(|change| matches!(change, EmailChange::Added { .. }))
+            .map(|change| change.uid())
+            .collect();
+        Ok(self
+            .cache
+            .get_emails(FEEDS_ACCOUNT, &feed.name)
+            .into_iter()
+            .filter(|email| added.contains(&email.uid.as_str()))
+            .collect())
+    }
+
+    // Feeds that are due and how long to wait for the next one.
+    fn due_feeds(&self) -> (Vec<Feed>, Duration) {
+        let feeds = self.feeds.lock().unwrap();
+        let next_sync_at = self.next_sync_at.lock().unwrap();
+        let now = Instant::now();
+        let mut wait = Duration::from_secs(IDLE_INTERVAL_SEC);
+        let mut due = Vec::new();
+        for feed in feeds.iter() {
+            match next_sync_at.get(&feed.url) {
+                Some(at) if *at > now => wait = wait.min(*at - now),
+                _ => due.push(feed.clone()),
+            }
+        }
+        (due, wait)
+    }
+
+    fn schedule(&self, url: &str, after: Duration) {
+        self.next_sync_at
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), Instant::now() + after);
+    }
+
+    fn wait(&self, interval: Duration) {
+        let feeds = self.feeds.lock().unwrap();
+        let _ = self.wakeup.wait_timeout(feeds, interval).unwrap();
+    }
+}
+
+fn notify(app: &AppHandle, history: &NotificationHistory, feed: &Feed, entries: &[CachedEmail]) {
+    let reference = |entries: &[CachedEmail]| NotificationReference {
+        account: Some(FEEDS_ACCOUNT.to_string()),
+        folder: Some(feed.name.clone()),
+        uids: entries.iter().map(|entry| entry.uid.clone()).collect(),
+    };
+
+    if entries.len() > MAX_ENTRY_NOTIFICATIONS {
+        let body = format!("{} new entries", entries.len());
+        notifications::show(app, history, &feed.name, &body, reference(entries));
+        return;
+    }
+    for entry in entries {
+        notifications::show(
+            app,
+            history,
+            &feed.name,
+            &entry.subject,
+            reference(std::slice::from_ref(entry)),
+        );
+    }
+}
+
+pub fn start_worker(app: AppHandle, feeds: Arc<Feeds>, history: Arc<NotificationHistory>) {
+    thread::spawn(move || loop {
+        let (due, wait) = feeds.due_feeds();
+        if due.is_empty() {
+            feeds.wait(wait);
+            continue;
+        }
+
+        for feed in due {
+            // Everything is new on the first sync.
+            let is_first_sync = feeds
+                .cache
+                .get_folder(FEEDS_ACCOUNT, &feed.name)
+                .synced_at
+                .is_none();
+            let after = match feeds.sync_feed(&feed) {
+                Ok(entries) => {
+                    if feed.notify && !is_first_sync && !entries.is_empty() {
+                        notify(&app, &history, &feed, &entries);
+                    }
+                    feed.sync_interval_sec
+                }
+                Err(err) => {
+                    println!("Failed to sync feed {}: {}", feed.url, err);
+                    RETRY_INTERVAL_SEC
+                }
+            };
+            feeds.schedule(&feed.url, Duration::from_secs(after));
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_feeds(feeds: State<'_, Arc<Feeds>>) -> Vec<Feed> {
+    feeds.get_feeds()
+}
+
+#[tauri::command]
+pub fn set_feed(feeds: State<'_, Arc<Feeds>>, feed: Feed) -> Result<(), String> {
+    feeds.set(feed)
+}
+
+#[tauri::command]
+pub fn remove_feed(feeds: State<'_, Arc<Feeds>>, url: String) -> Result<(), String> {
+    feeds.remove(&url)
+}
+
+#[tauri::command]
+pub fn set_feed_entries_seen(
+    feeds: State<'_, Arc<Feeds>>,
+    name: String,
+    uids: Vec<String>,
+    seen: bool,
+) -> Result<(), String> {
+    feeds.set_seen(&name, &uids, seen)
+}
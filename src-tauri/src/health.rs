@@ -0,0 +1,270 @@
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, State};
+
+use crate::api;
+use crate::consts;
+use crate::scheduler;
+use crate::utils;
+
+// Accounts are checked once the server is up on each launch (or when asked)
+// and the problems that need the user are sent to the UI as one report:
+// sign-in failing repeatedly in the last day, a mailbox that is nearly
+// full and a server certificate that expires soon. Accounts sign in with
+// passwords, so there are no tokens that could expire. The server only
+// keeps the sign-in failures since it started, so the sync worker records
+// them to remember the ones before a restart.
+pub const ACCOUNT_HEALTH_EVENT: &str = "account-health";
+const SERVER_WAIT_INTERVAL_SEC: u64 = 5;
+const MAX_SERVER_WAIT_SEC: u64 = 120;
+const AUTH_FAILURE_WINDOW_HOURS: i64 = 24;
+const MIN_AUTH_FAILURES: usize = 3;
+const QUOTA_WARNING_PERCENT: u64 = 90;
+const CERTIFICATE_WARNING_DAYS: i64 = 14;
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthIssue {
+    AuthFailing,
+    QuotaNearlyFull,
+    CertificateExpiring,
+    Unreachable,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthItem {
+    pub issue: HealthIssue,
+    pub severity: Severity,
+    pub message: String,
+    // What the user can do about it.
+    pub action: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AccountHealth {
+    pub account: String,
+    pub items: Vec<HealthItem>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthReport {
+    pub checked_at: String,
+    // Only the accounts that have something to fix.
+    pub accounts: Vec<AccountHealth>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ServerHealth {
+    quota_used_kb: Option<u64>,
+    quota_total_kb: Option<u64>,
+    certificate_expires_at: Option<String>,
+}
+
+// Account -> times of the failed sign-ins of the last day.
+pub type AuthFailures = HashMap<String, Vec<i64>>;
+
+// Called on each sync with the failed sign-in attempts of the server, the
+// ones that are recorded already (by their time) are not counted again.
+pub fn record_auth_failures(failed: &AuthFailures) -> Result<(), String> {
+    let mut failures: AuthFailures = utils::read_json_file(consts::AUTH_FAILURES_FILE_PATH)?;
+    if failed.is_empty() && failures.is_empty() {
+        return Ok(());
+    }
+    let now = Utc::now().timestamp();
+    let oldest = now - AUTH_FAILURE_WINDOW_HOURS * 60 * 60;
+    for (account, times) in failed {
+        let recorded = failures.entry(account.clone()).or_default();
+        for time in times {
+            if !recorded.contains(time) {
+                recorded.push(*time);
+            }
+        }
+    }
+    for times in failures.values_mut() {
+        times.retain(|time| *time >= oldest);
+    }
+    failures.retain(|_, times| !times.is_empty());
+    utils::write_json_file(consts::AUTH_FAILURES_FILE_PATH, &failures)
+}
+
+fn fetch_server_health(account: &str) -> Result<ServerHealth, String> {
+    let response = api::get(&format!("/get-account-health/{}", account), &[])?;
+    serde_json::from_value(api::get_account_data(response, account)?)
+        .map_err(|err| format!("Failed to parse health of {}: {}", account, err))
+}
+
+fn check_account(
+    account: &str,
+    is_failing: bool,
+    failures: usize,
+    now: DateTime<Utc>,
+) -> Vec<HealthItem> {
+    let mut items = Vec::new();
+    if failures >= MIN_AUTH_FAILURES || is_failing {
+        items.push(HealthItem {
+            issue: HealthIssue::AuthFailing,
+            severity: if is_failing {
+                Severity::Error
+            } else {
+                Severity::Warning
+            },
+            message: if failures > 0 {
+                format!(
+                    "Sign-in failed {} times in the last {} hours",
+                    failures, AUTH_FAILURE_WINDOW_HOURS
+                )
+            } else {
+                "Sign-in is failing".to_string()
+            },
+            action: "Check the password, the server may ask for an app password".to_string(),
+        });
+    }
+    // Nothing else can be asked while it can't sign in.
+    if is_failing {
+        return items;
+    }
+
+    let health = match fetch_server_health(account) {
+        Ok(health) => health,
+        Err(err) => {
+            items.push(HealthItem {
+                issue: HealthIssue::Unreachable,
+                severity: Severity::Warning,
+                message: err,
+                action: "Check the connection, the account is checked again on the next launch"
+                    .to_string(),
+            });
+            return items;
+        }
+    };
+    if let (Some(used), Some(total)) = (health.quota_used_kb, health.quota_total_kb) {
+        let percent = used * 100 / total.max(1);
+        if percent >= QUOTA_WARNING_PERCENT {
+            items.push(HealthItem {
+                issue: HealthIssue::QuotaNearlyFull,
+                severity: if used >= total {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                },
+                message: format!(
+                    "Mailbox is {}% full ({} of {} MB)",
+                    percent.min(100),
+                    used / 1024,
+                    total / 1024
+                ),
+                action: "Delete or archive large emails, new emails bounce once it is full"
+                    .to_string(),
+            });
+        }
+    }
+    let expires_at = health
+        .certificate_expires_at
+        .as_deref()
+        .and_then(|date| DateTime::parse_from_rfc3339(date).ok());
+    if let Some(expires_at) = expires_at {
+        let remaining = expires_at.with_timezone(&Utc) - now;
+        if remaining <= ChronoDuration::days(CERTIFICATE_WARNING_DAYS) {
+            items.push(HealthItem {
+                issue: HealthIssue::CertificateExpiring,
+                severity: if remaining < ChronoDuration::zero() {
+                    Severity::Error
+                } else {
+                    Severity::Warning
+                },
+                message: format!(
+                    "Certificate of the mail server expires on {}",
+                    expires_at.format("%Y-%m-%d")
+                ),
+                action: "Ask the mail provider to renew it, the app won't connect once it expired"
+                    .to_string(),
+            });
+        }
+    }
+    items
+}
+
+#[derive(Default)]
+pub struct Health {
+    last_report: Mutex<Option<HealthReport>>,
+}
+
+impl Health {
+    pub fn get_last_report(&self) -> Option<HealthReport> {
+        self.last_report.lock().unwrap().clone()
+    }
+
+    pub fn check(&self, app: &AppHandle) -> Result<HealthReport, String> {
+        let accounts = scheduler::get_accounts()?;
+        let failures: AuthFailures =
+            utils::read_json_file(consts::AUTH_FAILURES_FILE_PATH).unwrap_or_default();
+        let now = Utc::now();
+        let oldest = (now - ChronoDuration::hours(AUTH_FAILURE_WINDOW_HOURS)).timestamp();
+
+        let mut report = HealthReport {
+            checked_at: now.to_rfc3339(),
+            accounts: Vec::new(),
+        };
+        let all = accounts
+            .connected
+            .iter()
+            .map(|account| (account, false))
+            .chain(accounts.failed.iter().map(|account| (account, true)));
+        for (account, is_failing) in all {
+            let failures = failures
+                .get(account)
+                .map(|times| times.iter().filter(|time| **time >= oldest).count())
+                .unwrap_or(0);
+            let items = check_account(account, is_failing, failures, now);
+            if !items.is_empty() {
+                report.accounts.push(AccountHealth {
+                    account: account.clone(),
+                    items,
+                });
+            }
+        }
+
+        *self.last_report.lock().unwrap() = Some(report.clone());
+        app.emit(ACCOUNT_HEALTH_EVENT, &report).ok();
+        Ok(report)
+    }
+}
+
+// Checks once the server is up, the UI may not be listening yet so the
+// report is also kept for `get_account_health`.
+pub fn check_on_launch(app: AppHandle, health: Arc<Health>) {
+    thread::spawn(move || {
+        let mut waited = 0;
+        while scheduler::get_accounts().is_err() && waited < MAX_SERVER_WAIT_SEC {
+            thread::sleep(Duration::from_secs(SERVER_WAIT_INTERVAL_SEC));
+            waited += SERVER_WAIT_INTERVAL_SEC;
+        }
+        if let Err(err) = health.check(&app) {
+            println!("Failed to check account health: {}", err);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_account_health(health: State<'_, Arc<Health>>) -> Option<HealthReport> {
+    health.get_last_report()
+}
+
+#[tauri::command]
+pub async fn check_account_health(
+    app: AppHandle,
+    health: State<'_, Arc<Health>>,
+) -> Result<HealthReport, String> {
+    health.check(&app)
+}
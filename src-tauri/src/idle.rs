@@ -0,0 +1,142 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tungstenite::Message;
+
+use crate::account_schedule::AccountSchedules;
+use crate::api;
+use crate::fault_injection;
+use crate::scheduler::{self, SyncScheduler};
+
+// IMAP IDLE of the server reports the new emails of the Inbox as they
+// arrive. The reports of every connected account are listened to, and the
+// scheduler syncs the account right after them (which also indexes it for
+// the local search, see search.rs), so new emails can be found within
+// seconds instead of after the next scheduled sync. Syncs only happen on the
+// scheduler's thread, so they never run twice at once. A large delivery
+// reports every email, so the reports of an account are batched: the sync
+// is requested once they stop for `BATCH_QUIET_MS`, or at the latest
+// `BATCH_MAX_DELAY_MS` after the first.
+const BATCH_QUIET_MS: u64 = 2000;
+const BATCH_MAX_DELAY_MS: u64 = 10000;
+// Also how often the new accounts start to be listened to.
+const RECONNECT_INTERVAL_SEC: u64 = 30;
+
+struct Batch {
+    first_push: Instant,
+    last_push: Instant,
+}
+
+impl Batch {
+    fn due_at(&self) -> Instant {
+        (self.last_push + Duration::from_millis(BATCH_QUIET_MS))
+            .min(self.first_push + Duration::from_millis(BATCH_MAX_DELAY_MS))
+    }
+}
+
+#[derive(Default)]
+struct IdlePushes {
+    pending: Mutex<HashMap<String, Batch>>,
+    wakeup: Condvar,
+    listening: Mutex<HashSet<String>>,
+}
+
+impl IdlePushes {
+    fn push(&self, account: &str) {
+        let now = Instant::now();
+        self.pending
+            .lock()
+            .unwrap()
+            .entry(account.to_string())
+            .and_modify(|batch| batch.last_push = now)
+            .or_insert(Batch {
+                first_push: now,
+                last_push: now,
+            });
+        self.wakeup.notify_all();
+    }
+
+    // Waits until the batch of an account is due, and takes the due ones.
+    fn wait_for_batch(&self) -> Vec<String> {
+        let mut pending = self.pending.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            match pending.values().map(Batch::due_at).min() {
+                None => pending = self.wakeup.wait(pending).unwrap(),
+                Some(due_at) if due_at > now => {
+                    pending = self.wakeup.wait_timeout(pending, due_at - now).unwrap().0;
+                }
+                Some(_) => {
+                    let due: Vec<String> = pending
+                        .iter()
+                        .filter(|(_, batch)| batch.due_at() <= now)
+                        .map(|(account, _)| account.clone())
+                        .collect();
+                    pending.retain(|account, _| !due.contains(account));
+                    return due;
+                }
+            }
+        }
+    }
+}
+
+fn listen_account(pushes: &IdlePushes, account: &str) -> Result<(), String> {
+    let mut socket = api::connect_websocket(&format!("/new-message-pushes/{}", account))?;
+    loop {
+        match socket
+            .read()
+            .map_err(|err| format!("Failed to read new message pushes: {}", err))?
+        {
+            Message::Text(_) => {
+                fault_injection::delay_idle_event();
+                pushes.push(account);
+            }
+            Message::Close(_) => return Ok(()),
+            _ => {}
+        }
+    }
+}
+
+// Until the server closes the connection, the account is listened to again
+// on the next check.
+fn listen(pushes: Arc<IdlePushes>, account: String) {
+    thread::spawn(move || {
+        if let Err(err) = listen_account(&pushes, &account) {
+            println!("Stopped listening to {}: {}", account, err);
+        }
+        pushes.listening.lock().unwrap().remove(&account);
+    });
+}
+
+pub fn start_worker(scheduler: Arc<SyncScheduler>, schedules: Arc<AccountSchedules>) {
+    let pushes = Arc::new(IdlePushes::default());
+
+    let listener_pushes = pushes.clone();
+    thread::spawn(move || loop {
+        if let Ok(accounts) = scheduler::get_connected_accounts() {
+            for account in accounts {
+                if listener_pushes
+                    .listening
+                    .lock()
+                    .unwrap()
+                    .insert(account.clone())
+                {
+                    listen(listener_pushes.clone(), account);
+                }
+            }
+        }
+        thread::sleep(Duration::from_secs(RECONNECT_INTERVAL_SEC));
+    });
+
+    thread::spawn(move || loop {
+        for account in pushes.wait_for_batch() {
+            // Paused accounts catch up once they are resumed, see
+            // account_schedule.rs
+            if schedules.is_paused(&account) {
+                continue;
+            }
+            scheduler.request_account(&account);
+        }
+    });
+}
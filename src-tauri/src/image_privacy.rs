@@ -0,0 +1,429 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Serialize;
+
+// Outgoing images (attachments and the ones inlined into the body) are
+// staged here before they are sent, their EXIF/XMP metadata is removed
+// unless the user chose to keep it. Only the orientation is kept, so photos
+// aren't shown rotated. Formats other than JPEG and PNG are sent as they
+// are. The outbox strips the images inlined into the bodies (as data URIs)
+// when an email is queued, see `strip_inline_images`.
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_GPS_IFD: u16 = 0x8825;
+// GPSLatitude and GPSLongitude tags of the GPS IFD.
+const TAGS_GPS_POSITION: [u16; 2] = [0x0002, 0x0004];
+const XMP_GPS_PROPERTIES: [&str; 2] = ["GPSLatitude", "GPSLongitude"];
+const DATA_URI_PREFIX: &str = "data:image/";
+const BASE64_MARKER: &str = ";base64,";
+
+#[derive(Debug, Default, Serialize)]
+pub struct StagedImage {
+    // Base64, the same as the input if nothing was stripped.
+    pub data: String,
+    pub had_metadata: bool,
+    pub had_location: bool,
+    pub stripped: bool,
+}
+
+#[derive(Default)]
+struct Metadata {
+    found: bool,
+    location: bool,
+    orientation: Option<u16>,
+}
+
+struct Tiff<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Tiff<'_> {
+    fn u16_at(&self, offset: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32_at(&self, offset: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    // (tag, offset of the value field) of the entries of the IFD.
+    fn entries(&self, ifd_offset: usize) -> Vec<(u16, usize)> {
+        let count = self.u16_at(ifd_offset).unwrap_or(0) as usize;
+        (0..count)
+            .map(|index| ifd_offset + 2 + index * 12)
+            .filter_map(|entry| Some((self.u16_at(entry)?, entry + 8)))
+            .collect()
+    }
+}
+
+fn read_exif(tiff: &[u8], metadata: &mut Metadata) {
+    metadata.found = true;
+    let big_endian = match tiff.get(..2) {
+        Some(b"MM") => true,
+        Some(b"II") => false,
+        _ => return,
+    };
+    let tiff = Tiff {
+        data: tiff,
+        big_endian,
+    };
+    let Some(ifd0) = tiff.u32_at(4) else {
+        return;
+    };
+
+    for (tag, value) in tiff.entries(ifd0 as usize) {
+        match tag {
+            TAG_ORIENTATION => metadata.orientation = tiff.u16_at(value),
+            TAG_GPS_IFD => {
+                if let Some(gps_ifd) = tiff.u32_at(value) {
+                    metadata.location |= tiff
+                        .entries(gps_ifd as usize)
+                        .iter()
+                        .any(|(tag, _)| TAGS_GPS_POSITION.contains(tag));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn read_xmp(xmp: &[u8], metadata: &mut Metadata) {
+    metadata.found = true;
+    let xmp = String::from_utf8_lossy(xmp);
+    metadata.location |= XMP_GPS_PROPERTIES
+        .iter()
+        .any(|property| xmp.contains(property));
+}
+
+// Big endian TIFF with only the orientation in IFD0.
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+    let mut tiff = b"MM\0\x2a\0\0\0\x08".to_vec();
+    tiff.extend_from_slice(&1u16.to_be_bytes());
+    tiff.extend_from_slice(&TAG_ORIENTATION.to_be_bytes());
+    // SHORT, count 1, the value is left aligned in the 4 bytes.
+    tiff.extend_from_slice(&3u16.to_be_bytes());
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]);
+    // No next IFD.
+    tiff.extend_from_slice(&0u32.to_be_bytes());
+    tiff
+}
+
+fn strip_jpeg(data: &[u8], metadata: &mut Metadata) -> Result<Vec<u8>, String> {
+    let invalid = || "Invalid JPEG image".to_string();
+    let mut stripped = data[..2].to_vec();
+    let mut offset = 2;
+    loop {
+        let marker = *data.get(offset + 1).ok_or_else(invalid)?;
+        if data[offset] != 0xFF {
+            return Err(invalid());
+        }
+        // Fill bytes, any number of 0xFF can come before a marker.
+        if marker == 0xFF {
+            offset += 1;
+            continue;
+        }
+        // Markers without a length.
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            stripped.extend_from_slice(&data[offset..offset + 2]);
+            offset += 2;
+            continue;
+        }
+        // Start of scan, the rest is image data.
+        if marker == 0xDA || marker == 0xD9 {
+            stripped.extend_from_slice(&data[offset..]);
+            break;
+        }
+
+        let length = u16::from_be_bytes([
+            *data.get(offset + 2).ok_or_else(invalid)?,
+            *data.get(offset + 3).ok_or_else(invalid)?,
+        ]) as usize;
+        if length < 2 {
+            return Err(invalid());
+        }
+        let segment = data.get(offset..offset + 2 + length).ok_or_else(invalid)?;
+        let payload = &segment[4..];
+        match marker {
+            0xE1 if payload.starts_with(EXIF_HEADER) => {
+                read_exif(&payload[EXIF_HEADER.len()..], metadata)
+            }
+            0xE1 if payload.starts_with(XMP_HEADER) => {
+                read_xmp(&payload[XMP_HEADER.len()..], metadata)
+            }
+            // Photoshop resources, which contain IPTC data.
+            0xED => metadata.found = true,
+            _ => stripped.extend_from_slice(segment),
+        }
+        offset += segment.len();
+    }
+
+    if let Some(orientation) = metadata.orientation.filter(|orientation| *orientation != 1) {
+        let mut exif = EXIF_HEADER.to_vec();
+        exif.extend_from_slice(&orientation_exif(orientation));
+        let mut segment = vec![0xFF, 0xE1];
+        segment.extend_from_slice(&(exif.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(&exif);
+        // After SOI and the JFIF segment, where readers expect it.
+        let at = if stripped[2..].starts_with(&[0xFF, 0xE0]) {
+            4 + u16::from_be_bytes([stripped[4], stripped[5]]) as usize
+        } else {
+            2
+        };
+        stripped.splice(at..at, segment);
+    }
+    Ok(stripped)
+}
+
+fn strip_png(data: &[u8], metadata: &mut Metadata) -> Result<Vec<u8>, String> {
+    let invalid = || "Invalid PNG image".to_string();
+    let mut stripped = PNG_SIGNATURE.to_vec();
+    let mut offset = PNG_SIGNATURE.len();
+    while offset < data.len() {
+        let length = u32::from_be_bytes(
+            data.get(offset..offset + 4)
+                .ok_or_else(invalid)?
+                .try_into()
+                .map_err(|_| invalid())?,
+        ) as usize;
+        let chunk = data.get(offset..offset + 12 + length).ok_or_else(invalid)?;
+        let chunk_data = &chunk[8..8 + length];
+        // Orientation of PNG images is rarely used by readers, it is not
+        // kept.
+        match &chunk[4..8] {
+            b"eXIf" => read_exif(chunk_data, metadata),
+            b"tEXt" | b"zTXt" | b"iTXt" => read_xmp(chunk_data, metadata),
+            _ => stripped.extend_from_slice(chunk),
+        }
+        offset += chunk.len();
+    }
+    Ok(stripped)
+}
+
+fn stage(data: &str, keep_metadata: bool) -> Result<StagedImage, String> {
+    let image = BASE64
+        .decode(data)
+        .map_err(|err| format!("Invalid image: {}", err))?;
+    let mut metadata = Metadata::default();
+    let stripped = if image.starts_with(&[0xFF, 0xD8]) {
+        strip_jpeg(&image, &mut metadata)?
+    } else if image.starts_with(PNG_SIGNATURE) {
+        strip_png(&image, &mut metadata)?
+    } else {
+        return Ok(StagedImage {
+            data: data.to_string(),
+            ..Default::default()
+        });
+    };
+
+    let strip = !keep_metadata && metadata.found;
+    Ok(StagedImage {
+        data: if strip {
+            BASE64.encode(stripped)
+        } else {
+            data.to_string()
+        },
+        had_metadata: metadata.found,
+        had_location: metadata.location,
+        stripped: strip,
+    })
+}
+
+// Stages the `data:image/...;base64,` URIs of the HTML body, images that
+// can't be parsed are left as they are.
+pub fn strip_inline_images(html: &str) -> String {
+    let mut stripped = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find(DATA_URI_PREFIX) {
+        let data_start = match rest[start..].find(BASE64_MARKER) {
+            // The media type comes right before the marker.
+            Some(marker)
+                if rest[start + DATA_URI_PREFIX.len()..start + marker]
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-') =>
+            {
+                start + marker + BASE64_MARKER.len()
+            }
+            _ => {
+                stripped.push_str(&rest[..start + DATA_URI_PREFIX.len()]);
+                rest = &rest[start + DATA_URI_PREFIX.len()..];
+                continue;
+            }
+        };
+        let data_end = rest[data_start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '='))
+            .map_or(rest.len(), |length| data_start + length);
+
+        stripped.push_str(&rest[..data_start]);
+        let data = &rest[data_start..data_end];
+        match stage(data, false) {
+            Ok(staged) => stripped.push_str(&staged.data),
+            Err(_) => stripped.push_str(data),
+        }
+        rest = &rest[data_end..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[tauri::command]
+pub async fn stage_image(data: String, keep_metadata: bool) -> Result<StagedImage, String> {
+    stage(&data, keep_metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Big endian TIFF with the orientation and a GPS IFD with the position.
+    fn gps_exif(orientation: u16) -> Vec<u8> {
+        let mut tiff = b"MM\0\x2a\0\0\0\x08".to_vec();
+        tiff.extend_from_slice(&2u16.to_be_bytes());
+        tiff.extend_from_slice(&TAG_ORIENTATION.to_be_bytes());
+        tiff.extend_from_slice(&3u16.to_be_bytes());
+        tiff.extend_from_slice(&1u32.to_be_bytes());
+        tiff.extend_from_slice(&orientation.to_be_bytes());
+        tiff.extend_from_slice(&[0, 0]);
+        // The GPS IFD comes right after IFD0, at 8 + 2 + 2 * 12 + 4.
+        tiff.extend_from_slice(&TAG_GPS_IFD.to_be_bytes());
+        tiff.extend_from_slice(&4u16.to_be_bytes());
+        tiff.extend_from_slice(&1u32.to_be_bytes());
+        tiff.extend_from_slice(&38u32.to_be_bytes());
+        tiff.extend_from_slice(&0u32.to_be_bytes());
+        tiff.extend_from_slice(&2u16.to_be_bytes());
+        for tag in TAGS_GPS_POSITION {
+            tiff.extend_from_slice(&tag.to_be_bytes());
+            tiff.extend_from_slice(&5u16.to_be_bytes());
+            tiff.extend_from_slice(&3u32.to_be_bytes());
+            tiff.extend_from_slice(&0u32.to_be_bytes());
+        }
+        tiff.extend_from_slice(&0u32.to_be_bytes());
+        tiff
+    }
+
+    fn jpeg_segment(marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xFF, marker];
+        segment.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(payload);
+        segment
+    }
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        // The CRC isn't checked.
+        chunk.extend_from_slice(&[0; 4]);
+        chunk
+    }
+
+    fn jpeg_with_gps() -> Vec<u8> {
+        let mut exif = EXIF_HEADER.to_vec();
+        exif.extend_from_slice(&gps_exif(6));
+        let mut jpeg = vec![0xFF, 0xD8];
+        jpeg.extend(jpeg_segment(0xE0, b"JFIF\0\x01\x01\0\0\x01\0\x01\0\0"));
+        // Fill bytes before the marker.
+        jpeg.extend_from_slice(&[0xFF, 0xFF]);
+        jpeg.extend(jpeg_segment(0xE1, &exif));
+        jpeg.extend(jpeg_segment(0xDB, &[0; 4]));
+        jpeg.extend(jpeg_segment(0xDA, &[0; 6]));
+        jpeg.extend_from_slice(&[0x12, 0x34, 0xFF, 0x00, 0x56, 0xFF, 0xD9]);
+        jpeg
+    }
+
+    #[test]
+    fn strips_gps_of_jpeg_and_keeps_orientation() {
+        let staged = stage(&BASE64.encode(jpeg_with_gps()), false).unwrap();
+        assert!(staged.had_metadata);
+        assert!(staged.had_location);
+        assert!(staged.stripped);
+
+        let stripped = BASE64.decode(&staged.data).unwrap();
+        let mut metadata = Metadata::default();
+        let restripped = strip_jpeg(&stripped, &mut metadata).unwrap();
+        assert!(metadata.found);
+        assert!(!metadata.location);
+        assert_eq!(metadata.orientation, Some(6));
+        // Only the orientation is left, after the JFIF segment.
+        let mut exif = EXIF_HEADER.to_vec();
+        exif.extend_from_slice(&orientation_exif(6));
+        assert_eq!(stripped[20..22], [0xFF, 0xE1]);
+        assert_eq!(&stripped[24..24 + exif.len()], exif.as_slice());
+        assert!(stripped.ends_with(&[0x12, 0x34, 0xFF, 0x00, 0x56, 0xFF, 0xD9]));
+        assert_eq!(restripped, stripped);
+    }
+
+    #[test]
+    fn keeps_metadata_when_asked() {
+        let data = BASE64.encode(jpeg_with_gps());
+        let staged = stage(&data, true).unwrap();
+        assert!(staged.had_location);
+        assert!(!staged.stripped);
+        assert_eq!(staged.data, data);
+    }
+
+    #[test]
+    fn strips_exif_and_xmp_chunks_of_png() {
+        let ihdr = png_chunk(b"IHDR", &[0; 13]);
+        let idat = png_chunk(b"IDAT", b"pixels");
+        let iend = png_chunk(b"IEND", b"");
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(&ihdr);
+        png.extend(png_chunk(b"eXIf", &gps_exif(1)));
+        png.extend(png_chunk(
+            b"iTXt",
+            b"XML:com.adobe.xmp\0\0\0\0\0<rdf:Description exif:GPSLatitude=\"41,0N\"/>",
+        ));
+        png.extend_from_slice(&idat);
+        png.extend_from_slice(&iend);
+
+        let staged = stage(&BASE64.encode(png), false).unwrap();
+        assert!(staged.had_location);
+        assert!(staged.stripped);
+        assert_eq!(
+            BASE64.decode(&staged.data).unwrap(),
+            [PNG_SIGNATURE, &ihdr, &idat, &iend].concat()
+        );
+    }
+
+    #[test]
+    fn leaves_other_formats() {
+        let data = BASE64.encode(b"GIF89a\x01\0\x01\0");
+        let staged = stage(&data, false).unwrap();
+        assert!(!staged.had_metadata);
+        assert_eq!(staged.data, data);
+    }
+
+    #[test]
+    fn strips_inline_images() {
+        let data = BASE64.encode(jpeg_with_gps());
+        let html = format!(
+            "<p>Photo: data:image/</p><img src=\"data:image/jpeg;base64,{}\" alt=\"photo\">",
+            data
+        );
+
+        let stripped = strip_inline_images(&html);
+        let expected = format!(
+            "<p>Photo: data:image/</p><img src=\"data:image/jpeg;base64,{}\" alt=\"photo\">",
+            stage(&data, false).unwrap().data
+        );
+        assert_ne!(stripped, html);
+        assert_eq!(stripped, expected);
+        // Already stripped images come out the same.
+        assert_eq!(strip_inline_images(&stripped), stripped);
+    }
+}
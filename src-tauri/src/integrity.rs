@@ -0,0 +1,71 @@
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use tauri::utils::assets::{AssetKey, AssetsIter, CspHash};
+use tauri::{Assets, Context, Runtime};
+
+// The frontend has access to every account, so a tampered one could send
+// mail anywhere. The embedded assets are checked against the hashes taken
+// by build.rs when the app was built, and on a mismatch the app starts in
+// safe mode: the python server isn't started and the window only shows
+// SAFE_MODE_PAGE. The app doesn't register custom protocols, the embedded
+// assets are all the webview loads. The CSP isn't set in tauri.conf.json,
+// so the HTML files are embedded as they were built.
+const ASSET_HASHES: &str = include_str!(concat!(env!("OUT_DIR"), "/asset_hashes.txt"));
+const SAFE_MODE_PAGE: &str = include_str!("safe_mode.html");
+
+struct SafeModeAssets;
+
+impl<R: Runtime> Assets<R> for SafeModeAssets {
+    fn get(&self, _key: &AssetKey) -> Option<Cow<'_, [u8]>> {
+        Some(Cow::Borrowed(SAFE_MODE_PAGE.as_bytes()))
+    }
+
+    fn iter(&self) -> Box<AssetsIter<'_>> {
+        Box::new(std::iter::once((
+            Cow::Borrowed("/index.html"),
+            Cow::Borrowed(SAFE_MODE_PAGE.as_bytes()),
+        )))
+    }
+
+    fn csp_hashes(&self, _html_path: &AssetKey) -> Box<dyn Iterator<Item = CspHash<'_>> + '_> {
+        Box::new(std::iter::empty())
+    }
+}
+
+fn hash(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+pub fn verify_assets<R: Runtime>(context: &Context<R>) -> Result<(), String> {
+    // Assets are served by the dev server while developing.
+    if cfg!(dev) {
+        return Ok(());
+    }
+
+    let expected: HashMap<&str, &str> = ASSET_HASHES
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, key)| (key, hash))
+        .collect();
+    let assets = context.assets();
+    // Contents of `iter` can be compressed, `get` decompresses them.
+    for (key, _) in assets.iter() {
+        if !expected.contains_key(key.as_ref()) {
+            return Err(format!("Unexpected frontend asset: {}", key));
+        }
+    }
+    for (key, expected_hash) in expected {
+        let contents = assets
+            .get(&AssetKey::from(key))
+            .ok_or(format!("Missing frontend asset: {}", key))?;
+        if hash(&contents) != expected_hash {
+            return Err(format!("Frontend asset was modified: {}", key));
+        }
+    }
+    Ok(())
+}
+
+pub fn enter_safe_mode<R: Runtime>(context: &mut Context<R>) {
+    context.set_assets(Box::new(SafeModeAssets));
+}
@@ -0,0 +1,298 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::ipc::Response;
+use tauri::{AppHandle, Emitter, Manager, State};
+
+use crate::cache::Cache;
+use crate::consts;
+use crate::mail_counts;
+use crate::outbox::Outbox;
+use crate::scheduler::{self, SyncScheduler};
+use crate::utils;
+
+// Local channel of openmail-cli (see bin/openmail-cli.rs). The app listens
+// on a random loopback port and writes it to the IPC info file of the
+// session (see utils::build_runtime_path) with a random token, only
+// requests with the token are handled, so other users of the machine can't
+// use it (the file is only readable by the user).
+// Every connection is a single request and response, both are one line of
+// JSON, the response mirrors the `Response` model of the python server.
+pub const CLI_COMPOSE_EVENT: &str = "cli-compose";
+const TOKEN_LENGTH: usize = 32;
+const READ_TIMEOUT_SEC: u64 = 10;
+// Large enough for the base64 encoded messages of `send`.
+const MAX_REQUEST_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Deserialize)]
+struct IpcRequest {
+    token: String,
+    command: String,
+    #[serde(default)]
+    args: Value,
+}
+
+#[derive(Serialize)]
+struct IpcResponse {
+    success: bool,
+    message: String,
+    data: Option<Value>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ComposeArgs {
+    pub to: String,
+    pub cc: Option<String>,
+    pub bcc: Option<String>,
+    pub subject: String,
+    pub body: String,
+    // Paths of the files to attach.
+    pub attachments: Vec<String>,
+    // Attachments of openmail:// links, which can come from anywhere, are
+    // only attached once the user confirms them, see deep_link.rs
+    #[serde(skip_deserializing)]
+    pub confirm: bool,
+}
+
+// Files that were given to the composer, the UI can only read these (see
+// `read_compose_attachment`), and each of them once.
+#[derive(Default)]
+pub struct ComposeAttachments {
+    approved: Mutex<HashSet<String>>,
+    unconfirmed: Mutex<HashSet<String>>,
+}
+
+impl ComposeAttachments {
+    pub fn expect(&self, args: &ComposeArgs) {
+        let paths = if args.confirm {
+            &self.unconfirmed
+        } else {
+            &self.approved
+        };
+        paths
+            .lock()
+            .unwrap()
+            .extend(args.attachments.iter().cloned());
+    }
+}
+
+#[derive(Deserialize)]
+struct UnreadArgs {
+    account: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct SendArgs {
+    account: Option<String>,
+    // RFC 822 message encoded as base64.
+    message: String,
+}
+
+fn generate_token() -> String {
+    let mut token = [0u8; TOKEN_LENGTH];
+    OsRng.fill_bytes(&mut token);
+    token.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn write_info_file(port: u16, token: &str) -> Result<(), String> {
+    let path = utils::build_runtime_path(consts::IPC_INFO_FILE_NAME);
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| format!("Failed to create {}: {}", parent.display(), err))?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(&path)
+        .and_then(|mut file| write!(file, "port={}\ntoken={}\n", port, token))
+        .map_err(|err| format!("Failed to write {}: {}", path, err))
+}
+
+pub fn remove_info_file() -> Result<(), String> {
+    fs::remove_file(utils::build_runtime_path(consts::IPC_INFO_FILE_NAME))
+        .map_err(|err| format!("Failed to remove IPC info file: {}", err))
+}
+
+// Compares in constant time, so the token can't be guessed byte by byte
+// from the response times.
+fn is_valid_token(token: &str, expected: &str) -> bool {
+    token.len() == expected.len()
+        && token
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn parse_args<T: serde::de::DeserializeOwned>(args: Value) -> Result<T, String> {
+    serde_json::from_value(args).map_err(|err| format!("Invalid arguments: {}", err))
+}
+
+// Also used by the attach links, see deep_link.rs
+pub fn compose(app: &AppHandle, args: ComposeArgs) -> Result<Option<Value>, String> {
+    if let Some(window) = app.get_webview_window("main") {
+        window.show().ok();
+        window.set_focus().ok();
+    }
+    app.state::<ComposeAttachments>().expect(&args);
+    app.emit(CLI_COMPOSE_EVENT, args)
+        .map_err(|err| format!("Failed to open composer: {}", err))?;
+    Ok(None)
+}
+
+fn unread(app: &AppHandle, args: UnreadArgs) -> Result<Option<Value>, String> {
+    let accounts = match args.account {
+        Some(account) => vec![account],
+        None => scheduler::get_connected_accounts()?,
+    };
+    let cache = app.state::<Arc<Cache>>();
+    let unread: serde_json::Map<String, Value> = accounts
+        .into_iter()
+        .map(|account| {
+            let count = mail_counts::count(&cache, &account).unread;
+            (account, json!(count))
+        })
+        .collect();
+    Ok(Some(Value::Object(unread)))
+}
+
+// Queues a prebuilt message, see `Outbox::queue_message_file`.
+fn send(app: &AppHandle, args: SendArgs) -> Result<Option<Value>, String> {
+    let raw = BASE64
+        .decode(&args.message)
+        .map_err(|err| format!("Invalid message: {}", err))?;
+    let path = utils::build_temp_path(&format!("{}.eml", utils::generate_random_id()))?;
+    fs::write(&path, raw).map_err(|err| format!("Failed to write message: {}", err))?;
+    let id = app
+        .state::<Arc<Outbox>>()
+        .queue_message_file(&path.to_string_lossy(), args.account.as_deref());
+    fs::remove_file(&path).ok();
+    Ok(Some(json!({ "id": id? })))
+}
+
+fn handle(app: &AppHandle, command: &str, args: Value) -> Result<Option<Value>, String> {
+    match command {
+        "compose" => compose(app, parse_args(args)?),
+        "unread" => unread(app, parse_args(args)?),
+        "send" => send(app, parse_args(args)?),
+        "sync" => {
+            app.state::<Arc<SyncScheduler>>().request();
+            Ok(None)
+        }
+        _ => Err(format!("Unknown command: {}", command)),
+    }
+}
+
+fn handle_connection(app: &AppHandle, token: &str, stream: TcpStream) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(READ_TIMEOUT_SEC)))
+        .map_err(|err| format!("Failed to set timeout: {}", err))?;
+    let mut line = String::new();
+    BufReader::new((&stream).take(MAX_REQUEST_BYTES))
+        .read_line(&mut line)
+        .map_err(|err| format!("Failed to read request: {}", err))?;
+    // Connections that send nothing, like the liveness check of openmail-cli.
+    if line.is_empty() {
+        return Ok(());
+    }
+    if line.len() as u64 >= MAX_REQUEST_BYTES && !line.ends_with('\n') {
+        return Err("Request is too large".to_string());
+    }
+
+    let response = match serde_json::from_str::<IpcRequest>(&line) {
+        Ok(request) if !is_valid_token(&request.token, token) => IpcResponse {
+            success: false,
+            message: "Invalid token".to_string(),
+            data: None,
+        },
+        Ok(request) => match handle(app, &request.command, request.args) {
+            Ok(data) => IpcResponse {
+                success: true,
+                message: format!("{} done", request.command),
+                data,
+            },
+            Err(err) => IpcResponse {
+                success: false,
+                message: err,
+                data: None,
+            },
+        },
+        Err(err) => IpcResponse {
+            success: false,
+            message: format!("Invalid request: {}", err),
+            data: None,
+        },
+    };
+
+    let mut response = serde_json::to_string(&response)
+        .map_err(|err| format!("Failed to serialize response: {}", err))?;
+    response.push('\n');
+    (&stream)
+        .write_all(response.as_bytes())
+        .map_err(|err| format!("Failed to write response: {}", err))
+}
+
+pub fn start_server(app: AppHandle) -> Result<(), String> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .map_err(|err| format!("Failed to start IPC server: {}", err))?;
+    let port = listener
+        .local_addr()
+        .map_err(|err| format!("Failed to get IPC server address: {}", err))?
+        .port();
+    let token = generate_token();
+    write_info_file(port, &token)?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let app = app.clone();
+            let token = token.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle_connection(&app, &token, stream) {
+                    println!("IPC request failed: {}", err);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn read_compose_attachment(
+    attachments: State<'_, ComposeAttachments>,
+    path: String,
+) -> Result<Response, String> {
+    if !attachments.approved.lock().unwrap().remove(&path) {
+        return Err(format!("Not an attachment of the composer: {}", path));
+    }
+    fs::read(&path)
+        .map(Response::new)
+        .map_err(|err| format!("Failed to read {}: {}", path, err))
+}
+
+// Attachments of a link that the user confirmed, the others are never read.
+#[tauri::command]
+pub fn confirm_compose_attachments(attachments: State<'_, ComposeAttachments>, paths: Vec<String>) {
+    let mut unconfirmed = attachments.unconfirmed.lock().unwrap();
+    let mut approved = attachments.approved.lock().unwrap();
+    for path in paths {
+        if unconfirmed.remove(&path) {
+            approved.insert(path);
+        }
+    }
+}
@@ -0,0 +1,352 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tauri::State;
+
+use crate::api;
+use crate::cache::{Cache, CachedAttachment, CachedEmail};
+use crate::consts;
+use crate::events::EventBus;
+use crate::scheduler;
+use crate::search::SearchIndex;
+use crate::utils;
+use crate::worker;
+
+// "On this computer" folders, they never exist on the server. Emails that
+// are archived to them are stored as .eml files next to the cache and
+// deleted from the server, their envelopes are kept in the cache like the
+// synced ones (with a local id as uid).
+const FORBIDDEN_NAME_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+// Date format of the "From " separator lines of mbox files.
+const MBOX_DATE_FORMAT: &str = "%a %b %e %H:%M:%S %Y";
+
+#[derive(Debug, Serialize)]
+pub struct ArchiveResult {
+    pub archived: usize,
+    // Set when the emails are archived but are still on the server, they
+    // are not archived twice when they are archived again.
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LocalSearchResult {
+    pub folder: String,
+    pub email: CachedEmail,
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.trim().is_empty() || name.starts_with('.') || name.contains(FORBIDDEN_NAME_CHARS) {
+        return Err(format!("Invalid local folder name: {}", name));
+    }
+    Ok(())
+}
+
+fn folder_dir(account: &str, folder: &str) -> PathBuf {
+    PathBuf::from(utils::build_home_path(&format!(
+        "{}/{}/{}",
+        consts::LOCAL_FOLDERS_DIR_PATH,
+        account,
+        folder
+    )))
+}
+
+pub fn email_file(account: &str, folder: &str, uid: &str) -> PathBuf {
+    folder_dir(account, folder).join(format!("{}.eml", uid))
+}
+
+pub fn fetch_source(account: &str, folder: &str, uid: &str) -> Result<Vec<u8>, String> {
+    let response = api::get(
+        &format!("/get-email-source/{}/{}", account, uid),
+        &[("folder", folder)],
+    )?;
+    let source = api::get_account_data(response, account)?;
+    let source = source
+        .as_str()
+        .ok_or(format!("Email {} not found in {}", uid, folder))?;
+    BASE64
+        .decode(source)
+        .map_err(|err| format!("Failed to decode source of {}: {}", uid, err))
+}
+
+// Stores the email and returns its envelope, the source is parsed in the
+// parser worker like any other untrusted content.
+fn store_email(
+    account: &str,
+    local_folder: &str,
+    source: &[u8],
+    flags: Vec<String>,
+) -> Result<CachedEmail, String> {
+    let uid = utils::generate_random_id();
+    let path = email_file(account, local_folder, &uid);
+    fs::write(&path, source)
+        .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+
+    let parsed = match worker::parse_message_file(&path.to_string_lossy()) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            fs::remove_file(&path).ok();
+            return Err(err);
+        }
+    };
+    Ok(CachedEmail {
+        message_id: parsed.message_id,
+        uid,
+        sender: parsed.sender,
+        receivers: parsed.receivers,
+        date: parsed.date,
+        subject: parsed.subject,
+        body: parsed.body,
+        cc: parsed.cc,
+        bcc: parsed.bcc,
+        flags,
+        attachments: parsed
+            .attachments
+            .into_iter()
+            .map(|attachment| CachedAttachment {
+                name: attachment.name,
+                mime_type: attachment.mime_type,
+                cid: attachment.cid,
+            })
+            .collect(),
+        in_reply_to: parsed.in_reply_to,
+        references: parsed.references,
+    })
+}
+
+pub fn create(cache: &Cache, account: &str, folder: &str) -> Result<(), String> {
+    validate_name(folder)?;
+    let dir = folder_dir(account, folder);
+    fs::create_dir_all(&dir)
+        .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+    cache.create_local_folder(account, folder)
+}
+
+pub fn delete(cache: &Cache, account: &str, folder: &str) -> Result<(), String> {
+    cache.delete_local_folder(account, folder)?;
+    let dir = folder_dir(account, folder);
+    if dir.exists() {
+        fs::remove_dir_all(&dir)
+            .map_err(|err| format!("Failed to remove {}: {}", dir.display(), err))?;
+    }
+    Ok(())
+}
+
+// Moves the emails from a server folder to a local folder. Emails are only
+// deleted from the server once they are stored locally, the ones that are
+// already in the local folder (by their Message-ID) are only deleted.
+pub fn archive(
+    cache: &Cache,
+    events: &EventBus,
+    account: &str,
+    folder: &str,
+    uids: &[String],
+    local_folder: &str,
+) -> Result<ArchiveResult, String> {
+    if !cache
+        .get_local_folders(account)
+        .iter()
+        .any(|f| f == local_folder)
+    {
+        return Err(format!("Local folder not found: {}", local_folder));
+    }
+
+    let cached = cache.get_emails(account, folder);
+    let stored: HashSet<String> = cache
+        .get_local_emails(account, local_folder)
+        .into_iter()
+        .map(|email| email.message_id)
+        .filter(|message_id| !message_id.is_empty())
+        .collect();
+    let mut archived = Vec::new();
+    let mut errors = Vec::new();
+    for uid in uids {
+        let email = cached.iter().find(|email| &email.uid == uid);
+        if email.is_some_and(|email| stored.contains(&email.message_id)) {
+            archived.push(uid.clone());
+            continue;
+        }
+
+        let flags = email.map(|email| email.flags.clone()).unwrap_or_default();
+        let result = fetch_source(account, folder, uid)
+            .and_then(|source| store_email(account, local_folder, &source, flags))
+            .and_then(|email| cache.add_local_email(account, local_folder, email));
+        match result {
+            Ok(()) => archived.push(uid.clone()),
+            Err(err) => errors.push(format!("{}: {}", uid, err)),
+        }
+    }
+
+    let mut warning = None;
+    if !archived.is_empty() {
+        let deleted = api::post_json(
+            "/delete-email",
+            json!({
+                "account": account,
+                "folder": folder,
+                "sequence_set": archived.join(","),
+            }),
+        )
+        .and_then(|response| {
+            if response.success {
+                Ok(())
+            } else {
+                Err(response.message)
+            }
+        });
+        match deleted {
+            Ok(()) => {
+                let changes = cache.remove_emails(account, folder, &archived)?;
+                scheduler::publish_changes(events, account, folder, &changes);
+            }
+            Err(err) => {
+                warning = Some(format!(
+                    "Emails are archived but could not be deleted from the server: {}",
+                    err
+                ))
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(format!(
+            "{} of {} emails could not be archived: {}",
+            errors.len(),
+            uids.len(),
+            errors.join(", ")
+        ));
+    }
+    Ok(ArchiveResult {
+        archived: archived.len(),
+        warning,
+    })
+}
+
+// Writes the folder as mboxrd, oldest first. Returns the number of emails.
+pub fn export_mbox(
+    cache: &Cache,
+    account: &str,
+    folder: &str,
+    path: &str,
+) -> Result<usize, String> {
+    let emails = cache.get_local_emails(account, folder);
+    let file = File::create(path).map_err(|err| format!("Failed to create {}: {}", path, err))?;
+    let mut writer = BufWriter::new(file);
+    let write_err = |err: std::io::Error| format!("Failed to write {}: {}", path, err);
+
+    for email in emails.iter().rev() {
+        let source_path = email_file(account, folder, &email.uid);
+        let source = fs::read(&source_path)
+            .map_err(|err| format!("Failed to read {}: {}", source_path.display(), err))?;
+
+        let sender = utils::extract_email_address(&email.sender);
+        let date = utils::parse_email_date(&email.date)
+            .map(|date| date.format(MBOX_DATE_FORMAT).to_string())
+            .unwrap_or_else(|| Utc::now().format(MBOX_DATE_FORMAT).to_string());
+        writeln!(
+            writer,
+            "From {} {}",
+            if sender.is_empty() {
+                "MAILER-DAEMON"
+            } else {
+                &sender
+            },
+            date
+        )
+        .map_err(write_err)?;
+
+        let source = source.strip_suffix(b"\n").unwrap_or(&source);
+        for line in source.split(|byte| *byte == b'\n') {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            // Lines that would look like a separator are quoted.
+            let quotes = line.iter().take_while(|byte| **byte == b'>').count();
+            if line[quotes..].starts_with(b"From ") {
+                writer.write_all(b">").map_err(write_err)?;
+            }
+            writer.write_all(line).map_err(write_err)?;
+            writer.write_all(b"\n").map_err(write_err)?;
+        }
+        writer.write_all(b"\n").map_err(write_err)?;
+    }
+
+    writer.flush().map_err(write_err)?;
+    Ok(emails.len())
+}
+
+#[tauri::command]
+pub fn get_local_folders(cache: State<'_, Arc<Cache>>, account: String) -> Vec<String> {
+    cache.get_local_folders(&account)
+}
+
+#[tauri::command]
+pub fn get_local_folder_emails(
+    cache: State<'_, Arc<Cache>>,
+    account: String,
+    folder: String,
+) -> Vec<CachedEmail> {
+    cache.get_local_emails(&account, &folder)
+}
+
+#[tauri::command]
+pub fn create_local_folder(
+    cache: State<'_, Arc<Cache>>,
+    account: String,
+    folder: String,
+) -> Result<(), String> {
+    create(&cache, &account, &folder)
+}
+
+#[tauri::command]
+pub fn delete_local_folder(
+    cache: State<'_, Arc<Cache>>,
+    account: String,
+    folder: String,
+) -> Result<(), String> {
+    delete(&cache, &account, &folder)
+}
+
+#[tauri::command]
+pub async fn archive_to_local_folder(
+    cache: State<'_, Arc<Cache>>,
+    events: State<'_, Arc<EventBus>>,
+    account: String,
+    folder: String,
+    uids: Vec<String>,
+    local_folder: String,
+) -> Result<ArchiveResult, String> {
+    archive(&cache, &events, &account, &folder, &uids, &local_folder)
+}
+
+#[tauri::command]
+pub async fn export_local_folder_mbox(
+    cache: State<'_, Arc<Cache>>,
+    account: String,
+    folder: String,
+    path: String,
+) -> Result<usize, String> {
+    export_mbox(&cache, &account, &folder, &path)
+}
+
+// Same as `search_cached_emails` but only in the local folders.
+#[tauri::command]
+pub fn search_local_folders(
+    index: State<'_, Arc<SearchIndex>>,
+    account: String,
+    query: String,
+) -> Result<Vec<LocalSearchResult>, String> {
+    Ok(index
+        .search(&account, &query)?
+        .into_iter()
+        .filter(|result| result.local)
+        .map(|result| LocalSearchResult {
+            folder: result.folder,
+            email: result.email,
+        })
+        .collect())
+}
@@ -0,0 +1,181 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Listener, State};
+
+use crate::cache::Cache;
+use crate::consts;
+use crate::scheduler;
+use crate::utils;
+
+// Unread and flagged counts of the inboxes. They can be exported (opt-in)
+// to STATUS_FILE_PATH, so status bars like Polybar, Rainmeter or Übersicht
+// can show them by reading a file. The file is rewritten after every sync
+// and every REFRESH_INTERVAL_SEC for the local changes (when a count
+// changed), it is replaced atomically so it is
+// never read half written.
+const REFRESH_INTERVAL_SEC: u64 = 30;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct MailCounts {
+    pub unread: usize,
+    pub flagged: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+struct StatusFile {
+    updated_at: i64,
+    unread: usize,
+    flagged: usize,
+    accounts: BTreeMap<String, MailCounts>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatusExportSettings {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusExport {
+    pub enabled: bool,
+    pub path: String,
+}
+
+pub fn count(cache: &Cache, account: &str) -> MailCounts {
+    let inbox = cache.get_emails(account, "Inbox");
+    MailCounts {
+        unread: inbox.iter().filter(|email| !email.is_seen()).count(),
+        flagged: inbox.iter().filter(|email| email.is_flagged()).count(),
+    }
+}
+
+pub struct StatusExporter {
+    cache: Arc<Cache>,
+    settings: Mutex<StatusExportSettings>,
+    // Connected accounts as of the last sync.
+    accounts: Mutex<Option<Vec<String>>>,
+    last_written: Mutex<Option<StatusFile>>,
+    wakeup: Condvar,
+}
+
+impl StatusExporter {
+    pub fn load(cache: Arc<Cache>) -> Self {
+        StatusExporter {
+            cache,
+            settings: Mutex::new(
+                utils::read_json_file(consts::STATUS_EXPORT_SETTINGS_FILE_PATH).unwrap_or_default(),
+            ),
+            accounts: Mutex::new(None),
+            last_written: Mutex::new(None),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    pub fn get(&self) -> StatusExport {
+        StatusExport {
+            enabled: self.settings.lock().unwrap().enabled,
+            path: utils::build_home_path(consts::STATUS_FILE_PATH),
+        }
+    }
+
+    pub fn set_enabled(&self, enabled: bool) -> Result<StatusExport, String> {
+        let settings = StatusExportSettings { enabled };
+        utils::write_json_file(consts::STATUS_EXPORT_SETTINGS_FILE_PATH, &settings)?;
+        *self.settings.lock().unwrap() = settings;
+        *self.last_written.lock().unwrap() = None;
+        if !enabled {
+            fs::remove_file(utils::build_home_path(consts::STATUS_FILE_PATH)).ok();
+        }
+        self.wakeup.notify_all();
+        Ok(self.get())
+    }
+
+    fn refresh(&self) -> Result<(), String> {
+        if !self.settings.lock().unwrap().enabled {
+            return Ok(());
+        }
+        let known_accounts = self.accounts.lock().unwrap().clone();
+        let accounts = match known_accounts {
+            Some(accounts) => accounts,
+            None => {
+                let accounts = scheduler::get_connected_accounts()?;
+                *self.accounts.lock().unwrap() = Some(accounts.clone());
+                accounts
+            }
+        };
+
+        let accounts: BTreeMap<String, MailCounts> = accounts
+            .into_iter()
+            .map(|account| {
+                let counts = count(&self.cache, &account);
+                (account, counts)
+            })
+            .collect();
+        let status = StatusFile {
+            updated_at: 0,
+            unread: accounts.values().map(|counts| counts.unread).sum(),
+            flagged: accounts.values().map(|counts| counts.flagged).sum(),
+            accounts,
+        };
+        let mut last_written = self.last_written.lock().unwrap();
+        if last_written.as_ref() == Some(&status) {
+            return Ok(());
+        }
+
+        let path = utils::build_home_path(consts::STATUS_FILE_PATH);
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("Failed to create {}: {}", parent.display(), err))?;
+        }
+        let temp_path = format!("{}.tmp", path);
+        let mut written = status.clone();
+        written.updated_at = Utc::now().timestamp();
+        let content = serde_json::to_string_pretty(&written)
+            .map_err(|err| format!("Failed to serialize status: {}", err))?;
+        fs::write(&temp_path, content)
+            .and_then(|_| fs::rename(&temp_path, &path))
+            .map_err(|err| format!("Failed to write {}: {}", path, err))?;
+        *last_written = Some(status);
+        Ok(())
+    }
+
+    fn wait(&self, interval: Duration) {
+        let settings = self.settings.lock().unwrap();
+        let _ = self.wakeup.wait_timeout(settings, interval).unwrap();
+    }
+}
+
+pub fn listen_sync(app: &AppHandle, exporter: Arc<StatusExporter>) {
+    app.listen(scheduler::SYNC_FINISHED_EVENT, move |_| {
+        // Accounts may have been added or removed since.
+        *exporter.accounts.lock().unwrap() = None;
+        exporter.wakeup.notify_all();
+    });
+}
+
+pub fn start_worker(exporter: Arc<StatusExporter>) {
+    thread::spawn(move || loop {
+        if let Err(err) = exporter.refresh() {
+            println!("Failed to export mail counts: {}", err);
+        }
+        exporter.wait(Duration::from_secs(REFRESH_INTERVAL_SEC));
+    });
+}
+
+#[tauri::command]
+pub fn get_status_export(exporter: State<'_, Arc<StatusExporter>>) -> StatusExport {
+    exporter.get()
+}
+
+#[tauri::command]
+pub fn set_status_export(
+    exporter: State<'_, Arc<StatusExporter>>,
+    enabled: bool,
+) -> Result<StatusExport, String> {
+    exporter.set_enabled(enabled)
+}
@@ -1,8 +1,58 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+mod account_schedule;
+mod account_transfer;
+mod api;
+mod archive;
+mod audit;
+mod batch_delivery;
+mod benchmarks;
+mod cache;
+mod calendar;
+mod campaign;
+mod clipper;
 mod consts;
+mod context_menu;
+mod deep_link;
+mod downloads;
+mod drop_folder;
+mod events;
+mod fault_injection;
+mod feedback;
+mod feeds;
+mod health;
+mod idle;
+mod image_privacy;
+mod integrity;
+mod ipc;
+mod local_folder;
+mod mail_counts;
+mod mime;
+mod news;
+mod notifications;
+mod offline;
+mod outbox;
+mod pins;
+mod recipient_time;
+mod sandbox;
+mod scheduler;
+mod search;
+mod search_query;
+mod search_stubs;
+mod session;
+mod settings_sync;
+mod share;
+mod shared_mailbox;
+mod stats;
+mod template;
+mod thread_export;
+mod thread_subscriptions;
+mod threads;
+mod throttle;
+mod tray;
 mod utils;
+mod worker;
 
 use chrono::Local;
 use std::env;
@@ -10,30 +60,38 @@ use std::fs;
 use std::fs::OpenOptions;
 use std::io::Write;
 use std::process::Command;
+use std::sync::Arc;
 use tauri::{Manager, RunEvent};
 
+// Same as `RUNTIME_DIR_ENV` of the python server.
+const RUNTIME_DIR_ENV: &str = "OPENMAIL_RUNTIME_DIR";
+
 struct ServerInfo {
     url: String,
     pid: u32,
 }
 
 fn start_uvicorn() -> Result<(), String> {
+    let mut command;
     if consts::IS_WINDOWS {
-        Command::new("cmd")
-            .current_dir("src/script")
-            .arg("/C")
-            .arg(consts::UVICORN_START_SCRIPT_PATH)
-            .spawn()
-            .map_err(|err| format!("Failed to start Python server: {}", err))?;
+        command = Command::new("cmd");
+        command.arg("/C");
     } else {
-        Command::new("sh")
-            .current_dir("src/script")
-            .arg("-c")
-            .arg(consts::UVICORN_START_SCRIPT_PATH)
-            .spawn()
-            .map_err(|err| format!("Failed to start Python server: {}", err))?;
+        command = Command::new("sh");
+        command.arg("-c");
     }
-    Ok(())
+    // The server writes its info file to the runtime directory of the
+    // session and its temp files next to the ones of the app, so the
+    // servers of other users and sessions are not mixed up with it.
+    let temp_dir = utils::build_temp_dir()?;
+    command
+        .current_dir("src/script")
+        .arg(consts::UVICORN_START_SCRIPT_PATH)
+        .env(RUNTIME_DIR_ENV, utils::build_runtime_path(""))
+        .env("TMPDIR", &temp_dir)
+        .env("TEMP", &temp_dir)
+        .env("TMP", &temp_dir);
+    sandbox::spawn(command)
 }
 
 fn kill_uvicorn(pid: u32) -> Result<(), String> {
@@ -85,18 +143,29 @@ fn add_close_log(pid: &str) -> Result<(), String> {
 }
 
 fn read_uvicorn_info_file() -> Result<ServerInfo, String> {
-    let uvicorn_info = fs::read_to_string(utils::build_home_path(consts::UVICORN_INFO_FILE_PATH))
-        .map_err(|err| format!("Failed to read PID file: {}", err))?;
-    let uvicorn_info: Vec<&str> = uvicorn_info.split('\n').collect();
-    let url = uvicorn_info[0].split('=').collect::<Vec<&str>>()[1].to_string();
-    let pid = uvicorn_info[1].split('=').collect::<Vec<&str>>()[1]
+    let uvicorn_info =
+        fs::read_to_string(utils::build_runtime_path(consts::UVICORN_INFO_FILE_NAME))
+            .map_err(|err| format!("Failed to read PID file: {}", err))?;
+    // The server creates the file empty before writing the actual values,
+    // so missing lines mean the server is not ready yet rather than a panic.
+    let mut uvicorn_info = uvicorn_info
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(_, value)| value);
+    let url = uvicorn_info
+        .next()
+        .ok_or("Server URL not found in INFO file")?
+        .to_string();
+    let pid = uvicorn_info
+        .next()
+        .ok_or("PID not found in INFO file")?
         .parse::<u32>()
         .map_err(|err| format!("Invalid PID: {}", err))?;
     Ok(ServerInfo { url, pid })
 }
 
 fn remove_uvicorn_info_file() -> Result<(), String> {
-    fs::remove_file(utils::build_home_path(consts::UVICORN_INFO_FILE_PATH))
+    fs::remove_file(utils::build_runtime_path(consts::UVICORN_INFO_FILE_NAME))
         .map_err(|err| format!("Failed to remove INFO file: {}", err))
 }
 
@@ -106,40 +175,298 @@ fn get_server_url() -> String {
 }
 
 fn main() {
+    if env::args().any(|arg| arg == worker::WORKER_ARG) {
+        worker::run_worker();
+        return;
+    }
+
+    let mut context = tauri::generate_context!();
+    let safe_mode = match integrity::verify_assets(&context) {
+        Ok(()) => false,
+        Err(err) => {
+            println!("Starting in safe mode: {}", err);
+            integrity::enter_safe_mode(&mut context);
+            true
+        }
+    };
+
     let mut builder = tauri::Builder::default();
 
     #[cfg(desktop)]
     {
-        builder = builder.plugin(tauri_plugin_single_instance::init(|app, _args, _cwd| {
+        builder = builder.plugin(tauri_plugin_single_instance::init(|app, args, _cwd| {
             if let Some(window) = app.get_webview_window("main") {
                 window.set_focus().ok();
             } else {
                 println!("Main window not found");
             }
+            deep_link::open_args(app, &args);
         }));
     }
 
     builder
         .plugin(tauri_plugin_autostart::Builder::new().build())
+        .plugin(tauri_plugin_deep_link::init())
         .plugin(tauri_plugin_os::init())
         .plugin(tauri_plugin_process::init())
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_store::Builder::new().build())
-        .invoke_handler(tauri::generate_handler![get_server_url])
-        .build(tauri::generate_context!())
+        .setup(move |app| {
+            if safe_mode {
+                // The safe mode page has no title bar of its own.
+                if let Some(window) = app.get_webview_window("main") {
+                    window.set_decorations(true).ok();
+                }
+                return Ok(());
+            }
+            app.manage(ipc::ComposeAttachments::default());
+            deep_link::listen(app.handle());
+            if let Err(err) = tray::create(app.handle()) {
+                println!("Failed to create tray icon: {}", err);
+            }
+            let account_schedules = Arc::new(account_schedule::AccountSchedules::load());
+            let notification_history = Arc::new(notifications::NotificationHistory::load());
+            let shared_mailboxes = Arc::new(shared_mailbox::SharedMailboxes::load());
+            let outbox = Arc::new(outbox::Outbox::load(shared_mailboxes.clone()));
+            outbox::start_worker(app.handle().clone(), outbox.clone());
+            let campaigns = Arc::new(campaign::Campaigns::load(outbox.clone()));
+            let drop_folder = Arc::new(drop_folder::DropFolder::load(outbox.clone()));
+            drop_folder::listen_outbox(app.handle(), drop_folder.clone());
+            drop_folder::start_worker(drop_folder.clone());
+            campaign::listen_outbox(app.handle(), campaigns.clone());
+            let events = Arc::new(events::EventBus::default());
+            events::start_worker(app.handle().clone(), events.clone());
+            let cache = Arc::new(cache::Cache::default());
+            let batch_delivery = Arc::new(batch_delivery::BatchDelivery::load(
+                cache.clone(),
+                events.clone(),
+            ));
+            batch_delivery::start_worker(app.handle().clone(), batch_delivery.clone());
+            let downloads = Arc::new(downloads::Downloads::load(cache.clone()));
+            downloads::start_worker(app.handle().clone(), downloads.clone());
+            let scheduler = Arc::new(scheduler::SyncScheduler::new(
+                cache.clone(),
+                events.clone(),
+                downloads.clone(),
+                account_schedules.clone(),
+            ));
+            scheduler::start_worker(app.handle().clone(), scheduler.clone());
+            account_schedule::start_worker(
+                app.handle().clone(),
+                account_schedules.clone(),
+                scheduler.clone(),
+            );
+            shared_mailbox::start_worker(
+                app.handle().clone(),
+                shared_mailboxes.clone(),
+                scheduler.clone(),
+                cache.clone(),
+                notification_history.clone(),
+                account_schedules.clone(),
+            );
+            let feeds = Arc::new(feeds::Feeds::load(cache.clone(), events.clone()));
+            feeds::start_worker(
+                app.handle().clone(),
+                feeds.clone(),
+                notification_history.clone(),
+            );
+            let news = Arc::new(news::News::load(cache.clone(), events.clone()));
+            news::start_worker(news.clone());
+            let share = Arc::new(share::Share::load(cache.clone()));
+            let calendar = Arc::new(calendar::Calendar::load());
+            let recipient_time = Arc::new(recipient_time::RecipientTime::load(cache.clone()));
+            let pins = Arc::new(pins::Pins::load(cache.clone()));
+            tray::set_pinned(app.handle(), &pins.get_pinned());
+            pins::listen_sync(app.handle(), pins.clone());
+            let health = Arc::new(health::Health::default());
+            let benchmarks = Arc::new(benchmarks::Benchmarks::default());
+            health::check_on_launch(app.handle().clone(), health.clone());
+            let thread_subscriptions = Arc::new(thread_subscriptions::ThreadSubscriptions::load(
+                cache.clone(),
+            ));
+            thread_subscriptions::listen_sync(app.handle(), thread_subscriptions.clone());
+            let offline = Arc::new(offline::OfflineChanges::load(cache.clone(), events.clone()));
+            offline::start_worker(app.handle().clone(), offline.clone());
+            offline::listen_sync(app.handle(), offline.clone());
+            let settings_sync = Arc::new(settings_sync::SettingsSync::load());
+            settings_sync::listen_sync(app.handle(), settings_sync.clone());
+            outbox::listen_settings_sync(app.handle(), outbox.clone());
+            downloads::listen_settings_sync(app.handle(), downloads.clone());
+            let status_exporter = Arc::new(mail_counts::StatusExporter::load(cache.clone()));
+            mail_counts::listen_sync(app.handle(), status_exporter.clone());
+            mail_counts::start_worker(status_exporter.clone());
+            let search_index = Arc::new(search::SearchIndex::load(cache.clone()));
+            let clipper = Arc::new(clipper::Clipper::load(cache.clone()));
+            let search_stubs = Arc::new(search_stubs::SearchStubs::load(cache.clone()));
+            search_stubs::listen_sync(app.handle(), search_stubs.clone());
+            search::listen_sync(app.handle(), search_index.clone());
+            idle::start_worker(scheduler.clone(), account_schedules.clone());
+            app.manage(outbox);
+            app.manage(campaigns);
+            app.manage(events);
+            app.manage(cache);
+            app.manage(scheduler);
+            app.manage(offline);
+            app.manage(shared_mailboxes);
+            app.manage(settings_sync);
+            app.manage(notification_history);
+            app.manage(downloads);
+            app.manage(account_schedules);
+            app.manage(status_exporter);
+            app.manage(search_index);
+            app.manage(search_stubs);
+            app.manage(drop_folder);
+            app.manage(clipper);
+            app.manage(feeds);
+            app.manage(news);
+            app.manage(share);
+            app.manage(batch_delivery);
+            app.manage(thread_subscriptions);
+            app.manage(calendar);
+            app.manage(recipient_time);
+            app.manage(pins);
+            app.manage(health);
+            app.manage(benchmarks);
+            if let Err(err) = ipc::start_server(app.handle().clone()) {
+                println!("{}", err);
+            }
+            Ok(())
+        })
+        .invoke_handler(tauri::generate_handler![
+            get_server_url,
+            outbox::queue_email,
+            outbox::get_outbox,
+            outbox::remove_outbox_item,
+            outbox::retry_outbox_item,
+            outbox::get_send_limits,
+            outbox::set_send_limits,
+            campaign::create_campaign,
+            campaign::get_campaigns,
+            campaign::get_campaign_progress,
+            campaign::start_campaign,
+            campaign::pause_campaign,
+            campaign::cancel_campaign,
+            scheduler::sync_now,
+            stats::get_reply_time_stats,
+            stats::get_inbox_progress,
+            settings_sync::enable_settings_sync,
+            settings_sync::disable_settings_sync,
+            settings_sync::sync_settings_now,
+            settings_sync::get_settings_sync_status,
+            account_transfer::export_account_qr,
+            account_transfer::import_account_qr,
+            worker::parse_eml_file,
+            worker::extract_pdf_text,
+            worker::extract_image_text,
+            events::ack_event_batch,
+            offline::queue_flag_change,
+            offline::get_offline_changes,
+            offline::resolve_offline_conflict,
+            offline::get_conflict_policy,
+            offline::set_conflict_policy,
+            audit::get_audit_log,
+            shared_mailbox::get_shared_mailboxes,
+            shared_mailbox::set_shared_mailbox,
+            shared_mailbox::remove_shared_mailbox,
+            local_folder::get_local_folders,
+            local_folder::get_local_folder_emails,
+            local_folder::create_local_folder,
+            local_folder::delete_local_folder,
+            local_folder::archive_to_local_folder,
+            local_folder::export_local_folder_mbox,
+            local_folder::search_local_folders,
+            feedback::get_diagnostics,
+            feedback::send_feedback,
+            notifications::record_notification,
+            notifications::hold_notification,
+            notifications::get_notification_history,
+            notifications::mark_notifications_read,
+            notifications::clear_notification_history,
+            downloads::get_download_rules,
+            downloads::set_download_rules,
+            downloads::download_attachments,
+            downloads::read_downloaded_attachment,
+            image_privacy::stage_image,
+            archive::is_encrypted_attachment,
+            archive::extract_encrypted_attachment,
+            archive::remove_extracted_attachment,
+            account_schedule::get_account_schedules,
+            account_schedule::set_account_schedules,
+            account_schedule::get_paused_accounts,
+            mail_counts::get_status_export,
+            mail_counts::set_status_export,
+            search::search_cached_emails,
+            search::get_search_settings,
+            search::set_search_language,
+            search::get_email_language,
+            search_query::parse_search_query,
+            search_stubs::get_search_stubs,
+            search_stubs::set_search_stubs,
+            deep_link::take_opened_link,
+            ipc::read_compose_attachment,
+            ipc::confirm_compose_attachments,
+            drop_folder::get_drop_folder,
+            drop_folder::set_drop_folder,
+            context_menu::get_context_menu,
+            context_menu::set_context_menu,
+            threads::get_thread_id,
+            thread_export::export_thread_markdown,
+            clipper::get_clip_settings,
+            clipper::set_clip_settings,
+            clipper::clip_to_notes,
+            feeds::get_feeds,
+            feeds::set_feed,
+            feeds::remove_feed,
+            feeds::set_feed_entries_seen,
+            news::get_newsgroups,
+            news::set_newsgroup,
+            news::remove_newsgroup,
+            news::set_news_articles_seen,
+            share::get_share_targets,
+            share::set_share_target,
+            share::remove_share_target,
+            share::share_email,
+            share::share_attachment,
+            batch_delivery::get_batch_delivery,
+            batch_delivery::set_batch_delivery,
+            batch_delivery::release_held_mail,
+            thread_subscriptions::get_thread_subscriptions,
+            thread_subscriptions::set_thread_subscriptions_enabled,
+            thread_subscriptions::get_subscribed_threads,
+            thread_subscriptions::mute_thread,
+            calendar::get_calendar_settings,
+            calendar::set_calendar_settings,
+            calendar::suggest_free_slots,
+            recipient_time::get_send_time_settings,
+            recipient_time::set_send_time_settings,
+            recipient_time::suggest_send_time,
+            recipient_time::get_recipient_local_time,
+            recipient_time::get_correspondent_time_zones,
+            pins::get_pinned,
+            pins::pin_email,
+            pins::unpin_email,
+            health::get_account_health,
+            health::check_account_health,
+            fault_injection::get_fault_injection,
+            fault_injection::set_fault_injection,
+            benchmarks::run_benchmarks,
+            benchmarks::ack_benchmark_ping
+        ])
+        .build(context)
         .expect("Error building app")
         .run(move |_app_handle, event| match event {
-            RunEvent::Ready => {
+            RunEvent::Ready if !safe_mode => {
                 start_uvicorn().ok();
             }
             RunEvent::ExitRequested { api, .. } => {
                 api.prevent_exit();
                 if let Ok(info) = read_uvicorn_info_file() {
-                    if let Ok(_) = kill_uvicorn(info.pid) {
+                    if kill_uvicorn(info.pid).is_ok() {
                         remove_uvicorn_info_file().ok();
                     }
                 }
+                ipc::remove_info_file().ok();
                 std::process::exit(0);
             }
             _ => {}
@@ -0,0 +1,475 @@
+use mail_parser::{Address, HeaderValue, MessageParser, MessagePart, MimeHeaders};
+use serde::{Deserialize, Serialize};
+
+// Parsing of raw (RFC 822) messages that didn't come through the python
+// server, like .eml files. Only called from the parser worker, see worker.rs
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParsedAttachment {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub mime_type: String,
+    pub size: usize,
+    pub cid: Option<String>,
+}
+
+// Same fields and formats as the emails of the python server where possible.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ParsedMessage {
+    pub message_id: String,
+    pub sender: String,
+    pub receivers: String,
+    pub cc: Option<String>,
+    pub bcc: Option<String>,
+    pub date: String,
+    pub subject: String,
+    pub body: String,
+    pub html_body: Option<String>,
+    pub in_reply_to: Option<String>,
+    pub references: Option<String>,
+    pub attachments: Vec<ParsedAttachment>,
+}
+
+// winmail.dat attachments of Outlook, which wrap the real attachments in
+// TNEF. Only the attachments are decoded, the body inside the TNEF is a
+// copy of the one in the message.
+const TNEF_SIGNATURE: u32 = 0x223E_9F78;
+const TNEF_LEVEL_ATTACHMENT: u8 = 2;
+const ATT_ATTACH_REND_DATA: u32 = 0x0006_9002;
+const ATT_ATTACH_TITLE: u32 = 0x0001_8010;
+const ATT_ATTACH_DATA: u32 = 0x0006_800F;
+const ATT_ATTACHMENT: u32 = 0x0006_9005;
+const PR_ATTACH_DATA_OBJ: u16 = 0x3701;
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const PR_ATTACH_MIME_TAG: u16 = 0x370E;
+const PT_STRING8: u16 = 0x001E;
+const PT_UNICODE: u16 = 0x001F;
+const PT_BINARY: u16 = 0x0102;
+const MV_FLAG: u16 = 0x1000;
+
+#[derive(Default)]
+struct TnefAttachment {
+    name: String,
+    mime_type: Option<String>,
+    contents: Vec<u8>,
+}
+
+struct TnefReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> TnefReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        TnefReader { data, offset: 0 }
+    }
+
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.offset..self.offset.checked_add(length)?)?;
+        self.offset += length;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_le_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn is_empty(&self) -> bool {
+        self.offset >= self.data.len()
+    }
+}
+
+fn decode_tnef_string(prop_type: u16, value: &[u8]) -> String {
+    let string = if prop_type == PT_UNICODE {
+        let units: Vec<u16> = value
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        String::from_utf8_lossy(value).to_string()
+    };
+    string.trim_end_matches('\0').to_string()
+}
+
+// (type, id, values) of the next MAPI property, None if it can't be read.
+fn read_tnef_prop<'a>(reader: &mut TnefReader<'a>) -> Option<(u16, u16, Vec<&'a [u8]>)> {
+    let prop_type = reader.u16()?;
+    let prop_id = reader.u16()?;
+    // Named properties are followed by their GUID and name.
+    if prop_id >= 0x8000 {
+        reader.take(16)?;
+        if reader.u32()? == 0 {
+            reader.take(4)?;
+        } else {
+            let length = reader.u32()? as usize;
+            reader.take(length.next_multiple_of(4))?;
+        }
+    }
+
+    let base_type = prop_type & !MV_FLAG;
+    let fixed_size = match base_type {
+        0x0002 | 0x0003 | 0x0004 | 0x000A | 0x000B => Some(4),
+        0x0005 | 0x0006 | 0x0007 | 0x0014 | 0x0040 => Some(8),
+        0x0048 => Some(16),
+        PT_STRING8 | PT_UNICODE | PT_BINARY | 0x000D => None,
+        _ => return None,
+    };
+    let count = if prop_type & MV_FLAG != 0 || fixed_size.is_none() {
+        reader.u32()? as usize
+    } else {
+        1
+    };
+    let mut values = Vec::new();
+    for _ in 0..count {
+        match fixed_size {
+            Some(size) => values.push(reader.take(size)?),
+            None => {
+                let length = reader.u32()? as usize;
+                values.push(&reader.take(length.next_multiple_of(4))?[..length]);
+            }
+        }
+    }
+    Some((base_type, prop_id, values))
+}
+
+fn read_tnef_attachment_props(props: &[u8], attachment: &mut TnefAttachment) {
+    let mut reader = TnefReader::new(props);
+    let Some(count) = reader.u32() else {
+        return;
+    };
+    for _ in 0..count {
+        let Some((prop_type, prop_id, values)) = read_tnef_prop(&mut reader) else {
+            return;
+        };
+        let Some(value) = values.first() else {
+            continue;
+        };
+        match (prop_id, prop_type) {
+            (PR_ATTACH_LONG_FILENAME, PT_STRING8 | PT_UNICODE) => {
+                attachment.name = decode_tnef_string(prop_type, value)
+            }
+            (PR_ATTACH_MIME_TAG, PT_STRING8 | PT_UNICODE) => {
+                attachment.mime_type = Some(decode_tnef_string(prop_type, value))
+            }
+            (PR_ATTACH_DATA_OBJ, PT_BINARY) if attachment.contents.is_empty() => {
+                attachment.contents = value.to_vec()
+            }
+            _ => {}
+        }
+    }
+}
+
+fn decode_tnef(data: &[u8]) -> Option<Vec<TnefAttachment>> {
+    let mut reader = TnefReader::new(data);
+    if reader.u32()? != TNEF_SIGNATURE {
+        return None;
+    }
+    // Legacy key.
+    reader.take(2)?;
+
+    let mut attachments: Vec<TnefAttachment> = Vec::new();
+    while !reader.is_empty() {
+        let level = reader.take(1)?[0];
+        let id = reader.u32()?;
+        let length = reader.u32()? as usize;
+        let value = reader.take(length)?;
+        // Checksum.
+        reader.take(2)?;
+        if level != TNEF_LEVEL_ATTACHMENT {
+            continue;
+        }
+
+        // Every attachment starts with its rendering data.
+        if id == ATT_ATTACH_REND_DATA {
+            attachments.push(TnefAttachment::default());
+            continue;
+        }
+        let Some(attachment) = attachments.last_mut() else {
+            continue;
+        };
+        match id {
+            // Can be the short (8.3) name, the long one is in the props.
+            ATT_ATTACH_TITLE if attachment.name.is_empty() => {
+                attachment.name = decode_tnef_string(PT_STRING8, value)
+            }
+            ATT_ATTACH_DATA => attachment.contents = value.to_vec(),
+            ATT_ATTACHMENT => read_tnef_attachment_props(value, attachment),
+            _ => {}
+        }
+    }
+    Some(
+        attachments
+            .into_iter()
+            .filter(|attachment| !attachment.contents.is_empty())
+            .collect(),
+    )
+}
+
+fn is_tnef(part: &MessagePart) -> bool {
+    part.is_content_type("application", "ms-tnef")
+        || part
+            .attachment_name()
+            .is_some_and(|name| name.eq_ignore_ascii_case("winmail.dat"))
+}
+
+// "Name Surname <namesurname@domain.com>, other@domain.com"
+fn format_address(address: Option<&Address>) -> Option<String> {
+    let addresses: Vec<String> = address?
+        .iter()
+        .filter_map(|addr| {
+            let email = addr.address()?;
+            Some(match addr.name() {
+                Some(name) if !name.is_empty() => format!("{} <{}>", name, email),
+                _ => email.to_string(),
+            })
+        })
+        .collect();
+    (!addresses.is_empty()).then(|| addresses.join(", "))
+}
+
+fn format_message_ids(value: &HeaderValue) -> Option<String> {
+    match value {
+        HeaderValue::Text(id) => Some(format!("<{}>", id)),
+        HeaderValue::TextList(ids) => Some(
+            ids.iter()
+                .map(|id| format!("<{}>", id))
+                .collect::<Vec<String>>()
+                .join(" "),
+        ),
+        _ => None,
+    }
+}
+
+fn to_parsed_attachment(part: &MessagePart) -> ParsedAttachment {
+    ParsedAttachment {
+        name: part.attachment_name().unwrap_or("attachment").to_string(),
+        mime_type: part
+            .content_type()
+            .map(|content_type| match content_type.subtype() {
+                Some(subtype) => format!("{}/{}", content_type.ctype(), subtype),
+                None => content_type.ctype().to_string(),
+            })
+            .unwrap_or("application/octet-stream".to_string()),
+        size: part.contents().len(),
+        cid: part.content_id().map(str::to_string),
+    }
+}
+
+// The attachment with its contents, or the attachments in it if it is a
+// winmail.dat that could be decoded.
+fn expand_attachment(part: &MessagePart) -> Vec<(ParsedAttachment, Vec<u8>)> {
+    if is_tnef(part) {
+        if let Some(attachments) = decode_tnef(part.contents()).filter(|a| !a.is_empty()) {
+            return attachments
+                .into_iter()
+                .map(|attachment| {
+                    (
+                        ParsedAttachment {
+                            name: if attachment.name.is_empty() {
+                                "attachment".to_string()
+                            } else {
+                                attachment.name
+                            },
+                            mime_type: attachment
+                                .mime_type
+                                .unwrap_or("application/octet-stream".to_string()),
+                            size: attachment.contents.len(),
+                            cid: None,
+                        },
+                        attachment.contents,
+                    )
+                })
+                .collect();
+        }
+    }
+    vec![(to_parsed_attachment(part), part.contents().to_vec())]
+}
+
+pub fn parse_message(raw: &[u8]) -> Result<ParsedMessage, String> {
+    let message = MessageParser::default()
+        .parse(raw)
+        .ok_or("Failed to parse message".to_string())?;
+
+    let attachments = message
+        .attachments()
+        .flat_map(expand_attachment)
+        .map(|(attachment, _)| attachment)
+        .collect();
+
+    Ok(ParsedMessage {
+        message_id: message
+            .message_id()
+            .map(|id| format!("<{}>", id))
+            .unwrap_or_default(),
+        sender: format_address(message.from()).unwrap_or_default(),
+        receivers: format_address(message.to()).unwrap_or_default(),
+        cc: format_address(message.cc()),
+        bcc: format_address(message.bcc()),
+        date: message
+            .date()
+            .map(|date| date.to_rfc822())
+            .unwrap_or_default(),
+        subject: message.subject().unwrap_or_default().to_string(),
+        body: message
+            .body_text(0)
+            .map(|body| body.to_string())
+            .unwrap_or_default(),
+        // The parser converts plain text bodies to html as well.
+        html_body: message
+            .html_part(0)
+            .filter(|part| part.is_content_type("text", "html"))
+            .and_then(|_| message.body_html(0))
+            .map(|body| body.to_string()),
+        in_reply_to: format_message_ids(message.in_reply_to()),
+        references: format_message_ids(message.references()),
+        attachments,
+    })
+}
+
+// Decoded contents of the attachments, for saving them to the disk.
+pub fn parse_attachments(raw: &[u8]) -> Result<Vec<(ParsedAttachment, Vec<u8>)>, String> {
+    let message = MessageParser::default()
+        .parse(raw)
+        .ok_or("Failed to parse message".to_string())?;
+    Ok(message.attachments().flat_map(expand_attachment).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD as BASE64;
+    use base64::Engine;
+
+    fn tnef_attr(level: u8, id: u32, value: &[u8]) -> Vec<u8> {
+        let mut attr = vec![level];
+        attr.extend_from_slice(&id.to_le_bytes());
+        attr.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        attr.extend_from_slice(value);
+        let checksum = value
+            .iter()
+            .map(|byte| *byte as u16)
+            .fold(0, u16::wrapping_add);
+        attr.extend_from_slice(&checksum.to_le_bytes());
+        attr
+    }
+
+    fn tnef(attrs: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = TNEF_SIGNATURE.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0x01, 0x00]);
+        for attr in attrs {
+            data.extend_from_slice(attr);
+        }
+        data
+    }
+
+    // Variable size MAPI property with a single value, padded to 4 bytes.
+    fn mapi_prop(prop_type: u16, prop_id: u16, value: &[u8]) -> Vec<u8> {
+        let mut prop = prop_type.to_le_bytes().to_vec();
+        prop.extend_from_slice(&prop_id.to_le_bytes());
+        prop.extend_from_slice(&1u32.to_le_bytes());
+        prop.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        prop.extend_from_slice(value);
+        prop.resize(prop.len().next_multiple_of(4), 0);
+        prop
+    }
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    #[test]
+    fn decodes_tnef_attachments() {
+        let data = tnef(&[
+            // Message level attributes are skipped.
+            tnef_attr(1, 0x0001_8004, b"Subject\0"),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_TITLE, b"REPORT~1.TXT\0"),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"first"),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_TITLE, b"b.txt\0"),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"second"),
+        ]);
+        let attachments = decode_tnef(&data).unwrap();
+        assert_eq!(attachments.len(), 2);
+        assert_eq!(attachments[0].name, "REPORT~1.TXT");
+        assert_eq!(attachments[0].contents, b"first");
+        assert_eq!(attachments[1].name, "b.txt");
+        assert_eq!(attachments[1].contents, b"second");
+    }
+
+    #[test]
+    fn prefers_long_tnef_names() {
+        let mut props = 2u32.to_le_bytes().to_vec();
+        props.extend(mapi_prop(
+            PT_UNICODE,
+            PR_ATTACH_LONG_FILENAME,
+            &utf16("Quarterly report.txt\0"),
+        ));
+        props.extend(mapi_prop(PT_STRING8, PR_ATTACH_MIME_TAG, b"text/plain\0"));
+        let data = tnef(&[
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_TITLE, b"QUARTE~1.TXT\0"),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"report"),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACHMENT, &props),
+        ]);
+        let attachments = decode_tnef(&data).unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].name, "Quarterly report.txt");
+        assert_eq!(attachments[0].mime_type.as_deref(), Some("text/plain"));
+        assert_eq!(attachments[0].contents, b"report");
+    }
+
+    #[test]
+    fn rejects_invalid_tnef() {
+        assert!(decode_tnef(b"not tnef at all").is_none());
+        let mut truncated = tnef(&[tnef_attr(
+            TNEF_LEVEL_ATTACHMENT,
+            ATT_ATTACH_DATA,
+            b"contents",
+        )]);
+        truncated.truncate(truncated.len() - 4);
+        assert!(decode_tnef(&truncated).is_none());
+        // Attachments without contents are dropped.
+        let empty = tnef(&[tnef_attr(
+            TNEF_LEVEL_ATTACHMENT,
+            ATT_ATTACH_REND_DATA,
+            &[0; 14],
+        )]);
+        assert!(decode_tnef(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn expands_winmail_dat() {
+        let data = tnef(&[
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA, &[0; 14]),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_TITLE, b"a.txt\0"),
+            tnef_attr(TNEF_LEVEL_ATTACHMENT, ATT_ATTACH_DATA, b"inside"),
+        ]);
+        let raw = format!(
+            "From: a@b.com\r\nTo: c@d.com\r\nSubject: Hi\r\nMIME-Version: 1.0\r\n\
+             Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n\
+             --b\r\nContent-Type: text/plain\r\n\r\nBody\r\n\
+             --b\r\nContent-Type: application/ms-tnef; name=\"winmail.dat\"\r\n\
+             Content-Disposition: attachment; filename=\"winmail.dat\"\r\n\
+             Content-Transfer-Encoding: base64\r\n\r\n{}\r\n--b--\r\n",
+            BASE64.encode(&data)
+        );
+        let attachments = parse_attachments(raw.as_bytes()).unwrap();
+        assert_eq!(attachments.len(), 1);
+        assert_eq!(attachments[0].0.name, "a.txt");
+        assert_eq!(attachments[0].0.mime_type, "application/octet-stream");
+        assert_eq!(attachments[0].1, b"inside");
+
+        let parsed = parse_message(raw.as_bytes()).unwrap();
+        assert_eq!(parsed.attachments.len(), 1);
+        assert_eq!(parsed.attachments[0].name, "a.txt");
+    }
+}
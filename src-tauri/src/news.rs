@@ -0,0 +1,423 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::State;
+
+use crate::cache::{Cache, CachedAttachment, CachedEmail};
+use crate::consts;
+use crate::events::EventBus;
+use crate::feeds;
+use crate::scheduler;
+use crate::utils;
+use crate::worker;
+
+// Newsgroups are read like the feeds: every subscribed group is a folder of
+// the "News" account, which only exists in the cache, and its articles are
+// parsed in the parser worker like any other message. So the threading,
+// search and reader of the emails work on them as they are. Posting is not
+// supported, and neither is AUTHINFO, only the servers that allow reading
+// without an account.
+pub const NEWS_ACCOUNT: &str = "News";
+const DEFAULT_PORT: u16 = 119;
+const DEFAULT_TLS_PORT: u16 = 563;
+const MIN_SYNC_INTERVAL_SEC: u64 = 300;
+const RETRY_INTERVAL_SEC: u64 = 300;
+const IDLE_INTERVAL_SEC: u64 = 3600;
+const TIMEOUT_SEC: u64 = 30;
+// Only the newest articles of a group are fetched on the first sync.
+const MAX_INITIAL_ARTICLES: u64 = 100;
+const MAX_GROUP_ARTICLES: usize = 1000;
+const MAX_ARTICLE_BYTES: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Newsgroup {
+    // Name of the group and of its folder, like comp.lang.rust
+    pub group: String,
+    pub server: String,
+    pub port: Option<u16>,
+    pub tls: bool,
+    pub sync_interval_sec: u64,
+}
+
+fn validate(newsgroup: &Newsgroup) -> Result<(), String> {
+    let is_valid_group = !newsgroup.group.is_empty()
+        && newsgroup
+            .group
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".+-_".contains(c));
+    if !is_valid_group {
+        return Err(format!("Invalid newsgroup: {}", newsgroup.group));
+    }
+    if newsgroup.server.is_empty() || newsgroup.server.contains(['/', ':', ' ']) {
+        return Err(format!("Invalid server: {}", newsgroup.server));
+    }
+    if newsgroup.sync_interval_sec < MIN_SYNC_INTERVAL_SEC {
+        return Err(format!(
+            "Sync interval can not be less than {} seconds",
+            MIN_SYNC_INTERVAL_SEC
+        ));
+    }
+    Ok(())
+}
+
+trait Stream: Read + Write {}
+impl<T: Read + Write> Stream for T {}
+
+// A minimal NNTP (RFC 3977) client, only what reading a group takes.
+struct NntpClient {
+    stream: BufReader<Box<dyn Stream>>,
+}
+
+impl NntpClient {
+    fn connect(newsgroup: &Newsgroup) -> Result<Self, String> {
+        let port = newsgroup.port.unwrap_or(if newsgroup.tls {
+            DEFAULT_TLS_PORT
+        } else {
+            DEFAULT_PORT
+        });
+        let address = format!("{}:{}", newsgroup.server, port);
+        let tcp = TcpStream::connect(&address)
+            .map_err(|err| format!("Failed to connect to {}: {}", address, err))?;
+        tcp.set_read_timeout(Some(Duration::from_secs(TIMEOUT_SEC)))
+            .and_then(|_| tcp.set_write_timeout(Some(Duration::from_secs(TIMEOUT_SEC))))
+            .map_err(|err| format!("Failed to connect to {}: {}", address, err))?;
+        let stream: Box<dyn Stream> = if newsgroup.tls {
+            let connector = native_tls::TlsConnector::new()
+                .map_err(|err| format!("Failed to connect to {}: {}", address, err))?;
+            Box::new(
+                connector
+                    .connect(&newsgroup.server, tcp)
+                    .map_err(|err| format!("Failed to connect to {}: {}", address, err))?,
+            )
+        } else {
+            Box::new(tcp)
+        };
+
+        let mut client = NntpClient {
+            stream: BufReader::new(stream),
+        };
+        // 200 is posting allowed, 201 is not, both can be read.
+        let (code, greeting) = client.read_response()?;
+        if code != 200 && code != 201 {
+            return Err(format!("{} refused the connection: {}", address, greeting));
+        }
+        // Some servers only allow reading after it, the others may not know
+        // it, so its response is ignored.
+        client.command("MODE READER")?;
+        Ok(client)
+    }
+
+    fn read_line(&mut self) -> Result<Vec<u8>, String> {
+        let mut line = Vec::new();
+        self.stream
+            .read_until(b'\n', &mut line)
+            .map_err(|err| format!("Failed to read from server: {}", err))?;
+        if line.is_empty() {
+            return Err("Server closed the connection".to_string());
+        }
+        while line.last().is_some_and(|c| *c == b'\n' || *c == b'\r') {
+            line.pop();
+        }
+        Ok(line)
+    }
+
+    fn read_response(&mut self) -> Result<(u16, String), String> {
+        let line = String::from_utf8_lossy(&self.read_line()?).to_string();
+        let code = line
+            .get(..3)
+            .and_then(|code| code.parse().ok())
+            .ok_or(format!("Invalid response: {}", line))?;
+        Ok((code, line[3..].trim().to_string()))
+    }
+
+    fn command(&mut self, command: &str) -> Result<(u16, String), String> {
+        let stream = self.stream.get_mut();
+        stream
+            .write_all(format!("{}\r\n", command).as_bytes())
+            .and_then(|_| stream.flush())
+            .map_err(|err| format!("Failed to write to server: {}", err))?;
+        self.read_response()
+    }
+
+    // Body of a multi-line response, which ends with a line of a single dot
+    // and has the dots at the start of the lines doubled.
+    fn read_block(&mut self) -> Result<Vec<u8>, String> {
+        let mut block = Vec::new();
+        let mut too_large = false;
+        loop {
+            let line = self.read_line()?;
+            if line == b"." {
+                break;
+            }
+            if too_large {
+                continue;
+            }
+            block.extend_from_slice(line.strip_prefix(b".").unwrap_or(&line));
+            block.extend_from_slice(b"\r\n");
+            // The rest is still read so the connection can be used.
+            too_large = block.len() > MAX_ARTICLE_BYTES;
+        }
+        if too_large {
+            return Err("Article is too large".to_string());
+        }
+        Ok(block)
+    }
+
+    // Returns the first and the last article numbers of the group.
+    fn group(&mut self, group: &str) -> Result<(u64, u64), String> {
+        let (code, response) = self.command(&format!("GROUP {}", group))?;
+        if code != 211 {
+            return Err(format!("Failed to select {}: {}", group, response));
+        }
+        // 211 <count> <first> <last> <group>
+        let numbers: Vec<u64> = response
+            .split_whitespace()
+            .take(3)
+            .filter_map(|number| number.parse().ok())
+            .collect();
+        match numbers[..] {
+            [_, first, last] => Ok((first, last)),
+            _ => Err(format!("Invalid response to GROUP: {}", response)),
+        }
+    }
+
+    // None if the article was removed.
+    fn article(&mut self, number: u64) -> Result<Option<Vec<u8>>, String> {
+        let (code, response) = self.command(&format!("ARTICLE {}", number))?;
+        match code {
+            220 => Ok(Some(self.read_block()?)),
+            423 | 430 => Ok(None),
+            _ => Err(format!("Failed to fetch article {}: {}", number, response)),
+        }
+    }
+
+    fn quit(mut self) {
+        self.command("QUIT").ok();
+    }
+}
+
+fn parse_article(source: &[u8]) -> Result<CachedEmail, String> {
+    let path = utils::build_temp_path(&format!("news-{}.eml", utils::generate_random_id()))?;
+    fs::write(&path, source).map_err(|err| format!("Failed to write article: {}", err))?;
+    let parsed = worker::parse_message_file(&path.to_string_lossy());
+    fs::remove_file(&path).ok();
+    let parsed = parsed?;
+    Ok(CachedEmail {
+        message_id: parsed.message_id,
+        sender: parsed.sender,
+        receivers: parsed.receivers,
+        date: parsed.date,
+        subject: parsed.subject,
+        body: parsed.body,
+        attachments: parsed
+            .attachments
+            .into_iter()
+            .map(|attachment| CachedAttachment {
+                name: attachment.name,
+                mime_type: attachment.mime_type,
+                cid: attachment.cid,
+            })
+            .collect(),
+        in_reply_to: parsed.in_reply_to,
+        references: parsed.references,
+        ..Default::default()
+    })
+}
+
+pub struct News {
+    cache: Arc<Cache>,
+    events: Arc<EventBus>,
+    newsgroups: Mutex<Vec<Newsgroup>>,
+    // Last fetched article number of the groups, the articles that were
+    // fetched before a restart are skipped by their Message-ID.
+    last_articles: Mutex<HashMap<String, u64>>,
+    next_sync_at: Mutex<HashMap<String, Instant>>,
+    wakeup: Condvar,
+}
+
+impl News {
+    pub fn load(cache: Arc<Cache>, events: Arc<EventBus>) -> Self {
+        News {
+            cache,
+            events,
+            newsgroups: Mutex::new(
+                utils::read_json_file(consts::NEWSGROUPS_FILE_PATH).unwrap_or_default(),
+            ),
+            last_articles: Mutex::new(HashMap::new()),
+            next_sync_at: Mutex::new(HashMap::new()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    fn save(&self, newsgroups: &[Newsgroup]) -> Result<(), String> {
+        utils::write_json_file(consts::NEWSGROUPS_FILE_PATH, &newsgroups)
+    }
+
+    pub fn get_newsgroups(&self) -> Vec<Newsgroup> {
+        self.newsgroups.lock().unwrap().clone()
+    }
+
+    // Subscribes to the group or updates its server.
+    pub fn set(&self, mut newsgroup: Newsgroup) -> Result<(), String> {
+        newsgroup.group = newsgroup.group.trim().to_lowercase();
+        newsgroup.server = newsgroup.server.trim().to_string();
+        validate(&newsgroup)?;
+
+        let mut newsgroups = self.newsgroups.lock().unwrap();
+        match newsgroups
+            .iter_mut()
+            .find(|other| other.group == newsgroup.group)
+        {
+            Some(other) => {
+                // Article numbers are per server.
+                if other.server != newsgroup.server {
+                    self.last_articles.lock().unwrap().remove(&newsgroup.group);
+                }
+                *other = newsgroup.clone();
+            }
+            None => newsgroups.push(newsgroup.clone()),
+        }
+        self.next_sync_at.lock().unwrap().remove(&newsgroup.group);
+        self.save(&newsgroups)?;
+        self.wakeup.notify_all();
+        Ok(())
+    }
+
+    pub fn remove(&self, group: &str) -> Result<(), String> {
+        let mut newsgroups = self.newsgroups.lock().unwrap();
+        let position = newsgroups
+            .iter()
+            .position(|newsgroup| newsgroup.group == group)
+            .ok_or(format!("Newsgroup not found: {}", group))?;
+        newsgroups.remove(position);
+        self.save(&newsgroups)?;
+        self.last_articles.lock().unwrap().remove(group);
+        self.cache.remove_folder(NEWS_ACCOUNT, group)
+    }
+
+    fn sync_group(&self, newsgroup: &Newsgroup) -> Result<(), String> {
+        let mut client = NntpClient::connect(newsgroup)?;
+        let (first, last) = client.group(&newsgroup.group)?;
+        let fetched = self
+            .last_articles
+            .lock()
+            .unwrap()
+            .get(&newsgroup.group)
+            .copied();
+        // The numbers start over if the group is recreated on the server.
+        // After a long time offline, only the articles that are kept in the
+        // cache are fetched.
+        let start = match fetched {
+            Some(fetched) if fetched <= last => fetched + 1,
+            _ => last.saturating_sub(MAX_INITIAL_ARTICLES - 1),
+        }
+        .max(last.saturating_sub(MAX_GROUP_ARTICLES as u64 - 1))
+        .max(first);
+
+        let mut articles = Vec::new();
+        for number in start..=last {
+            let Some(source) = client.article(number)? else {
+                continue;
+            };
+            match parse_article(&source) {
+                Ok(article) => articles.push(article),
+                Err(err) => println!("Skipped article {} of {}: {}", number, newsgroup.group, err),
+            }
+        }
+        client.quit();
+
+        let changes = self.cache.add_emails(
+            NEWS_ACCOUNT,
+            &newsgroup.group,
+            articles,
+            MAX_GROUP_ARTICLES,
+            Utc::now().timestamp(),
+        )?;
+        self.last_articles
+            .lock()
+            .unwrap()
+            .insert(newsgroup.group.clone(), last);
+        scheduler::publish_changes(&self.events, NEWS_ACCOUNT, &newsgroup.group, &changes);
+        Ok(())
+    }
+
+    // Groups that are due and how long to wait for the next one.
+    fn due_groups(&self) -> (Vec<Newsgroup>, Duration) {
+        let newsgroups = self.newsgroups.lock().unwrap();
+        let next_sync_at = self.next_sync_at.lock().unwrap();
+        let now = Instant::now();
+        let mut wait = Duration::from_secs(IDLE_INTERVAL_SEC);
+        let mut due = Vec::new();
+        for newsgroup in newsgroups.iter() {
+            match next_sync_at.get(&newsgroup.group) {
+                Some(at) if *at > now => wait = wait.min(*at - now),
+                _ => due.push(newsgroup.clone()),
+            }
+        }
+        (due, wait)
+    }
+
+    fn schedule(&self, group: &str, after: Duration) {
+        self.next_sync_at
+            .lock()
+            .unwrap()
+            .insert(group.to_string(), Instant::now() + after);
+    }
+
+    fn wait(&self, interval: Duration) {
+        let newsgroups = self.newsgroups.lock().unwrap();
+        let _ = self.wakeup.wait_timeout(newsgroups, interval).unwrap();
+    }
+}
+
+pub fn start_worker(news: Arc<News>) {
+    thread::spawn(move || loop {
+        let (due, wait) = news.due_groups();
+        if due.is_empty() {
+            news.wait(wait);
+            continue;
+        }
+
+        for newsgroup in due {
+            let after = match news.sync_group(&newsgroup) {
+                Ok(()) => newsgroup.sync_interval_sec,
+                Err(err) => {
+                    println!("Failed to sync {}: {}", newsgroup.group, err);
+                    RETRY_INTERVAL_SEC
+                }
+            };
+            news.schedule(&newsgroup.group, Duration::from_secs(after));
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_newsgroups(news: State<'_, Arc<News>>) -> Vec<Newsgroup> {
+    news.get_newsgroups()
+}
+
+#[tauri::command]
+pub fn set_newsgroup(news: State<'_, Arc<News>>, newsgroup: Newsgroup) -> Result<(), String> {
+    news.set(newsgroup)
+}
+
+#[tauri::command]
+pub fn remove_newsgroup(news: State<'_, Arc<News>>, group: String) -> Result<(), String> {
+    news.remove(&group)
+}
+
+#[tauri::command]
+pub fn set_news_articles_seen(
+    news: State<'_, Arc<News>>,
+    group: String,
+    uids: Vec<String>,
+    seen: bool,
+) -> Result<(), String> {
+    feeds::set_seen(&news.cache, &news.events, NEWS_ACCOUNT, &group, &uids, seen)
+}
@@ -0,0 +1,253 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tauri::{AppHandle, Emitter, State};
+use tauri_plugin_notification::NotificationExt;
+
+use crate::account_schedule;
+use crate::batch_delivery;
+use crate::consts;
+use crate::utils;
+
+// Every notification of the app is recorded here, so it can still be found
+// after the toast is dismissed. Notifications shown by the shell go through
+// `show`, the ones shown by the webview are recorded with
+// `record_notification`. Only the latest ones of the last days are kept.
+const MAX_NOTIFICATIONS: usize = 500;
+const MAX_NOTIFICATION_AGE_SEC: i64 = 30 * 24 * 60 * 60;
+// More released notifications than this are shown as a single summary.
+const MAX_RELEASED_NOTIFICATIONS: usize = 3;
+
+pub const NOTIFICATION_RECORDED_EVENT: &str = "notification-recorded";
+
+// What the notification is about, to open it from the history.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationReference {
+    pub account: Option<String>,
+    pub folder: Option<String>,
+    pub uids: Vec<String>,
+}
+
+// A notification that is held until the batch of new emails is released.
+#[derive(Debug, Clone)]
+pub struct HeldNotification {
+    pub title: String,
+    pub body: String,
+    pub reference: NotificationReference,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationEntry {
+    pub id: String,
+    pub title: String,
+    pub body: String,
+    #[serde(flatten)]
+    pub reference: NotificationReference,
+    pub created_at: i64,
+    pub read: bool,
+}
+
+pub struct NotificationHistory {
+    entries: Mutex<Vec<NotificationEntry>>,
+}
+
+impl NotificationHistory {
+    pub fn load() -> Self {
+        NotificationHistory {
+            entries: Mutex::new(
+                utils::read_json_file(consts::NOTIFICATION_HISTORY_FILE_PATH).unwrap_or_default(),
+            ),
+        }
+    }
+
+    fn save(&self, entries: &[NotificationEntry]) -> Result<(), String> {
+        utils::write_json_file(consts::NOTIFICATION_HISTORY_FILE_PATH, &entries)
+    }
+
+    pub fn record(
+        &self,
+        title: &str,
+        body: &str,
+        reference: NotificationReference,
+    ) -> Result<NotificationEntry, String> {
+        let now = Utc::now().timestamp();
+        let entry = NotificationEntry {
+            id: utils::generate_random_id(),
+            title: title.to_string(),
+            body: body.to_string(),
+            reference,
+            created_at: now,
+            read: false,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|entry| now - entry.created_at < MAX_NOTIFICATION_AGE_SEC);
+        entries.insert(0, entry.clone());
+        entries.truncate(MAX_NOTIFICATIONS);
+        self.save(&entries)?;
+        Ok(entry)
+    }
+
+    // Newest first.
+    pub fn get_entries(&self) -> Vec<NotificationEntry> {
+        self.entries.lock().unwrap().clone()
+    }
+
+    // Marks all of them if no ids are given.
+    pub fn mark_read(&self, ids: Option<Vec<String>>) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        for entry in entries.iter_mut() {
+            if ids.as_ref().is_none_or(|ids| ids.contains(&entry.id)) {
+                entry.read = true;
+            }
+        }
+        self.save(&entries)
+    }
+
+    pub fn clear(&self) -> Result<(), String> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.clear();
+        self.save(&entries)
+    }
+}
+
+fn recorded(
+    app: &AppHandle,
+    history: &NotificationHistory,
+    title: &str,
+    body: &str,
+    reference: NotificationReference,
+) {
+    match history.record(title, body, reference) {
+        Ok(entry) => {
+            app.emit(NOTIFICATION_RECORDED_EVENT, entry).ok();
+        }
+        Err(err) => println!("Failed to record notification: {}", err),
+    }
+}
+
+fn display(
+    app: &AppHandle,
+    history: &NotificationHistory,
+    title: &str,
+    body: &str,
+    reference: NotificationReference,
+) {
+    if let Err(err) = app.notification().builder().title(title).body(body).show() {
+        println!("Failed to show notification: {}", err);
+        return;
+    }
+    recorded(app, history, title, body, reference);
+}
+
+pub fn show(
+    app: &AppHandle,
+    history: &NotificationHistory,
+    title: &str,
+    body: &str,
+    reference: NotificationReference,
+) {
+    if reference
+        .account
+        .as_ref()
+        .is_some_and(|account| account_schedule::is_paused(app, account))
+    {
+        return;
+    }
+    let held = HeldNotification {
+        title: title.to_string(),
+        body: body.to_string(),
+        reference,
+    };
+    let Some(held) = batch_delivery::hold_notification(app, held) else {
+        return;
+    };
+    display(app, history, &held.title, &held.body, held.reference);
+}
+
+// Shown even while the account is paused or the new emails are held, for
+// the replies to the user, see thread_subscriptions.rs
+pub fn show_urgent(
+    app: &AppHandle,
+    history: &NotificationHistory,
+    title: &str,
+    body: &str,
+    reference: NotificationReference,
+) {
+    display(app, history, title, body, reference);
+}
+
+// All of them are recorded, but only a summary is shown if there are many.
+pub fn show_released(app: &AppHandle, history: &NotificationHistory, held: Vec<HeldNotification>) {
+    if held.len() <= MAX_RELEASED_NOTIFICATIONS {
+        for held in held {
+            display(app, history, &held.title, &held.body, held.reference);
+        }
+        return;
+    }
+
+    let body = format!("{} notifications while mail was held", held.len());
+    if let Err(err) = app
+        .notification()
+        .builder()
+        .title("Openmail")
+        .body(&body)
+        .show()
+    {
+        println!("Failed to show notification: {}", err);
+    }
+    for held in held {
+        recorded(app, history, &held.title, &held.body, held.reference);
+    }
+}
+
+#[tauri::command]
+pub fn record_notification(
+    app: AppHandle,
+    history: State<'_, Arc<NotificationHistory>>,
+    title: String,
+    body: String,
+    reference: Option<NotificationReference>,
+) {
+    recorded(&app, &history, &title, &body, reference.unwrap_or_default());
+}
+
+// Notifications of the UI are held like the others while batched delivery
+// is on, see batch_delivery.rs. Returns false if it should be shown now.
+#[tauri::command]
+pub fn hold_notification(
+    app: AppHandle,
+    title: String,
+    body: String,
+    reference: Option<NotificationReference>,
+) -> bool {
+    let held = HeldNotification {
+        title,
+        body,
+        reference: reference.unwrap_or_default(),
+    };
+    batch_delivery::hold_notification(&app, held).is_none()
+}
+
+#[tauri::command]
+pub fn get_notification_history(
+    history: State<'_, Arc<NotificationHistory>>,
+) -> Vec<NotificationEntry> {
+    history.get_entries()
+}
+
+#[tauri::command]
+pub fn mark_notifications_read(
+    history: State<'_, Arc<NotificationHistory>>,
+    ids: Option<Vec<String>>,
+) -> Result<(), String> {
+    history.mark_read(ids)
+}
+
+#[tauri::command]
+pub fn clear_notification_history(
+    history: State<'_, Arc<NotificationHistory>>,
+) -> Result<(), String> {
+    history.clear()
+}
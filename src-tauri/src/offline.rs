@@ -0,0 +1,537 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Listener, State};
+
+use crate::api;
+use crate::audit;
+use crate::cache::{Cache, EmailChange};
+use crate::consts;
+use crate::events::EventBus;
+use crate::scheduler;
+use crate::utils;
+
+// Flag changes (read, flagged etc.) are applied to the cache right away and
+// queued here, then replayed in order once the server is reachable, so they
+// work offline too. Before a change is replayed the current flags of the
+// email on the server are compared with the flags it had when the change
+// was made, if the email was moved/deleted or the flag was changed on the
+// server meanwhile, the conflict is resolved by the conflict policy.
+// A change that the server refuses is retried with a backoff, the other
+// changes are replayed meanwhile, and after `MAX_REPLAY_ATTEMPTS` it waits
+// for the user like a conflict.
+const RETRY_INTERVAL_SEC: u64 = 30;
+const IDLE_INTERVAL_SEC: u64 = 60;
+const MAX_REPLAY_ATTEMPTS: u32 = 5;
+const MAX_BACKOFF_SEC: i64 = 3600;
+
+pub const OFFLINE_CHANGE_CONFLICT_EVENT: &str = "offline-change-conflict";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    ServerWins,
+    LocalWins,
+    // Keeps the change (and the later changes of the same email) in the
+    // queue until the user resolves it.
+    #[default]
+    Ask,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictKind {
+    EmailMissing,
+    FlagChanged,
+    ReplayFailed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FlagChange {
+    pub id: String,
+    pub account: String,
+    pub folder: String,
+    pub uid: String,
+    pub flag: String,
+    pub set: bool,
+    // Whether the email had the flag when the change was made.
+    pub had_flag: bool,
+    pub queued_at: i64,
+    // Set while the change waits for the user (see `ConflictPolicy::Ask`).
+    pub conflict: Option<ConflictKind>,
+    // The user chose to keep the change on a conflict.
+    pub keep_local: bool,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(default)]
+    pub retry_at: Option<i64>,
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+struct OfflineChangesFile {
+    policy: ConflictPolicy,
+    changes: Vec<FlagChange>,
+}
+
+enum Check {
+    Apply,
+    UpToDate,
+    Conflict(ConflictKind),
+}
+
+fn check(change: &FlagChange, server_flags: Option<&[String]>) -> Check {
+    let Some(server_flags) = server_flags else {
+        return Check::Conflict(ConflictKind::EmailMissing);
+    };
+
+    let has_flag = server_flags.contains(&change.flag);
+    if has_flag == change.set {
+        Check::UpToDate
+    } else if has_flag != change.had_flag {
+        Check::Conflict(ConflictKind::FlagChanged)
+    } else {
+        Check::Apply
+    }
+}
+
+fn describe(change: &FlagChange) -> String {
+    format!(
+        "{} {} of email {} in {}",
+        if change.set { "Set" } else { "Unset" },
+        change.flag,
+        change.uid,
+        change.folder
+    )
+}
+
+fn fetch_flags(change: &FlagChange) -> Result<Option<Vec<String>>, String> {
+    let response = api::get(
+        &format!("/get-email-flags/{}/{}", change.account, change.uid),
+        &[("folder", &change.folder)],
+    )?;
+    serde_json::from_value(api::get_account_data(response, &change.account)?)
+        .map_err(|err| format!("Failed to parse flags of {}: {}", change.uid, err))
+}
+
+fn push(change: &FlagChange) -> Result<(), String> {
+    let response = api::post_json(
+        if change.set {
+            "/mark-email"
+        } else {
+            "/unmark-email"
+        },
+        json!({
+            "account": change.account,
+            "folder": change.folder,
+            "sequence_set": change.uid,
+            "mark": change.flag,
+        }),
+    )?;
+    if !response.success {
+        return Err(response.message);
+    }
+    Ok(())
+}
+
+pub struct OfflineChanges {
+    cache: Arc<Cache>,
+    events: Arc<EventBus>,
+    state: Mutex<OfflineChangesFile>,
+    wakeup: Condvar,
+}
+
+impl OfflineChanges {
+    pub fn load(cache: Arc<Cache>, events: Arc<EventBus>) -> Self {
+        OfflineChanges {
+            cache,
+            events,
+            state: Mutex::new(
+                utils::read_json_file(consts::OFFLINE_CHANGES_FILE_PATH).unwrap_or_default(),
+            ),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    fn save(&self, state: &OfflineChangesFile) -> Result<(), String> {
+        utils::write_json_file(consts::OFFLINE_CHANGES_FILE_PATH, state)
+    }
+
+    fn set_cached_flag(&self, change: &FlagChange, set: bool) -> Result<Option<bool>, String> {
+        let Some(flags) = self.cache.set_flag(
+            &change.account,
+            &change.folder,
+            &change.uid,
+            &change.flag,
+            set,
+        )?
+        else {
+            return Ok(None);
+        };
+
+        let mut new_flags: Vec<String> = flags
+            .iter()
+            .filter(|flag| **flag != change.flag)
+            .cloned()
+            .collect();
+        if set {
+            new_flags.push(change.flag.clone());
+        }
+        if new_flags != flags {
+            scheduler::publish_changes(
+                &self.events,
+                &change.account,
+                &change.folder,
+                &[EmailChange::FlagsChanged {
+                    uid: change.uid.clone(),
+                    flags: new_flags,
+                }],
+            );
+        }
+        Ok(Some(flags.contains(&change.flag)))
+    }
+
+    pub fn queue(
+        &self,
+        account: &str,
+        folder: &str,
+        uid: &str,
+        flag: &str,
+        set: bool,
+    ) -> Result<String, String> {
+        let mut change = FlagChange {
+            id: utils::generate_random_id(),
+            account: utils::extract_email_address(account),
+            folder: folder.to_string(),
+            uid: uid.to_string(),
+            flag: flag.to_string(),
+            set,
+            had_flag: !set,
+            queued_at: Utc::now().timestamp(),
+            conflict: None,
+            keep_local: false,
+            attempts: 0,
+            retry_at: None,
+            last_error: None,
+        };
+        // Emails that are not cached (search results etc.) are assumed
+        // to be changed from the opposite state.
+        if let Some(had_flag) = self.set_cached_flag(&change, set)? {
+            change.had_flag = had_flag;
+        }
+
+        let id = change.id.clone();
+        let mut state = self.state.lock().unwrap();
+        state.changes.push(change);
+        self.save(&state)?;
+        self.wakeup.notify_all();
+        Ok(id)
+    }
+
+    // A sync overwrites the cached flags with the ones on the server, the
+    // changes that are not replayed yet are applied again on top of them.
+    fn reapply(&self) {
+        for change in self.get_changes() {
+            if let Err(err) = self.set_cached_flag(&change, change.set) {
+                println!("Failed to reapply {}: {}", describe(&change), err);
+            }
+        }
+    }
+
+    pub fn get_changes(&self) -> Vec<FlagChange> {
+        self.state.lock().unwrap().changes.clone()
+    }
+
+    pub fn get_policy(&self) -> ConflictPolicy {
+        self.state.lock().unwrap().policy
+    }
+
+    pub fn set_policy(&self, policy: ConflictPolicy) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        state.policy = policy;
+        self.save(&state)?;
+        self.wakeup.notify_all();
+        Ok(())
+    }
+
+    pub fn resolve(&self, id: &str, keep_local: bool) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let position = state
+            .changes
+            .iter()
+            .position(|change| change.id == id)
+            .ok_or(format!("Offline change not found: {}", id))?;
+        if state.changes[position].conflict.is_none() {
+            return Err(format!("Offline change has no conflict: {}", id));
+        }
+
+        if keep_local {
+            let change = &mut state.changes[position];
+            change.conflict = None;
+            change.keep_local = true;
+            change.attempts = 0;
+            change.retry_at = None;
+            self.save(&state)?;
+            self.wakeup.notify_all();
+            return Ok(());
+        }
+
+        let change = state.changes.remove(position);
+        self.save(&state)?;
+        drop(state);
+        if matches!(
+            change.conflict,
+            Some(ConflictKind::FlagChanged | ConflictKind::ReplayFailed)
+        ) {
+            self.set_cached_flag(&change, !change.set)?;
+        }
+        audit::record(
+            &change.account,
+            "offline_change_discarded",
+            format!("{}: discarded by the user", describe(&change)),
+        )
+        .ok();
+        Ok(())
+    }
+
+    // First change that is not waiting for the user or a retry and has no
+    // earlier change of the same email waiting, so the changes of an email
+    // are replayed in the order they were made. Accounts with a change that
+    // waits for a retry are skipped until then.
+    fn next_change(&self) -> Option<(FlagChange, ConflictPolicy)> {
+        let state = self.state.lock().unwrap();
+        let now = Utc::now().timestamp();
+        let blocked_accounts: Vec<&str> = state
+            .changes
+            .iter()
+            .filter(|change| change.conflict.is_none() && change.retry_at > Some(now))
+            .map(|change| change.account.as_str())
+            .collect();
+        let mut waiting: Vec<&FlagChange> = Vec::new();
+        for change in state.changes.iter() {
+            let is_blocked = waiting.iter().any(|other| {
+                other.account == change.account
+                    && other.folder == change.folder
+                    && other.uid == change.uid
+            });
+            if change.conflict.is_some() || is_blocked {
+                waiting.push(change);
+                continue;
+            }
+            if blocked_accounts.contains(&change.account.as_str()) {
+                continue;
+            }
+
+            let policy = if change.keep_local {
+                ConflictPolicy::LocalWins
+            } else {
+                state.policy
+            };
+            return Some((change.clone(), policy));
+        }
+        None
+    }
+
+    fn wait(&self, interval: Duration) {
+        let state = self.state.lock().unwrap();
+        let _ = self.wakeup.wait_timeout(state, interval).unwrap();
+    }
+
+    fn remove(&self, id: &str) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        state.changes.retain(|change| change.id != id);
+        self.save(&state)
+    }
+
+    fn mark_conflict(&self, id: &str, kind: ConflictKind) -> Result<Option<FlagChange>, String> {
+        let mut state = self.state.lock().unwrap();
+        let Some(change) = state.changes.iter_mut().find(|change| change.id == id) else {
+            return Ok(None);
+        };
+        change.conflict = Some(kind);
+        let change = change.clone();
+        self.save(&state)?;
+        Ok(Some(change))
+    }
+
+    // Counts a failed replay of the change, it is retried with a doubling
+    // backoff and waits for the user once it failed too many times.
+    fn record_failure(&self, app: &AppHandle, id: &str, err: &str) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        let Some(change) = state.changes.iter_mut().find(|change| change.id == id) else {
+            return Ok(());
+        };
+        change.attempts += 1;
+        change.last_error = Some(err.to_string());
+        if change.attempts < MAX_REPLAY_ATTEMPTS {
+            let backoff = (RETRY_INTERVAL_SEC as i64) << (change.attempts - 1);
+            change.retry_at = Some(Utc::now().timestamp() + backoff.min(MAX_BACKOFF_SEC));
+            return self.save(&state);
+        }
+
+        change.retry_at = None;
+        change.conflict = Some(ConflictKind::ReplayFailed);
+        let change = change.clone();
+        self.save(&state)?;
+        drop(state);
+        app.emit(OFFLINE_CHANGE_CONFLICT_EVENT, &change).ok();
+        audit::record(
+            &change.account,
+            "offline_change_conflict",
+            format!(
+                "{}: failed {} times, waiting for the user: {}",
+                describe(&change),
+                change.attempts,
+                err
+            ),
+        )
+        .ok();
+        Ok(())
+    }
+
+    // Returns false if there is nothing to replay. Errors mean the server
+    // is not reachable, the change stays in the queue. Other failures are
+    // counted on the change, see `record_failure`.
+    fn replay_next(&self, app: &AppHandle) -> Result<bool, String> {
+        let Some((change, policy)) = self.next_change() else {
+            return Ok(false);
+        };
+
+        match self.replay(app, &change, policy) {
+            Err(err) if !api::is_unreachable(&err) => {
+                self.record_failure(app, &change.id, &err)?;
+                Ok(true)
+            }
+            result => result.map(|()| true),
+        }
+    }
+
+    fn replay(
+        &self,
+        app: &AppHandle,
+        change: &FlagChange,
+        policy: ConflictPolicy,
+    ) -> Result<(), String> {
+        let server_flags = fetch_flags(change)?;
+        let (action, details) = match (check(change, server_flags.as_deref()), policy) {
+            (Check::UpToDate, _) => ("offline_change_skipped", "already on the server"),
+            (Check::Apply, _) => {
+                push(change)?;
+                ("offline_change_applied", "applied")
+            }
+            (Check::Conflict(kind), ConflictPolicy::Ask) => {
+                self.ask(app, change, kind)?;
+                return Ok(());
+            }
+            (Check::Conflict(ConflictKind::FlagChanged), ConflictPolicy::LocalWins) => {
+                push(change)?;
+                (
+                    "offline_change_applied",
+                    "flag was changed on the server, kept the local change",
+                )
+            }
+            (Check::Conflict(ConflictKind::FlagChanged), ConflictPolicy::ServerWins) => {
+                self.set_cached_flag(change, !change.set)?;
+                (
+                    "offline_change_discarded",
+                    "flag was changed on the server, kept the server state",
+                )
+            }
+            // The email is missing, `check` doesn't report the other kinds.
+            (Check::Conflict(_), _) => (
+                "offline_change_discarded",
+                "email was moved or deleted on the server",
+            ),
+        };
+
+        self.remove(&change.id)?;
+        audit::record(
+            &change.account,
+            action,
+            format!("{}: {}", describe(change), details),
+        )
+        .ok();
+        Ok(())
+    }
+
+    fn ask(&self, app: &AppHandle, change: &FlagChange, kind: ConflictKind) -> Result<(), String> {
+        if let Some(change) = self.mark_conflict(&change.id, kind)? {
+            app.emit(OFFLINE_CHANGE_CONFLICT_EVENT, &change).ok();
+        }
+        audit::record(
+            &change.account,
+            "offline_change_conflict",
+            format!("{}: waiting for the user", describe(change)),
+        )
+        .ok();
+        Ok(())
+    }
+}
+
+// A finished sync means the server is reachable again.
+pub fn listen_sync(app: &AppHandle, offline: Arc<OfflineChanges>) {
+    app.listen(scheduler::SYNC_FINISHED_EVENT, move |_| {
+        offline.reapply();
+        offline.wakeup.notify_all();
+    });
+}
+
+pub fn start_worker(app: AppHandle, offline: Arc<OfflineChanges>) {
+    thread::spawn(move || loop {
+        match offline.replay_next(&app) {
+            Ok(true) => {}
+            Ok(false) => offline.wait(Duration::from_secs(IDLE_INTERVAL_SEC)),
+            Err(err) => {
+                println!(
+                    "Offline changes could not be replayed, retrying later: {}",
+                    err
+                );
+                offline.wait(Duration::from_secs(RETRY_INTERVAL_SEC));
+            }
+        }
+    });
+}
+
+#[tauri::command]
+pub fn queue_flag_change(
+    offline: State<'_, Arc<OfflineChanges>>,
+    account: String,
+    folder: String,
+    uid: String,
+    flag: String,
+    set: bool,
+) -> Result<String, String> {
+    offline.queue(&account, &folder, &uid, &flag, set)
+}
+
+#[tauri::command]
+pub fn get_offline_changes(offline: State<'_, Arc<OfflineChanges>>) -> Vec<FlagChange> {
+    offline.get_changes()
+}
+
+#[tauri::command]
+pub fn resolve_offline_conflict(
+    offline: State<'_, Arc<OfflineChanges>>,
+    id: String,
+    keep_local: bool,
+) -> Result<(), String> {
+    offline.resolve(&id, keep_local)
+}
+
+#[tauri::command]
+pub fn get_conflict_policy(offline: State<'_, Arc<OfflineChanges>>) -> ConflictPolicy {
+    offline.get_policy()
+}
+
+#[tauri::command]
+pub fn set_conflict_policy(
+    offline: State<'_, Arc<OfflineChanges>>,
+    policy: ConflictPolicy,
+) -> Result<(), String> {
+    offline.set_policy(policy)
+}
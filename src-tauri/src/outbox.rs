@@ -0,0 +1,396 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::fs;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Listener, State};
+
+use crate::api;
+use crate::consts;
+use crate::image_privacy;
+use crate::settings_sync;
+use crate::shared_mailbox::SharedMailboxes;
+use crate::throttle::{SendLimits, SendThrottle};
+use crate::utils;
+use crate::worker;
+
+// If the server is not reachable (not started yet, restarting etc.)
+// the item stays in the queue and is retried after this interval.
+const RETRY_INTERVAL_SEC: u64 = 30;
+const IDLE_INTERVAL_SEC: u64 = 60;
+// Sent, failed and unknown items are kept this long to be shown in the
+// outbox, then removed.
+const FINISHED_RETENTION_DAYS: i64 = 7;
+
+pub const OUTBOX_ITEM_UPDATED_EVENT: &str = "outbox-item-updated";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutgoingEmail {
+    pub sender: String,
+    pub receivers: String,
+    pub subject: String,
+    pub body: String,
+    pub cc: Option<String>,
+    pub bcc: Option<String>,
+    // Prebuilt message (base64) that is sent as it is, like the ones piped
+    // to openmail-cli, the other fields only describe it then.
+    #[serde(default)]
+    pub raw: Option<String>,
+    // Images inlined into the body are sent without their metadata unless
+    // this is set, see image_privacy.rs
+    #[serde(default)]
+    pub keep_image_metadata: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    Queued,
+    Sending,
+    Sent,
+    Failed,
+    // The server was reached but didn't answer, so it may have been sent.
+    // It is not retried to not send it twice, the user decides.
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutboxItem {
+    pub id: String,
+    pub account: String,
+    pub email: OutgoingEmail,
+    pub status: OutboxStatus,
+    pub error: Option<String>,
+    pub queued_at: i64,
+    pub sent_at: Option<i64>,
+    // When it was sent, failed or became unknown.
+    #[serde(default)]
+    pub finished_at: Option<i64>,
+}
+
+impl OutboxItem {
+    fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            OutboxStatus::Sent | OutboxStatus::Failed | OutboxStatus::Unknown
+        )
+    }
+}
+
+fn prune_finished(items: &mut Vec<OutboxItem>) -> bool {
+    let oldest = Utc::now().timestamp() - FINISHED_RETENTION_DAYS * 24 * 60 * 60;
+    let count = items.len();
+    items.retain(|item| {
+        !item.is_finished() || item.finished_at.or(item.sent_at).unwrap_or(item.queued_at) >= oldest
+    });
+    items.len() != count
+}
+
+pub struct Outbox {
+    shared_mailboxes: Arc<SharedMailboxes>,
+    items: Mutex<Vec<OutboxItem>>,
+    throttle: Mutex<SendThrottle>,
+    wakeup: Condvar,
+}
+
+impl Outbox {
+    pub fn load(shared_mailboxes: Arc<SharedMailboxes>) -> Self {
+        let mut items: Vec<OutboxItem> =
+            utils::read_json_file(consts::OUTBOX_FILE_PATH).unwrap_or_default();
+        // App might be closed while an item was being sent, there is
+        // no way to know the result and sending it again may send it twice.
+        let mut changed = prune_finished(&mut items);
+        for item in items.iter_mut() {
+            if item.status == OutboxStatus::Sending {
+                item.status = OutboxStatus::Unknown;
+                item.error = Some("App was closed while it was being sent".to_string());
+                item.finished_at = Some(Utc::now().timestamp());
+                changed = true;
+            }
+        }
+        if changed {
+            utils::write_json_file(consts::OUTBOX_FILE_PATH, &items).ok();
+        }
+
+        Outbox {
+            shared_mailboxes,
+            items: Mutex::new(items),
+            throttle: Mutex::new(SendThrottle::load()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    fn save(&self, items: &[OutboxItem]) -> Result<(), String> {
+        utils::write_json_file(consts::OUTBOX_FILE_PATH, &items)
+    }
+
+    pub fn queue(&self, mut email: OutgoingEmail) -> Result<String, String> {
+        let sender = utils::extract_email_address(&email.sender);
+        if sender.is_empty() {
+            return Err(format!("Invalid sender: {}", email.sender));
+        }
+        let account = self.shared_mailboxes.resolve_sender(&sender)?;
+        // Prebuilt messages are sent as they are.
+        if email.raw.is_none() && !email.keep_image_metadata {
+            email.body = image_privacy::strip_inline_images(&email.body);
+        }
+
+        let id = utils::generate_random_id();
+        let mut items = self.items.lock().unwrap();
+        items.push(OutboxItem {
+            id: id.clone(),
+            account,
+            email,
+            status: OutboxStatus::Queued,
+            error: None,
+            queued_at: Utc::now().timestamp(),
+            sent_at: None,
+            finished_at: None,
+        });
+        self.save(&items)?;
+        self.wakeup.notify_all();
+        Ok(id)
+    }
+
+    // Queues a prebuilt message like the ones piped to openmail-cli, it is
+    // parsed (in the parser worker) only to find its sender and to show it
+    // in the outbox. `account` is the account it has to be sent with, if
+    // given.
+    pub fn queue_message_file(&self, path: &str, account: Option<&str>) -> Result<String, String> {
+        let raw = fs::read(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+        let parsed = worker::parse_message_file(path)?;
+        if parsed.receivers.is_empty() && parsed.cc.is_none() && parsed.bcc.is_none() {
+            return Err("Message has no receivers".to_string());
+        }
+        let sender = utils::extract_email_address(&parsed.sender);
+        if let Some(account) = account {
+            let sent_with = self.shared_mailboxes.resolve_sender(&sender)?;
+            if !sent_with.eq_ignore_ascii_case(utils::extract_email_address(account).as_str()) {
+                return Err(format!(
+                    "Message is from {}, it can not be sent with {}",
+                    sender, account
+                ));
+            }
+        }
+
+        self.queue(OutgoingEmail {
+            sender: parsed.sender,
+            receivers: parsed.receivers,
+            subject: parsed.subject,
+            body: parsed.body,
+            cc: parsed.cc,
+            bcc: parsed.bcc,
+            raw: Some(BASE64.encode(raw)),
+            keep_image_metadata: true,
+        })
+    }
+
+    pub fn get_items(&self) -> Vec<OutboxItem> {
+        self.items.lock().unwrap().clone()
+    }
+
+    pub fn remove(&self, id: &str) -> Result<(), String> {
+        let mut items = self.items.lock().unwrap();
+        let position = items
+            .iter()
+            .position(|item| item.id == id)
+            .ok_or(format!("Outbox item not found: {}", id))?;
+        if items[position].status == OutboxStatus::Sending {
+            return Err(format!("Outbox item is being sent: {}", id));
+        }
+        items.remove(position);
+        self.save(&items)
+    }
+
+    // Queues a failed or unknown item again, for unknown ones the user
+    // should check the Sent folder first.
+    pub fn retry(&self, id: &str) -> Result<(), String> {
+        let mut items = self.items.lock().unwrap();
+        let item = items
+            .iter_mut()
+            .find(|item| item.id == id)
+            .ok_or(format!("Outbox item not found: {}", id))?;
+        if !matches!(item.status, OutboxStatus::Failed | OutboxStatus::Unknown) {
+            return Err(format!("Outbox item is not failed: {}", id));
+        }
+        item.status = OutboxStatus::Queued;
+        item.error = None;
+        item.finished_at = None;
+        self.save(&items)?;
+        self.wakeup.notify_all();
+        Ok(())
+    }
+
+    pub fn get_send_limits(&self, account: &str) -> SendLimits {
+        self.throttle.lock().unwrap().get_limits(account)
+    }
+
+    pub fn set_send_limits(&self, account: &str, limits: SendLimits) -> Result<(), String> {
+        self.throttle.lock().unwrap().set_limits(account, limits)?;
+        self.wakeup.notify_all();
+        Ok(())
+    }
+
+    pub fn reload_send_limits(&self) {
+        self.throttle.lock().unwrap().reload();
+        self.wakeup.notify_all();
+    }
+
+    // Picks the first queued item whose account is not throttled and marks
+    // it as sending. Otherwise blocks until the earliest throttled account
+    // is available again or a new item is queued. Items are sent one by one,
+    // so a send is only counted by the throttle once it succeeded.
+    fn next_item(&self) -> Option<OutboxItem> {
+        let mut items = self.items.lock().unwrap();
+        let mut wait = Duration::from_secs(IDLE_INTERVAL_SEC);
+        {
+            let mut throttle = self.throttle.lock().unwrap();
+            for item in items.iter_mut() {
+                if item.status != OutboxStatus::Queued {
+                    continue;
+                }
+
+                match throttle.wait_time(&item.account) {
+                    Some(left) => wait = wait.min(left),
+                    None => {
+                        item.status = OutboxStatus::Sending;
+                        return Some(item.clone());
+                    }
+                }
+            }
+        }
+
+        let _ = self.wakeup.wait_timeout(items, wait).unwrap();
+        None
+    }
+
+    fn complete(
+        &self,
+        id: &str,
+        status: OutboxStatus,
+        error: Option<String>,
+    ) -> Option<OutboxItem> {
+        let mut items = self.items.lock().unwrap();
+        let item = items.iter_mut().find(|item| item.id == id)?;
+        let now = Utc::now().timestamp();
+        if status == OutboxStatus::Sent {
+            self.throttle.lock().unwrap().record(&item.account);
+            item.sent_at = Some(now);
+        }
+        item.status = status;
+        item.error = error;
+        item.finished_at = Some(now);
+        let item = item.clone();
+        prune_finished(&mut items);
+        self.save(&items).ok();
+        Some(item)
+    }
+
+    fn requeue(&self, id: &str) {
+        let mut items = self.items.lock().unwrap();
+        if let Some(item) = items.iter_mut().find(|item| item.id == id) {
+            item.status = OutboxStatus::Queued;
+        }
+    }
+}
+
+fn send(item: &OutboxItem) -> Result<api::ApiResponse, String> {
+    let email = &item.email;
+    if let Some(raw) = &email.raw {
+        return api::post_json(
+            "/send-raw-email",
+            json!({
+                "account": item.account,
+                "message": raw,
+            }),
+        );
+    }
+
+    let mut form = vec![
+        ("sender", email.sender.as_str()),
+        ("receivers", email.receivers.as_str()),
+        ("subject", email.subject.as_str()),
+        ("body", email.body.as_str()),
+    ];
+    if let Some(cc) = &email.cc {
+        form.push(("cc", cc));
+    }
+    if let Some(bcc) = &email.bcc {
+        form.push(("bcc", bcc));
+    }
+    // Sent as a shared mailbox.
+    if item.account != utils::extract_email_address(&email.sender) {
+        form.push(("account", &item.account));
+    }
+    api::post_form("/send-email", &form)
+}
+
+pub fn listen_settings_sync(app: &AppHandle, outbox: Arc<Outbox>) {
+    app.listen(settings_sync::SETTINGS_SYNCED_EVENT, move |_| {
+        outbox.reload_send_limits();
+    });
+}
+
+pub fn start_worker(app: AppHandle, outbox: Arc<Outbox>) {
+    thread::spawn(move || loop {
+        let Some(item) = outbox.next_item() else {
+            continue;
+        };
+
+        let (status, error) = match send(&item) {
+            Ok(response) if response.success => (OutboxStatus::Sent, None),
+            Ok(response) => (OutboxStatus::Failed, Some(response.message)),
+            Err(err) if api::may_have_been_handled(&err) => (
+                OutboxStatus::Unknown,
+                Some(format!("{}, check the Sent folder before retrying", err)),
+            ),
+            Err(err) => {
+                println!("Outbox could not reach the server, retrying later: {}", err);
+                outbox.requeue(&item.id);
+                thread::sleep(Duration::from_secs(RETRY_INTERVAL_SEC));
+                continue;
+            }
+        };
+        if let Some(item) = outbox.complete(&item.id, status, error) {
+            app.emit(OUTBOX_ITEM_UPDATED_EVENT, item).ok();
+        }
+    });
+}
+
+#[tauri::command]
+pub fn queue_email(outbox: State<'_, Arc<Outbox>>, email: OutgoingEmail) -> Result<String, String> {
+    outbox.queue(email)
+}
+
+#[tauri::command]
+pub fn get_outbox(outbox: State<'_, Arc<Outbox>>) -> Vec<OutboxItem> {
+    outbox.get_items()
+}
+
+#[tauri::command]
+pub fn remove_outbox_item(outbox: State<'_, Arc<Outbox>>, id: String) -> Result<(), String> {
+    outbox.remove(&id)
+}
+
+#[tauri::command]
+pub fn retry_outbox_item(outbox: State<'_, Arc<Outbox>>, id: String) -> Result<(), String> {
+    outbox.retry(&id)
+}
+
+#[tauri::command]
+pub fn get_send_limits(outbox: State<'_, Arc<Outbox>>, account: String) -> SendLimits {
+    outbox.get_send_limits(&account)
+}
+
+#[tauri::command]
+pub fn set_send_limits(
+    outbox: State<'_, Arc<Outbox>>,
+    account: String,
+    limits: SendLimits,
+) -> Result<(), String> {
+    outbox.set_send_limits(&account, limits)
+}
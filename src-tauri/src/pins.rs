@@ -0,0 +1,249 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Listener, State};
+
+use crate::api;
+use crate::cache::{Cache, CachedEmail};
+use crate::consts;
+use crate::scheduler;
+use crate::threads;
+use crate::tray;
+use crate::utils;
+
+// A few emails or threads can be pinned, their subjects are listed in the
+// tray menu to open them quickly. Pins of the synced folders are removed
+// once their email is not in the folder anymore (archived, moved or
+// deleted), a pinned thread follows its newest email in the folder and is
+// only removed when none is left. The other folders are not synced, so the
+// server is asked whether their pinned emails are still there, and a pinned
+// thread in them is removed with its email.
+const MAX_PINNED: usize = 8;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pin {
+    pub account: String,
+    pub folder: String,
+    pub uid: String,
+    pub subject: String,
+    // Set for the pinned threads.
+    pub thread_id: Option<String>,
+    pub pinned_at: String,
+}
+
+impl Pin {
+    // Also the id of its tray menu item.
+    pub fn key(&self) -> String {
+        format!("{}/{}/{}", self.account, self.folder, self.uid)
+    }
+
+    fn is_of(&self, account: &str, folder: &str, uid: &str) -> bool {
+        self.account == account && self.folder == folder && self.uid == uid
+    }
+}
+
+pub struct Pins {
+    cache: Arc<Cache>,
+    pins: Mutex<Vec<Pin>>,
+}
+
+impl Pins {
+    pub fn load(cache: Arc<Cache>) -> Self {
+        Pins {
+            cache,
+            pins: Mutex::new(utils::read_json_file(consts::PINNED_FILE_PATH).unwrap_or_default()),
+        }
+    }
+
+    pub fn get_pinned(&self) -> Vec<Pin> {
+        self.pins.lock().unwrap().clone()
+    }
+
+    fn save(&self, app: &AppHandle, pins: &[Pin]) -> Result<(), String> {
+        utils::write_json_file(consts::PINNED_FILE_PATH, &pins)?;
+        tray::set_pinned(app, pins);
+        Ok(())
+    }
+
+    pub fn pin(
+        &self,
+        app: &AppHandle,
+        account: &str,
+        folder: &str,
+        uid: &str,
+        thread: bool,
+    ) -> Result<Pin, String> {
+        let email = self
+            .cache
+            .get_emails(account, folder)
+            .into_iter()
+            .find(|email| email.uid == uid)
+            .ok_or(format!("Email {} not found in {}", uid, folder))?;
+        let thread_id = thread.then(|| {
+            let emails = threads::get_account_emails(&self.cache, account);
+            threads::thread_id(&email, &threads::by_message_id(&emails))
+        });
+
+        let mut pins = self.pins.lock().unwrap();
+        let already = pins.iter().any(|pin| {
+            pin.is_of(account, folder, uid)
+                || (thread_id.is_some() && pin.account == account && pin.thread_id == thread_id)
+        });
+        if already {
+            return Err("Already pinned".to_string());
+        }
+        if pins.len() >= MAX_PINNED {
+            return Err(format!("At most {} emails can be pinned", MAX_PINNED));
+        }
+
+        let pin = Pin {
+            account: account.to_string(),
+            folder: folder.to_string(),
+            uid: uid.to_string(),
+            subject: email.subject,
+            thread_id,
+            pinned_at: Utc::now().to_rfc3339(),
+        };
+        let mut updated = pins.clone();
+        updated.push(pin.clone());
+        self.save(app, &updated)?;
+        *pins = updated;
+        Ok(pin)
+    }
+
+    pub fn unpin(
+        &self,
+        app: &AppHandle,
+        account: &str,
+        folder: &str,
+        uid: &str,
+    ) -> Result<(), String> {
+        let mut pins = self.pins.lock().unwrap();
+        let updated: Vec<Pin> = pins
+            .iter()
+            .filter(|pin| !pin.is_of(account, folder, uid))
+            .cloned()
+            .collect();
+        self.save(app, &updated)?;
+        *pins = updated;
+        Ok(())
+    }
+
+    // Where the pin is now, None if it should be removed.
+    fn follow(&self, pin: &Pin, emails: &[CachedEmail]) -> Option<Pin> {
+        if emails.iter().any(|email| email.uid == pin.uid) {
+            return Some(pin.clone());
+        }
+        let thread = pin.thread_id.as_ref()?;
+        let account_emails = threads::get_account_emails(&self.cache, &pin.account);
+        let by_message_id = threads::by_message_id(&account_emails);
+        let newest = emails
+            .iter()
+            .filter(|email| &threads::thread_id(email, &by_message_id) == thread)
+            .max_by_key(|email| email.uid_number())?;
+        Some(Pin {
+            uid: newest.uid.clone(),
+            ..pin.clone()
+        })
+    }
+
+    // Called after the account is synced, only its synced folders are
+    // known to be up to date.
+    fn prune(&self, app: &AppHandle, account: &str) {
+        let mut pins = self.pins.lock().unwrap();
+        let mut changed = false;
+        let mut updated = Vec::new();
+        for pin in pins.iter() {
+            if pin.account != account {
+                updated.push(pin.clone());
+                continue;
+            }
+            if !scheduler::SYNCED_FOLDERS.contains(&pin.folder.as_str()) {
+                // Kept while the server can't be asked.
+                match is_on_server(pin) {
+                    Ok(false) => changed = true,
+                    _ => updated.push(pin.clone()),
+                }
+                continue;
+            }
+            let emails = self.cache.get_emails(account, &pin.folder);
+            // Not cached yet, nothing to compare with.
+            if emails.is_empty() {
+                updated.push(pin.clone());
+                continue;
+            }
+            match self.follow(pin, &emails) {
+                Some(followed) => {
+                    changed |= followed.uid != pin.uid;
+                    updated.push(followed);
+                }
+                None => changed = true,
+            }
+        }
+        if !changed {
+            return;
+        }
+        match self.save(app, &updated) {
+            Ok(()) => *pins = updated,
+            Err(err) => println!("{}", err),
+        }
+    }
+}
+
+// Flags of an email that is not in the folder anymore are None.
+fn is_on_server(pin: &Pin) -> Result<bool, String> {
+    let response = api::get(
+        &format!("/get-email-flags/{}/{}", pin.account, pin.uid),
+        &[("folder", &pin.folder)],
+    )?;
+    let flags: Option<Vec<String>> =
+        serde_json::from_value(api::get_account_data(response, &pin.account)?)
+            .map_err(|err| format!("Failed to parse flags of {}: {}", pin.uid, err))?;
+    Ok(flags.is_some())
+}
+
+pub fn listen_sync(app: &AppHandle, pins: Arc<Pins>) {
+    let handle = app.clone();
+    app.listen(scheduler::SYNC_FINISHED_EVENT, move |event| {
+        let Ok(accounts) = serde_json::from_str::<Vec<String>>(event.payload()) else {
+            return;
+        };
+        let app = handle.clone();
+        let pins = pins.clone();
+        thread::spawn(move || {
+            for account in accounts {
+                pins.prune(&app, &account);
+            }
+        });
+    });
+}
+
+#[tauri::command]
+pub fn get_pinned(pins: State<'_, Arc<Pins>>) -> Vec<Pin> {
+    pins.get_pinned()
+}
+
+// `thread` pins the thread of the email instead of the email alone.
+#[tauri::command]
+pub fn pin_email(
+    app: AppHandle,
+    pins: State<'_, Arc<Pins>>,
+    account: String,
+    folder: String,
+    uid: String,
+    thread: bool,
+) -> Result<Pin, String> {
+    pins.pin(&app, &account, &folder, &uid, thread)
+}
+
+#[tauri::command]
+pub fn unpin_email(
+    app: AppHandle,
+    pins: State<'_, Arc<Pins>>,
+    account: String,
+    folder: String,
+    uid: String,
+) -> Result<(), String> {
+    pins.unpin(&app, &account, &folder, &uid)
+}
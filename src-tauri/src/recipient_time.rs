@@ -0,0 +1,297 @@
+use chrono::{
+    DateTime, Datelike, Duration as ChronoDuration, FixedOffset, NaiveDate, NaiveTime, Timelike,
+    Utc,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tauri::State;
+
+use crate::cache::{Cache, CachedEmail};
+use crate::consts;
+use crate::threads;
+use crate::utils;
+
+// The time zone of a correspondent is inferred from the offsets of the Date
+// headers of their emails: the most common offset of the latest ones, so a
+// DST change only takes over once most recent emails have it. Compose shows
+// the local time of the recipients with it, and scheduled sends are
+// suggested for the working hours of the recipient, skipping the weekends
+// and the holidays of the settings.
+const INFERRED_EMAILS: usize = 20;
+const WORK_START_HOUR: u32 = 9;
+const WORK_END_HOUR: u32 = 18;
+// Sends that are due in less than this are suggested as they are.
+const MIN_DELAY_MIN: i64 = 5;
+// Correspondents with fewer emails are not listed as frequent.
+const MIN_FREQUENT_EMAILS: usize = 3;
+const MAX_FREQUENT_CORRESPONDENTS: usize = 50;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SendTimeSettings {
+    // YYYY-MM-DD, skipped like the weekends.
+    pub holidays: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SendTimeSuggestion {
+    // RFC 3339, in the offset of the recipient.
+    pub send_at: String,
+    // Minutes east of UTC.
+    pub offset_min: i32,
+    // Whether the offset was inferred from the emails of the recipient,
+    // the local one is used otherwise.
+    pub inferred: bool,
+    // Whether now is already a good time.
+    pub now: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecipientLocalTime {
+    pub address: String,
+    // Display name of their latest email, if it has one.
+    pub name: Option<String>,
+    // RFC 3339, in the offset of the recipient.
+    pub now: String,
+    pub offset_min: i32,
+    // How many emails the offset is inferred from.
+    pub emails: usize,
+    // Outside of their working hours or on a weekend.
+    pub off_hours: bool,
+}
+
+// Most common offset of the latest dates, ties go to the offset of the
+// latest one.
+fn most_common_offset(mut dates: Vec<DateTime<FixedOffset>>) -> Option<FixedOffset> {
+    dates.sort_by_key(|date| std::cmp::Reverse(date.timestamp()));
+    let mut counts: HashMap<i32, usize> = HashMap::new();
+    for date in dates.iter().take(INFERRED_EMAILS) {
+        *counts.entry(date.offset().local_minus_utc()).or_default() += 1;
+    }
+    let latest = dates.first()?.offset().local_minus_utc();
+    let best = counts
+        .iter()
+        .max_by_key(|(offset, count)| (**count, **offset == latest))
+        .map(|(offset, _)| *offset)?;
+    FixedOffset::east_opt(best)
+}
+
+fn display_name(sender: &str) -> Option<String> {
+    let (name, _) = sender.rsplit_once('<')?;
+    let name = name.trim().trim_matches('"').trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+// Emails the correspondents sent to the account, by their address.
+fn emails_by_sender(cache: &Cache, account: &str) -> HashMap<String, Vec<CachedEmail>> {
+    let mut senders: HashMap<String, Vec<CachedEmail>> = HashMap::new();
+    for email in threads::get_account_emails(cache, account) {
+        let address = utils::extract_email_address(&email.sender).to_lowercase();
+        if !address.is_empty() && !address.eq_ignore_ascii_case(account) {
+            senders.entry(address).or_default().push(email);
+        }
+    }
+    senders
+}
+
+fn local_time(address: &str, emails: &[CachedEmail]) -> Option<RecipientLocalTime> {
+    let dates: Vec<(DateTime<FixedOffset>, &CachedEmail)> = emails
+        .iter()
+        .filter_map(|email| Some((utils::parse_email_date(&email.date)?, email)))
+        .collect();
+    let latest = dates.iter().max_by_key(|(date, _)| date.timestamp())?.1;
+    let offset = most_common_offset(dates.iter().map(|(date, _)| *date).collect())?;
+    let now = Utc::now().with_timezone(&offset);
+    let hour = now.hour();
+    Some(RecipientLocalTime {
+        address: address.to_string(),
+        name: display_name(&latest.sender),
+        now: now.to_rfc3339(),
+        offset_min: offset.local_minus_utc() / 60,
+        emails: dates.len(),
+        off_hours: !is_work_day(now.date_naive(), &[])
+            || !(WORK_START_HOUR..WORK_END_HOUR).contains(&hour),
+    })
+}
+
+// Offset of the emails the address sent to the account, None if it never
+// sent one.
+pub fn infer_offset(cache: &Cache, account: &str, address: &str) -> Option<FixedOffset> {
+    let address = utils::extract_email_address(address);
+    let dates = threads::get_account_emails(cache, account)
+        .iter()
+        .filter(|email| utils::extract_email_address(&email.sender).eq_ignore_ascii_case(&address))
+        .filter_map(|email| utils::parse_email_date(&email.date))
+        .collect();
+    most_common_offset(dates)
+}
+
+fn is_work_day(date: NaiveDate, holidays: &[NaiveDate]) -> bool {
+    date.weekday().num_days_from_monday() < 5 && !holidays.contains(&date)
+}
+
+// Now if it is in the working hours of the recipient, otherwise the start
+// of their next working day.
+fn suggest(
+    now: DateTime<Utc>,
+    offset: FixedOffset,
+    holidays: &[NaiveDate],
+) -> (DateTime<FixedOffset>, bool) {
+    let local = now.with_timezone(&offset);
+    let start = NaiveTime::from_hms_opt(WORK_START_HOUR, 0, 0).unwrap_or(NaiveTime::MIN);
+    let end = NaiveTime::from_hms_opt(WORK_END_HOUR, 0, 0).unwrap_or(NaiveTime::MIN);
+    let latest_today = end - ChronoDuration::minutes(MIN_DELAY_MIN);
+    if is_work_day(local.date_naive(), holidays)
+        && local.time() >= start
+        && local.time() < latest_today
+    {
+        return (local, true);
+    }
+
+    let mut date = local.date_naive();
+    if local.time() >= start {
+        date += ChronoDuration::days(1);
+    }
+    // Bounded for a list of holidays that covers everything.
+    for _ in 0..366 {
+        if is_work_day(date, holidays) {
+            break;
+        }
+        date += ChronoDuration::days(1);
+    }
+    let send_at = date
+        .and_time(start)
+        .and_local_timezone(offset)
+        .single()
+        .unwrap_or(local);
+    (send_at, false)
+}
+
+pub struct RecipientTime {
+    cache: Arc<Cache>,
+    settings: Mutex<SendTimeSettings>,
+}
+
+impl RecipientTime {
+    pub fn load(cache: Arc<Cache>) -> Self {
+        RecipientTime {
+            cache,
+            settings: Mutex::new(
+                utils::read_json_file(consts::SEND_TIME_SETTINGS_FILE_PATH).unwrap_or_default(),
+            ),
+        }
+    }
+
+    pub fn get_settings(&self) -> SendTimeSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: SendTimeSettings) -> Result<(), String> {
+        for holiday in &settings.holidays {
+            NaiveDate::parse_from_str(holiday, "%Y-%m-%d")
+                .map_err(|_| format!("Invalid date: {}, expected YYYY-MM-DD", holiday))?;
+        }
+        utils::write_json_file(consts::SEND_TIME_SETTINGS_FILE_PATH, &settings)?;
+        *self.settings.lock().unwrap() = settings;
+        Ok(())
+    }
+
+    // The offset is inferred from the recipient if it is not given.
+    pub fn suggest_send_time(
+        &self,
+        account: &str,
+        recipient: Option<&str>,
+        offset_min: Option<i32>,
+    ) -> Result<SendTimeSuggestion, String> {
+        let (offset, inferred) = match offset_min {
+            Some(offset_min) => (
+                FixedOffset::east_opt(offset_min * 60)
+                    .ok_or(format!("Invalid offset: {}", offset_min))?,
+                false,
+            ),
+            None => match recipient
+                .and_then(|recipient| infer_offset(&self.cache, account, recipient))
+            {
+                Some(offset) => (offset, true),
+                None => (*chrono::Local::now().offset(), false),
+            },
+        };
+        let holidays: Vec<NaiveDate> = self
+            .get_settings()
+            .holidays
+            .iter()
+            .filter_map(|holiday| NaiveDate::parse_from_str(holiday, "%Y-%m-%d").ok())
+            .collect();
+
+        let (send_at, now) = suggest(Utc::now(), offset, &holidays);
+        Ok(SendTimeSuggestion {
+            send_at: send_at.to_rfc3339(),
+            offset_min: offset.local_minus_utc() / 60,
+            inferred,
+            now,
+        })
+    }
+
+    pub fn get_local_time(&self, account: &str, address: &str) -> Option<RecipientLocalTime> {
+        let address = utils::extract_email_address(address).to_lowercase();
+        let mut senders = emails_by_sender(&self.cache, account);
+        local_time(&address, &senders.remove(&address)?)
+    }
+
+    // Correspondents with the most emails first.
+    pub fn get_frequent(&self, account: &str) -> Vec<RecipientLocalTime> {
+        let mut senders: Vec<(String, Vec<CachedEmail>)> = emails_by_sender(&self.cache, account)
+            .into_iter()
+            .filter(|(_, emails)| emails.len() >= MIN_FREQUENT_EMAILS)
+            .collect();
+        senders.sort_by_key(|(_, emails)| std::cmp::Reverse(emails.len()));
+        senders
+            .iter()
+            .take(MAX_FREQUENT_CORRESPONDENTS)
+            .filter_map(|(address, emails)| local_time(address, emails))
+            .collect()
+    }
+}
+
+#[tauri::command]
+pub fn get_send_time_settings(recipient_time: State<'_, Arc<RecipientTime>>) -> SendTimeSettings {
+    recipient_time.get_settings()
+}
+
+#[tauri::command]
+pub fn set_send_time_settings(
+    recipient_time: State<'_, Arc<RecipientTime>>,
+    settings: SendTimeSettings,
+) -> Result<(), String> {
+    recipient_time.set_settings(settings)
+}
+
+#[tauri::command]
+pub fn suggest_send_time(
+    recipient_time: State<'_, Arc<RecipientTime>>,
+    account: String,
+    recipient: Option<String>,
+    offset_min: Option<i32>,
+) -> Result<SendTimeSuggestion, String> {
+    recipient_time.suggest_send_time(&account, recipient.as_deref(), offset_min)
+}
+
+// Like "It's 02:14 for Yuki" in compose, None if the address never sent an
+// email to the account.
+#[tauri::command]
+pub fn get_recipient_local_time(
+    recipient_time: State<'_, Arc<RecipientTime>>,
+    account: String,
+    address: String,
+) -> Option<RecipientLocalTime> {
+    recipient_time.get_local_time(&account, &address)
+}
+
+#[tauri::command]
+pub fn get_correspondent_time_zones(
+    recipient_time: State<'_, Arc<RecipientTime>>,
+    account: String,
+) -> Vec<RecipientLocalTime> {
+    recipient_time.get_frequent(&account)
+}
@@ -0,0 +1,767 @@
+#[cfg(not(target_os = "windows"))]
+use std::path::Path;
+use std::process::Command;
+
+use crate::utils;
+
+// Starts the server with reduced privileges, so a compromised dependency of
+// the python stack can use the network (it has to reach the mail servers)
+// but can't tamper with other processes or write outside of the server's own
+// data, runtime and temp directories. The rest of the app data (outbox, drop
+// folder, share targets...) is trusted by the app, so it is out of reach.
+// On Linux and macOS reads are limited to the python install, the data of
+// the server and the system libraries as well, so personal files can't be
+// sent anywhere. Sandboxing is best effort: if the OS doesn't support it, a
+// warning is printed and the server is started as before.
+
+// Same as `ROOT_DIR` of the python server.
+const SERVER_DATA_DIR_PATH: &str = "/.openmail/server";
+
+fn writable_dirs() -> Vec<String> {
+    let mut dirs = vec![
+        utils::build_home_path(SERVER_DATA_DIR_PATH),
+        utils::build_runtime_path(""),
+    ];
+    dirs.extend(utils::build_temp_dir().map(|dir| dir.display().to_string()));
+    for dir in &dirs {
+        std::fs::create_dir_all(dir).ok();
+    }
+    dirs
+}
+
+// The sandboxes of Linux and macOS match the real paths, so symlinks (like
+// /var or /tmp on macOS) are resolved.
+#[cfg(not(target_os = "windows"))]
+fn resolve(dir: &str) -> String {
+    Path::new(dir)
+        .canonicalize()
+        .map_or(dir.to_string(), |dir| dir.display().to_string())
+}
+
+// The start script and the server next to it, and the python install the
+// virtual environment of the server is created from.
+#[cfg(not(target_os = "windows"))]
+fn python_dirs(command: &Command) -> Vec<String> {
+    let Some(script_dir) = command
+        .get_current_dir()
+        .and_then(|dir| dir.canonicalize().ok())
+    else {
+        return Vec::new();
+    };
+    let server_dir = script_dir.with_file_name("server");
+    let python_home = std::fs::read_to_string(server_dir.join(".venv/pyvenv.cfg"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(key, _)| key.trim() == "home")
+        .and_then(|(_, home)| Path::new(home.trim()).parent().map(Path::to_path_buf));
+    [Some(script_dir), Some(server_dir), python_home]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.display().to_string())
+        .collect()
+}
+
+#[cfg(not(target_os = "windows"))]
+fn readable_dirs(command: &Command) -> Vec<String> {
+    let mut dirs: Vec<String> = platform::SYSTEM_DIRS
+        .iter()
+        .map(|dir| dir.to_string())
+        .collect();
+    dirs.extend(
+        platform::HOME_READABLE_PATHS
+            .iter()
+            .map(|path| utils::build_home_path(path)),
+    );
+    dirs.extend(python_dirs(command));
+    dirs.iter().map(|dir| resolve(dir)).collect()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use std::ffi::CString;
+    use std::os::fd::{FromRawFd, OwnedFd};
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    // Libraries, certificates, DNS and locale settings, and /proc for the
+    // process itself (other processes are out of reach of a landlocked one).
+    pub const SYSTEM_DIRS: &[&str] = &[
+        "/usr",
+        "/lib",
+        "/lib64",
+        "/lib32",
+        "/bin",
+        "/sbin",
+        "/etc",
+        "/opt",
+        "/nix/store",
+        "/proc",
+        "/run/systemd/resolve",
+    ];
+    // Config of the keyring package, the passwords themselves are read over
+    // D-Bus.
+    pub const HOME_READABLE_PATHS: &[&str] =
+        &["/.config/python_keyring", "/.local/share/python_keyring"];
+
+    // Landlock ABI, see include/uapi/linux/landlock.h
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1 << 0;
+    const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+    const LANDLOCK_ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    const LANDLOCK_ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const LANDLOCK_ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    const LANDLOCK_ACCESS_FS_READ_DIR: u64 = 1 << 3;
+    const LANDLOCK_ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const LANDLOCK_ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const LANDLOCK_ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const LANDLOCK_ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const LANDLOCK_ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const LANDLOCK_ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const LANDLOCK_ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const LANDLOCK_ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const LANDLOCK_ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+    const LANDLOCK_ACCESS_FS_REFER: u64 = 1 << 13;
+    const LANDLOCK_ACCESS_FS_TRUNCATE: u64 = 1 << 14;
+
+    const READ_ACCESS: u64 =
+        LANDLOCK_ACCESS_FS_EXECUTE | LANDLOCK_ACCESS_FS_READ_FILE | LANDLOCK_ACCESS_FS_READ_DIR;
+    const WRITE_ACCESS: u64 = LANDLOCK_ACCESS_FS_WRITE_FILE
+        | LANDLOCK_ACCESS_FS_REMOVE_DIR
+        | LANDLOCK_ACCESS_FS_REMOVE_FILE
+        | LANDLOCK_ACCESS_FS_MAKE_CHAR
+        | LANDLOCK_ACCESS_FS_MAKE_DIR
+        | LANDLOCK_ACCESS_FS_MAKE_REG
+        | LANDLOCK_ACCESS_FS_MAKE_SOCK
+        | LANDLOCK_ACCESS_FS_MAKE_FIFO
+        | LANDLOCK_ACCESS_FS_MAKE_BLOCK
+        | LANDLOCK_ACCESS_FS_MAKE_SYM;
+
+    #[repr(C)]
+    struct LandlockRulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct LandlockPathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xc000_00b7;
+    // Syscalls of the x32 ABI have this bit set on x86_64.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    // Nothing the server needs, but everything a compromised dependency
+    // could use to escape or to tamper with the rest of the system.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const DENIED_SYSCALLS: &[libc::c_long] = &[
+        libc::SYS_ptrace,
+        libc::SYS_process_vm_readv,
+        libc::SYS_process_vm_writev,
+        libc::SYS_mount,
+        libc::SYS_umount2,
+        libc::SYS_pivot_root,
+        libc::SYS_chroot,
+        libc::SYS_setns,
+        libc::SYS_unshare,
+        libc::SYS_open_by_handle_at,
+        libc::SYS_bpf,
+        libc::SYS_perf_event_open,
+        libc::SYS_userfaultfd,
+        libc::SYS_kexec_load,
+        libc::SYS_kexec_file_load,
+        libc::SYS_init_module,
+        libc::SYS_finit_module,
+        libc::SYS_delete_module,
+        libc::SYS_reboot,
+        libc::SYS_swapon,
+        libc::SYS_swapoff,
+        libc::SYS_acct,
+        libc::SYS_settimeofday,
+        libc::SYS_clock_settime,
+        libc::SYS_sethostname,
+        libc::SYS_setdomainname,
+    ];
+
+    fn add_rule(ruleset: &OwnedFd, dir: &str, access: u64) -> Result<(), String> {
+        let path = CString::new(dir).map_err(|err| format!("Invalid path {}: {}", dir, err))?;
+        let parent_fd = unsafe { libc::open(path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if parent_fd < 0 {
+            // Doesn't exist on this system.
+            return Ok(());
+        }
+        let parent = unsafe { OwnedFd::from_raw_fd(parent_fd) };
+
+        let rule = LandlockPathBeneathAttr {
+            allowed_access: access,
+            parent_fd: std::os::fd::AsRawFd::as_raw_fd(&parent),
+        };
+        let result = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                std::os::fd::AsRawFd::as_raw_fd(ruleset),
+                LANDLOCK_RULE_PATH_BENEATH,
+                &rule as *const LandlockPathBeneathAttr,
+                0,
+            )
+        };
+        if result < 0 {
+            return Err(format!(
+                "Failed to add landlock rule for {}: {}",
+                dir,
+                std::io::Error::last_os_error()
+            ));
+        }
+        Ok(())
+    }
+
+    fn create_ruleset(
+        writable_dirs: &[String],
+        readable_dirs: &[String],
+    ) -> Result<OwnedFd, String> {
+        let abi = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                std::ptr::null::<LandlockRulesetAttr>(),
+                0,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        if abi < 1 {
+            return Err("Landlock is not supported by the kernel".to_string());
+        }
+
+        // Without handling REFER, files can't be moved between directories
+        // at all, so it is handled (and allowed below) whenever possible.
+        let mut access = READ_ACCESS | WRITE_ACCESS;
+        if abi >= 2 {
+            access |= LANDLOCK_ACCESS_FS_REFER;
+        }
+        if abi >= 3 {
+            access |= LANDLOCK_ACCESS_FS_TRUNCATE;
+        }
+
+        let attr = LandlockRulesetAttr {
+            handled_access_fs: access,
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr as *const LandlockRulesetAttr,
+                std::mem::size_of::<LandlockRulesetAttr>(),
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(format!(
+                "Failed to create landlock ruleset: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        let ruleset = unsafe { OwnedFd::from_raw_fd(fd as i32) };
+
+        for dir in readable_dirs {
+            add_rule(&ruleset, dir, READ_ACCESS)?;
+        }
+        // Nothing is run from the writable directories.
+        for dir in writable_dirs.iter().map(String::as_str).chain(["/dev"]) {
+            add_rule(&ruleset, dir, access & !LANDLOCK_ACCESS_FS_EXECUTE)?;
+        }
+        Ok(ruleset)
+    }
+
+    fn statement(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn seccomp_filter() -> Vec<libc::sock_filter> {
+        let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+        let jeq = libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K;
+        let jge = libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K;
+        let ret = libc::BPF_RET | libc::BPF_K;
+        let denied = DENIED_SYSCALLS.len() as u8;
+
+        // Offsets of `arch` and `nr` in `struct seccomp_data`. Every jump
+        // lands either on the next statement or on the final "deny".
+        let mut filter = vec![
+            statement(load, 4, 0, 0),
+            statement(jeq, AUDIT_ARCH, 0, denied + 3),
+            statement(load, 0, 0, 0),
+            statement(jge, X32_SYSCALL_BIT, denied + 1, 0),
+        ];
+        for (index, syscall) in DENIED_SYSCALLS.iter().enumerate() {
+            filter.push(statement(jeq, *syscall as u32, denied - index as u8, 0));
+        }
+        filter.push(statement(ret, libc::SECCOMP_RET_ALLOW, 0, 0));
+        filter.push(statement(
+            ret,
+            libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA),
+            0,
+            0,
+        ));
+        filter
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    fn seccomp_filter() -> Vec<libc::sock_filter> {
+        Vec::new()
+    }
+
+    pub fn restrict(
+        command: &mut Command,
+        writable_dirs: &[String],
+        readable_dirs: &[String],
+    ) -> Result<(), String> {
+        let ruleset = create_ruleset(writable_dirs, readable_dirs)?;
+        let filter = seccomp_filter();
+        // Runs in the forked child right before exec, so only plain syscalls
+        // are made here. The ruleset is closed on exec.
+        unsafe {
+            command.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                let ruleset_fd = std::os::fd::AsRawFd::as_raw_fd(&ruleset);
+                if libc::syscall(libc::SYS_landlock_restrict_self, ruleset_fd, 0) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if !filter.is_empty() {
+                    let program = libc::sock_fprog {
+                        len: filter.len() as u16,
+                        filter: filter.as_ptr() as *mut libc::sock_filter,
+                    };
+                    if libc::prctl(
+                        libc::PR_SET_SECCOMP,
+                        libc::SECCOMP_MODE_FILTER,
+                        &program as *const libc::sock_fprog,
+                    ) != 0
+                    {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod platform {
+    use std::process::Command;
+
+    // /private/var/select/sh is the shell /bin/sh runs, and dyld, time
+    // zone and certificate files are under /private/var/db and /private/etc.
+    pub const SYSTEM_DIRS: &[&str] = &[
+        "/System",
+        "/Library",
+        "/usr",
+        "/bin",
+        "/sbin",
+        "/opt",
+        "/dev",
+        "/private/etc",
+        "/private/var/db/dyld",
+        "/private/var/db/timezone",
+        "/private/var/select",
+    ];
+    // Keychains and the config of the keyring package, the keychains are
+    // encrypted and unlocked by the OS.
+    pub const HOME_READABLE_PATHS: &[&str] = &[
+        "/Library/Keychains",
+        "/Library/Application Support/python_keyring",
+    ];
+
+    fn quote(path: &str) -> String {
+        format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+
+    fn subpaths(dirs: &[String]) -> String {
+        dirs.iter()
+            .map(|dir| format!("(subpath {})", quote(dir)))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    // sandbox-exec only wraps a program, so the command is rebuilt
+    // to run the original one inside of it. Metadata can be read everywhere
+    // so paths can be resolved.
+    pub fn restrict(
+        command: &mut Command,
+        writable_dirs: &[String],
+        readable_dirs: &[String],
+    ) -> Result<(), String> {
+        let writable = subpaths(writable_dirs);
+        let profile = format!(
+            "(version 1)\
+             (allow default)\
+             (deny process-info* (target others))\
+             (deny file-read* file-write*)\
+             (allow file-read-metadata)\
+             (allow file-read* (literal \"/\") {} {})\
+             (allow file-write* {} (subpath \"/dev\"))",
+            subpaths(readable_dirs),
+            writable,
+            writable
+        );
+
+        let mut sandboxed = Command::new("sandbox-exec");
+        sandboxed
+            .arg("-p")
+            .arg(profile)
+            .arg(command.get_program())
+            .args(command.get_args());
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => sandboxed.env(key, value),
+                None => sandboxed.env_remove(key),
+            };
+        }
+        if let Some(dir) = command.get_current_dir() {
+            sandboxed.current_dir(dir);
+        }
+        *command = sandboxed;
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod platform {
+    use std::collections::BTreeMap;
+    use std::env;
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStrExt;
+    use std::process::Command;
+    use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, LocalFree, HANDLE};
+    use windows_sys::Win32::Security::Authorization::{
+        ConvertStringSecurityDescriptorToSecurityDescriptorW, SetNamedSecurityInfoW,
+        SDDL_REVISION_1, SE_FILE_OBJECT,
+    };
+    use windows_sys::Win32::Security::{
+        CreateRestrictedToken, CreateWellKnownSid, GetSecurityDescriptorSacl, SetTokenInformation,
+        TokenIntegrityLevel, WinBuiltinAdministratorsSid, WinLowLabelSid, ACL,
+        DISABLE_MAX_PRIVILEGE, LABEL_SECURITY_INFORMATION, PSECURITY_DESCRIPTOR,
+        SID_AND_ATTRIBUTES, TOKEN_ADJUST_DEFAULT, TOKEN_ASSIGN_PRIMARY, TOKEN_DUPLICATE,
+        TOKEN_MANDATORY_LABEL, TOKEN_QUERY, WELL_KNOWN_SID_TYPE,
+    };
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectBasicUIRestrictions,
+        SetInformationJobObject, JOBOBJECT_BASIC_UI_RESTRICTIONS, JOB_OBJECT_UILIMIT_DESKTOP,
+        JOB_OBJECT_UILIMIT_DISPLAYSETTINGS, JOB_OBJECT_UILIMIT_EXITWINDOWS,
+        JOB_OBJECT_UILIMIT_GLOBALATOMS, JOB_OBJECT_UILIMIT_HANDLES,
+        JOB_OBJECT_UILIMIT_READCLIPBOARD, JOB_OBJECT_UILIMIT_SYSTEMPARAMETERS,
+        JOB_OBJECT_UILIMIT_WRITECLIPBOARD,
+    };
+    use windows_sys::Win32::System::Threading::{
+        CreateProcessAsUserW, GetCurrentProcess, OpenProcessToken, ResumeThread, CREATE_SUSPENDED,
+        CREATE_UNICODE_ENVIRONMENT, PROCESS_INFORMATION, STARTUPINFOW,
+    };
+
+    // SECURITY_MAX_SID_SIZE and SE_GROUP_INTEGRITY of winnt.h
+    const MAX_SID_SIZE: usize = 68;
+    const SE_GROUP_INTEGRITY: u32 = 0x20;
+    // Low integrity processes can only write to objects that are labeled
+    // low, like the writable directories of the server once this is set
+    // on them (and inherited by everything in them).
+    const LOW_INTEGRITY_LABEL: &str = "S:(ML;OICI;NW;;;LW)";
+
+    fn last_error(message: &str) -> String {
+        format!("{}: {}", message, std::io::Error::last_os_error())
+    }
+
+    fn wide(value: &str) -> Vec<u16> {
+        value.encode_utf16().chain([0]).collect()
+    }
+
+    fn well_known_sid(kind: WELL_KNOWN_SID_TYPE) -> Result<Vec<u8>, String> {
+        let mut sid = vec![0u8; MAX_SID_SIZE];
+        let mut size = MAX_SID_SIZE as u32;
+        let result = unsafe {
+            CreateWellKnownSid(
+                kind,
+                std::ptr::null_mut(),
+                sid.as_mut_ptr().cast(),
+                &mut size,
+            )
+        };
+        if result == 0 {
+            return Err(last_error("Failed to create SID"));
+        }
+        sid.truncate(size as usize);
+        Ok(sid)
+    }
+
+    fn label_low(dir: &str) -> Result<(), String> {
+        unsafe {
+            let mut descriptor: PSECURITY_DESCRIPTOR = std::ptr::null_mut();
+            if ConvertStringSecurityDescriptorToSecurityDescriptorW(
+                wide(LOW_INTEGRITY_LABEL).as_ptr(),
+                SDDL_REVISION_1,
+                &mut descriptor,
+                std::ptr::null_mut(),
+            ) == 0
+            {
+                return Err(last_error("Failed to create low integrity label"));
+            }
+
+            let mut present = 0;
+            let mut defaulted = 0;
+            let mut sacl: *mut ACL = std::ptr::null_mut();
+            let error =
+                if GetSecurityDescriptorSacl(descriptor, &mut present, &mut sacl, &mut defaulted)
+                    != 0
+                {
+                    SetNamedSecurityInfoW(
+                        wide(dir).as_ptr(),
+                        SE_FILE_OBJECT,
+                        LABEL_SECURITY_INFORMATION,
+                        std::ptr::null_mut(),
+                        std::ptr::null_mut(),
+                        std::ptr::null(),
+                        sacl,
+                    )
+                } else {
+                    GetLastError()
+                };
+            LocalFree(descriptor as _);
+            if error != 0 {
+                return Err(format!(
+                    "Failed to label {}: {}",
+                    dir,
+                    std::io::Error::from_raw_os_error(error as i32)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // The token of the app without any privileges (except the one that's
+    // needed to traverse directories), with the administrators group only
+    // used to deny access (when the app is run as administrator) and with
+    // low integrity.
+    fn restricted_token() -> Result<HANDLE, String> {
+        let mut administrators = well_known_sid(WinBuiltinAdministratorsSid)?;
+        let mut low_integrity = well_known_sid(WinLowLabelSid)?;
+        unsafe {
+            let mut token: HANDLE = std::ptr::null_mut();
+            if OpenProcessToken(
+                GetCurrentProcess(),
+                TOKEN_DUPLICATE | TOKEN_ASSIGN_PRIMARY | TOKEN_QUERY | TOKEN_ADJUST_DEFAULT,
+                &mut token,
+            ) == 0
+            {
+                return Err(last_error("Failed to open app token"));
+            }
+
+            let disabled = SID_AND_ATTRIBUTES {
+                Sid: administrators.as_mut_ptr().cast(),
+                Attributes: 0,
+            };
+            let mut restricted: HANDLE = std::ptr::null_mut();
+            let result = CreateRestrictedToken(
+                token,
+                DISABLE_MAX_PRIVILEGE,
+                1,
+                &disabled,
+                0,
+                std::ptr::null(),
+                0,
+                std::ptr::null(),
+                &mut restricted,
+            );
+            CloseHandle(token);
+            if result == 0 {
+                return Err(last_error("Failed to create restricted token"));
+            }
+
+            let label = TOKEN_MANDATORY_LABEL {
+                Label: SID_AND_ATTRIBUTES {
+                    Sid: low_integrity.as_mut_ptr().cast(),
+                    Attributes: SE_GROUP_INTEGRITY,
+                },
+            };
+            if SetTokenInformation(
+                restricted,
+                TokenIntegrityLevel,
+                &label as *const TOKEN_MANDATORY_LABEL as *const _,
+                (std::mem::size_of::<TOKEN_MANDATORY_LABEL>() + low_integrity.len()) as u32,
+            ) == 0
+            {
+                CloseHandle(restricted);
+                return Err(last_error("Failed to lower token integrity"));
+            }
+            Ok(restricted)
+        }
+    }
+
+    // The server has no window, so it doesn't need the desktop, clipboard
+    // or the handles of other windows.
+    fn restrict_ui(process: HANDLE) -> Result<(), String> {
+        unsafe {
+            let job = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if job.is_null() {
+                return Err(last_error("Failed to create job object"));
+            }
+
+            let restrictions = JOBOBJECT_BASIC_UI_RESTRICTIONS {
+                UIRestrictionsClass: JOB_OBJECT_UILIMIT_DESKTOP
+                    | JOB_OBJECT_UILIMIT_DISPLAYSETTINGS
+                    | JOB_OBJECT_UILIMIT_EXITWINDOWS
+                    | JOB_OBJECT_UILIMIT_GLOBALATOMS
+                    | JOB_OBJECT_UILIMIT_HANDLES
+                    | JOB_OBJECT_UILIMIT_READCLIPBOARD
+                    | JOB_OBJECT_UILIMIT_SYSTEMPARAMETERS
+                    | JOB_OBJECT_UILIMIT_WRITECLIPBOARD,
+            };
+            let result = SetInformationJobObject(
+                job,
+                JobObjectBasicUIRestrictions,
+                &restrictions as *const JOBOBJECT_BASIC_UI_RESTRICTIONS as *const _,
+                std::mem::size_of::<JOBOBJECT_BASIC_UI_RESTRICTIONS>() as u32,
+            ) != 0
+                && AssignProcessToJobObject(job, process) != 0;
+            // The job lives as long as the processes assigned to it.
+            CloseHandle(job);
+            if !result {
+                return Err(last_error("Failed to restrict server UI access"));
+            }
+        }
+        Ok(())
+    }
+
+    // CreateProcess takes the program and the arguments as one string.
+    fn command_line(command: &Command) -> Vec<u16> {
+        let line = std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| {
+                let arg = arg.to_string_lossy();
+                if arg.is_empty() || arg.contains([' ', '\t', '"']) {
+                    format!("\"{}\"", arg.replace('"', "\\\""))
+                } else {
+                    arg.into_owned()
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        wide(&line)
+    }
+
+    // Environment of the app with the variables of the command, sorted by
+    // name (case insensitively, like the names themselves) as CreateProcess
+    // expects it.
+    fn environment(command: &Command) -> Vec<u16> {
+        let mut variables: BTreeMap<String, (OsString, OsString)> = env::vars_os()
+            .map(|(key, value)| (key.to_string_lossy().to_uppercase(), (key, value)))
+            .collect();
+        for (key, value) in command.get_envs() {
+            let name = key.to_string_lossy().to_uppercase();
+            match value {
+                Some(value) => {
+                    variables.insert(name, (key.to_os_string(), value.to_os_string()));
+                }
+                None => {
+                    variables.remove(&name);
+                }
+            }
+        }
+
+        let mut block = Vec::new();
+        for (key, value) in variables.values() {
+            block.extend(key.encode_wide());
+            block.push('=' as u16);
+            block.extend(value.encode_wide());
+            block.push(0);
+        }
+        block.push(0);
+        block
+    }
+
+    // The process is created suspended with the restricted token and put
+    // in the job before it is resumed, so nothing (the script or python
+    // started by it) runs with more. An error is only returned if the
+    // process couldn't be created.
+    pub fn spawn(command: &Command, writable_dirs: &[String]) -> Result<(), String> {
+        for dir in writable_dirs {
+            label_low(dir)?;
+        }
+        let token = restricted_token()?;
+
+        let mut command_line = command_line(command);
+        let environment = environment(command);
+        let current_dir = command.get_current_dir().and_then(|dir| {
+            let dir = env::current_dir().ok()?.join(dir);
+            Some(
+                dir.as_os_str()
+                    .encode_wide()
+                    .chain([0])
+                    .collect::<Vec<u16>>(),
+            )
+        });
+        unsafe {
+            let mut startup: STARTUPINFOW = std::mem::zeroed();
+            startup.cb = std::mem::size_of::<STARTUPINFOW>() as u32;
+            let mut process: PROCESS_INFORMATION = std::mem::zeroed();
+            let created = CreateProcessAsUserW(
+                token,
+                std::ptr::null(),
+                command_line.as_mut_ptr(),
+                std::ptr::null(),
+                std::ptr::null(),
+                0,
+                CREATE_SUSPENDED | CREATE_UNICODE_ENVIRONMENT,
+                environment.as_ptr().cast(),
+                current_dir
+                    .as_ref()
+                    .map_or(std::ptr::null(), |dir| dir.as_ptr()),
+                &startup,
+                &mut process,
+            );
+            CloseHandle(token);
+            if created == 0 {
+                return Err(last_error("Failed to start restricted server"));
+            }
+
+            if let Err(err) = restrict_ui(process.hProcess) {
+                println!("Server is started without UI restrictions: {}", err);
+            }
+            ResumeThread(process.hThread);
+            CloseHandle(process.hThread);
+            CloseHandle(process.hProcess);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn spawn(mut command: Command) -> Result<(), String> {
+    let writable_dirs: Vec<String> = writable_dirs().iter().map(|dir| resolve(dir)).collect();
+    let readable_dirs = readable_dirs(&command);
+    if let Err(err) = platform::restrict(&mut command, &writable_dirs, &readable_dirs) {
+        println!("Server is started without sandbox: {}", err);
+    }
+    command
+        .spawn()
+        .map_err(|err| format!("Failed to start Python server: {}", err))?;
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+pub fn spawn(mut command: Command) -> Result<(), String> {
+    if let Err(err) = platform::spawn(&command, &writable_dirs()) {
+        println!("Server is started without sandbox: {}", err);
+        command
+            .spawn()
+            .map_err(|err| format!("Failed to start Python server: {}", err))?;
+    }
+    Ok(())
+}
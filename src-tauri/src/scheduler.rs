@@ -0,0 +1,393 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, State};
+
+use crate::account_schedule::AccountSchedules;
+use crate::api;
+use crate::cache::{Cache, CachedEmail, EmailChange, SyncCheckpoint, MAX_SYNCED_EMAILS};
+use crate::downloads::Downloads;
+use crate::events::EventBus;
+use crate::health;
+use crate::stats;
+
+const SYNC_INTERVAL_SEC: u64 = 300;
+// Server is usually not ready yet on the first try.
+const RETRY_INTERVAL_SEC: u64 = 30;
+const SYNC_PAGE_SIZE: usize = 50;
+
+pub const SYNCED_FOLDERS: [&str; 2] = ["Inbox", "Sent"];
+pub const SYNC_FINISHED_EVENT: &str = "sync-finished";
+// Published through the event bus, there can be thousands of them per sync.
+pub const EMAIL_ADDED_EVENT: &str = "email-added";
+pub const EMAIL_REMOVED_EVENT: &str = "email-removed";
+pub const EMAIL_FLAGS_CHANGED_EVENT: &str = "email-flags-changed";
+
+#[derive(Deserialize)]
+pub struct FolderPage {
+    pub emails: Vec<CachedEmail>,
+    pub total: usize,
+}
+
+struct SyncedPage {
+    is_last: bool,
+    total: usize,
+    all_cached: bool,
+    newest_uid: Option<u64>,
+    oldest_uid: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct EmailChangePayload<'a> {
+    account: &'a str,
+    folder: &'a str,
+    #[serde(flatten)]
+    change: &'a EmailChange,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    email_address: String,
+}
+
+// A sync of every account (like the "sync now" button), or only of some of
+// them (like the ones IDLE reported new emails of, see idle.rs).
+#[derive(Default)]
+struct SyncRequest {
+    all: bool,
+    accounts: HashSet<String>,
+}
+
+#[derive(Deserialize)]
+struct Accounts {
+    connected: Vec<Account>,
+    failed: Vec<Account>,
+    #[serde(default)]
+    sign_in_failures: HashMap<String, Vec<i64>>,
+}
+
+// Addresses of the accounts the server signed in to and the ones it
+// couldn't, with the times of its failed sign-in attempts.
+pub struct AccountStates {
+    pub connected: Vec<String>,
+    pub failed: Vec<String>,
+    pub sign_in_failures: HashMap<String, Vec<i64>>,
+}
+
+pub struct SyncScheduler {
+    cache: Arc<Cache>,
+    events: Arc<EventBus>,
+    downloads: Arc<Downloads>,
+    schedules: Arc<AccountSchedules>,
+    requested: Mutex<SyncRequest>,
+    wakeup: Condvar,
+}
+
+pub fn get_accounts() -> Result<AccountStates, String> {
+    let response = api::get("/get-accounts", &[])?;
+    if !response.success {
+        return Err(response.message);
+    }
+
+    let accounts: Accounts = serde_json::from_value(response.data.unwrap_or(Value::Null))
+        .map_err(|err| format!("Failed to parse accounts: {}", err))?;
+    let addresses = |accounts: Vec<Account>| {
+        accounts
+            .into_iter()
+            .map(|account| account.email_address)
+            .collect()
+    };
+    Ok(AccountStates {
+        connected: addresses(accounts.connected),
+        failed: addresses(accounts.failed),
+        sign_in_failures: accounts.sign_in_failures,
+    })
+}
+
+pub fn get_connected_accounts() -> Result<Vec<String>, String> {
+    get_accounts().map(|accounts| accounts.connected)
+}
+
+pub fn fetch_page(
+    account: &str,
+    folder: &str,
+    offset_start: usize,
+    offset_end: usize,
+) -> Result<FolderPage, String> {
+    let response = api::get(
+        &format!("/get-folder-emails/{}", account),
+        &[
+            ("folder", folder),
+            ("offset_start", &offset_start.to_string()),
+            ("offset_end", &offset_end.to_string()),
+        ],
+    )?;
+    serde_json::from_value(api::get_account_data(response, account)?)
+        .map_err(|err| format!("Failed to parse emails of {}: {}", folder, err))
+}
+
+// Also used for the changes that are made locally, see offline.rs
+pub fn publish_changes(events: &EventBus, account: &str, folder: &str, changes: &[EmailChange]) {
+    for change in changes {
+        let event = match change {
+            EmailChange::Added { .. } => EMAIL_ADDED_EVENT,
+            EmailChange::Removed { .. } => EMAIL_REMOVED_EVENT,
+            EmailChange::FlagsChanged { .. } => EMAIL_FLAGS_CHANGED_EVENT,
+        };
+        let key = format!("{}/{}/{}", account, folder, change.uid());
+        events.publish(
+            event,
+            Some(&key),
+            EmailChangePayload {
+                account,
+                folder,
+                change,
+            },
+        );
+    }
+}
+
+impl SyncScheduler {
+    pub fn new(
+        cache: Arc<Cache>,
+        events: Arc<EventBus>,
+        downloads: Arc<Downloads>,
+        schedules: Arc<AccountSchedules>,
+    ) -> Self {
+        SyncScheduler {
+            cache,
+            events,
+            downloads,
+            schedules,
+            requested: Mutex::new(SyncRequest::default()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    pub fn request(&self) {
+        self.requested.lock().unwrap().all = true;
+        self.wakeup.notify_all();
+    }
+
+    pub fn request_account(&self, account: &str) {
+        self.requested
+            .lock()
+            .unwrap()
+            .accounts
+            .insert(account.to_string());
+        self.wakeup.notify_all();
+    }
+
+    // Waits until a sync is requested or `until`. Returns the accounts to
+    // sync if only some of them were requested, None for all of them.
+    fn wait_for_next_sync(&self, until: Instant) -> Option<HashSet<String>> {
+        let mut requested = self.requested.lock().unwrap();
+        loop {
+            if requested.all {
+                *requested = SyncRequest::default();
+                return None;
+            }
+            if !requested.accounts.is_empty() {
+                return Some(std::mem::take(&mut requested.accounts));
+            }
+            let now = Instant::now();
+            if now >= until {
+                return None;
+            }
+            requested = self.wakeup.wait_timeout(requested, until - now).unwrap().0;
+        }
+    }
+
+    fn sync_page(
+        &self,
+        account: &str,
+        mailbox: &str,
+        folder: &str,
+        offset_start: usize,
+        auto_download: bool,
+    ) -> Result<SyncedPage, String> {
+        let offset_end = offset_start + SYNC_PAGE_SIZE - 1;
+        let page = fetch_page(account, folder, offset_start, offset_end)?;
+        let uids: Vec<u64> = page.emails.iter().map(CachedEmail::uid_number).collect();
+        let synced_page = SyncedPage {
+            is_last: page.emails.len() < SYNC_PAGE_SIZE
+                || offset_end >= page.total.min(MAX_SYNCED_EMAILS),
+            total: page.total,
+            all_cached: page
+                .emails
+                .iter()
+                .all(|email| self.cache.contains(mailbox, folder, &email.uid)),
+            newest_uid: uids.iter().max().copied(),
+            oldest_uid: uids.iter().min().copied(),
+        };
+        let new_emails: Vec<CachedEmail> = if auto_download {
+            page.emails
+                .iter()
+                .filter(|email| !self.cache.contains(mailbox, folder, &email.uid))
+                .cloned()
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let changes = self.cache.update_folder(
+            mailbox,
+            folder,
+            page.emails,
+            offset_start == 1,
+            page.total,
+        )?;
+        publish_changes(&self.events, mailbox, folder, &changes);
+        self.downloads
+            .auto_download(account, mailbox, folder, &new_emails);
+        Ok(synced_page)
+    }
+
+    // Fetches the folder page by page, newest first. Stops at the first page
+    // that has only already cached emails, unless the folder was never
+    // synced before, then continues until `MAX_SYNCED_EMAILS`. Such a sync
+    // saves a checkpoint after every page, if it is interrupted, the next
+    // sync continues from the checkpoint once it caught up with the new
+    // emails instead of starting over. The folder is fetched with `account`
+    // and cached as `mailbox`, they only differ for shared mailboxes.
+    // Attachments are only auto downloaded for the emails that arrive after
+    // the first sync, see downloads.rs. The folder only counts as synced
+    // once the sync reached its end, a resumed first sync is still one.
+    pub fn sync_folder(&self, account: &str, mailbox: &str, folder: &str) -> Result<(), String> {
+        let result = self
+            .sync_folder_pages(account, mailbox, folder)
+            .and_then(|()| {
+                self.cache
+                    .finish_sync(mailbox, folder, Utc::now().timestamp())
+            });
+        self.cache.flush(mailbox)?;
+        result
+    }
+
+    fn sync_folder_pages(&self, account: &str, mailbox: &str, folder: &str) -> Result<(), String> {
+        let folder_cache = self.cache.get_folder(mailbox, folder);
+        let is_first_sync = folder_cache.synced_at.is_none();
+        // An interrupted first sync catches up with the new emails first
+        // and continues from its checkpoint.
+        let is_resumed = folder_cache.checkpoint.is_some();
+        let mut checkpoint = folder_cache.checkpoint;
+
+        let mut offset_start = 1;
+        let total = loop {
+            let page = self.sync_page(account, mailbox, folder, offset_start, !is_first_sync)?;
+            if page.is_last {
+                return Ok(());
+            }
+
+            offset_start += SYNC_PAGE_SIZE;
+            if is_first_sync && !is_resumed {
+                checkpoint = Some(SyncCheckpoint {
+                    next_offset: offset_start,
+                    total: page.total,
+                    oldest_uid: page.oldest_uid.unwrap_or_default(),
+                });
+                self.cache.set_checkpoint(mailbox, folder, checkpoint)?;
+            } else if page.all_cached {
+                break page.total;
+            }
+        };
+
+        let Some(mut checkpoint) = checkpoint else {
+            return Ok(());
+        };
+        // Starts a page earlier than the shifted checkpoint, since deleted
+        // emails shift the offsets the other way.
+        let mut offset_start = (checkpoint.next_offset + total)
+            .saturating_sub(checkpoint.total + SYNC_PAGE_SIZE)
+            .max(1);
+        loop {
+            let page = self.sync_page(account, mailbox, folder, offset_start, !is_first_sync)?;
+            if page.is_last {
+                return Ok(());
+            }
+
+            // A page that doesn't overlap with the synced emails may have
+            // skipped some, so goes back until it does.
+            if offset_start > 1
+                && page
+                    .newest_uid
+                    .is_some_and(|newest| newest < checkpoint.oldest_uid)
+            {
+                offset_start = offset_start.saturating_sub(SYNC_PAGE_SIZE).max(1);
+                continue;
+            }
+
+            offset_start += SYNC_PAGE_SIZE;
+            checkpoint = SyncCheckpoint {
+                next_offset: offset_start,
+                total: page.total,
+                oldest_uid: page.oldest_uid.map_or(checkpoint.oldest_uid, |oldest| {
+                    oldest.min(checkpoint.oldest_uid)
+                }),
+            };
+            self.cache
+                .set_checkpoint(mailbox, folder, Some(checkpoint))?;
+        }
+    }
+
+    fn sync_account(&self, account: &str) -> Result<(), String> {
+        for folder in SYNCED_FOLDERS {
+            self.sync_folder(account, account, folder)?;
+        }
+
+        let inbox = self.cache.get_folder(account, "Inbox");
+        let unread = inbox.emails.iter().filter(|email| !email.is_seen()).count();
+        stats::record_inbox_progress(account, inbox.total, unread)
+    }
+}
+
+// Syncs of some accounts don't postpone the next sync of all of them.
+pub fn start_worker(app: AppHandle, scheduler: Arc<SyncScheduler>) {
+    thread::spawn(move || {
+        let mut only: Option<HashSet<String>> = None;
+        let mut next_sync = Instant::now();
+        loop {
+            let interval = match get_accounts() {
+                Ok(accounts) => {
+                    if only.is_none() {
+                        if let Err(err) = health::record_auth_failures(&accounts.sign_in_failures) {
+                            println!("{}", err);
+                        }
+                    }
+                    // Paused accounts are synced once they are resumed, see
+                    // account_schedule.rs
+                    let accounts: Vec<String> = accounts
+                        .connected
+                        .into_iter()
+                        .filter(|account| !scheduler.schedules.is_paused(account))
+                        .filter(|account| only.as_ref().is_none_or(|only| only.contains(account)))
+                        .collect();
+                    for account in accounts.iter() {
+                        if let Err(err) = scheduler.sync_account(account) {
+                            println!("Failed to sync {}: {}", account, err);
+                        }
+                    }
+                    app.emit(SYNC_FINISHED_EVENT, accounts).ok();
+                    SYNC_INTERVAL_SEC
+                }
+                Err(err) => {
+                    println!("Sync could not reach the server: {}", err);
+                    RETRY_INTERVAL_SEC
+                }
+            };
+            if only.is_none() || interval == RETRY_INTERVAL_SEC {
+                next_sync = Instant::now() + Duration::from_secs(interval);
+            }
+            only = scheduler.wait_for_next_sync(next_sync);
+        }
+    });
+}
+
+#[tauri::command]
+pub fn sync_now(scheduler: State<'_, Arc<SyncScheduler>>) {
+    scheduler.request();
+}
@@ -0,0 +1,763 @@
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Listener, State};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+use whatlang::Lang;
+
+use crate::cache::{Cache, CachedEmail};
+use crate::consts;
+use crate::scheduler;
+use crate::search_query::{self, Filter, Query};
+use crate::utils;
+
+// Local search over the cached emails, synced and "on this computer" ones.
+// Texts are split into terms by an analyzer: words are lowercased, stemmed
+// with the language of the account (if it has one) and folded to their
+// base letters, so "Café" finds "cafe" (and "cafés" with a stemmer). CJK
+// scripts don't separate words, they are split into their characters and
+// overlapping bigrams instead, so single characters can be searched too. The terms of a folder are kept until the folder changes in the
+// cache, then only the new emails of it are analyzed. The query language
+// is in search_query.rs. Results come with snippets of the fields that
+// matched, with the byte ranges of the matched terms, so the reader can
+// highlight them and scroll to the first one. The language of every email
+// is detected when it is indexed, for the lang: filter and for picking the
+// spellcheck dictionary and voice of the reader.
+const SNIPPET_CONTEXT_BYTES: usize = 60;
+const SNIPPET_LENGTH_BYTES: usize = 200;
+// Shorter texts are mostly signatures and greetings, not enough to tell.
+const MIN_DETECTED_TEXT_CHARS: usize = 20;
+// Detection only reads the start of the body.
+const MAX_DETECTED_TEXT_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchSettings {
+    // ISO 639-1 code of the language most emails of the account are in.
+    pub languages: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Snippet {
+    pub field: &'static str,
+    pub text: String,
+    // Byte offset of `text` in the field.
+    pub offset: usize,
+    // Byte ranges of the matched terms in `text`.
+    pub highlights: Vec<(usize, usize)>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub folder: String,
+    // Whether the folder is an "on this computer" one.
+    pub local: bool,
+    pub email: CachedEmail,
+    pub language: Option<String>,
+    pub snippets: Vec<Snippet>,
+}
+
+fn stemmer_algorithm(language: &str) -> Option<Algorithm> {
+    Some(match language {
+        "ar" => Algorithm::Arabic,
+        "da" => Algorithm::Danish,
+        "de" => Algorithm::German,
+        "el" => Algorithm::Greek,
+        "en" => Algorithm::English,
+        "es" => Algorithm::Spanish,
+        "fi" => Algorithm::Finnish,
+        "fr" => Algorithm::French,
+        "hu" => Algorithm::Hungarian,
+        "it" => Algorithm::Italian,
+        "nl" => Algorithm::Dutch,
+        "no" => Algorithm::Norwegian,
+        "pt" => Algorithm::Portuguese,
+        "ro" => Algorithm::Romanian,
+        "ru" => Algorithm::Russian,
+        "sv" => Algorithm::Swedish,
+        "ta" => Algorithm::Tamil,
+        "tr" => Algorithm::Turkish,
+        _ => return None,
+    })
+}
+
+// ISO 639-1 code of the languages whatlang detects.
+fn language_code(language: Lang) -> Option<&'static str> {
+    Some(match language {
+        Lang::Afr => "af",
+        Lang::Ara => "ar",
+        Lang::Aze => "az",
+        Lang::Bel => "be",
+        Lang::Ben => "bn",
+        Lang::Bul => "bg",
+        Lang::Cat => "ca",
+        Lang::Ces => "cs",
+        Lang::Cmn => "zh",
+        Lang::Dan => "da",
+        Lang::Deu => "de",
+        Lang::Ell => "el",
+        Lang::Eng => "en",
+        Lang::Epo => "eo",
+        Lang::Est => "et",
+        Lang::Fin => "fi",
+        Lang::Fra => "fr",
+        Lang::Heb => "he",
+        Lang::Hin => "hi",
+        Lang::Hrv => "hr",
+        Lang::Hun => "hu",
+        Lang::Hye => "hy",
+        Lang::Ind => "id",
+        Lang::Ita => "it",
+        Lang::Jpn => "ja",
+        Lang::Kat => "ka",
+        Lang::Kor => "ko",
+        Lang::Lat => "la",
+        Lang::Lav => "lv",
+        Lang::Lit => "lt",
+        Lang::Mkd => "mk",
+        Lang::Nld => "nl",
+        Lang::Nob => "no",
+        Lang::Pes => "fa",
+        Lang::Pol => "pl",
+        Lang::Por => "pt",
+        Lang::Ron => "ro",
+        Lang::Rus => "ru",
+        Lang::Slk => "sk",
+        Lang::Slv => "sl",
+        Lang::Spa => "es",
+        Lang::Srp => "sr",
+        Lang::Swe => "sv",
+        Lang::Tam => "ta",
+        Lang::Tha => "th",
+        Lang::Tgl => "tl",
+        Lang::Tur => "tr",
+        Lang::Ukr => "uk",
+        Lang::Urd => "ur",
+        Lang::Vie => "vi",
+        _ => return None,
+    })
+}
+
+// ISO 639-1 code of the dominant language of the email, None if it can't
+// be told reliably.
+pub fn detect_language(email: &CachedEmail) -> Option<String> {
+    let text: String = format!("{}\n{}", email.subject, email.body)
+        .chars()
+        .take(MAX_DETECTED_TEXT_CHARS)
+        .collect();
+    if text.trim().chars().count() < MIN_DETECTED_TEXT_CHARS {
+        return None;
+    }
+    whatlang::detect(&text)
+        .filter(|info| info.is_reliable())
+        .and_then(|info| language_code(info.lang()))
+        .map(str::to_string)
+}
+
+pub fn is_supported_language(language: &str) -> bool {
+    stemmer_algorithm(language).is_some() || ["ja", "ko", "zh"].contains(&language)
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}' // Hiragana, Katakana
+        | '\u{3400}'..='\u{4DBF}' // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}' // Hangul Syllables
+        | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+        | '\u{FF66}'..='\u{FF9F}' // Halfwidth Katakana
+    )
+}
+
+// Compatibility decomposition without the combining marks, "ｃａｆé" is
+// "cafe".
+fn fold(text: &str) -> String {
+    text.nfkd().filter(|c| !is_combining_mark(*c)).collect()
+}
+
+type Term = (String, Range<usize>);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Run {
+    Word,
+    Cjk,
+}
+
+pub struct Analyzer {
+    stemmer: Option<Stemmer>,
+}
+
+impl Analyzer {
+    pub fn new(language: Option<&str>) -> Self {
+        Analyzer {
+            stemmer: language.and_then(stemmer_algorithm).map(Stemmer::create),
+        }
+    }
+
+    fn word_term(&self, word: &str) -> String {
+        let word = word.to_lowercase();
+        match &self.stemmer {
+            // Stemmers expect the accented words of their language.
+            Some(stemmer) => fold(&stemmer.stem(&word)),
+            None => fold(&word),
+        }
+    }
+
+    fn push_run(&self, text: &str, run: Run, range: Range<usize>, terms: &mut Vec<Term>) {
+        match run {
+            Run::Word => terms.push((self.word_term(&text[range.clone()]), range)),
+            // Every character followed by the bigram it starts, in the same
+            // order for the texts and the queries, so phrases still match.
+            Run::Cjk => {
+                let chars: Vec<Range<usize>> = text[range.clone()]
+                    .char_indices()
+                    .map(|(index, c)| range.start + index..range.start + index + c.len_utf8())
+                    .collect();
+                for (index, unigram) in chars.iter().enumerate() {
+                    terms.push((fold(&text[unigram.clone()]), unigram.clone()));
+                    if let Some(next) = chars.get(index + 1) {
+                        let bigram = unigram.start..next.end;
+                        terms.push((fold(&text[bigram.clone()]), bigram));
+                    }
+                }
+            }
+        }
+    }
+
+    // Terms of the text with the byte range of the text they come from.
+    pub fn terms_with_ranges(&self, text: &str) -> Vec<Term> {
+        let mut terms = Vec::new();
+        let mut current: Option<(Run, usize)> = None;
+        for (index, c) in text.char_indices() {
+            let run = if is_cjk(c) {
+                Some(Run::Cjk)
+            } else if c.is_alphanumeric() || is_combining_mark(c) {
+                Some(Run::Word)
+            } else {
+                None
+            };
+            if current.map(|(current, _)| current) == run {
+                continue;
+            }
+            if let Some((current, start)) = current {
+                self.push_run(text, current, start..index, &mut terms);
+            }
+            current = run.map(|run| (run, index));
+        }
+        if let Some((current, start)) = current {
+            self.push_run(text, current, start..text.len(), &mut terms);
+        }
+        terms
+    }
+
+    pub fn terms(&self, text: &str) -> Vec<String> {
+        self.terms_with_ranges(text)
+            .into_iter()
+            .map(|(term, _)| term)
+            .collect()
+    }
+}
+
+struct IndexedEmail {
+    email: CachedEmail,
+    // Terms of every field in order, for phrases.
+    fields: Vec<Vec<String>>,
+    terms: HashSet<String>,
+    language: Option<String>,
+}
+
+// What the folder was indexed from, the folder is indexed again once the
+// cache doesn't match it anymore. Flags change without a sync (like the
+// offline changes), so they are a part of it too.
+#[derive(PartialEq)]
+struct FolderVersion {
+    synced_at: Option<i64>,
+    length: usize,
+    newest_uid: Option<String>,
+    // Hash of the uids and the flags of the emails.
+    flags: u64,
+}
+
+struct FolderIndex {
+    version: FolderVersion,
+    emails: Vec<IndexedEmail>,
+}
+
+fn folder_version(emails: &[CachedEmail], synced_at: Option<i64>) -> FolderVersion {
+    let mut flags = DefaultHasher::new();
+    for email in emails {
+        email.uid.hash(&mut flags);
+        email.flags.hash(&mut flags);
+    }
+    FolderVersion {
+        synced_at,
+        length: emails.len(),
+        newest_uid: emails.first().map(|email| email.uid.clone()),
+        flags: flags.finish(),
+    }
+}
+
+// Lowercased and folded, for comparing addresses.
+fn normalize(text: &str) -> String {
+    fold(&text.to_lowercase())
+}
+
+// Terms of the text and phrase filters of the query, analyzed once.
+fn analyze_query(query: &Query, analyzer: &Analyzer, terms: &mut HashMap<String, Vec<String>>) {
+    match query {
+        Query::Filter {
+            filter: Filter::Text { text } | Filter::Phrase { text },
+        } => {
+            terms
+                .entry(text.clone())
+                .or_insert_with(|| analyzer.terms(text));
+        }
+        Query::Filter { .. } => {}
+        Query::Not { query } => analyze_query(query, analyzer, terms),
+        Query::And { queries } | Query::Or { queries } => queries
+            .iter()
+            .for_each(|query| analyze_query(query, analyzer, terms)),
+    }
+}
+
+// Terms of the text and phrase filters that are not negated.
+fn highlighted_terms(
+    query: &Query,
+    negated: bool,
+    terms: &HashMap<String, Vec<String>>,
+    highlighted: &mut HashSet<String>,
+) {
+    match query {
+        Query::Filter {
+            filter: Filter::Text { text } | Filter::Phrase { text },
+        } if !negated => highlighted.extend(terms[text].iter().cloned()),
+        Query::Filter { .. } => {}
+        Query::Not { query } => highlighted_terms(query, !negated, terms, highlighted),
+        Query::And { queries } | Query::Or { queries } => queries
+            .iter()
+            .for_each(|query| highlighted_terms(query, negated, terms, highlighted)),
+    }
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+// The whole text, or a part of it around the first match if it is long.
+fn snippet(
+    analyzer: &Analyzer,
+    field: &'static str,
+    text: &str,
+    highlighted: &HashSet<String>,
+) -> Option<Snippet> {
+    // Terms of CJK texts overlap, they are merged.
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (_, range) in analyzer
+        .terms_with_ranges(text)
+        .into_iter()
+        .filter(|(term, _)| highlighted.contains(term))
+    {
+        match ranges.last_mut() {
+            Some(last) if range.start < last.end => last.end = last.end.max(range.end),
+            _ => ranges.push(range),
+        }
+    }
+    let first = ranges.first()?;
+
+    let (start, end) = if text.len() <= SNIPPET_LENGTH_BYTES {
+        (0, text.len())
+    } else {
+        let mut start =
+            floor_char_boundary(text, first.start.saturating_sub(SNIPPET_CONTEXT_BYTES));
+        // Starts at a word if there is one in the context.
+        if let Some((index, space)) = text[start..first.start]
+            .char_indices()
+            .find(|(_, c)| c.is_whitespace())
+        {
+            start += index + space.len_utf8();
+        }
+        let end = floor_char_boundary(text, (start + SNIPPET_LENGTH_BYTES).min(text.len()));
+        (start, end.max(first.end))
+    };
+    Some(Snippet {
+        field,
+        text: text[start..end].to_string(),
+        offset: start,
+        highlights: ranges
+            .iter()
+            .filter(|range| range.start >= start && range.end <= end)
+            .map(|range| (range.start - start, range.end - start))
+            .collect(),
+    })
+}
+
+fn snippets(
+    analyzer: &Analyzer,
+    email: &CachedEmail,
+    highlighted: &HashSet<String>,
+) -> Vec<Snippet> {
+    [
+        ("subject", Some(&email.subject)),
+        ("sender", Some(&email.sender)),
+        ("receivers", Some(&email.receivers)),
+        ("cc", email.cc.as_ref()),
+        ("body", Some(&email.body)),
+    ]
+    .into_iter()
+    .filter_map(|(field, text)| snippet(analyzer, field, text?, highlighted))
+    .collect()
+}
+
+fn matches_filter(
+    filter: &Filter,
+    indexed: &IndexedEmail,
+    terms: &HashMap<String, Vec<String>>,
+) -> bool {
+    let email = &indexed.email;
+    let date = || utils::parse_email_date(&email.date).map(|date| date.date_naive());
+    match filter {
+        Filter::Text { text } => terms[text].iter().all(|term| indexed.terms.contains(term)),
+        Filter::Phrase { text } => {
+            let phrase = &terms[text];
+            phrase.is_empty()
+                || indexed.fields.iter().any(|field| {
+                    field
+                        .windows(phrase.len())
+                        .any(|window| window == phrase.as_slice())
+                })
+        }
+        Filter::From { address } => normalize(&email.sender).contains(&normalize(address)),
+        Filter::To { address } => [
+            Some(&email.receivers),
+            email.cc.as_ref(),
+            email.bcc.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|receivers| normalize(receivers).contains(&normalize(address))),
+        Filter::HasAttachment => !email.attachments.is_empty(),
+        Filter::Before { date: before } => search_query::parse_date(before)
+            .is_ok_and(|before| date().is_some_and(|date| date < before)),
+        Filter::After { date: after } => search_query::parse_date(after)
+            .is_ok_and(|after| date().is_some_and(|date| date >= after)),
+        Filter::IsUnread => !email.is_seen(),
+        Filter::IsRead => email.is_seen(),
+        Filter::IsFlagged => email.is_flagged(),
+        Filter::Language { language } => indexed.language.as_ref() == Some(language),
+    }
+}
+
+fn matches(query: &Query, indexed: &IndexedEmail, terms: &HashMap<String, Vec<String>>) -> bool {
+    match query {
+        Query::Filter { filter } => matches_filter(filter, indexed, terms),
+        Query::Not { query } => !matches(query, indexed, terms),
+        Query::And { queries } => queries.iter().all(|query| matches(query, indexed, terms)),
+        Query::Or { queries } => queries.iter().any(|query| matches(query, indexed, terms)),
+    }
+}
+
+fn index_email(analyzer: &Analyzer, email: CachedEmail) -> IndexedEmail {
+    let fields: Vec<Vec<String>> = [
+        Some(&email.subject),
+        Some(&email.sender),
+        Some(&email.receivers),
+        email.cc.as_ref(),
+        Some(&email.body),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|field| analyzer.terms(field))
+    .collect();
+    let terms = fields.iter().flatten().cloned().collect();
+    IndexedEmail {
+        language: detect_language(&email),
+        email,
+        fields,
+        terms,
+    }
+}
+
+// Only the emails that are not in the previous index of the folder are
+// analyzed, the content of an email doesn't change once it has a uid, only
+// its flags do.
+fn index_emails(
+    analyzer: &Analyzer,
+    emails: Vec<CachedEmail>,
+    previous: Option<FolderIndex>,
+) -> Vec<IndexedEmail> {
+    let mut previous: HashMap<String, IndexedEmail> = previous
+        .map(|index| index.emails)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|indexed| (indexed.email.uid.clone(), indexed))
+        .collect();
+    emails
+        .into_iter()
+        .map(|email| match previous.remove(&email.uid) {
+            Some(indexed) => IndexedEmail { email, ..indexed },
+            None => index_email(analyzer, email),
+        })
+        .collect()
+}
+
+// (folder, whether it is local) -> index, a local folder can have the same
+// name as a synced one.
+type FolderIndexes = HashMap<(String, bool), FolderIndex>;
+
+pub struct SearchIndex {
+    cache: Arc<Cache>,
+    settings: Mutex<SearchSettings>,
+    // By account.
+    folders: Mutex<HashMap<String, FolderIndexes>>,
+}
+
+impl SearchIndex {
+    pub fn load(cache: Arc<Cache>) -> Self {
+        SearchIndex {
+            cache,
+            settings: Mutex::new(
+                utils::read_json_file(consts::SEARCH_SETTINGS_FILE_PATH).unwrap_or_default(),
+            ),
+            folders: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_settings(&self) -> SearchSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    pub fn set_language(&self, account: &str, language: Option<String>) -> Result<(), String> {
+        if let Some(language) = language.as_ref() {
+            if !is_supported_language(language) {
+                return Err(format!("Unsupported language: {}", language));
+            }
+        }
+
+        let mut settings = self.settings.lock().unwrap();
+        let mut updated = settings.clone();
+        match language {
+            Some(language) => updated.languages.insert(account.to_string(), language),
+            None => updated.languages.remove(account),
+        };
+        utils::write_json_file(consts::SEARCH_SETTINGS_FILE_PATH, &updated)?;
+        *settings = updated;
+        // Terms of the old language don't match the new ones.
+        self.folders.lock().unwrap().remove(account);
+        Ok(())
+    }
+
+    pub fn analyzer(&self, account: &str) -> Analyzer {
+        let settings = self.settings.lock().unwrap();
+        Analyzer::new(settings.languages.get(account).map(String::as_str))
+    }
+
+    // Indexes the folders of the account that changed since they were
+    // indexed. Searches call it before searching, and every sync of the
+    // account (like the ones IDLE pushes request, see idle.rs) right after.
+    pub fn refresh(&self, account: &str) {
+        let analyzer = self.analyzer(account);
+        let mut folders = self.folders.lock().unwrap();
+        let indexes = folders.entry(account.to_string()).or_default();
+
+        let synced = self
+            .cache
+            .get_folder_names(account)
+            .into_iter()
+            .map(|folder| {
+                let cached = self.cache.get_folder(account, &folder);
+                let version = folder_version(&cached.emails, cached.synced_at);
+                ((folder, false), version, cached.emails)
+            });
+        let local = self
+            .cache
+            .get_local_folders(account)
+            .into_iter()
+            .map(|folder| {
+                let emails = self.cache.get_local_emails(account, &folder);
+                let version = folder_version(&emails, None);
+                ((folder, true), version, emails)
+            });
+
+        let mut current = HashSet::new();
+        for (key, version, emails) in synced.chain(local) {
+            if indexes
+                .get(&key)
+                .is_none_or(|index| index.version != version)
+            {
+                let emails = index_emails(&analyzer, emails, indexes.remove(&key));
+                indexes.insert(key.clone(), FolderIndex { version, emails });
+            }
+            current.insert(key);
+        }
+        indexes.retain(|key, _| current.contains(key));
+    }
+
+    // Emails that match the query (see search_query.rs), newest first
+    // within each folder.
+    pub fn search(&self, account: &str, query: &str) -> Result<Vec<SearchResult>, String> {
+        let Some(query) = search_query::parse_query(query)?.query else {
+            return Ok(Vec::new());
+        };
+        let analyzer = self.analyzer(account);
+        let mut terms = HashMap::new();
+        analyze_query(&query, &analyzer, &mut terms);
+        let mut highlighted = HashSet::new();
+        highlighted_terms(&query, false, &terms, &mut highlighted);
+
+        self.refresh(account);
+        let folders = self.folders.lock().unwrap();
+        let Some(indexes) = folders.get(account) else {
+            return Ok(Vec::new());
+        };
+        let mut keys: Vec<&(String, bool)> = indexes.keys().collect();
+        keys.sort();
+        Ok(keys
+            .into_iter()
+            .flat_map(|(folder, local)| {
+                indexes[&(folder.clone(), *local)]
+                    .emails
+                    .iter()
+                    .filter(|indexed| matches(&query, indexed, &terms))
+                    .map(|indexed| SearchResult {
+                        folder: folder.clone(),
+                        local: *local,
+                        email: indexed.email.clone(),
+                        language: indexed.language.clone(),
+                        snippets: snippets(&analyzer, &indexed.email, &highlighted),
+                    })
+            })
+            .collect())
+    }
+
+    pub fn get_language(
+        &self,
+        account: &str,
+        folder: &str,
+        uid: &str,
+        local: bool,
+    ) -> Result<Option<String>, String> {
+        self.refresh(account);
+        let folders = self.folders.lock().unwrap();
+        folders
+            .get(account)
+            .and_then(|indexes| indexes.get(&(folder.to_string(), local)))
+            .and_then(|index| index.emails.iter().find(|indexed| indexed.email.uid == uid))
+            .map(|indexed| indexed.language.clone())
+            .ok_or(format!("Email {} not found in {}", uid, folder))
+    }
+}
+
+// New emails can be found right after they are synced instead of on the
+// next search.
+pub fn listen_sync(app: &AppHandle, index: Arc<SearchIndex>) {
+    app.listen(scheduler::SYNC_FINISHED_EVENT, move |event| {
+        let Ok(accounts) = serde_json::from_str::<Vec<String>>(event.payload()) else {
+            return;
+        };
+
+        let index = index.clone();
+        thread::spawn(move || {
+            for account in accounts.iter() {
+                index.refresh(account);
+            }
+        });
+    });
+}
+
+#[tauri::command]
+pub fn search_cached_emails(
+    index: State<'_, Arc<SearchIndex>>,
+    account: String,
+    query: String,
+) -> Result<Vec<SearchResult>, String> {
+    index.search(&account, &query)
+}
+
+#[tauri::command]
+pub fn get_search_settings(index: State<'_, Arc<SearchIndex>>) -> SearchSettings {
+    index.get_settings()
+}
+
+#[tauri::command]
+pub fn set_search_language(
+    index: State<'_, Arc<SearchIndex>>,
+    account: String,
+    language: Option<String>,
+) -> Result<(), String> {
+    index.set_language(&account, language)
+}
+
+// Language of the email for the spellcheck, the voice of the reader and
+// whether to offer a translation.
+#[tauri::command]
+pub fn get_email_language(
+    index: State<'_, Arc<SearchIndex>>,
+    account: String,
+    folder: String,
+    uid: String,
+    local: bool,
+) -> Result<Option<String>, String> {
+    index.get_language(&account, &folder, &uid, local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_cjk_into_unigrams_and_bigrams() {
+        let analyzer = Analyzer::new(None);
+        assert_eq!(
+            analyzer.terms("東京都 tokyo"),
+            vec!["東", "東京", "京", "京都", "都", "tokyo"]
+        );
+        assert_eq!(analyzer.terms("東"), vec!["東"]);
+
+        let text = "東京都";
+        let ranges: Vec<Range<usize>> = analyzer
+            .terms_with_ranges(text)
+            .into_iter()
+            .map(|(_, range)| range)
+            .collect();
+        assert_eq!(ranges, vec![0..3, 0..6, 3..6, 3..9, 6..9]);
+    }
+
+    #[test]
+    fn folds_and_stems_words() {
+        assert_eq!(
+            Analyzer::new(None).terms("Café, ｃａｆé-au_lait"),
+            vec!["cafe", "cafe", "au", "lait"]
+        );
+        assert_eq!(
+            Analyzer::new(Some("en")).terms("Quarterly reports"),
+            vec!["quarter", "report"]
+        );
+    }
+
+    #[test]
+    fn flag_changes_change_the_folder_version() {
+        let mut emails = vec![CachedEmail {
+            uid: "1".to_string(),
+            ..Default::default()
+        }];
+        let before = folder_version(&emails, Some(1));
+        emails[0].flags.push("\\Seen".to_string());
+        assert!(before != folder_version(&emails, Some(1)));
+    }
+
+    #[test]
+    fn merges_overlapping_cjk_highlights() {
+        let analyzer = Analyzer::new(None);
+        let highlighted: HashSet<String> = analyzer.terms("東京").into_iter().collect();
+        let snippet = snippet(&analyzer, "subject", "東京都", &highlighted).unwrap();
+        assert_eq!(snippet.highlights, vec![(0, 6)]);
+    }
+}
@@ -0,0 +1,384 @@
+use chrono::NaiveDate;
+use serde::Serialize;
+
+// Query language of the local search, like:
+// from:alice@example.com "quarterly report" (has:attachment OR is:unread) -draft
+// Terms next to each other must all match, AND/OR/NOT (uppercase) and
+// parentheses combine them, "-" negates the term after it. Unknown
+// "key:value" words are searched as text.
+const DATE_FORMATS: [&str; 2] = ["%Y-%m-%d", "%Y/%m/%d"];
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Filter {
+    Text { text: String },
+    Phrase { text: String },
+    From { address: String },
+    To { address: String },
+    HasAttachment,
+    // YYYY-MM-DD, before is exclusive and after is inclusive.
+    Before { date: String },
+    After { date: String },
+    IsUnread,
+    IsRead,
+    IsFlagged,
+    // ISO 639-1, detected when the email is indexed.
+    Language { language: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Query {
+    Filter { filter: Filter },
+    Not { query: Box<Query> },
+    And { queries: Vec<Query> },
+    Or { queries: Vec<Query> },
+}
+
+// A filter of the query and whether it is negated, for showing the query
+// as chips in the UI.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueryChip {
+    #[serde(flatten)]
+    pub filter: Filter,
+    pub negated: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ParsedQuery {
+    pub query: Option<Query>,
+    pub chips: Vec<QueryChip>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Word(String),
+    Quoted(String),
+    Field(String, String),
+}
+
+fn read_quoted(chars: &mut std::iter::Peekable<std::str::Chars>) -> Result<String, String> {
+    // Opening quote.
+    chars.next();
+    let mut text = String::new();
+    for c in chars.by_ref() {
+        if c == '"' {
+            return Ok(text);
+        }
+        text.push(c);
+    }
+    Err("Quote is not closed".to_string())
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => tokens.push(Token::Quoted(read_quoted(&mut chars)?)),
+            '-' => {
+                chars.next();
+                if chars.peek().is_some_and(|next| !next.is_whitespace()) {
+                    tokens.push(Token::Not);
+                }
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                    // Quoted values like from:"Alice Smith".
+                    if c == ':' && chars.peek() == Some(&'"') {
+                        word.push_str(&read_quoted(&mut chars)?);
+                        break;
+                    }
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => match word.split_once(':') {
+                        Some((key, value)) if !value.is_empty() => {
+                            Token::Field(key.to_lowercase(), value.to_string())
+                        }
+                        _ => Token::Word(word),
+                    },
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+pub fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+        .ok_or(format!("Invalid date: {}, expected YYYY-MM-DD", value))
+}
+
+fn field_filter(key: &str, value: &str) -> Result<Option<Filter>, String> {
+    let invalid = || format!("Unknown filter: {}:{}", key, value);
+    Ok(Some(match key {
+        "from" => Filter::From {
+            address: value.to_string(),
+        },
+        "to" => Filter::To {
+            address: value.to_string(),
+        },
+        "has" => match value.to_lowercase().as_str() {
+            "attachment" | "attachments" => Filter::HasAttachment,
+            _ => return Err(invalid()),
+        },
+        "is" => match value.to_lowercase().as_str() {
+            "unread" => Filter::IsUnread,
+            "read" => Filter::IsRead,
+            "flagged" => Filter::IsFlagged,
+            _ => return Err(invalid()),
+        },
+        "lang" | "language" => {
+            let language = value.to_lowercase();
+            if language.len() != 2 || !language.chars().all(|c| c.is_ascii_lowercase()) {
+                return Err(invalid());
+            }
+            Filter::Language { language }
+        }
+        "before" => Filter::Before {
+            date: parse_date(value)?.to_string(),
+        },
+        "after" => Filter::After {
+            date: parse_date(value)?.to_string(),
+        },
+        _ => return Ok(None),
+    }))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Query, String> {
+        let mut queries = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            queries.push(self.parse_and()?);
+        }
+        Ok(if queries.len() == 1 {
+            queries.remove(0)
+        } else {
+            Query::Or { queries }
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Query, String> {
+        let mut queries = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(Token::And) => {
+                    self.next();
+                }
+                _ => {}
+            }
+            queries.push(self.parse_unary()?);
+        }
+        Ok(if queries.len() == 1 {
+            queries.remove(0)
+        } else {
+            Query::And { queries }
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Query, String> {
+        match self.next() {
+            Some(Token::Not) => Ok(Query::Not {
+                query: Box::new(self.parse_unary()?),
+            }),
+            Some(Token::Open) => {
+                let query = self.parse_or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(query),
+                    _ => Err("Parenthesis is not closed".to_string()),
+                }
+            }
+            Some(Token::Word(text)) => Ok(Query::Filter {
+                filter: Filter::Text { text },
+            }),
+            Some(Token::Quoted(text)) => Ok(Query::Filter {
+                filter: Filter::Phrase { text },
+            }),
+            Some(Token::Field(key, value)) => {
+                let filter = field_filter(&key, &value)?.unwrap_or(Filter::Text {
+                    text: format!("{}:{}", key, value),
+                });
+                Ok(Query::Filter { filter })
+            }
+            Some(Token::Close) => Err("Unexpected closing parenthesis".to_string()),
+            Some(token) => Err(format!("Expected a search term, found {:?}", token)),
+            None => Err("Query ends with an operator".to_string()),
+        }
+    }
+}
+
+fn collect_chips(query: &Query, negated: bool, chips: &mut Vec<QueryChip>) {
+    match query {
+        Query::Filter { filter } => chips.push(QueryChip {
+            filter: filter.clone(),
+            negated,
+        }),
+        Query::Not { query } => collect_chips(query, !negated, chips),
+        Query::And { queries } | Query::Or { queries } => queries
+            .iter()
+            .for_each(|query| collect_chips(query, negated, chips)),
+    }
+}
+
+pub fn parse_query(query: &str) -> Result<ParsedQuery, String> {
+    let tokens = tokenize(query)?;
+    if tokens.is_empty() {
+        return Ok(ParsedQuery {
+            query: None,
+            chips: Vec::new(),
+        });
+    }
+
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+    };
+    let parsed = parser.parse_or()?;
+    if parser.peek().is_some() {
+        return Err("Unexpected closing parenthesis".to_string());
+    }
+    let mut chips = Vec::new();
+    collect_chips(&parsed, false, &mut chips);
+    Ok(ParsedQuery {
+        query: Some(parsed),
+        chips,
+    })
+}
+
+#[tauri::command]
+pub fn parse_search_query(query: String) -> Result<ParsedQuery, String> {
+    parse_query(&query)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Query {
+        Query::Filter {
+            filter: Filter::Text {
+                text: text.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn tokenizes_fields_quotes_and_operators() {
+        assert_eq!(
+            tokenize(r#"from:"Alice Smith" (a OR -b) "two words" NOT c:"#).unwrap(),
+            vec![
+                Token::Field("from".to_string(), "Alice Smith".to_string()),
+                Token::Open,
+                Token::Word("a".to_string()),
+                Token::Or,
+                Token::Not,
+                Token::Word("b".to_string()),
+                Token::Close,
+                Token::Quoted("two words".to_string()),
+                Token::Not,
+                Token::Word("c:".to_string()),
+            ]
+        );
+        // A lone "-" is not an operator.
+        assert_eq!(tokenize("a - b").unwrap().len(), 2);
+        assert!(tokenize(r#"from:"Alice"#).is_err());
+    }
+
+    #[test]
+    fn parses_precedence_and_negation() {
+        let parsed = parse_query("a b OR -c").unwrap();
+        assert_eq!(
+            parsed.query,
+            Some(Query::Or {
+                queries: vec![
+                    Query::And {
+                        queries: vec![text("a"), text("b")]
+                    },
+                    Query::Not {
+                        query: Box::new(text("c"))
+                    },
+                ]
+            })
+        );
+        let negated: Vec<bool> = parsed.chips.iter().map(|chip| chip.negated).collect();
+        assert_eq!(negated, vec![false, false, true]);
+    }
+
+    #[test]
+    fn parses_filters() {
+        let parsed =
+            parse_query("has:attachment is:UNREAD lang:EN before:2024/01/31 key:value").unwrap();
+        let filters: Vec<Filter> = parsed.chips.into_iter().map(|chip| chip.filter).collect();
+        assert_eq!(
+            filters,
+            vec![
+                Filter::HasAttachment,
+                Filter::IsUnread,
+                Filter::Language {
+                    language: "en".to_string()
+                },
+                Filter::Before {
+                    date: "2024-01-31".to_string()
+                },
+                Filter::Text {
+                    text: "key:value".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_queries() {
+        assert!(parse_query("is:something").is_err());
+        assert!(parse_query("after:yesterday").is_err());
+        assert!(parse_query("lang:english").is_err());
+        assert!(parse_query("(a OR b").is_err());
+        assert!(parse_query("a)").is_err());
+        assert!(parse_query("a OR").is_err());
+        assert!(parse_query("   ").unwrap().query.is_none());
+    }
+}
@@ -0,0 +1,264 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Listener, State};
+
+use crate::cache::{Cache, CachedEmail};
+use crate::consts;
+use crate::deep_link::MessageLink;
+use crate::scheduler;
+use crate::utils;
+
+// Emails of the synced folders can be found with Spotlight on macOS and
+// Windows Search on Windows (opt-in). Every cached email gets a stub file
+// in a directory the OS indexes, a .webloc on macOS and a .url on Windows.
+// Stubs only have the subject, the sender and the date in their name and
+// an openmail:// link to the email, see deep_link.rs, the body never leaves
+// the app. They are updated after every sync and removed with the emails.
+const SUPPORTED: bool = cfg!(any(target_os = "macos", target_os = "windows"));
+const STUB_EXTENSION: &str = if consts::IS_WINDOWS { "url" } else { "webloc" };
+const MAX_SUBJECT_CHARS: usize = 80;
+const MAX_SENDER_CHARS: usize = 60;
+// File names can be at most 255 bytes on most file systems.
+const MAX_NAME_BYTES: usize = 255;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchStubSettings {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchStubStatus {
+    pub supported: bool,
+    pub enabled: bool,
+    pub path: String,
+}
+
+// "<subject> - <sender> - <date> [<uid>].webloc", the uid is what the stubs
+// are matched with the emails by, so it is never cut.
+fn stub_name(email: &CachedEmail) -> String {
+    let subject: String = utils::sanitize_file_name(&email.subject)
+        .chars()
+        .take(MAX_SUBJECT_CHARS)
+        .collect();
+    let sender = email
+        .sender
+        .split_once('<')
+        .map_or(email.sender.as_str(), |(name, _)| name);
+    let sender: String = utils::sanitize_file_name(sender)
+        .chars()
+        .take(MAX_SENDER_CHARS)
+        .collect();
+    let date = utils::parse_email_date(&email.date)
+        .map(|date| date.format(" - %Y-%m-%d").to_string())
+        .unwrap_or_default();
+    let mut name = format!("{} - {}{}", subject, sender, date);
+    let suffix = format!(
+        " [{}].{}",
+        utils::sanitize_file_name(&email.uid),
+        STUB_EXTENSION
+    );
+    // Multi-byte characters can still make the name too long.
+    while !name.is_empty() && name.len() + suffix.len() > MAX_NAME_BYTES {
+        name.pop();
+    }
+    name + &suffix
+}
+
+fn stub_uid(name: &str) -> Option<&str> {
+    let name = name.strip_suffix(&format!("].{}", STUB_EXTENSION))?;
+    name.rsplit_once('[').map(|(_, uid)| uid)
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn stub_content(link: &MessageLink) -> String {
+    let url = link.to_url();
+    if consts::IS_WINDOWS {
+        format!("[InternetShortcut]\r\nURL={}\r\n", url)
+    } else {
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n<dict>\n\t<key>URL</key>\n\t<string>{}</string>\n</dict>\n</plist>\n",
+            escape_xml(&url)
+        )
+    }
+}
+
+pub struct SearchStubs {
+    cache: Arc<Cache>,
+    settings: Mutex<SearchStubSettings>,
+    // Exports after syncs don't run at the same time.
+    exporting: Mutex<()>,
+}
+
+impl SearchStubs {
+    pub fn load(cache: Arc<Cache>) -> Self {
+        SearchStubs {
+            cache,
+            settings: Mutex::new(
+                utils::read_json_file(consts::SEARCH_STUBS_SETTINGS_FILE_PATH).unwrap_or_default(),
+            ),
+            exporting: Mutex::new(()),
+        }
+    }
+
+    pub fn get_status(&self) -> SearchStubStatus {
+        SearchStubStatus {
+            supported: SUPPORTED,
+            enabled: self.is_enabled(),
+            path: utils::build_home_path(consts::SEARCH_STUBS_DIR_PATH),
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        SUPPORTED && self.settings.lock().unwrap().enabled
+    }
+
+    pub fn set_enabled(&self, enabled: bool) -> Result<SearchStubStatus, String> {
+        if enabled && !SUPPORTED {
+            return Err("System search is only supported on macOS and Windows".to_string());
+        }
+        let settings = SearchStubSettings { enabled };
+        utils::write_json_file(consts::SEARCH_STUBS_SETTINGS_FILE_PATH, &settings)?;
+        *self.settings.lock().unwrap() = settings;
+        if !enabled {
+            let _exporting = self.exporting.lock().unwrap();
+            let dir = utils::build_home_path(consts::SEARCH_STUBS_DIR_PATH);
+            if Path::new(&dir).exists() {
+                fs::remove_dir_all(&dir)
+                    .map_err(|err| format!("Failed to remove {}: {}", dir, err))?;
+            }
+        }
+        Ok(self.get_status())
+    }
+
+    fn export_folder(&self, account: &str, folder: &str) -> Result<(), String> {
+        let dir = Path::new(&utils::build_home_path(consts::SEARCH_STUBS_DIR_PATH))
+            .join(utils::sanitize_file_name(account))
+            .join(utils::sanitize_file_name(folder));
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+
+        let mut emails: HashMap<String, CachedEmail> = self
+            .cache
+            .get_emails(account, folder)
+            .into_iter()
+            .map(|email| (utils::sanitize_file_name(&email.uid), email))
+            .collect();
+        let entries = fs::read_dir(&dir)
+            .map_err(|err| format!("Failed to read {}: {}", dir.display(), err))?;
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // Stubs of the emails that are still cached are kept as they
+            // are, unless the email changed.
+            match stub_uid(&name).and_then(|uid| emails.get(uid)) {
+                Some(email) if stub_name(email) == name => {
+                    let uid = utils::sanitize_file_name(&email.uid);
+                    emails.remove(&uid);
+                }
+                _ => {
+                    fs::remove_file(entry.path()).ok();
+                }
+            }
+        }
+
+        for email in emails.into_values() {
+            let link = MessageLink {
+                account: account.to_string(),
+                folder: folder.to_string(),
+                uid: email.uid.clone(),
+            };
+            // One stub that can't be written doesn't stop the others.
+            let path = dir.join(stub_name(&email));
+            if let Err(err) = fs::write(&path, stub_content(&link)) {
+                println!("Failed to write {}: {}", path.display(), err);
+            }
+        }
+        Ok(())
+    }
+
+    fn export(&self, accounts: &[String]) -> Result<(), String> {
+        let _exporting = self.exporting.lock().unwrap();
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        for account in accounts {
+            for folder in scheduler::SYNCED_FOLDERS {
+                self.export_folder(account, folder)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn listen_sync(app: &AppHandle, stubs: Arc<SearchStubs>) {
+    app.listen(scheduler::SYNC_FINISHED_EVENT, move |event| {
+        if !stubs.is_enabled() {
+            return;
+        }
+        let Ok(accounts) = serde_json::from_str::<Vec<String>>(event.payload()) else {
+            return;
+        };
+
+        let stubs = stubs.clone();
+        thread::spawn(move || {
+            if let Err(err) = stubs.export(&accounts) {
+                println!("Failed to export search stubs: {}", err);
+            }
+        });
+    });
+}
+
+#[tauri::command]
+pub fn get_search_stubs(stubs: State<'_, Arc<SearchStubs>>) -> SearchStubStatus {
+    stubs.get_status()
+}
+
+#[tauri::command]
+pub fn set_search_stubs(
+    stubs: State<'_, Arc<SearchStubs>>,
+    enabled: bool,
+) -> Result<SearchStubStatus, String> {
+    let status = stubs.set_enabled(enabled)?;
+    // The already cached emails don't wait for the next sync.
+    if enabled {
+        let stubs = stubs.inner().clone();
+        thread::spawn(move || {
+            let exported =
+                scheduler::get_connected_accounts().and_then(|accounts| stubs.export(&accounts));
+            if let Err(err) = exported {
+                println!("Failed to export search stubs: {}", err);
+            }
+        });
+    }
+    Ok(status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn caps_long_stub_names() {
+        let email = CachedEmail {
+            uid: "42".to_string(),
+            sender: format!("{} <sender@example.com>", "名".repeat(200)),
+            subject: "件".repeat(200),
+            date: "Tue, 1 Jul 2025 10:00:00 +0000".to_string(),
+            ..Default::default()
+        };
+        let name = stub_name(&email);
+        assert!(name.len() <= MAX_NAME_BYTES);
+        assert_eq!(stub_uid(&name), Some("42"));
+    }
+}
@@ -15,3 +15,20 @@ class PortScanner:
             if PortScanner.is_port_available(port):
                 return port
         raise RuntimeError("No free ports available in the specified range")
+
+    @staticmethod
+    def bind_free_port(host: str, start_port: int, end_port: int) -> socket.socket:
+        """
+        Binds the first free port of the range and returns the socket.
+        Unlike `find_free_port`, the port can't be taken by someone else
+        (like the server of another user on the same machine) before it is
+        used.
+        """
+        for port in range(start_port, end_port + 1):
+            sock = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+            try:
+                sock.bind((host, port))
+                return sock
+            except OSError:
+                sock.close()
+        raise RuntimeError("No free ports available in the specified range")
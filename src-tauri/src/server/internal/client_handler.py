@@ -1,4 +1,5 @@
 import asyncio
+import time
 from typing import cast
 from concurrent.futures import ThreadPoolExecutor
 
@@ -21,9 +22,14 @@ type FailedOpenmailClients = list[str]
 
 MAX_TASK_WORKER = 5
 IMAP_LOGGED_OUT_INTERVAL = 60
+SIGN_IN_FAILURE_WINDOW_SEC = 24 * 60 * 60
 
 openmail_clients: OpenmailClients = {}
 failed_openmail_clients: list[str] = []
+# Unix times of the failed sign-in attempts of the last day, an account
+# stays in `failed_openmail_clients` without being tried again, so the
+# app can't count the attempts from it.
+sign_in_failures: dict[str, list[int]] = {}
 openmail_clients_for_new_messages: OpenmailClients = {}
 
 
@@ -94,6 +100,26 @@ class ClientHandler:
     def get_failed_clients(self) -> FailedOpenmailClients:
         return failed_openmail_clients
 
+    def get_sign_in_failures(self) -> dict[str, list[int]]:
+        return sign_in_failures
+
+    def _record_sign_in_failure(
+        self,
+        email_address: str,
+        target_failed_openmail_clients: FailedOpenmailClients | None
+    ):
+        # Clients for new messages sign in to the same accounts again.
+        if target_failed_openmail_clients is None:
+            return
+
+        if email_address not in target_failed_openmail_clients:
+            target_failed_openmail_clients.append(email_address)
+        now = int(time.time())
+        sign_in_failures[email_address] = [
+            failed_at for failed_at in sign_in_failures.get(email_address, [])
+            if failed_at > now - SIGN_IN_FAILURE_WINDOW_SEC
+        ] + [now]
+
     def is_client_exists(self, account: str, for_new_messages: bool = False) -> bool:
         if for_new_messages:
             return account in openmail_clients_for_new_messages
@@ -129,19 +155,17 @@ class ClientHandler:
                 # TODO: Open this later.
                 # target_openmail_clients[account.email_address].imap.idle()
                 try:
-                    if target_failed_openmail_clients:
+                    if target_failed_openmail_clients is not None:
                         target_failed_openmail_clients.remove(account.email_address)
                 except ValueError:
                     pass
             else:
                 uvicorn_logger.warning(f"Could not successfully connected to {account.email_address}.")
                 del target_openmail_clients[account.email_address]
-                if target_failed_openmail_clients:
-                    target_failed_openmail_clients.append(account.email_address)
+                self._record_sign_in_failure(account.email_address, target_failed_openmail_clients)
         except Exception as e:
             del target_openmail_clients[account.email_address]
-            if target_failed_openmail_clients:
-                target_failed_openmail_clients.append(account.email_address)
+            self._record_sign_in_failure(account.email_address, target_failed_openmail_clients)
             uvicorn_logger.error(
                 f"Failed while connecting to {account.email_address}: {e}"
             )
@@ -170,8 +194,7 @@ class ClientHandler:
                 uvicorn_logger.warning(f"{email_address} could not found while trying to reconnect.")
         except Exception as e:
             del target_openmail_clients[email_address]
-            if target_failed_openmail_clients:
-                target_failed_openmail_clients.append(email_address)
+            self._record_sign_in_failure(email_address, target_failed_openmail_clients)
             uvicorn_logger.error(f"Failed while reconnecting to {email_address}: {e}")
 
     def reconnect_logged_out_openmail_clients(self):
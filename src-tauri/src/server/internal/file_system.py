@@ -139,10 +139,20 @@ class DirObject:
 Constants
 """
 ROOT_DIR = os.path.join(os.path.expanduser("~"), "." + APP_NAME.lower(), "server")
+# Set by the app to the runtime directory of the login session, so the
+# servers of the same user in different sessions don't overwrite each
+# other's info file.
+RUNTIME_DIR_ENV = "OPENMAIL_RUNTIME_DIR"
+RUNTIME_DIR = os.getenv(RUNTIME_DIR_ENV) or ROOT_DIR
+RUNTIME_STRUCTURE = DirObject(
+    RUNTIME_DIR,
+    [
+        FileObject("uvicorn.info"),
+    ],
+)
 BASE_STRUCTURE = DirObject(
     ROOT_DIR,
     [
-        FileObject("uvicorn.info"),
         DirObject(
             "logs",
             [
@@ -155,11 +165,13 @@ BASE_STRUCTURE = DirObject(
 class FileSystem:
     _instance = None
     _root = BASE_STRUCTURE
+    _runtime = RUNTIME_STRUCTURE
 
     def __new__(cls):
         if not cls._instance:
             cls._instance = super().__new__(cls)
             cls._instance._create_structure(cls._root)
+            cls._instance._create_structure(cls._runtime)
 
         return cls._instance
 
@@ -191,21 +203,17 @@ class FileSystem:
         raise AttributeError("Modification of 'root' is not allowed.")
 
     def reset(self) -> None:
-        # In the `uvicorn.info` file we store the current URL and PID
-        # so that we can get the server's URL from tha tauri app
-        # and also can kill the server completely. So get the current
-        # URL and PID from the `uvicorn.info` file and write them back
-        # as initial content.
-        current_uvicorn_info = self.get_uvicorn_info().read()
+        # The `uvicorn.info` file (the current URL and PID of the server,
+        # so the tauri app can reach and kill it) is in the runtime
+        # directory and is kept as it is.
         self._root = BASE_STRUCTURE
         self._create_structure(self._root, remove_exists=True)
-        cast(FileObject, self.root["uvicorn.info"]).write(current_uvicorn_info)
 
 
     # Base FileObject/DirObject methods.
 
     def get_uvicorn_info(self) -> FileObject:
-        return cast(FileObject, self._root["uvicorn.info"])
+        return cast(FileObject, self._runtime["uvicorn.info"])
 
     def get_uvicorn_log(self) -> FileObject:
         return cast(FileObject, cast(DirObject, self._root["logs"])["uvicorn.log"])
@@ -78,12 +78,17 @@ async def hello() -> Response:
     return Response(success=True, message="Hello, Server is ready for you!")
 
 def main():
-    port = PortScanner.find_free_port(PORT_RANGE[0], PORT_RANGE[1])
+    # The socket is bound before the port is written to the info file, so
+    # servers started at the same time (by other users or sessions) can't
+    # end up with the same port.
+    sock = PortScanner.bind_free_port(HOST, PORT_RANGE[0], PORT_RANGE[1])
+    port = sock.getsockname()[1]
     pid = str(os.getpid())
     FileSystem().get_uvicorn_info().write(f"URL=http://{HOST}:{str(port)}\nPID={pid}\n")
 
     uvicorn_logger.info("Starting server at http://%s:%d | PID: %s", HOST, port, pid)
-    uvicorn.run(app, host=HOST, port=port)
+    server = uvicorn.Server(uvicorn.Config(app, host=HOST, port=port))
+    server.run(sockets=[sock])
 
 
 if __name__ == "__main__":
@@ -17,6 +17,9 @@ Author: <berkaykayaforbusiness@outlook.com>
 """
 
 from email.message import EmailMessage
+from email.parser import BytesParser
+from email import policy as email_policy
+import base64
 import imaplib
 import re
 import threading
@@ -24,7 +27,7 @@ import time
 from zoneinfo import ZoneInfo
 from typing import Callable, override, List
 from enum import Enum
-from ssl import SSLContext
+from ssl import SSLContext, cert_time_to_seconds
 from types import MappingProxyType
 from datetime import datetime, timedelta
 from dataclasses import dataclass
@@ -174,6 +177,7 @@ class IMAPManager(imaplib.IMAP4_SSL):
         self._wait_response: IMAPManager.WaitResponse | None = None
         self._previous_mailbox_size = 0
         self._new_message_timestamps: List[datetime] = []
+        self._new_message_count = 0
         self._readline_event = threading.Event()
         self._readline_thread = threading.Thread(
             target=self._start_reading_lines, daemon=True
@@ -1056,6 +1060,7 @@ class IMAPManager(imaplib.IMAP4_SSL):
             received_at = datetime.now() - timedelta(minutes=EMAIL_LOOKBACK_WINDOW)
             received_at = received_at.astimezone(ZoneInfo("UTC"))
             self._new_message_timestamps.append(received_at)
+            self._new_message_count += 1
 
     def _handle_bye_response(self):
         """
@@ -1826,6 +1831,270 @@ class IMAPManager(imaplib.IMAP4_SSL):
             folder=self._searched_emails.folder, emails=emails, total=self._searched_emails.count
         )
 
+    @handle_idle
+    def get_folder_emails(self,
+        folder: str,
+        offset_start: int | None = None,
+        offset_end: int | None = None
+    ) -> Mailbox:
+        """
+        Fetch emails of a folder without touching the result of the last
+        `search_emails()` call, so background tasks (like the sync of the
+        tauri app) don't break the pagination of the client.
+
+        Args:
+            folder (str): Folder to fetch emails from.
+            offset_start (int, optional): Starting index of the emails to fetch. Defaults to 1.
+            offset_end (int, optional): Ending index of the emails to fetch. Defaults to 10.
+
+        Returns:
+            Mailbox: Dataclass containing the fetched emails, folder, and total number of emails.
+
+        Example:
+            >>> get_folder_emails("Sent", 1, 2)
+            Mailbox(folder='Sent', emails=[Email(uid="7", sender="a@gmail.com", ...),
+            Email(uid="6", sender="a@gmail.com", ...)], total=30)
+        """
+        last_searched_emails = self._searched_emails
+        try:
+            self._searched_emails = None
+            self.search_emails(folder)
+            if not self._searched_emails:
+                return Mailbox(folder=folder, emails=[], total=0)
+            return self.get_emails(offset_start, offset_end)
+        finally:
+            self._searched_emails = last_searched_emails
+            if last_searched_emails:
+                self.select(last_searched_emails.folder, readonly=True)
+
+    @handle_idle
+    def save_app_data(self, folder: str, subject: str, content: str) -> IMAPCommandResult:
+        """
+        Store a piece of app data (like the settings of the tauri app) as the
+        only email of the given folder. The folder is created and unsubscribed
+        on first use so other email clients don't show it.
+
+        Args:
+            folder (str): Folder to store the data in.
+            subject (str): Subject of the email that holds the data.
+            content (str): Data to store, should be plain text.
+
+        Returns:
+            IMAPCommandResult: A tuple containing:
+                - A bool indicating whether the data was saved successfully.
+                - A string containing a success message or an error message.
+
+        Example:
+            >>> save_app_data("Openmail Settings", "Openmail Settings", "eyJ2ZXJzaW9u...")
+            (True, "App data saved to `Openmail Settings` successfully.")
+        """
+        self._check_folder_names(folder)
+
+        if folder not in self.get_folders():
+            status, msg = self.create_folder(folder)
+            if not status:
+                return status, msg
+            self.unsubscribe(self._encode_folder(folder))
+
+        last_searched_emails = self._searched_emails
+        try:
+            self.select(folder)
+            status, data = self.uid("SEARCH", "ALL")
+            old_uids = data[0].decode().split() if status == "OK" and data and data[0] else []
+
+            message = EmailMessage()
+            message["Subject"] = subject
+            message.set_content(content)
+            status, _ = self.append(
+                self._encode_folder(folder),
+                "\\Seen",
+                imaplib.Time2Internaldate(time.time()),
+                message.as_bytes(),
+            )  # type: ignore
+            if status != "OK":
+                return False, f"There was an error while saving app data to `{folder}`."
+
+            # Old versions are removed only after the new one is saved.
+            if old_uids:
+                self.uid("STORE", ",".join(old_uids), "+FLAGS", "\\Deleted")
+                self.expunge()
+
+            return True, f"App data saved to `{folder}` successfully."
+        finally:
+            if last_searched_emails:
+                self.select(last_searched_emails.folder, readonly=True)
+
+    @handle_idle
+    def get_app_data(self, folder: str) -> str | None:
+        """
+        Get the app data stored with `save_app_data()`.
+
+        Args:
+            folder (str): Folder the data is stored in.
+
+        Returns:
+            str | None: The stored data or None if nothing was stored yet.
+
+        Example:
+            >>> get_app_data("Openmail Settings")
+            "eyJ2ZXJzaW9u..."
+        """
+        self._check_folder_names(folder)
+
+        if folder not in self.get_folders():
+            return None
+
+        last_searched_emails = self._searched_emails
+        try:
+            self.select(folder, readonly=True)
+            status, data = self.uid("SEARCH", "ALL")
+            uids = data[0].decode().split() if status == "OK" and data and data[0] else []
+            if not uids:
+                return None
+
+            status, message = self.uid("FETCH", uids[-1], "(BODY.PEEK[])")
+            if status != "OK" or not message or not isinstance(message[0], tuple):
+                raise IMAPManagerException(
+                    f"Error while fetching app data from folder `{folder}`: `{status}`"
+                )
+
+            return BytesParser(policy=email_policy.default).parsebytes(
+                message[0][1]
+            ).get_content().strip()
+        finally:
+            if last_searched_emails:
+                self.select(last_searched_emails.folder, readonly=True)
+
+    @handle_idle
+    def get_email_flags_in_folder(self, folder: str, uid: str) -> list[str] | None:
+        """
+        Get the current flags of an email without fetching its content.
+
+        Args:
+            folder (str): Folder containing the email.
+            uid (str): Unique identifier of the email.
+
+        Returns:
+            list[str] | None: Flags of the email or None if there is no
+            email with given uid in the folder (moved or deleted).
+
+        Example:
+            >>> get_email_flags_in_folder(Folder.Inbox, "1")
+            ['\\Seen', '\\Flagged']
+        """
+        last_searched_emails = self._searched_emails
+        try:
+            self.select(folder, readonly=True)
+            status, message = self.uid("FETCH", uid, "(FLAGS)")
+            if status != "OK":
+                raise IMAPManagerException(
+                    f"Error while getting flags of email `{uid}` in folder `{folder}`: `{status}`"
+                )
+
+            if not message or not message[0]:
+                return None
+
+            response = message[0][0] if isinstance(message[0], tuple) else message[0]
+            flags = re.search(rb"FLAGS \((.*?)\)", response)
+            return flags.group(1).decode().split() if flags else []
+        finally:
+            if last_searched_emails:
+                self.select(last_searched_emails.folder, readonly=True)
+
+    @handle_idle
+    def get_email_source(self, folder: str, uid: str) -> str | None:
+        """
+        Get the raw source (RFC 822) of an email without marking it as seen.
+
+        Args:
+            folder (str): Folder containing the email.
+            uid (str): Unique identifier of the email.
+
+        Returns:
+            str | None: Source of the email encoded as base64 or None if
+            there is no email with given uid in the folder.
+
+        Example:
+            >>> get_email_source(Folder.Inbox, "1")
+            "UmV0dXJuLVBhdGg6IDxh..."
+        """
+        last_searched_emails = self._searched_emails
+        try:
+            self.select(folder, readonly=True)
+            status, message = self.uid("FETCH", uid, "(BODY.PEEK[])")
+            if status != "OK":
+                raise IMAPManagerException(
+                    f"Error while getting source of email `{uid}` in folder `{folder}`: `{status}`"
+                )
+
+            if not message or not isinstance(message[0], tuple):
+                return None
+
+            return base64.b64encode(message[0][1]).decode()
+        finally:
+            if last_searched_emails:
+                self.select(last_searched_emails.folder, readonly=True)
+
+    def get_new_message_count(self) -> int:
+        """
+        Returns how many times the server reported new messages since the
+        connection was created. Unlike `get_recent_emails`, reading it
+        doesn't reset anything, so any number of listeners can watch it.
+
+        Returns:
+            int: Number of new message reports.
+        """
+        return self._new_message_count
+
+    @handle_idle
+    def get_storage_quota(self) -> tuple[int, int] | None:
+        """
+        Returns the used and the total storage of the account from the
+        quota root of the inbox.
+
+        Returns:
+            tuple[int, int] | None: Used and total storage in KB, None if
+            the server doesn't support quotas or has no storage limit.
+
+        Example:
+            >>> get_storage_quota()
+            (10240, 15728640)
+
+        References:
+            https://datatracker.ietf.org/doc/html/rfc9208#name-getquotaroot
+        """
+        if not self.is_supported("QUOTA"):
+            return None
+
+        status, response = self.getquotaroot("INBOX")
+        if status != "OK":
+            raise IMAPManagerException(f"Failed to get quota with status: {status}.")
+
+        # Response is like [[b'"INBOX" ""'], [b'"" (STORAGE 10 512)']]
+        for line in response:
+            for item in (line if isinstance(line, list) else [line]):
+                if not isinstance(item, bytes):
+                    continue
+                match = re.search(rb"STORAGE (\d+) (\d+)", item, re.IGNORECASE)
+                if match:
+                    return int(match.group(1)), int(match.group(2))
+        return None
+
+    def get_certificate_expiry(self) -> datetime | None:
+        """
+        Returns when the certificate of the IMAP server expires.
+
+        Returns:
+            datetime | None: Expiry date in UTC, None if the certificate
+            was not validated so its details are not known.
+        """
+        certificate = self.sock.getpeercert()
+        if not certificate or "notAfter" not in certificate:
+            return None
+        return datetime.fromtimestamp(
+            cert_time_to_seconds(str(certificate["notAfter"])), ZoneInfo("UTC")
+        )
+
     def any_new_email(self) -> bool:
         """
         Checks if there are any new emails by verifying if new message timestamps
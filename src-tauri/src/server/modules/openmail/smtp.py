@@ -24,6 +24,8 @@ import urllib.error
 from typing import Sequence, override
 from types import MappingProxyType
 from email.message import EmailMessage, Message
+from email.parser import BytesParser
+from email import policy as email_policy
 from email.headerregistry import Address
 
 from .parser import HTMLParser, MessageParser
@@ -360,6 +362,28 @@ class SMTPManager(smtplib.SMTP):
         """
         return self.send_message(self.create_email(draft))
 
+    def send_raw_email(self, message: bytes) -> SMTPCommandResult:
+        """
+        Send a prebuilt (RFC 822) message as it is.
+
+        Args:
+            message (bytes): Raw message to send.
+
+        Returns:
+            SMTPCommandResult: A tuple containing:
+                - A bool indicating whether the email was sent successfully.
+                - A string containing a success message or an error message.
+
+        Notes:
+            - Receivers are taken from the `To`, `Cc` and `Bcc` headers of
+            the message and the `Bcc` header is removed before sending.
+        """
+        msg = BytesParser(policy=email_policy.default).parsebytes(message)
+        if not msg["From"]:
+            raise SMTPManagerException("Message has no `From` header.")
+
+        return self.send_message(msg)
+
     def reply_email(self,
         original_message_id: str,
         draft: Draft,
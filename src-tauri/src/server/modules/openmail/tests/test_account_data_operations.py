@@ -0,0 +1,96 @@
+import json
+import unittest
+from datetime import datetime, timedelta, timezone
+
+from modules.openmail import Openmail
+from modules.openmail.imap import Mark, Folder
+from .utils.dummy_operator import DummyOperator
+from .utils.name_generator import NameGenerator
+
+class TestAccountDataOperations(unittest.TestCase):
+
+    @classmethod
+    def setUpClass(cls):
+        print("Setting up test `TestAccountDataOperations`...")
+        cls.addClassCleanup(cls.cleanup)
+
+        cls._openmail = Openmail()
+        with open("./credentials.json") as credentials:
+            credentials = json.load(credentials)
+        cls._email = credentials[0]["email"]
+        cls._openmail.connect(cls._email, credentials[0]["password"])
+        print(f"Connected to {cls._email}...")
+
+        cls._created_test_folders = []
+        cls._sent_test_email_uids = []
+
+    def test_get_storage_quota(self):
+        print("test_get_storage_quota...")
+        quota = self.__class__._openmail.imap.get_storage_quota()
+        if quota is None:
+            print(f"{self.__class__._email} does not support quotas. Skipping...")
+            return
+
+        used, total = quota
+        self.assertGreaterEqual(used, 0)
+        self.assertGreater(total, 0)
+        self.assertLessEqual(used, total)
+
+    def test_get_certificate_expiry(self):
+        print("test_get_certificate_expiry...")
+        expires_at = self.__class__._openmail.imap.get_certificate_expiry()
+        if expires_at is None:
+            print(f"Certificate of {self.__class__._email} was not validated. Skipping...")
+            return
+
+        self.assertEqual(expires_at.utcoffset(), timedelta(0))
+        self.assertGreater(expires_at, datetime.now(timezone.utc))
+
+    def test_get_email_flags_in_folder(self):
+        print("test_get_email_flags_in_folder...")
+
+        print(f"Sending new email to get its flags...")
+        uid = DummyOperator.send_test_email_to_self_and_get_uid(self.__class__._openmail, self.__class__._email)
+        self.__class__._sent_test_email_uids.append(uid)
+
+        status, _ = self.__class__._openmail.imap.mark_email(uid, Mark.Flagged)
+        self.assertTrue(status)
+        self.assertIn(
+            Mark.Flagged,
+            self.__class__._openmail.imap.get_email_flags_in_folder(Folder.Inbox, uid)
+        )
+
+    def test_get_email_flags_in_folder_of_missing_email(self):
+        print("test_get_email_flags_in_folder_of_missing_email...")
+
+        print(f"Sending new email to delete...")
+        uid = DummyOperator.send_test_email_to_self_and_get_uid(self.__class__._openmail, self.__class__._email)
+        status, _ = self.__class__._openmail.imap.delete_email(Folder.Inbox, uid)
+        self.assertTrue(status)
+
+        self.assertIsNone(self.__class__._openmail.imap.get_email_flags_in_folder(Folder.Inbox, uid))
+
+    def test_save_and_get_app_data(self):
+        print("test_save_and_get_app_data...")
+        folder = NameGenerator.folder_name()[0]
+        self.__class__._created_test_folders.append(folder)
+
+        self.assertIsNone(self.__class__._openmail.imap.get_app_data(folder))
+
+        status, _ = self.__class__._openmail.imap.save_app_data(folder, folder, "first")
+        self.assertTrue(status)
+        self.assertEqual(self.__class__._openmail.imap.get_app_data(folder), "first")
+
+        # Only the latest version is kept.
+        status, _ = self.__class__._openmail.imap.save_app_data(folder, folder, "second")
+        self.assertTrue(status)
+        self.assertEqual(self.__class__._openmail.imap.get_app_data(folder), "second")
+
+    @classmethod
+    def cleanup(cls):
+        print("Cleaning up test `TestAccountDataOperations`...")
+        for folder_name in cls._created_test_folders:
+            cls._openmail.imap.delete_folder(folder_name, True)
+        if cls._sent_test_email_uids:
+            cls._openmail.imap.delete_email(Folder.Inbox, ",".join(sorted(cls._sent_test_email_uids, key=int)))
+        cls._openmail.disconnect()
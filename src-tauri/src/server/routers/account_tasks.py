@@ -1,3 +1,6 @@
+import base64
+import json
+import time
 from fastapi import APIRouter
 from pydantic import BaseModel
 from typing import Optional, cast
@@ -6,13 +9,17 @@ from _types import Response
 from utils import err_msg, is_email_valid
 from modules.openmail import Openmail
 from internal.account_manager import AccountManager, Account, AccountWithPassword
-from internal.secure_storage import SecureStorage, SecureStorageKey, RSACipher, SecureStorageKeyValue
+from internal.secure_storage import SecureStorage, SecureStorageKey, RSACipher, AESGCMCipher, SecureStorageKeyValue
 from internal.client_handler import ClientHandler
 
 client_handler = ClientHandler()
 secure_storage = SecureStorage()
 account_manager = AccountManager()
 
+# Bound to the encrypted transfer payloads so they can't be
+# mixed up with anything else encrypted with the same key.
+ACCOUNT_TRANSFER_ASSOCIATED_DATA = b"openmail-account-transfer"
+
 router = APIRouter(
     tags=["Accounts"]
 )
@@ -33,6 +40,8 @@ async def get_public_key() -> Response[GetPublicKeyData]:
 class GetAccountsData(BaseModel):
     connected: list[Account]
     failed: list[Account]
+    # Unix times of the failed sign-in attempts of the last day.
+    sign_in_failures: dict[str, list[int]] = {}
 
 @router.get("/get-accounts")
 def get_accounts() -> Response[GetAccountsData]:
@@ -50,7 +59,8 @@ def get_accounts() -> Response[GetAccountsData]:
             message="Email accounts fetched successfully",
             data=GetAccountsData(
                 connected=connected_accounts,
-                failed=failed_accounts
+                failed=failed_accounts,
+                sign_in_failures=client_handler.get_sign_in_failures()
             )
         )
     except Exception as e:
@@ -157,6 +167,67 @@ def edit_account(request: EditAccountRequest) -> Response:
     except Exception as e:
         return Response(success=False, message=err_msg("Failed to add email.", str(e)))
 
+class ExportAccountRequest(BaseModel):
+    account: str
+    transfer_key: str # base64 encoded 32 bytes key
+    expires_at: int # unix timestamp
+
+class ExportAccountData(BaseModel):
+    payload: str
+
+@router.post("/export-account")
+def export_account(request: ExportAccountRequest) -> Response[ExportAccountData]:
+    try:
+        account = account_manager.get(request.account)
+        if not isinstance(account, AccountWithPassword):
+            return Response(success=False, message="Email address does not exists")
+
+        payload = AESGCMCipher(base64.b64decode(request.transfer_key)).encrypt(
+            json.dumps({
+                "email_address": account.email_address,
+                "fullname": account.fullname,
+                "password": RSACipher.decrypt_password(
+                    account.encrypted_password,
+                    cast(SecureStorageKeyValue, secure_storage.get_key_value(SecureStorageKey.PrivatePem))["value"]
+                ),
+                "expires_at": request.expires_at
+            }),
+            ACCOUNT_TRANSFER_ASSOCIATED_DATA
+        )
+        return Response[ExportAccountData](
+            success=True,
+            message="Account exported successfully",
+            data=ExportAccountData(payload=payload)
+        )
+    except Exception as e:
+        return Response(success=False, message=err_msg("Failed to export account.", str(e)))
+
+class ImportAccountRequest(BaseModel):
+    payload: str
+    transfer_key: str # base64 encoded 32 bytes key
+
+@router.post("/import-account")
+def import_account(request: ImportAccountRequest) -> Response:
+    try:
+        account = json.loads(AESGCMCipher(base64.b64decode(request.transfer_key)).decrypt(
+            request.payload,
+            ACCOUNT_TRANSFER_ASSOCIATED_DATA
+        ))
+    except Exception as e:
+        return Response(success=False, message=err_msg("Invalid transfer code or payload.", str(e)))
+
+    if account["expires_at"] < time.time():
+        return Response(success=False, message="Account transfer is expired")
+
+    return add_account(AddAccountRequest(
+        email_address=account["email_address"],
+        encrypted_password=RSACipher.encrypt_password(
+            account["password"],
+            cast(SecureStorageKeyValue, secure_storage.get_key_value(SecureStorageKey.PublicPem))["value"]
+        ),
+        fullname=account["fullname"]
+    ))
+
 class RemoveAccountRequest(BaseModel):
     account: str
 
@@ -1,4 +1,6 @@
 import asyncio
+import base64
+import time
 from urllib.parse import unquote
 from fastapi import APIRouter, WebSocket, WebSocketDisconnect, Form, UploadFile
 from pydantic import BaseModel
@@ -10,6 +12,7 @@ from internal.account_manager import AccountManager
 from internal.client_handler import ClientHandler
 from helpers.uvicorn_logger import UvicornLogger
 from modules.openmail.types import Email, Mailbox, Folder, Draft, Attachment, SearchCriteria
+from modules.openmail.parser import MessageParser
 from modules.openmail.utils import extract_email_address
 
 client_handler = ClientHandler()
@@ -20,6 +23,8 @@ T = TypeVar("T")
 OpenmailTaskResults = dict[str, T]
 
 NEW_EMAIL_CHECK_INTERVAL_SEC = 60
+NEW_MESSAGE_PUSH_CHECK_INTERVAL_SEC = 1
+MAX_SANITIZER_BENCHMARK_ROUNDS = 100
 
 router = APIRouter(
     tags=["Emails"]
@@ -38,6 +43,30 @@ def check_openmail_connection_availability(
         message=f'Error, {account} connection is not available or timed out and could not reconnected.'
     )
 
+async def connect_for_new_messages(websocket: WebSocket, account: str) -> bool:
+    if client_handler.is_client_exists(account, True):
+        response = check_openmail_connection_availability(account, True)
+        if isinstance(response, Response):
+            await websocket.close(reason=response.message)
+            uvicorn_logger.websocket(websocket, response.message)
+            return False
+    else:
+        account = account_manager.get(account)
+        if account:
+            client_handler.connect_to_account(account, True)
+        else:
+            reason = f"There is no account with {account} email address."
+            await websocket.close(reason=reason)
+            uvicorn_logger.websocket(websocket, reason)
+            return False
+    return True
+
+# Nothing is received from the clients of the push sockets, so a client
+# that is gone is only noticed by waiting for its disconnect message.
+async def wait_for_disconnect(websocket: WebSocket) -> None:
+    while (await websocket.receive())["type"] != "websocket.disconnect":
+        pass
+
 @router.websocket("/notifications/{account}")
 async def notifications_socket(websocket: WebSocket, account: str):
     await websocket.accept()
@@ -45,21 +74,8 @@ async def notifications_socket(websocket: WebSocket, account: str):
     try:
         while True:
             account = extract_email_address(account)
-            if client_handler.is_client_exists(account, True):
-                response = check_openmail_connection_availability(account, True)
-                if isinstance(response, Response):
-                    await websocket.close(reason=response.message)
-                    uvicorn_logger.websocket(websocket, response.message)
-                    break
-            else:
-                account = account_manager.get(account)
-                if account:
-                    client_handler.connect_to_account(account, True)
-                else:
-                    reason = f"There is no account with {account} email address."
-                    await websocket.close(reason=reason)
-                    uvicorn_logger.websocket(websocket, reason)
-                    break
+            if not await connect_for_new_messages(websocket, account):
+                break
 
             # Listen for new messages and send notification when
             # any new message received.
@@ -80,6 +96,41 @@ async def notifications_socket(websocket: WebSocket, account: str):
     except WebSocketDisconnect:
         pass
 
+# Sends the number of new message reports whenever IDLE receives some,
+# without the emails themselves and without resetting the recent emails of
+# the notifications socket. Used by the app to sync and index them.
+@router.websocket("/new-message-pushes/{account}")
+async def new_message_pushes_socket(websocket: WebSocket, account: str):
+    await websocket.accept()
+    uvicorn_logger.websocket(websocket, "New message push subscription created")
+    disconnected = asyncio.ensure_future(wait_for_disconnect(websocket))
+    try:
+        account = extract_email_address(account)
+        if not await connect_for_new_messages(websocket, account):
+            return
+
+        last_count = client_handler.get_client(account, True).imap.get_new_message_count()
+        while True:
+            try:
+                done, _ = await asyncio.wait([disconnected], timeout=NEW_MESSAGE_PUSH_CHECK_INTERVAL_SEC)
+                if done:
+                    uvicorn_logger.websocket(websocket, "New message push subscription closed")
+                    break
+                count = client_handler.get_client(account, True).imap.get_new_message_count()
+                if count != last_count:
+                    await websocket.send_json({account: count - last_count})
+                    last_count = count
+            except WebSocketDisconnect:
+                break
+            except Exception as e:
+                await websocket.close(reason="There was an error while receving new messages.")
+                uvicorn_logger.websocket(websocket, e)
+                break
+    except WebSocketDisconnect:
+        pass
+    finally:
+        disconnected.cancel()
+
 @router.get("/get-hierarchy-delimiter/{account}")
 async def get_hierarchy_delimiter(
     account: str
@@ -98,6 +149,59 @@ async def get_hierarchy_delimiter(
     except Exception as e:
         return Response(success=False, message=err_msg("There was an error while getting IMAP hierarchy delimiter", str(e)))
 
+class AccountHealthData(BaseModel):
+    quota_used_kb: Optional[int] = None
+    quota_total_kb: Optional[int] = None
+    certificate_expires_at: Optional[str] = None
+
+@router.get("/get-account-health/{account}")
+async def get_account_health(
+    account: str
+) -> Response[OpenmailTaskResults[AccountHealthData]]:
+    try:
+        account = extract_email_address(account)
+        response = check_openmail_connection_availability(account)
+        if isinstance(response, Response):
+            return response
+
+        imap = client_handler.get_client(account).imap
+        quota = imap.get_storage_quota()
+        certificate_expires_at = imap.get_certificate_expiry()
+        return Response(
+            success=True,
+            message="Account health fetched successfully.",
+            data={account: AccountHealthData(
+                quota_used_kb=quota[0] if quota else None,
+                quota_total_kb=quota[1] if quota else None,
+                certificate_expires_at=certificate_expires_at.isoformat() if certificate_expires_at else None
+            )}
+        )
+    except Exception as e:
+        return Response(success=False, message=err_msg("There was an error while getting account health", str(e)))
+
+class SanitizerBenchmarkRequest(BaseModel):
+    html: str
+    rounds: int
+
+# Times the sanitizer of the email bodies here, so sending the document
+# is not counted. Used by the benchmarks of the app, see benchmarks.rs
+@router.post("/benchmark-sanitizer")
+def benchmark_sanitizer(request_body: SanitizerBenchmarkRequest) -> Response[list[float]]:
+    try:
+        durations = []
+        for _ in range(max(1, min(request_body.rounds, MAX_SANITIZER_BENCHMARK_ROUNDS))):
+            start = time.perf_counter()
+            MessageParser.body(request_body.html, sanitize=True)
+            durations.append(time.perf_counter() - start)
+
+        return Response(
+            success=True,
+            message="Sanitizer benchmarked successfully.",
+            data=durations
+        )
+    except Exception as e:
+        return Response(success=False, message=err_msg("There was an error while benchmarking the sanitizer", str(e)))
+
 @router.get("/search-emails/{account}")
 async def search_emails(
     account: str,
@@ -174,6 +278,50 @@ async def paginate_mailbox(
     except Exception as e:
         return Response(success=False, message=err_msg("There was an error while paginating emails.", str(e)))
 
+@router.get("/get-folder-emails/{account}")
+async def get_folder_emails(
+    account: str,
+    folder: str,
+    offset_start: Optional[int] = None,
+    offset_end: Optional[int] = None,
+) -> Response[OpenmailTaskResults[Mailbox]]:
+    try:
+        account = extract_email_address(account)
+        response = check_openmail_connection_availability(account)
+        if isinstance(response, Response):
+            return response
+
+        return Response(
+            success=True,
+            message="Folder emails fetched successfully.",
+            data={account: client_handler.get_client(account).imap.get_folder_emails(
+                folder,
+                offset_start,
+                offset_end
+            )}
+        )
+    except Exception as e:
+        return Response(success=False, message=err_msg("There was an error while fetching folder emails.", str(e)))
+
+@router.get("/get-app-data/{account}")
+async def get_app_data(
+    account: str,
+    folder: str,
+) -> Response[OpenmailTaskResults[str | None]]:
+    try:
+        account = extract_email_address(account)
+        response = check_openmail_connection_availability(account)
+        if isinstance(response, Response):
+            return response
+
+        return Response(
+            success=True,
+            message="App data fetched successfully.",
+            data={account: client_handler.get_client(account).imap.get_app_data(folder)}
+        )
+    except Exception as e:
+        return Response(success=False, message=err_msg("There was an error while fetching app data.", str(e)))
+
 @router.get("/get-folders/{account}")
 async def get_folders(
     account: str,
@@ -212,6 +360,46 @@ def get_email_content(
     except Exception as e:
         return Response(success=False, message=err_msg("There was an error while fetching email content.", str(e)))
 
+@router.get("/get-email-flags/{account}/{uid}")
+def get_email_flags(
+    account: str,
+    uid: str,
+    folder: str
+) -> Response[OpenmailTaskResults[list[str] | None]]:
+    try:
+        account = extract_email_address(account)
+        response = check_openmail_connection_availability(account)
+        if isinstance(response, Response):
+            return response
+
+        return Response(
+            success=True,
+            message="Email flags fetched successfully.",
+            data={account: client_handler.get_client(account).imap.get_email_flags_in_folder(folder, uid)}
+        )
+    except Exception as e:
+        return Response(success=False, message=err_msg("There was an error while fetching email flags.", str(e)))
+
+@router.get("/get-email-source/{account}/{uid}")
+def get_email_source(
+    account: str,
+    uid: str,
+    folder: str
+) -> Response[OpenmailTaskResults[str | None]]:
+    try:
+        account = extract_email_address(account)
+        response = check_openmail_connection_availability(account)
+        if isinstance(response, Response):
+            return response
+
+        return Response(
+            success=True,
+            message="Email source fetched successfully.",
+            data={account: client_handler.get_client(account).imap.get_email_source(folder, uid)}
+        )
+    except Exception as e:
+        return Response(success=False, message=err_msg("There was an error while fetching email source.", str(e)))
+
 @router.get("/download-attachment/{account}/{folder}/{uid}/{name}")
 def download_attachment(
     account: str,
@@ -266,13 +454,14 @@ class SendEmailFormData(BaseModel):
     cc: Optional[str] = None # mail addresses separated by comma
     bcc: Optional[str] = None # mail addresses separated by comma
     attachments: list[UploadFile] = []
+    account: Optional[str] = None # account to send with if the sender is a shared mailbox
 
 @router.post("/send-email")
 async def send_email(
     form_data: Annotated[SendEmailFormData, Form()],
 ) -> Response:
     try:
-        account = extract_email_address(form_data.sender)
+        account = extract_email_address(form_data.account or form_data.sender)
         response = check_openmail_connection_availability(account)
         if isinstance(response, Response):
             return response
@@ -294,6 +483,27 @@ async def send_email(
         return Response(success=False, message=err_msg("There was an error while sending email.", str(e)))
 
 
+class SendRawEmailRequest(BaseModel):
+    account: str
+    message: str # RFC 822 message encoded as base64
+
+
+@router.post("/send-raw-email")
+async def send_raw_email(request_body: SendRawEmailRequest) -> Response:
+    try:
+        account = extract_email_address(request_body.account)
+        response = check_openmail_connection_availability(account)
+        if isinstance(response, Response):
+            return response
+
+        status, msg = client_handler.get_client(account).smtp.send_raw_email(
+            base64.b64decode(request_body.message)
+        )
+        return Response(success=status, message=msg)
+    except Exception as e:
+        return Response(success=False, message=err_msg("There was an error while sending raw email.", str(e)))
+
+
 @router.post("/reply-email/{original_message_id}")
 async def reply_email(
     original_message_id: str,
@@ -502,6 +712,29 @@ async def delete_email(request_body: DeleteEmailRequest) -> Response:
         return Response(success=False, message=err_msg("There was an error while deleting email.", str(e)))
 
 
+class SaveAppDataRequest(BaseModel):
+    account: str
+    folder: str
+    subject: str
+    content: str
+
+
+@router.post("/save-app-data")
+async def save_app_data(request_body: SaveAppDataRequest) -> Response:
+    try:
+        account = extract_email_address(request_body.account)
+        response = check_openmail_connection_availability(account)
+        if isinstance(response, Response):
+            return response
+
+        status, msg = client_handler.get_client(account).imap.save_app_data(
+            request_body.folder, request_body.subject, request_body.content
+        )
+        return Response(success=status, message=msg)
+    except Exception as e:
+        return Response(success=False, message=err_msg("There was an error while saving app data.", str(e)))
+
+
 class CreateFolderRequest(BaseModel):
     account: str
     folder_name: str
@@ -0,0 +1,41 @@
+use std::env;
+
+// Login session of the user. Files in the home directory are already per
+// user, the session also keeps apart the same user logged in twice (like
+// on a terminal server). Shared with openmail-cli (see bin/openmail-cli.rs),
+// which has to find the app of its session.
+pub const SESSION_ENV: &str = "OPENMAIL_SESSION";
+
+pub fn path_safe(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// `SESSION_ENV` overrides it, like for a terminal that doesn't have the
+// variables of the desktop session.
+pub fn session_id() -> String {
+    if let Some(session) = env::var(SESSION_ENV).ok().filter(|value| !value.is_empty()) {
+        return path_safe(&session);
+    }
+    #[cfg(target_os = "windows")]
+    {
+        use windows_sys::Win32::System::RemoteDesktop::ProcessIdToSessionId;
+        use windows_sys::Win32::System::Threading::GetCurrentProcessId;
+        let mut session = 0u32;
+        if unsafe { ProcessIdToSessionId(GetCurrentProcessId(), &mut session) } != 0 {
+            return session.to_string();
+        }
+    }
+    ["XDG_SESSION_ID", "WAYLAND_DISPLAY", "DISPLAY"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+        .map(|value| path_safe(&value))
+        .unwrap_or("default".to_string())
+}
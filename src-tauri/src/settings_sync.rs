@@ -0,0 +1,365 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Emitter, Listener, State};
+
+use crate::api;
+use crate::consts;
+use crate::fault_injection;
+use crate::scheduler;
+use crate::utils;
+
+// Settings are stored as a single encrypted email in a folder of the chosen
+// account, so every install that uses the same account and passphrase ends
+// up with the same settings without any third party service. Changes are
+// found by comparing the revision of the remote settings and the hashes of
+// the files with the ones of the last sync, so the clocks of the installs
+// don't matter.
+pub const SETTINGS_SYNCED_EVENT: &str = "settings-synced";
+const SYNCED_FILE_PATHS: [&str; 3] = [
+    consts::PREFERENCES_FILE_PATH,
+    consts::SEND_LIMITS_FILE_PATH,
+    consts::DOWNLOAD_RULES_FILE_PATH,
+];
+const BLOB_VERSION: u32 = 1;
+const KEYRING_USER: &str = "settings_sync_key";
+const NONCE_LENGTH: usize = 12;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SettingsSyncStatus {
+    pub account: Option<String>,
+    // Revision of the remote settings and the hashes of the synced files,
+    // both as of the last successful sync.
+    pub remote_revision: Option<u64>,
+    pub file_hashes: HashMap<String, String>,
+    pub synced_at: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SettingsBlob {
+    version: u32,
+    // Increased by every push.
+    #[serde(default)]
+    revision: u64,
+    // Only shown to the user.
+    updated_at: i64,
+    files: HashMap<String, String>,
+}
+
+fn keyring_entry() -> Result<keyring::Entry, String> {
+    keyring::Entry::new(consts::KEYRING_SERVICE, KEYRING_USER)
+        .map_err(|err| format!("Failed to access keychain: {}", err))
+}
+
+// The key only depends on the account and the passphrase, so every install
+// derives the same key without exchanging anything.
+fn derive_key(account: &str, passphrase: &str) -> [u8; 32] {
+    let salt = format!("openmail-settings-sync:{}", account);
+    utils::derive_key(passphrase, salt.as_bytes())
+}
+
+fn read_key() -> Result<[u8; 32], String> {
+    fault_injection::check_keychain_read()?;
+    let encoded = keyring_entry()?
+        .get_password()
+        .map_err(|err| format!("Failed to read settings sync key: {}", err))?;
+    BASE64
+        .decode(encoded)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or("Invalid settings sync key".to_string())
+}
+
+fn encrypt(key: &[u8; 32], blob: &SettingsBlob) -> Result<String, String> {
+    let plaintext =
+        serde_json::to_vec(blob).map_err(|err| format!("Failed to serialize settings: {}", err))?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|err| format!("Failed to encrypt settings: {}", err))?;
+
+    let mut content = nonce.to_vec();
+    content.extend(ciphertext);
+    Ok(BASE64.encode(content))
+}
+
+fn decrypt(key: &[u8; 32], content: &str) -> Result<SettingsBlob, String> {
+    let content = BASE64
+        .decode(content.trim())
+        .map_err(|err| format!("Failed to decode synced settings: {}", err))?;
+    if content.len() < NONCE_LENGTH {
+        return Err("Synced settings are corrupted".to_string());
+    }
+
+    let (nonce, ciphertext) = content.split_at(NONCE_LENGTH);
+    let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| "Failed to decrypt synced settings, check the passphrase".to_string())?;
+    serde_json::from_slice(&plaintext)
+        .map_err(|err| format!("Failed to parse synced settings: {}", err))
+}
+
+fn hash(content: &str) -> String {
+    format!("{:x}", Sha256::digest(content.as_bytes()))
+}
+
+fn read_local_files() -> HashMap<String, String> {
+    SYNCED_FILE_PATHS
+        .iter()
+        .filter_map(|file| {
+            let content = fs::read_to_string(utils::build_home_path(file)).ok()?;
+            Some((file.to_string(), content))
+        })
+        .collect()
+}
+
+// Only the known files are written, whatever else the blob contains.
+fn write_local_files(files: &HashMap<String, String>) -> Result<(), String> {
+    for file in SYNCED_FILE_PATHS {
+        let Some(content) = files.get(file) else {
+            continue;
+        };
+
+        let path = utils::build_home_path(file);
+        if let Some(parent) = Path::new(&path).parent() {
+            fs::create_dir_all(parent)
+                .map_err(|err| format!("Failed to create {}: {}", parent.display(), err))?;
+        }
+        fs::write(&path, content).map_err(|err| format!("Failed to write {}: {}", path, err))?;
+    }
+    Ok(())
+}
+
+fn fetch_remote(account: &str, key: &[u8; 32]) -> Result<Option<SettingsBlob>, String> {
+    let response = api::get(
+        &format!("/get-app-data/{}", account),
+        &[("folder", consts::SETTINGS_SYNC_FOLDER)],
+    )?;
+    match api::get_account_data(response, account)? {
+        Value::String(content) => decrypt(key, &content).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn push_remote(account: &str, key: &[u8; 32], blob: &SettingsBlob) -> Result<(), String> {
+    let response = api::post_json(
+        "/save-app-data",
+        json!({
+            "account": account,
+            "folder": consts::SETTINGS_SYNC_FOLDER,
+            "subject": consts::SETTINGS_SYNC_FOLDER,
+            "content": encrypt(key, blob)?,
+        }),
+    )?;
+    if !response.success {
+        return Err(response.message);
+    }
+    Ok(())
+}
+
+// Each file is pulled if only the remote one changed since the last sync
+// and pushed if only the local one changed. If both changed, the remote one
+// wins since another install pushed it first. The first sync of an install
+// always pulls, otherwise the defaults of a fresh install would overwrite
+// the settings of the others.
+fn reconcile(
+    app: &AppHandle,
+    account: &str,
+    status: &mut SettingsSyncStatus,
+) -> Result<(), String> {
+    let key = read_key()?;
+    let remote = fetch_remote(account, &key)?;
+    let local = read_local_files();
+    let is_first_sync = status.synced_at.is_none();
+    let remote_changed = remote.as_ref().is_some_and(|blob| {
+        is_first_sync
+            || status
+                .remote_revision
+                .is_none_or(|synced| blob.revision > synced)
+    });
+
+    let mut files = remote
+        .as_ref()
+        .map(|blob| blob.files.clone())
+        .unwrap_or_default();
+    let mut pulled = HashMap::new();
+    let mut push = remote.is_none();
+    for file in SYNCED_FILE_PATHS {
+        let synced = status.file_hashes.get(file);
+        let local_content = local.get(file);
+        let local_changed =
+            !is_first_sync && local_content.map(|content| hash(content)).as_ref() != synced;
+        let remote_content = files
+            .get(file)
+            .filter(|content| remote_changed && (is_first_sync || Some(&hash(content)) != synced));
+
+        if let Some(content) = remote_content {
+            if local_content != Some(content) {
+                pulled.insert(file.to_string(), content.clone());
+            }
+        } else if local_changed || !files.contains_key(file) {
+            match local_content {
+                Some(content) => files.insert(file.to_string(), content.clone()),
+                None => files.remove(file),
+            };
+            push |= local_changed || local_content.is_some();
+        }
+    }
+
+    if !pulled.is_empty() {
+        write_local_files(&pulled)?;
+        app.emit(SETTINGS_SYNCED_EVENT, ()).ok();
+    }
+    let mut revision = remote.as_ref().map_or(0, |blob| blob.revision);
+    if push {
+        revision += 1;
+        let blob = SettingsBlob {
+            version: BLOB_VERSION,
+            revision,
+            updated_at: Utc::now().timestamp_millis(),
+            files: files.clone(),
+        };
+        push_remote(account, &key, &blob)?;
+    }
+
+    status.remote_revision = Some(revision);
+    status.file_hashes = files
+        .iter()
+        .filter(|(file, _)| SYNCED_FILE_PATHS.contains(&file.as_str()))
+        .map(|(file, content)| (file.clone(), hash(content)))
+        .collect();
+    status.synced_at = Some(Utc::now().timestamp_millis());
+    Ok(())
+}
+
+pub struct SettingsSync {
+    status: Mutex<SettingsSyncStatus>,
+}
+
+impl SettingsSync {
+    pub fn load() -> Self {
+        SettingsSync {
+            status: Mutex::new(
+                utils::read_json_file(consts::SETTINGS_SYNC_FILE_PATH).unwrap_or_default(),
+            ),
+        }
+    }
+
+    pub fn get_status(&self) -> SettingsSyncStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    pub fn is_enabled_for(&self, account: &str) -> bool {
+        self.status.lock().unwrap().account.as_deref() == Some(account)
+    }
+
+    // The passphrase is checked against the settings that are already stored
+    // in the account (if any) before it is saved to the keychain.
+    pub fn enable(&self, account: &str, passphrase: &str) -> Result<(), String> {
+        if passphrase.is_empty() {
+            return Err("Passphrase cannot be empty".to_string());
+        }
+
+        let key = derive_key(account, passphrase);
+        fetch_remote(account, &key)?;
+        keyring_entry()?
+            .set_password(&BASE64.encode(key))
+            .map_err(|err| format!("Failed to save settings sync key: {}", err))?;
+
+        let mut status = self.status.lock().unwrap();
+        *status = SettingsSyncStatus {
+            account: Some(account.to_string()),
+            ..Default::default()
+        };
+        utils::write_json_file(consts::SETTINGS_SYNC_FILE_PATH, &*status)
+    }
+
+    pub fn disable(&self) -> Result<(), String> {
+        match keyring_entry()?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => {}
+            Err(err) => return Err(format!("Failed to delete settings sync key: {}", err)),
+        }
+
+        let mut status = self.status.lock().unwrap();
+        *status = SettingsSyncStatus::default();
+        utils::write_json_file(consts::SETTINGS_SYNC_FILE_PATH, &*status)
+    }
+
+    pub fn sync(&self, app: &AppHandle) -> Result<SettingsSyncStatus, String> {
+        let mut status = self.status.lock().unwrap();
+        let Some(account) = status.account.clone() else {
+            return Err("Settings sync is not enabled".to_string());
+        };
+
+        let result = reconcile(app, &account, &mut status);
+        status.last_error = result.as_ref().err().cloned();
+        utils::write_json_file(consts::SETTINGS_SYNC_FILE_PATH, &*status)?;
+        result.map(|_| status.clone())
+    }
+}
+
+// Settings are reconciled after every mail sync of the chosen account, so
+// they follow the same interval and retry behaviour.
+pub fn listen_sync(app: &AppHandle, settings_sync: Arc<SettingsSync>) {
+    let handle = app.clone();
+    app.listen(scheduler::SYNC_FINISHED_EVENT, move |event| {
+        let Ok(accounts) = serde_json::from_str::<Vec<String>>(event.payload()) else {
+            return;
+        };
+        if !accounts
+            .iter()
+            .any(|account| settings_sync.is_enabled_for(account))
+        {
+            return;
+        }
+
+        let app = handle.clone();
+        let settings_sync = settings_sync.clone();
+        thread::spawn(move || {
+            if let Err(err) = settings_sync.sync(&app) {
+                println!("Failed to sync settings: {}", err);
+            }
+        });
+    });
+}
+
+#[tauri::command]
+pub async fn enable_settings_sync(
+    app: AppHandle,
+    settings_sync: State<'_, Arc<SettingsSync>>,
+    account: String,
+    passphrase: String,
+) -> Result<SettingsSyncStatus, String> {
+    settings_sync.enable(&account, &passphrase)?;
+    settings_sync.sync(&app)
+}
+
+#[tauri::command]
+pub fn disable_settings_sync(settings_sync: State<'_, Arc<SettingsSync>>) -> Result<(), String> {
+    settings_sync.disable()
+}
+
+#[tauri::command]
+pub async fn sync_settings_now(
+    app: AppHandle,
+    settings_sync: State<'_, Arc<SettingsSync>>,
+) -> Result<SettingsSyncStatus, String> {
+    settings_sync.sync(&app)
+}
+
+#[tauri::command]
+pub fn get_settings_sync_status(settings_sync: State<'_, Arc<SettingsSync>>) -> SettingsSyncStatus {
+    settings_sync.get_status()
+}
@@ -0,0 +1,390 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tauri::State;
+use url::Url;
+
+use crate::cache::{Cache, CachedEmail};
+use crate::consts;
+use crate::fault_injection;
+use crate::utils;
+
+// "Send to chat" forwards an email or an attachment to a chat channel. A
+// target is a generic webhook (Slack, Mattermost, Rocket.Chat and the like
+// take {"text": ...}) or a Matrix room. Their secrets, the url of the
+// webhook and the access token of Matrix, are stored in the keychain, the
+// settings file only has what is shown in the UI.
+const KEYRING_USER_PREFIX: &str = "share_target:";
+const REQUEST_TIMEOUT_SEC: u64 = 30;
+const MAX_TEXT_CHARS: usize = 4000;
+const MAX_FILE_BYTES: u64 = 50 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ShareTargetKind {
+    Webhook,
+    Matrix { homeserver: String, room_id: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareTarget {
+    // Empty for the new targets.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    pub kind: ShareTargetKind,
+}
+
+fn keyring_entry(id: &str) -> Result<keyring::Entry, String> {
+    keyring::Entry::new(
+        consts::KEYRING_SERVICE,
+        &format!("{}{}", KEYRING_USER_PREFIX, id),
+    )
+    .map_err(|err| format!("Failed to access keychain: {}", err))
+}
+
+fn read_secret(target: &ShareTarget) -> Result<String, String> {
+    fault_injection::check_keychain_read()?;
+    keyring_entry(&target.id)?
+        .get_password()
+        .map_err(|err| format!("Failed to read the secret of {}: {}", target.name, err))
+}
+
+fn validate(target: &ShareTarget, secret: Option<&str>) -> Result<(), String> {
+    if target.name.trim().is_empty() {
+        return Err("Target name can not be empty".to_string());
+    }
+    let is_http =
+        |url: &str| Url::parse(url).is_ok_and(|url| ["http", "https"].contains(&url.scheme()));
+    match &target.kind {
+        ShareTargetKind::Webhook => {
+            if secret.is_some_and(|url| !is_http(url)) {
+                return Err("Invalid webhook url".to_string());
+            }
+        }
+        ShareTargetKind::Matrix {
+            homeserver,
+            room_id,
+        } => {
+            if !is_http(homeserver) {
+                return Err(format!("Invalid homeserver: {}", homeserver));
+            }
+            // Room ids look like !abc:example.org, aliases like
+            // #room:example.org
+            if !room_id.starts_with(['!', '#']) || !room_id.contains(':') {
+                return Err(format!("Invalid room: {}", room_id));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn agent() -> ureq::Agent {
+    ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(REQUEST_TIMEOUT_SEC))
+        .build()
+}
+
+fn request_error(target: &ShareTarget, err: ureq::Error) -> String {
+    match err {
+        ureq::Error::Status(status, response) => format!(
+            "{} refused the message ({}): {}",
+            target.name,
+            status,
+            response.into_string().unwrap_or_default()
+        ),
+        err => format!("Failed to send to {}: {}", target.name, err),
+    }
+}
+
+// Url of a Matrix client api endpoint, the segments are escaped since room
+// ids have ! and : in them.
+fn matrix_url(homeserver: &str, segments: &[&str]) -> Result<Url, String> {
+    let mut url = Url::parse(homeserver)
+        .map_err(|err| format!("Invalid homeserver {}: {}", homeserver, err))?;
+    url.path_segments_mut()
+        .map_err(|_| format!("Invalid homeserver: {}", homeserver))?
+        .pop_if_empty()
+        .extend(segments);
+    Ok(url)
+}
+
+fn send_matrix_message(
+    target: &ShareTarget,
+    homeserver: &str,
+    room_id: &str,
+    token: &str,
+    content: Value,
+) -> Result<(), String> {
+    // Aliases have to be resolved to the room id first.
+    let room_id = if room_id.starts_with('#') {
+        let url = matrix_url(
+            homeserver,
+            &["_matrix", "client", "v3", "directory", "room", room_id],
+        )?;
+        let response: Value = agent()
+            .get(url.as_str())
+            .set("Authorization", &format!("Bearer {}", token))
+            .call()
+            .map_err(|err| request_error(target, err))?
+            .into_json()
+            .map_err(|err| format!("Invalid response of {}: {}", target.name, err))?;
+        response["room_id"]
+            .as_str()
+            .ok_or(format!("Room not found: {}", room_id))?
+            .to_string()
+    } else {
+        room_id.to_string()
+    };
+
+    let transaction_id = utils::generate_random_id();
+    let url = matrix_url(
+        homeserver,
+        &[
+            "_matrix",
+            "client",
+            "v3",
+            "rooms",
+            &room_id,
+            "send",
+            "m.room.message",
+            &transaction_id,
+        ],
+    )?;
+    agent()
+        .put(url.as_str())
+        .set("Authorization", &format!("Bearer {}", token))
+        .send_json(content)
+        .map_err(|err| request_error(target, err))?;
+    Ok(())
+}
+
+fn send_text(target: &ShareTarget, text: &str) -> Result<(), String> {
+    let secret = read_secret(target)?;
+    match &target.kind {
+        ShareTargetKind::Webhook => {
+            agent()
+                .post(&secret)
+                .send_json(json!({ "text": text }))
+                .map_err(|err| request_error(target, err))?;
+            Ok(())
+        }
+        ShareTargetKind::Matrix {
+            homeserver,
+            room_id,
+        } => send_matrix_message(
+            target,
+            homeserver,
+            room_id,
+            &secret,
+            json!({ "msgtype": "m.text", "body": text }),
+        ),
+    }
+}
+
+fn send_file(target: &ShareTarget, path: &str) -> Result<(), String> {
+    let size = fs::metadata(path)
+        .map_err(|err| format!("Failed to read {}: {}", path, err))?
+        .len();
+    if size > MAX_FILE_BYTES {
+        return Err(format!("{} is too large to share", path));
+    }
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    match &target.kind {
+        // Incoming webhooks only take text.
+        ShareTargetKind::Webhook => Err(format!("{} can't receive files", target.name)),
+        ShareTargetKind::Matrix {
+            homeserver,
+            room_id,
+        } => {
+            let token = read_secret(target)?;
+            let content =
+                fs::read(path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+            let mut url = matrix_url(homeserver, &["_matrix", "media", "v3", "upload"])?;
+            url.query_pairs_mut().append_pair("filename", &name);
+            let response: Value = agent()
+                .post(url.as_str())
+                .set("Authorization", &format!("Bearer {}", token))
+                .set("Content-Type", "application/octet-stream")
+                .send_bytes(&content)
+                .map_err(|err| request_error(target, err))?
+                .into_json()
+                .map_err(|err| format!("Invalid response of {}: {}", target.name, err))?;
+            let uri = response["content_uri"]
+                .as_str()
+                .ok_or(format!("{} didn't accept the file", target.name))?;
+            send_matrix_message(
+                target,
+                homeserver,
+                room_id,
+                &token,
+                json!({
+                    "msgtype": "m.file",
+                    "body": name,
+                    "url": uri,
+                    "info": { "size": size },
+                }),
+            )
+        }
+    }
+}
+
+// Subject, sender and date above the body, like a forwarded email.
+fn email_text(email: &CachedEmail) -> String {
+    let text = format!(
+        "{}\nFrom: {}\nDate: {}\n\n{}",
+        email.subject,
+        email.sender,
+        email.date,
+        email.body.trim()
+    );
+    if text.chars().count() <= MAX_TEXT_CHARS {
+        return text;
+    }
+    let mut truncated: String = text.chars().take(MAX_TEXT_CHARS - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+pub struct Share {
+    cache: Arc<Cache>,
+    targets: Mutex<Vec<ShareTarget>>,
+}
+
+impl Share {
+    pub fn load(cache: Arc<Cache>) -> Self {
+        Share {
+            cache,
+            targets: Mutex::new(
+                utils::read_json_file(consts::SHARE_TARGETS_FILE_PATH).unwrap_or_default(),
+            ),
+        }
+    }
+
+    pub fn get_targets(&self) -> Vec<ShareTarget> {
+        self.targets.lock().unwrap().clone()
+    }
+
+    fn get_target(&self, id: &str) -> Result<ShareTarget, String> {
+        self.get_targets()
+            .into_iter()
+            .find(|target| target.id == id)
+            .ok_or(format!("Share target not found: {}", id))
+    }
+
+    // Adds the target or updates the one with the same id, the secret is
+    // kept if it is not given.
+    pub fn set_target(
+        &self,
+        mut target: ShareTarget,
+        secret: Option<String>,
+    ) -> Result<ShareTarget, String> {
+        target.name = target.name.trim().to_string();
+        let secret = secret.map(|secret| secret.trim().to_string());
+        validate(&target, secret.as_deref())?;
+
+        let mut targets = self.targets.lock().unwrap();
+        let is_new = !targets.iter().any(|other| other.id == target.id);
+        if is_new {
+            if secret.is_none() {
+                return Err(format!("{} needs a secret", target.name));
+            }
+            target.id = utils::generate_random_id();
+        }
+        if let Some(secret) = secret {
+            keyring_entry(&target.id)?
+                .set_password(&secret)
+                .map_err(|err| format!("Failed to save the secret of {}: {}", target.name, err))?;
+        }
+
+        match targets.iter_mut().find(|other| other.id == target.id) {
+            Some(other) => *other = target.clone(),
+            None => targets.push(target.clone()),
+        }
+        utils::write_json_file(consts::SHARE_TARGETS_FILE_PATH, &*targets)?;
+        Ok(target)
+    }
+
+    pub fn remove_target(&self, id: &str) -> Result<(), String> {
+        let mut targets = self.targets.lock().unwrap();
+        targets.retain(|target| target.id != id);
+        utils::write_json_file(consts::SHARE_TARGETS_FILE_PATH, &*targets)?;
+        match keyring_entry(id)?.delete_credential() {
+            Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(err) => Err(format!("Failed to delete the secret: {}", err)),
+        }
+    }
+
+    pub fn share_email(
+        &self,
+        id: &str,
+        account: &str,
+        folder: &str,
+        uid: &str,
+        local: bool,
+    ) -> Result<(), String> {
+        let target = self.get_target(id)?;
+        let emails = if local {
+            self.cache.get_local_emails(account, folder)
+        } else {
+            self.cache.get_emails(account, folder)
+        };
+        let email = emails
+            .into_iter()
+            .find(|email| email.uid == uid)
+            .ok_or(format!("Email {} not found in {}", uid, folder))?;
+        send_text(&target, &email_text(&email))
+    }
+
+    pub fn share_file(&self, id: &str, path: &str) -> Result<(), String> {
+        send_file(&self.get_target(id)?, path)
+    }
+}
+
+#[tauri::command]
+pub fn get_share_targets(share: State<'_, Arc<Share>>) -> Vec<ShareTarget> {
+    share.get_targets()
+}
+
+#[tauri::command]
+pub fn set_share_target(
+    share: State<'_, Arc<Share>>,
+    target: ShareTarget,
+    secret: Option<String>,
+) -> Result<ShareTarget, String> {
+    share.set_target(target, secret)
+}
+
+#[tauri::command]
+pub fn remove_share_target(share: State<'_, Arc<Share>>, id: String) -> Result<(), String> {
+    share.remove_target(&id)
+}
+
+#[tauri::command]
+pub async fn share_email(
+    share: State<'_, Arc<Share>>,
+    id: String,
+    account: String,
+    folder: String,
+    uid: String,
+    local: bool,
+) -> Result<(), String> {
+    share.share_email(&id, &account, &folder, &uid, local)
+}
+
+// Attachments are shared from where they were downloaded or extracted.
+#[tauri::command]
+pub async fn share_attachment(
+    share: State<'_, Arc<Share>>,
+    id: String,
+    path: String,
+) -> Result<(), String> {
+    share.share_file(&id, &path)
+}
@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, State};
+
+use crate::account_schedule::AccountSchedules;
+use crate::cache::{Cache, CachedEmail};
+use crate::consts;
+use crate::notifications::{self, NotificationHistory, NotificationReference};
+use crate::scheduler::SyncScheduler;
+use crate::utils;
+
+// Shared/delegated mailboxes are not accounts of the server, they are
+// folders of a connected account (like "Shared/support/INBOX") that are
+// synced and cached under the address of the mailbox, on their own
+// interval. New emails only notify for the folders that are chosen to.
+// They follow the schedule of their own address and of the account, see
+// account_schedule.rs
+const MIN_SYNC_INTERVAL_SEC: u64 = 60;
+const RETRY_INTERVAL_SEC: u64 = 30;
+const IDLE_INTERVAL_SEC: u64 = 300;
+// More new emails than this are notified as a single summary.
+const MAX_EMAIL_NOTIFICATIONS: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedFolder {
+    // Full path of the folder on the server.
+    pub path: String,
+    pub notify: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedMailbox {
+    pub address: String,
+    // Connected account the mailbox is accessed (and sent) with.
+    pub account: String,
+    pub color: String,
+    pub sync_interval_sec: u64,
+    pub folders: Vec<SharedFolder>,
+    // Whether emails can be sent with the address of the mailbox, the
+    // server of the account still has to allow it.
+    pub send_as: bool,
+}
+
+fn validate(mailbox: &SharedMailbox) -> Result<(), String> {
+    for address in [&mailbox.address, &mailbox.account] {
+        if utils::extract_email_address(address) != *address {
+            return Err(format!("Invalid email address: {}", address));
+        }
+    }
+    if mailbox.address == mailbox.account {
+        return Err("Shared mailbox can not be the account itself".to_string());
+    }
+
+    let is_hex_color = mailbox.color.len() == 7
+        && mailbox.color.starts_with('#')
+        && mailbox.color[1..].chars().all(|c| c.is_ascii_hexdigit());
+    if !is_hex_color {
+        return Err(format!("Invalid color: {}", mailbox.color));
+    }
+
+    if mailbox.sync_interval_sec < MIN_SYNC_INTERVAL_SEC {
+        return Err(format!(
+            "Sync interval can not be less than {} seconds",
+            MIN_SYNC_INTERVAL_SEC
+        ));
+    }
+
+    if mailbox.folders.is_empty() {
+        return Err("Shared mailbox has no folders".to_string());
+    }
+    let mut paths = HashSet::new();
+    for folder in mailbox.folders.iter() {
+        if folder.path.trim().is_empty() {
+            return Err("Folder path can not be empty".to_string());
+        }
+        if !paths.insert(folder.path.as_str()) {
+            return Err(format!("Folder is added more than once: {}", folder.path));
+        }
+    }
+    Ok(())
+}
+
+pub struct SharedMailboxes {
+    mailboxes: Mutex<Vec<SharedMailbox>>,
+    next_sync_at: Mutex<HashMap<String, Instant>>,
+    wakeup: Condvar,
+}
+
+impl SharedMailboxes {
+    pub fn load() -> Self {
+        SharedMailboxes {
+            mailboxes: Mutex::new(
+                utils::read_json_file(consts::SHARED_MAILBOXES_FILE_PATH).unwrap_or_default(),
+            ),
+            next_sync_at: Mutex::new(HashMap::new()),
+            wakeup: Condvar::new(),
+        }
+    }
+
+    fn save(&self, mailboxes: &[SharedMailbox]) -> Result<(), String> {
+        utils::write_json_file(consts::SHARED_MAILBOXES_FILE_PATH, &mailboxes)
+    }
+
+    pub fn get_mailboxes(&self) -> Vec<SharedMailbox> {
+        self.mailboxes.lock().unwrap().clone()
+    }
+
+    // Adds the mailbox or replaces the one with the same address.
+    pub fn set(&self, mut mailbox: SharedMailbox) -> Result<(), String> {
+        mailbox.address = mailbox.address.trim().to_lowercase();
+        mailbox.account = mailbox.account.trim().to_lowercase();
+        validate(&mailbox)?;
+
+        let mut mailboxes = self.mailboxes.lock().unwrap();
+        if mailboxes
+            .iter()
+            .any(|other| other.account == mailbox.address)
+        {
+            return Err(format!(
+                "{} is used to access another shared mailbox",
+                mailbox.address
+            ));
+        }
+        self.next_sync_at.lock().unwrap().remove(&mailbox.address);
+        match mailboxes
+            .iter_mut()
+            .find(|other| other.address == mailbox.address)
+        {
+            Some(other) => *other = mailbox,
+            None => mailboxes.push(mailbox),
+        }
+        self.save(&mailboxes)?;
+        self.wakeup.notify_all();
+        Ok(())
+    }
+
+    pub fn remove(&self, address: &str) -> Result<(), String> {
+        let mut mailboxes = self.mailboxes.lock().unwrap();
+        let position = mailboxes
+            .iter()
+            .position(|mailbox| mailbox.address == address)
+            .ok_or(format!("Shared mailbox not found: {}", address))?;
+        mailboxes.remove(position);
+        self.save(&mailboxes)
+    }
+
+    // Account to send an email of `sender` with, which is the sender itself
+    // unless it is a shared mailbox.
+    pub fn resolve_sender(&self, sender: &str) -> Result<String, String> {
+        let mailboxes = self.mailboxes.lock().unwrap();
+        match mailboxes
+            .iter()
+            .find(|mailbox| mailbox.address.eq_ignore_ascii_case(sender))
+        {
+            Some(mailbox) if mailbox.send_as => Ok(mailbox.account.clone()),
+            Some(_) => Err(format!("Sending as {} is not allowed", sender)),
+            None => Ok(sender.to_string()),
+        }
+    }
+
+    // Mailboxes that are due and how long to wait for the next one.
+    fn due_mailboxes(&self) -> (Vec<SharedMailbox>, Duration) {
+        let mailboxes = self.mailboxes.lock().unwrap();
+        let next_sync_at = self.next_sync_at.lock().unwrap();
+        let now = Instant::now();
+        let mut wait = Duration::from_secs(IDLE_INTERVAL_SEC);
+        let mut due = Vec::new();
+        for mailbox in mailboxes.iter() {
+            match next_sync_at.get(&mailbox.address) {
+                Some(at) if *at > now => wait = wait.min(*at - now),
+                _ => due.push(mailbox.clone()),
+            }
+        }
+        (due, wait)
+    }
+
+    fn schedule(&self, address: &str, after: Duration) {
+        self.next_sync_at
+            .lock()
+            .unwrap()
+            .insert(address.to_string(), Instant::now() + after);
+    }
+
+    fn wait(&self, interval: Duration) {
+        let mailboxes = self.mailboxes.lock().unwrap();
+        let _ = self.wakeup.wait_timeout(mailboxes, interval).unwrap();
+    }
+}
+
+fn notify(
+    app: &AppHandle,
+    history: &NotificationHistory,
+    mailbox: &SharedMailbox,
+    folder: &str,
+    emails: &[CachedEmail],
+) {
+    let reference = |emails: &[CachedEmail]| NotificationReference {
+        account: Some(mailbox.address.clone()),
+        folder: Some(folder.to_string()),
+        uids: emails.iter().map(|email| email.uid.clone()).collect(),
+    };
+
+    if emails.len() > MAX_EMAIL_NOTIFICATIONS {
+        let body = format!("{} new emails in {}", emails.len(), folder);
+        notifications::show(app, history, &mailbox.address, &body, reference(emails));
+        return;
+    }
+    for email in emails {
+        let body = format!("{}: {}", email.sender, email.subject);
+        notifications::show(
+            app,
+            history,
+            &mailbox.address,
+            &body,
+            reference(std::slice::from_ref(email)),
+        );
+    }
+}
+
+fn sync_mailbox(
+    app: &AppHandle,
+    history: &NotificationHistory,
+    scheduler: &SyncScheduler,
+    cache: &Cache,
+    mailbox: &SharedMailbox,
+) -> Result<(), String> {
+    for folder in mailbox.folders.iter() {
+        let before = cache.get_folder(&mailbox.address, &folder.path);
+        scheduler.sync_folder(&mailbox.account, &mailbox.address, &folder.path)?;
+
+        // Everything is new on the first sync.
+        if !folder.notify || before.synced_at.is_none() {
+            continue;
+        }
+        let newest_uid = before
+            .emails
+            .first()
+            .map(CachedEmail::uid_number)
+            .unwrap_or_default();
+        let new_emails: Vec<CachedEmail> = cache
+            .get_emails(&mailbox.address, &folder.path)
+            .into_iter()
+            .filter(|email| email.uid_number() > newest_uid)
+            .collect();
+        if !new_emails.is_empty() {
+            notify(app, history, mailbox, &folder.path, &new_emails);
+        }
+    }
+    Ok(())
+}
+
+pub fn start_worker(
+    app: AppHandle,
+    mailboxes: Arc<SharedMailboxes>,
+    scheduler: Arc<SyncScheduler>,
+    cache: Arc<Cache>,
+    history: Arc<NotificationHistory>,
+    schedules: Arc<AccountSchedules>,
+) {
+    thread::spawn(move || loop {
+        let (due, wait) = mailboxes.due_mailboxes();
+        if due.is_empty() {
+            mailboxes.wait(wait);
+            continue;
+        }
+
+        for mailbox in due {
+            // Paused with the account it is accessed with too.
+            if schedules.is_paused(&mailbox.address) || schedules.is_paused(&mailbox.account) {
+                mailboxes.schedule(
+                    &mailbox.address,
+                    Duration::from_secs(mailbox.sync_interval_sec),
+                );
+                continue;
+            }
+            let after = match sync_mailbox(&app, &history, &scheduler, &cache, &mailbox) {
+                Ok(()) => mailbox.sync_interval_sec,
+                Err(err) => {
+                    println!("Failed to sync shared mailbox {}: {}", mailbox.address, err);
+                    RETRY_INTERVAL_SEC
+                }
+            };
+            mailboxes.schedule(&mailbox.address, Duration::from_secs(after));
+        }
+    });
+}
+
+#[tauri::command]
+pub fn get_shared_mailboxes(mailboxes: State<'_, Arc<SharedMailboxes>>) -> Vec<SharedMailbox> {
+    mailboxes.get_mailboxes()
+}
+
+#[tauri::command]
+pub fn set_shared_mailbox(
+    mailboxes: State<'_, Arc<SharedMailboxes>>,
+    mailbox: SharedMailbox,
+) -> Result<(), String> {
+    mailboxes.set(mailbox)
+}
+
+#[tauri::command]
+pub fn remove_shared_mailbox(
+    mailboxes: State<'_, Arc<SharedMailboxes>>,
+    address: String,
+) -> Result<(), String> {
+    mailboxes.remove(&address)
+}
@@ -0,0 +1,151 @@
+use chrono::{Duration, Local, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tauri::State;
+
+use crate::cache::Cache;
+use crate::consts;
+use crate::utils;
+
+// Everything here is computed from the local cache, nothing is sent
+// anywhere. Inbox progress is recorded by the scheduler after each sync.
+const MAX_PROGRESS_DAYS: usize = 365;
+const DEFAULT_PROGRESS_DAYS: usize = 30;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DailyInboxProgress {
+    pub date: String,
+    pub total: usize,
+    pub unread: usize,
+    pub lowest_total: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InboxProgress {
+    pub days: Vec<DailyInboxProgress>,
+    pub inbox_zero_streak: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplyTimeStats {
+    pub replies: usize,
+    pub median_reply_minutes: Option<i64>,
+    pub last_week_median_reply_minutes: Option<i64>,
+}
+
+type ProgressHistory = HashMap<String, Vec<DailyInboxProgress>>;
+
+pub fn record_inbox_progress(account: &str, total: usize, unread: usize) -> Result<(), String> {
+    let mut history: ProgressHistory = utils::read_json_file(consts::INBOX_PROGRESS_FILE_PATH)?;
+    let days = history.entry(account.to_string()).or_default();
+    let today = Local::now().format(DATE_FORMAT).to_string();
+    match days.last_mut() {
+        Some(day) if day.date == today => {
+            day.total = total;
+            day.unread = unread;
+            day.lowest_total = day.lowest_total.min(total);
+        }
+        _ => days.push(DailyInboxProgress {
+            date: today,
+            total,
+            unread,
+            lowest_total: total,
+        }),
+    }
+    if days.len() > MAX_PROGRESS_DAYS {
+        days.drain(..days.len() - MAX_PROGRESS_DAYS);
+    }
+
+    utils::write_json_file(consts::INBOX_PROGRESS_FILE_PATH, &history)
+}
+
+// Consecutive days the inbox was emptied at least once, counted back from
+// today. Today is not required to be finished yet, so the streak is not
+// broken until the end of the day.
+fn inbox_zero_streak(days: &[DailyInboxProgress]) -> usize {
+    let mut expected = Local::now().date_naive();
+    let mut streak = 0;
+    for (index, day) in days.iter().rev().enumerate() {
+        let Ok(date) = NaiveDate::parse_from_str(&day.date, DATE_FORMAT) else {
+            break;
+        };
+        if index == 0 && date == expected && day.lowest_total > 0 {
+            expected -= Duration::days(1);
+            continue;
+        }
+        if date != expected || day.lowest_total > 0 {
+            break;
+        }
+        streak += 1;
+        expected -= Duration::days(1);
+    }
+    streak
+}
+
+fn median(mut values: Vec<i64>) -> Option<i64> {
+    if values.is_empty() {
+        return None;
+    }
+
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some((values[middle - 1] + values[middle]) / 2)
+    } else {
+        Some(values[middle])
+    }
+}
+
+// Matches the sent emails with the received emails they reply to and
+// returns when each reply was sent together with how long it took.
+fn reply_times(cache: &Cache, account: &str) -> Vec<(i64, i64)> {
+    let received: HashMap<String, i64> = cache
+        .get_emails(account, "Inbox")
+        .into_iter()
+        .filter_map(|email| {
+            let date = utils::parse_email_date(&email.date)?;
+            Some((email.message_id.trim().to_string(), date.timestamp()))
+        })
+        .collect();
+
+    cache
+        .get_emails(account, "Sent")
+        .into_iter()
+        .filter_map(|email| {
+            let in_reply_to = email.in_reply_to.as_deref()?.trim();
+            let received_at = received.get(in_reply_to)?;
+            let sent_at = utils::parse_email_date(&email.date)?.timestamp();
+            (sent_at >= *received_at).then_some((sent_at, (sent_at - received_at) / 60))
+        })
+        .collect()
+}
+
+#[tauri::command]
+pub fn get_reply_time_stats(cache: State<'_, Arc<Cache>>, account: String) -> ReplyTimeStats {
+    let replies = reply_times(&cache, &account);
+    let last_week = (Utc::now() - Duration::days(7)).timestamp();
+    ReplyTimeStats {
+        replies: replies.len(),
+        median_reply_minutes: median(replies.iter().map(|(_, minutes)| *minutes).collect()),
+        last_week_median_reply_minutes: median(
+            replies
+                .iter()
+                .filter(|(sent_at, _)| *sent_at >= last_week)
+                .map(|(_, minutes)| *minutes)
+                .collect(),
+        ),
+    }
+}
+
+#[tauri::command]
+pub fn get_inbox_progress(account: String, days: Option<usize>) -> Result<InboxProgress, String> {
+    let history: ProgressHistory = utils::read_json_file(consts::INBOX_PROGRESS_FILE_PATH)?;
+    let progress = history.get(&account).cloned().unwrap_or_default();
+    let days = days.unwrap_or(DEFAULT_PROGRESS_DAYS).min(progress.len());
+    Ok(InboxProgress {
+        inbox_zero_streak: inbox_zero_streak(&progress),
+        days: progress[progress.len() - days..].to_vec(),
+    })
+}
@@ -0,0 +1,96 @@
+// `{{placeholder}}` templates of the campaigns and the clipped notes.
+// Names are trimmed, so `{{name}}`, `{{ name }}` and `{{name }}` are the
+// same placeholder. Templates are rendered in a single pass, a value that
+// contains a placeholder is never expanded again.
+enum Part<'a> {
+    Text(&'a str),
+    // Name and the placeholder as it is written.
+    Placeholder(&'a str, &'a str),
+}
+
+fn split(template: &str) -> Vec<Part<'_>> {
+    let mut parts = vec![];
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(length) = rest[start + 2..].find("}}") else {
+            break;
+        };
+        let end = start + 2 + length;
+        parts.push(Part::Text(&rest[..start]));
+        parts.push(Part::Placeholder(
+            rest[start + 2..end].trim(),
+            &rest[start..end + 2],
+        ));
+        rest = &rest[end + 2..];
+    }
+    parts.push(Part::Text(rest));
+    parts
+}
+
+pub fn find_placeholders(template: &str) -> Vec<String> {
+    split(template)
+        .into_iter()
+        .filter_map(|part| match part {
+            Part::Placeholder(name, _) => Some(name.to_string()),
+            Part::Text(_) => None,
+        })
+        .collect()
+}
+
+// Placeholders without a value are kept as they are written.
+pub fn render(template: &str, value: impl Fn(&str) -> Option<String>) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    for part in split(template) {
+        match part {
+            Part::Text(text) => rendered.push_str(text),
+            Part::Placeholder(name, written) => match value(name) {
+                Some(value) => rendered.push_str(&value),
+                None => rendered.push_str(written),
+            },
+        }
+    }
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(name: &str) -> Option<String> {
+        match name {
+            "name" => Some("Ada".to_string()),
+            "other" => Some("{{name}}".to_string()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn finds_trimmed_placeholders() {
+        assert_eq!(
+            find_placeholders("Hi {{name}}, {{ other }} and {{name }}{{"),
+            vec!["name", "other", "name"]
+        );
+        assert!(find_placeholders("no placeholders }} {").is_empty());
+    }
+
+    #[test]
+    fn renders_every_spacing() {
+        assert_eq!(
+            render("{{name}} {{ name }} {{name }} {{  name}}", values),
+            "Ada Ada Ada Ada"
+        );
+    }
+
+    #[test]
+    fn does_not_expand_values() {
+        assert_eq!(render("{{other}} {{name}}", values), "{{name}} Ada");
+    }
+
+    #[test]
+    fn keeps_unknown_and_unclosed_placeholders() {
+        assert_eq!(
+            render("{{unknown}} {{name}} {{name", values),
+            "{{unknown}} Ada {{name"
+        );
+    }
+}
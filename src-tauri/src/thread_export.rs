@@ -0,0 +1,136 @@
+use std::fs;
+use std::sync::Arc;
+use tauri::State;
+
+use crate::cache::{Cache, CachedEmail};
+use crate::threads;
+use crate::utils;
+
+// Threads as Markdown for note taking apps. Every email is quoted under an
+// "On <date>, <sender> wrote:" line, oldest first, without the history it
+// quotes itself since the earlier emails are already in the file. The
+// attachments of the whole thread are listed at the end.
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+const REPLY_PREFIXES: [&str; 5] = ["re:", "fw:", "fwd:", "aw:", "sv:"];
+
+fn format_date(date: &str) -> String {
+    utils::parse_email_date(date)
+        .map(|date| date.format(DATE_FORMAT).to_string())
+        .unwrap_or_else(|| date.to_string())
+}
+
+fn strip_reply_prefixes(subject: &str) -> &str {
+    let mut subject = subject.trim();
+    while let Some(prefix) = REPLY_PREFIXES
+        .iter()
+        .find(|prefix| subject.to_lowercase().starts_with(**prefix))
+    {
+        subject = subject[prefix.len()..].trim_start();
+    }
+    subject
+}
+
+fn is_attribution(line: &str) -> bool {
+    let line = line.trim();
+    line.ends_with("wrote:") || line.ends_with("writes:")
+}
+
+// The body without the history quoted at its end, like:
+//     On Mon, Alice wrote:
+//     > ...
+// or everything after "-----Original Message-----" of Outlook.
+fn strip_quoted_history(body: &str) -> String {
+    let mut lines: Vec<&str> = body.lines().collect();
+    if let Some(index) = lines.iter().position(|line| {
+        line.trim()
+            .eq_ignore_ascii_case("-----Original Message-----")
+    }) {
+        lines.truncate(index);
+    }
+    loop {
+        match lines.last() {
+            Some(line) if line.trim().is_empty() || line.trim_start().starts_with('>') => {
+                lines.pop();
+            }
+            Some(line) if is_attribution(line) => {
+                lines.pop();
+                break;
+            }
+            _ => break,
+        }
+    }
+    lines.join("\n").trim_end().to_string()
+}
+
+fn quote(text: &str) -> String {
+    text.lines()
+        .map(|line| {
+            if line.trim().is_empty() {
+                ">".to_string()
+            } else {
+                format!("> {}", line)
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+pub fn to_markdown(emails: &[CachedEmail]) -> String {
+    let Some(first) = emails.first() else {
+        return String::new();
+    };
+    let mut markdown = format!("# {}\n\n", strip_reply_prefixes(&first.subject));
+
+    for email in emails {
+        markdown.push_str(&format!(
+            "On {}, {} wrote:\n\n",
+            format_date(&email.date),
+            email.sender
+        ));
+        let mut receivers = format!("To: {}", email.receivers);
+        if let Some(cc) = email.cc.as_ref().filter(|cc| !cc.is_empty()) {
+            receivers.push_str(&format!("  \n> Cc: {}", cc));
+        }
+        markdown.push_str(&format!("> {}\n>\n", receivers));
+        markdown.push_str(&quote(&strip_quoted_history(&email.body)));
+        markdown.push_str("\n\n");
+    }
+
+    let attachments: Vec<String> = emails
+        .iter()
+        .flat_map(|email| {
+            email.attachments.iter().map(move |attachment| {
+                format!(
+                    "- {} ({}), from {} on {}",
+                    attachment.name,
+                    attachment.mime_type,
+                    email.sender,
+                    format_date(&email.date)
+                )
+            })
+        })
+        .collect();
+    if !attachments.is_empty() {
+        markdown.push_str("## Attachments\n\n");
+        markdown.push_str(&attachments.join("\n"));
+        markdown.push('\n');
+    }
+    markdown
+}
+
+// Returns the number of emails that are exported.
+#[tauri::command]
+pub fn export_thread_markdown(
+    cache: State<'_, Arc<Cache>>,
+    account: String,
+    thread_id: String,
+    path: String,
+) -> Result<usize, String> {
+    let emails = threads::get_thread(&cache, &account, &thread_id);
+    if emails.is_empty() {
+        return Err(format!("Thread not found: {}", thread_id));
+    }
+    fs::write(&path, to_markdown(&emails))
+        .map_err(|err| format!("Failed to write {}: {}", path, err))?;
+    Ok(emails.len())
+}
@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tauri::{AppHandle, Listener, Manager, State};
+
+use crate::cache::{Cache, CachedEmail};
+use crate::consts;
+use crate::notifications::{self, NotificationHistory, NotificationReference};
+use crate::scheduler;
+use crate::threads;
+use crate::utils;
+
+// Threads the user replied to are subscribed: the replies in them always
+// notify, even while the account is paused or the new emails are held for
+// batched delivery. The replied threads come from the cached Sent folder,
+// so nothing has to be recorded when sending. Threads can be muted one by
+// one, or the whole feature turned off.
+const INBOX_FOLDER: &str = "Inbox";
+const SENT_FOLDER: &str = "Sent";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThreadSubscriptionSettings {
+    pub enabled: bool,
+    // Account -> ids of the threads that are not notified.
+    pub muted: HashMap<String, Vec<String>>,
+}
+
+impl Default for ThreadSubscriptionSettings {
+    fn default() -> Self {
+        ThreadSubscriptionSettings {
+            enabled: true,
+            muted: HashMap::new(),
+        }
+    }
+}
+
+pub struct ThreadSubscriptions {
+    cache: Arc<Cache>,
+    settings: Mutex<ThreadSubscriptionSettings>,
+    // Newest Inbox uid of the accounts that was checked, the emails before
+    // the first check are not notified.
+    checked_uids: Mutex<HashMap<String, u64>>,
+}
+
+impl ThreadSubscriptions {
+    pub fn load(cache: Arc<Cache>) -> Self {
+        ThreadSubscriptions {
+            cache,
+            settings: Mutex::new(
+                utils::read_json_file(consts::THREAD_SUBSCRIPTIONS_FILE_PATH).unwrap_or_default(),
+            ),
+            checked_uids: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get_settings(&self) -> ThreadSubscriptionSettings {
+        self.settings.lock().unwrap().clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ThreadSubscriptionSettings)) -> Result<(), String> {
+        let mut settings = self.settings.lock().unwrap();
+        let mut updated = settings.clone();
+        f(&mut updated);
+        utils::write_json_file(consts::THREAD_SUBSCRIPTIONS_FILE_PATH, &updated)?;
+        *settings = updated;
+        Ok(())
+    }
+
+    pub fn set_enabled(&self, enabled: bool) -> Result<(), String> {
+        self.update(|settings| settings.enabled = enabled)
+    }
+
+    pub fn set_muted(&self, account: &str, thread: &str, muted: bool) -> Result<(), String> {
+        self.update(|settings| {
+            let threads = settings.muted.entry(account.to_string()).or_default();
+            threads.retain(|other| other != thread);
+            if muted {
+                threads.push(thread.to_string());
+            }
+            if threads.is_empty() {
+                settings.muted.remove(account);
+            }
+        })
+    }
+
+    // Threads of the account the user replied to, muted ones excluded.
+    pub fn get_subscribed(&self, account: &str) -> HashSet<String> {
+        let emails = threads::get_account_emails(&self.cache, account);
+        let by_message_id = threads::by_message_id(&emails);
+        let muted = self
+            .get_settings()
+            .muted
+            .remove(account)
+            .unwrap_or_default();
+        self.cache
+            .get_emails(account, SENT_FOLDER)
+            .iter()
+            .filter(|email| email.in_reply_to.is_some() || email.references.is_some())
+            .map(|email| threads::thread_id(email, &by_message_id))
+            .filter(|thread| !muted.contains(thread))
+            .collect()
+    }
+
+    // New Inbox emails of the account since the last check that are in a
+    // subscribed thread.
+    fn new_replies(&self, account: &str) -> Vec<CachedEmail> {
+        let inbox = self.cache.get_emails(account, INBOX_FOLDER);
+        let newest = inbox.iter().map(CachedEmail::uid_number).max();
+        let checked = {
+            let mut checked_uids = self.checked_uids.lock().unwrap();
+            let checked = checked_uids.get(account).copied();
+            if let Some(newest) = newest {
+                checked_uids.insert(account.to_string(), newest.max(checked.unwrap_or(0)));
+            }
+            checked
+        };
+        let Some(checked) = checked else {
+            return Vec::new();
+        };
+
+        let new: Vec<&CachedEmail> = inbox
+            .iter()
+            .filter(|email| email.uid_number() > checked && !email.is_seen())
+            .filter(|email| {
+                !utils::extract_email_address(&email.sender).eq_ignore_ascii_case(account)
+            })
+            .collect();
+        if new.is_empty() {
+            return Vec::new();
+        }
+        let subscribed = self.get_subscribed(account);
+        let emails = threads::get_account_emails(&self.cache, account);
+        let by_message_id = threads::by_message_id(&emails);
+        new.into_iter()
+            .filter(|email| subscribed.contains(&threads::thread_id(email, &by_message_id)))
+            .cloned()
+            .collect()
+    }
+}
+
+pub fn listen_sync(app: &AppHandle, subscriptions: Arc<ThreadSubscriptions>) {
+    let handle = app.clone();
+    app.listen(scheduler::SYNC_FINISHED_EVENT, move |event| {
+        let Ok(accounts) = serde_json::from_str::<Vec<String>>(event.payload()) else {
+            return;
+        };
+        let app = handle.clone();
+        let subscriptions = subscriptions.clone();
+        thread::spawn(move || {
+            let Some(history) = app.try_state::<Arc<NotificationHistory>>() else {
+                return;
+            };
+            for account in accounts {
+                // Still checked while it is off, so turning it on doesn't
+                // notify the replies that came in meanwhile.
+                let replies = subscriptions.new_replies(&account);
+                if !subscriptions.get_settings().enabled {
+                    continue;
+                }
+                for email in replies {
+                    notifications::show_urgent(
+                        &app,
+                        &history,
+                        &email.sender,
+                        &email.subject,
+                        NotificationReference {
+                            account: Some(account.clone()),
+                            folder: Some(INBOX_FOLDER.to_string()),
+                            uids: vec![email.uid],
+                        },
+                    );
+                }
+            }
+        });
+    });
+}
+
+#[tauri::command]
+pub fn get_thread_subscriptions(
+    subscriptions: State<'_, Arc<ThreadSubscriptions>>,
+) -> ThreadSubscriptionSettings {
+    subscriptions.get_settings()
+}
+
+#[tauri::command]
+pub fn set_thread_subscriptions_enabled(
+    subscriptions: State<'_, Arc<ThreadSubscriptions>>,
+    enabled: bool,
+) -> Result<(), String> {
+    subscriptions.set_enabled(enabled)
+}
+
+#[tauri::command]
+pub fn get_subscribed_threads(
+    subscriptions: State<'_, Arc<ThreadSubscriptions>>,
+    account: String,
+) -> Vec<String> {
+    let mut threads: Vec<String> = subscriptions.get_subscribed(&account).into_iter().collect();
+    threads.sort();
+    threads
+}
+
+#[tauri::command]
+pub fn mute_thread(
+    subscriptions: State<'_, Arc<ThreadSubscriptions>>,
+    account: String,
+    thread_id: String,
+    muted: bool,
+) -> Result<(), String> {
+    subscriptions.set_muted(&account, &thread_id, muted)
+}
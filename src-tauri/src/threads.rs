@@ -0,0 +1,91 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tauri::State;
+
+use crate::cache::{Cache, CachedEmail};
+use crate::utils;
+
+// Emails are grouped into threads by their headers. The id of a thread is
+// the Message-ID of the email that started it (even if that one is not
+// cached): the first of the References of an email, or the thread of the
+// email it replies to when it only has In-Reply-To, or its own Message-ID
+// when it replies to nothing.
+const MAX_REPLY_DEPTH: usize = 100;
+
+fn first_message_id(ids: Option<&str>) -> Option<String> {
+    ids?.split_whitespace().next().map(str::to_string)
+}
+
+// Cached emails of the account, synced and "on this computer" ones, once
+// each even if they are in more than one folder.
+pub fn get_account_emails(cache: &Cache, account: &str) -> Vec<CachedEmail> {
+    let synced = cache
+        .get_folder_names(account)
+        .into_iter()
+        .flat_map(|folder| cache.get_emails(account, &folder));
+    let local = cache
+        .get_local_folders(account)
+        .into_iter()
+        .flat_map(|folder| cache.get_local_emails(account, &folder));
+
+    let mut seen = HashSet::new();
+    synced
+        .chain(local)
+        .filter(|email| {
+            email.message_id.trim().is_empty() || seen.insert(email.message_id.trim().to_string())
+        })
+        .collect()
+}
+
+pub fn by_message_id(emails: &[CachedEmail]) -> HashMap<String, &CachedEmail> {
+    emails
+        .iter()
+        .map(|email| (email.message_id.trim().to_string(), email))
+        .collect()
+}
+
+pub fn thread_id(email: &CachedEmail, by_message_id: &HashMap<String, &CachedEmail>) -> String {
+    let mut email = email;
+    for _ in 0..MAX_REPLY_DEPTH {
+        if let Some(root) = first_message_id(email.references.as_deref()) {
+            return root;
+        }
+        let Some(parent) = first_message_id(email.in_reply_to.as_deref()) else {
+            break;
+        };
+        match by_message_id.get(&parent) {
+            Some(parent) => email = parent,
+            None => return parent,
+        }
+    }
+    email.message_id.trim().to_string()
+}
+
+// Emails of the thread, oldest first.
+pub fn get_thread(cache: &Cache, account: &str, thread: &str) -> Vec<CachedEmail> {
+    if thread.trim().is_empty() {
+        return Vec::new();
+    }
+    let emails = get_account_emails(cache, account);
+    let by_message_id = by_message_id(&emails);
+    let mut thread_emails: Vec<CachedEmail> = emails
+        .iter()
+        .filter(|email| thread_id(email, &by_message_id) == thread)
+        .cloned()
+        .collect();
+    thread_emails.sort_by_key(|email| utils::parse_email_date(&email.date));
+    thread_emails
+}
+
+#[tauri::command]
+pub fn get_thread_id(
+    cache: State<'_, Arc<Cache>>,
+    account: String,
+    message_id: String,
+) -> Option<String> {
+    let emails = get_account_emails(&cache, &account);
+    let by_message_id = by_message_id(&emails);
+    by_message_id
+        .get(message_id.trim())
+        .map(|email| thread_id(email, &by_message_id))
+}
@@ -0,0 +1,175 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+
+use crate::consts;
+use crate::utils;
+
+const MINUTE: Duration = Duration::from_secs(60);
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SendLimits {
+    pub per_minute: Option<u32>,
+    pub per_hour: Option<u32>,
+}
+
+impl Default for SendLimits {
+    fn default() -> Self {
+        SendLimits {
+            per_minute: Some(consts::DEFAULT_SENDS_PER_MINUTE),
+            per_hour: Some(consts::DEFAULT_SENDS_PER_HOUR),
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct AccountThrottle {
+    #[serde(flatten)]
+    limits: SendLimits,
+    // Wall clock times of the sends of the last hour, so restarting the app
+    // doesn't reset the windows.
+    #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
+    sent: VecDeque<DateTime<Utc>>,
+}
+
+// Sends in the future (the clock was turned back) count as just sent.
+fn elapsed(now: DateTime<Utc>, sent_at: &DateTime<Utc>) -> Duration {
+    (now - *sent_at).to_std().unwrap_or_default()
+}
+
+// Keeps the limits and the send times of the last hour per account in
+// `send_limits.json` and tells the outbox how long it has to wait before
+// sending the next email of an account without exceeding its limits.
+#[derive(Default)]
+pub struct SendThrottle {
+    accounts: HashMap<String, AccountThrottle>,
+}
+
+impl SendThrottle {
+    pub fn load() -> Self {
+        SendThrottle {
+            accounts: utils::read_json_file(consts::SEND_LIMITS_FILE_PATH).unwrap_or_default(),
+        }
+    }
+
+    // The file can be replaced on disk by the settings sync, the sends of
+    // the other installs count too since the limits are the server's.
+    pub fn reload(&mut self) {
+        *self = SendThrottle::load();
+    }
+
+    fn save(&self) -> Result<(), String> {
+        utils::write_json_file(consts::SEND_LIMITS_FILE_PATH, &self.accounts)
+    }
+
+    pub fn get_limits(&self, account: &str) -> SendLimits {
+        self.accounts
+            .get(account)
+            .map_or_else(SendLimits::default, |throttle| throttle.limits)
+    }
+
+    pub fn set_limits(&mut self, account: &str, limits: SendLimits) -> Result<(), String> {
+        if limits.per_minute == Some(0) || limits.per_hour == Some(0) {
+            return Err("Send limits must be greater than zero".to_string());
+        }
+
+        self.accounts.entry(account.to_string()).or_default().limits = limits;
+        self.save()
+    }
+
+    // Returns `None` if the account can send right now, otherwise
+    // the time left until the oldest send leaves the exceeded window.
+    pub fn wait_time(&mut self, account: &str) -> Option<Duration> {
+        let throttle = self.accounts.get_mut(account)?;
+        let limits = throttle.limits;
+        let history = &mut throttle.sent;
+        let now = Utc::now();
+        while history
+            .front()
+            .is_some_and(|sent_at| elapsed(now, sent_at) >= HOUR)
+        {
+            history.pop_front();
+        }
+
+        let mut wait = None;
+        for (limit, window) in [(limits.per_minute, MINUTE), (limits.per_hour, HOUR)] {
+            let Some(limit) = limit else { continue };
+            let in_window: Vec<&DateTime<Utc>> = history
+                .iter()
+                .filter(|sent_at| elapsed(now, sent_at) < window)
+                .collect();
+            if limit == 0 || in_window.len() < limit as usize {
+                continue;
+            }
+
+            // The send that has to leave the window to free a slot.
+            let blocking = in_window[in_window.len() - limit as usize];
+            let left = window - elapsed(now, blocking);
+            wait = Some(wait.map_or(left, |wait: Duration| wait.max(left)));
+        }
+
+        wait
+    }
+
+    pub fn record(&mut self, account: &str) {
+        self.accounts
+            .entry(account.to_string())
+            .or_default()
+            .sent
+            .push_back(Utc::now());
+        self.save().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_limits_without_sends() {
+        let accounts: HashMap<String, AccountThrottle> =
+            serde_json::from_str(r#"{"a@b.com": {"per_minute": 5, "per_hour": null}}"#).unwrap();
+        let throttle = SendThrottle { accounts };
+        let limits = throttle.get_limits("a@b.com");
+        assert_eq!(limits.per_minute, Some(5));
+        assert_eq!(limits.per_hour, None);
+        assert!(throttle.accounts["a@b.com"].sent.is_empty());
+        assert_eq!(
+            throttle.get_limits("c@d.com").per_hour,
+            Some(consts::DEFAULT_SENDS_PER_HOUR)
+        );
+    }
+
+    #[test]
+    fn waits_for_sends_read_back_from_the_file() {
+        let now = Utc::now();
+        let mut throttle = SendThrottle::default();
+        throttle.accounts.insert(
+            "a@b.com".to_string(),
+            AccountThrottle {
+                limits: SendLimits {
+                    per_minute: Some(2),
+                    per_hour: None,
+                },
+                sent: VecDeque::from([
+                    now - chrono::Duration::hours(2),
+                    now - chrono::Duration::seconds(30),
+                    now - chrono::Duration::seconds(10),
+                ]),
+            },
+        );
+        let json = serde_json::to_string(&throttle.accounts).unwrap();
+        let mut throttle = SendThrottle {
+            accounts: serde_json::from_str(&json).unwrap(),
+        };
+
+        // The oldest send of the minute leaves it in about 30 seconds.
+        let wait = throttle.wait_time("a@b.com").unwrap();
+        assert!(wait > Duration::from_secs(28) && wait <= Duration::from_secs(30));
+        // Sends older than an hour are dropped.
+        assert_eq!(throttle.accounts["a@b.com"].sent.len(), 2);
+        assert_eq!(throttle.wait_time("c@d.com"), None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+use tauri::menu::{Menu, MenuEvent, MenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Manager};
+
+use crate::deep_link::{self, MessageLink};
+use crate::pins::{Pin, Pins};
+
+const TRAY_ID: &str = "main";
+const TRAY_TITLE: &str = "Openmail";
+const MAX_MENU_SUBJECT_CHARS: usize = 40;
+
+pub fn create(app: &AppHandle) -> tauri::Result<()> {
+    let mut builder = TrayIconBuilder::with_id(TRAY_ID)
+        .tooltip(TRAY_TITLE)
+        .on_menu_event(open_pinned);
+    if let Some(icon) = app.default_window_icon() {
+        builder = builder.icon(icon.clone());
+    }
+    builder.build(app)?;
+    Ok(())
+}
+
+// Paused accounts are listed in the tooltip, see account_schedule.rs
+pub fn set_paused_accounts(app: &AppHandle, accounts: &[String]) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    let tooltip = if accounts.is_empty() {
+        TRAY_TITLE.to_string()
+    } else {
+        format!("{} (paused: {})", TRAY_TITLE, accounts.join(", "))
+    };
+    tray.set_tooltip(Some(tooltip)).ok();
+}
+
+fn menu_subject(pin: &Pin) -> String {
+    let subject = if pin.subject.trim().is_empty() {
+        "(no subject)"
+    } else {
+        pin.subject.trim()
+    };
+    if subject.chars().count() <= MAX_MENU_SUBJECT_CHARS {
+        return subject.to_string();
+    }
+    let mut truncated: String = subject.chars().take(MAX_MENU_SUBJECT_CHARS - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+// Pinned emails are the items of the tray menu, see pins.rs
+pub fn set_pinned(app: &AppHandle, pins: &[Pin]) {
+    let Some(tray) = app.tray_by_id(TRAY_ID) else {
+        return;
+    };
+    if pins.is_empty() {
+        tray.set_menu(None::<Menu<tauri::Wry>>).ok();
+        return;
+    }
+    let items: Vec<MenuItem<tauri::Wry>> = pins
+        .iter()
+        .filter_map(|pin| {
+            MenuItem::with_id(app, pin.key(), menu_subject(pin), true, None::<&str>).ok()
+        })
+        .collect();
+    let items: Vec<&dyn tauri::menu::IsMenuItem<tauri::Wry>> = items
+        .iter()
+        .map(|item| item as &dyn tauri::menu::IsMenuItem<tauri::Wry>)
+        .collect();
+    match Menu::with_items(app, &items) {
+        Ok(menu) => {
+            tray.set_menu(Some(menu)).ok();
+        }
+        Err(err) => println!("Failed to create tray menu: {}", err),
+    }
+}
+
+fn open_pinned(app: &AppHandle, event: MenuEvent) {
+    let Some(pins) = app.try_state::<Arc<Pins>>() else {
+        return;
+    };
+    let Some(pin) = pins
+        .get_pinned()
+        .into_iter()
+        .find(|pin| pin.key() == event.id().as_ref())
+    else {
+        return;
+    };
+    deep_link::open_message(
+        app,
+        MessageLink {
+            account: pin.account,
+            folder: pin.folder,
+            uid: pin.uid,
+        },
+    );
+}
@@ -1,5 +1,136 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::consts;
+use crate::session::{path_safe, session_id};
+
+static ID_COUNTER: AtomicU32 = AtomicU32::new(0);
+const PBKDF2_ROUNDS: u32 = 600_000;
 
 pub fn build_home_path(file: &str) -> String {
     format!("{}/{}", env::var("HOME").unwrap(), file)
 }
+
+// Files that only live as long as the app runs in the session, like the
+// info files of the server and the IPC channel.
+pub fn build_runtime_path(file: &str) -> String {
+    build_home_path(&format!(
+        "{}/{}/{}",
+        consts::RUNTIME_DIR_PATH,
+        session_id(),
+        file
+    ))
+}
+
+// Temp directory of the app for the user and the session, under the temp
+// dir of the OS which is shared by all users on Linux. Only the user can
+// read it, and one that was created by someone else is not used.
+pub fn build_temp_dir() -> Result<PathBuf, String> {
+    let user = env::var(if consts::IS_WINDOWS {
+        "USERNAME"
+    } else {
+        "USER"
+    })
+    .unwrap_or_default();
+    let dir = env::temp_dir().join(format!("openmail-{}-{}", path_safe(&user), session_id()));
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(&dir)
+        .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let owner = fs::symlink_metadata(&dir)
+            .map_err(|err| format!("Failed to read {}: {}", dir.display(), err))?
+            .uid();
+        if owner != unsafe { libc::getuid() } {
+            return Err(format!("{} belongs to another user", dir.display()));
+        }
+    }
+    Ok(dir)
+}
+
+pub fn build_temp_path(file: &str) -> Result<PathBuf, String> {
+    Ok(build_temp_dir()?.join(file))
+}
+
+pub fn extract_email_address(sender: &str) -> String {
+    // Same as `extract_email_address` of the python server, sender could
+    // be either "Name Surname <namesurname@domain.com>" or "namesurname@domain.com".
+    if let Some((_, address)) = sender.split_once('<') {
+        address.replace('>', "").trim().to_string()
+    } else if sender.contains('@') {
+        sender.trim().to_string()
+    } else {
+        String::new()
+    }
+}
+
+pub fn generate_random_id() -> String {
+    let counter = ID_COUNTER.fetch_add(1, Ordering::Relaxed) % 10000;
+    format!("{}{:04}", Utc::now().timestamp_millis(), counter)
+}
+
+pub fn read_json_file<T: DeserializeOwned + Default>(file: &str) -> Result<T, String> {
+    let path = build_home_path(file);
+    if !Path::new(&path).exists() {
+        return Ok(T::default());
+    }
+
+    let content =
+        fs::read_to_string(&path).map_err(|err| format!("Failed to read {}: {}", path, err))?;
+    serde_json::from_str(&content).map_err(|err| format!("Failed to parse {}: {}", path, err))
+}
+
+pub fn write_json_file<T: Serialize>(file: &str, value: &T) -> Result<(), String> {
+    let path = build_home_path(file);
+    if let Some(parent) = Path::new(&path).parent() {
+        fs::create_dir_all(parent)
+            .map_err(|err| format!("Failed to create {}: {}", parent.display(), err))?;
+    }
+
+    let content = serde_json::to_string_pretty(value)
+        .map_err(|err| format!("Failed to serialize {}: {}", path, err))?;
+    fs::write(&path, content).map_err(|err| format!("Failed to write {}: {}", path, err))
+}
+
+// Characters that are not allowed in file names on one of the systems are
+// replaced with spaces.
+pub fn sanitize_file_name(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => ' ',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+pub fn parse_email_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    // Date headers often end with a comment like "(UTC)" which is not
+    // accepted by the RFC 2822 parser of chrono.
+    let date = match date.find('(') {
+        Some(index) => &date[..index],
+        None => date,
+    };
+    DateTime::parse_from_rfc2822(date.trim()).ok()
+}
+
+// 256 bit key for AES-GCM from a passphrase or a transfer code.
+pub fn derive_key(secret: &str, salt: &[u8]) -> [u8; 32] {
+    pbkdf2::pbkdf2_hmac_array::<sha2::Sha256, 32>(secret.as_bytes(), salt, PBKDF2_ROUNDS)
+}
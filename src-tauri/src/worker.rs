@@ -0,0 +1,274 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+use crate::archive;
+use crate::feeds;
+use crate::mime::{self, ParsedMessage};
+
+// Untrusted content (raw messages, attachments) is parsed in a short lived
+// child process, so a parser that crashes or hangs on a malformed input takes
+// down the child instead of the app. The child is the app binary started
+// with WORKER_ARG, it reads one job from stdin, writes its result to stdout
+// and exits. Both are JSON prefixed with their length as big endian u32.
+pub const WORKER_ARG: &str = "--parse-worker";
+const JOB_TIMEOUT_SEC: u64 = 30;
+const MAX_MESSAGE_LENGTH: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Job {
+    ParseMessage {
+        path: String,
+    },
+    SaveAttachments {
+        path: String,
+        dir: String,
+    },
+    ExtractPdfText {
+        path: String,
+    },
+    Ocr {
+        path: String,
+    },
+    CheckArchive {
+        path: String,
+    },
+    ExtractArchive {
+        path: String,
+        dir: String,
+        password: String,
+    },
+    ParseFeed {
+        path: String,
+    },
+}
+
+type JobResult = Result<Value, String>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedAttachment {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub mime_type: String,
+    pub size: usize,
+    pub path: String,
+    // Password protected zip, see archive.rs
+    #[serde(default)]
+    pub encrypted: bool,
+}
+
+fn read_message(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut length = [0u8; 4];
+    reader.read_exact(&mut length)?;
+    let length = u32::from_be_bytes(length) as usize;
+    if length > MAX_MESSAGE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Message is too long: {} bytes", length),
+        ));
+    }
+
+    let mut message = vec![0u8; length];
+    reader.read_exact(&mut message)?;
+    Ok(message)
+}
+
+fn write_message(writer: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    writer.write_all(&(message.len() as u32).to_be_bytes())?;
+    writer.write_all(message)?;
+    writer.flush()
+}
+
+fn to_value<T: Serialize>(value: T) -> JobResult {
+    serde_json::to_value(value).map_err(|err| format!("Failed to serialize result: {}", err))
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("Failed to read {}: {}", path, err))
+}
+
+// Names come from the message, so they can't be trusted as paths.
+fn attachment_file_name(name: &str, taken: &[String]) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || "/\\:*?\"<>|".contains(c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let name = name.trim_start_matches('.').trim();
+    let name = if name.is_empty() { "attachment" } else { name };
+
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+    let mut file_name = name.to_string();
+    let mut counter = 1;
+    while taken.contains(&file_name) {
+        file_name = format!("{} ({}){}", stem, counter, extension);
+        counter += 1;
+    }
+    file_name
+}
+
+fn save_attachments_of(path: &str, dir: &str) -> Result<Vec<SavedAttachment>, String> {
+    fs::create_dir_all(dir).map_err(|err| format!("Failed to create {}: {}", dir, err))?;
+    let mut file_names = Vec::new();
+    let mut saved = Vec::new();
+    for (attachment, contents) in mime::parse_attachments(&read_file(path)?)? {
+        let file_name = attachment_file_name(&attachment.name, &file_names);
+        let file = Path::new(dir).join(&file_name);
+        fs::write(&file, contents)
+            .map_err(|err| format!("Failed to write {}: {}", file.display(), err))?;
+        file_names.push(file_name);
+        let encrypted = archive::is_archive(&attachment.name, &attachment.mime_type)
+            && archive::is_encrypted_archive(&file.display().to_string()).unwrap_or(false);
+        saved.push(SavedAttachment {
+            name: attachment.name,
+            mime_type: attachment.mime_type,
+            size: attachment.size,
+            path: file.display().to_string(),
+            encrypted,
+        });
+    }
+    Ok(saved)
+}
+
+// Runs in the worker process.
+fn handle(job: Job) -> JobResult {
+    match job {
+        Job::ParseMessage { path } => to_value(mime::parse_message(&read_file(&path)?)?),
+        Job::SaveAttachments { path, dir } => to_value(save_attachments_of(&path, &dir)?),
+        Job::ExtractPdfText { path } => to_value(
+            pdf_extract::extract_text_from_mem(&read_file(&path)?)
+                .map_err(|err| format!("Failed to extract text of {}: {}", path, err))?,
+        ),
+        // There is no OCR engine in the app itself, tesseract is used if
+        // it is installed.
+        Job::Ocr { path } => {
+            let output = Command::new("tesseract")
+                .arg(&path)
+                .arg("stdout")
+                .stderr(Stdio::null())
+                .output()
+                .map_err(|err| format!("Failed to run tesseract: {}", err))?;
+            if !output.status.success() {
+                return Err(format!("Tesseract failed on {}: {}", path, output.status));
+            }
+            to_value(String::from_utf8_lossy(&output.stdout).trim().to_string())
+        }
+        Job::CheckArchive { path } => to_value(archive::is_encrypted_archive(&path)?),
+        Job::ExtractArchive {
+            path,
+            dir,
+            password,
+        } => to_value(archive::extract_archive(&path, &dir, &password)?),
+        Job::ParseFeed { path } => to_value(feeds::parse_feed(&read_file(&path)?)?),
+    }
+}
+
+pub fn run_worker() {
+    let result: JobResult = read_message(&mut io::stdin())
+        .map_err(|err| format!("Failed to read job: {}", err))
+        .and_then(|job| {
+            serde_json::from_slice::<Job>(&job).map_err(|err| format!("Invalid job: {}", err))
+        })
+        .and_then(handle);
+
+    if let Ok(result) = serde_json::to_vec(&result) {
+        write_message(&mut io::stdout(), &result).ok();
+    }
+}
+
+pub fn run(job: &Job) -> JobResult {
+    let job = serde_json::to_vec(job).map_err(|err| format!("Failed to serialize job: {}", err))?;
+    let executable =
+        env::current_exe().map_err(|err| format!("Failed to find app executable: {}", err))?;
+    let mut child = Command::new(executable)
+        .arg(WORKER_ARG)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("Failed to start parser worker: {}", err))?;
+
+    let mut stdin = child.stdin.take().ok_or("Failed to open worker stdin")?;
+    let mut stdout = child.stdout.take().ok_or("Failed to open worker stdout")?;
+    if let Err(err) = write_message(&mut stdin, &job) {
+        child.kill().ok();
+        child.wait().ok();
+        return Err(format!("Failed to send job to parser worker: {}", err));
+    }
+    drop(stdin);
+
+    // Read on another thread, so the worker can be killed if it hangs.
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        sender.send(read_message(&mut stdout)).ok();
+    });
+    let Ok(output) = receiver.recv_timeout(Duration::from_secs(JOB_TIMEOUT_SEC)) else {
+        child.kill().ok();
+        child.wait().ok();
+        return Err(format!(
+            "Parser worker timed out after {} seconds",
+            JOB_TIMEOUT_SEC
+        ));
+    };
+
+    let status = child
+        .wait()
+        .map_err(|err| format!("Failed to wait for parser worker: {}", err))?;
+    let output = output.map_err(|_| format!("Parser worker crashed: {}", status))?;
+    serde_json::from_slice::<JobResult>(&output)
+        .map_err(|err| format!("Failed to parse worker result: {}", err))?
+}
+
+pub fn parse_message_file(path: &str) -> Result<ParsedMessage, String> {
+    let value = run(&Job::ParseMessage {
+        path: path.to_string(),
+    })?;
+    serde_json::from_value(value).map_err(|err| format!("Invalid parsed message: {}", err))
+}
+
+// Writes the attachments of the message file into `dir`.
+pub fn save_attachments(path: &str, dir: &str) -> Result<Vec<SavedAttachment>, String> {
+    let value = run(&Job::SaveAttachments {
+        path: path.to_string(),
+        dir: dir.to_string(),
+    })?;
+    serde_json::from_value(value).map_err(|err| format!("Invalid saved attachments: {}", err))
+}
+
+fn run_text_job(job: Job) -> Result<String, String> {
+    match run(&job)? {
+        Value::String(text) => Ok(text),
+        _ => Err("Invalid worker result".to_string()),
+    }
+}
+
+#[tauri::command]
+pub async fn parse_eml_file(path: String) -> Result<ParsedMessage, String> {
+    parse_message_file(&path)
+}
+
+#[tauri::command]
+pub async fn extract_pdf_text(path: String) -> Result<String, String> {
+    run_text_job(Job::ExtractPdfText { path })
+}
+
+#[tauri::command]
+pub async fn extract_image_text(path: String) -> Result<String, String> {
+    run_text_job(Job::Ocr { path })
+}
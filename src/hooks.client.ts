@@ -5,6 +5,7 @@ import { SharedStore } from "$lib/stores/shared.svelte";
 import { ApiService } from "$lib/services/ApiService";
 import { FileSystem } from "$lib/services/FileSystem";
 import { AccountController } from "$lib/controllers/AccountController";
+import { AppEventHandler } from "$lib/services/AppEventHandler";
 
 const SERVER_CONNECTION_TRY_SLEEP_MS = 500;
 
@@ -39,7 +40,8 @@ async function initializeFileSystem(): Promise<void> {
 export const init: ClientInit = async () => {
     const fsReady = initializeFileSystem();
     const serverReady = connectToLocalServer().then(async () => await loadAccounts());
-    Promise.all([fsReady, serverReady]).then(() => {
+    Promise.all([fsReady, serverReady]).then(async () => {
         SharedStore.isAppLoaded = true;
+        await AppEventHandler.listen();
     });
 };
@@ -18,6 +18,8 @@ import {
     type Draft,
     type Email,
     type SearchCriteria,
+    type DownloadedAttachments,
+    TauriCommand,
 } from "$lib/types";
 import {
     extractFolderName,
@@ -30,6 +32,7 @@ import {
     replaceFolderName,
     roundUpToMultiple,
 } from "$lib/utils";
+import { invoke } from "@tauri-apps/api/core";
 
 export class MailboxController {
     public static async init(
@@ -390,6 +393,32 @@ export class MailboxController {
         });
     }
 
+    /**
+     * Downloads the attachments through the backend, which
+     * decodes the files in winmail.dat attachments, see mime.rs
+     */
+    public static async downloadDecodedAttachments(
+        account: Account,
+        folder: string,
+        uid: string,
+    ): Promise<DownloadedAttachments> {
+        if (isStandardFolder(folder))
+            folder = MailboxController._resolveStandardFolder(
+                account,
+                folder as Folder,
+                Folder.All,
+            );
+
+        return await invoke<DownloadedAttachments>(
+            TauriCommand.DOWNLOAD_ATTACHMENTS,
+            {
+                account: account.email_address,
+                folder: folder,
+                uid: uid,
+            },
+        );
+    }
+
     public static async saveDraft(
         email: FormData | Draft,
         appenduid?: string,
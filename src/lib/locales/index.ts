@@ -293,6 +293,9 @@ export const local = {
     are_you_certain_body_is_empty: {
         en: "The message body is empty. Are you sure you want to send the email without any content?"
     },
+    are_you_certain_attach_link_files: {
+        en: "A link asks to attach the following files. Are you sure you want to attach them?"
+    },
     which_accounts_added: {
         en: "Which accounts have I added?",
     },
@@ -506,6 +509,9 @@ export const local = {
     yes_send: {
         en: "Yes, send."
     },
+    yes_attach: {
+        en: "Yes, attach."
+    },
     body: {
         en: "Body"
     },
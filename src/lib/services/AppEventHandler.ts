@@ -0,0 +1,192 @@
+import { listen } from "@tauri-apps/api/event";
+import { invoke } from "@tauri-apps/api/core";
+import { SharedStore } from "$lib/stores/shared.svelte";
+import {
+    type Account,
+    type ComposeArgs,
+    type MessageLink,
+    type OpenedLink,
+    TauriCommand,
+} from "$lib/types";
+import { MailboxController } from "$lib/controllers/MailboxController";
+import { showThis as showContent } from "$lib/ui/Layout/Main/Content.svelte";
+import { show as showMessage } from "$lib/ui/Components/Message";
+import { local } from "$lib/locales";
+import { DEFAULT_LANGUAGE } from "$lib/constants";
+import Compose from "$lib/ui/Layout/Main/Content/Compose.svelte";
+import Email from "$lib/ui/Layout/Main/Content/Email.svelte";
+
+/**
+ * High frequency events of the backend are sent in batches,
+ * see events.rs. Every batch must be acknowledged, otherwise
+ * the next one is only sent after a timeout.
+ */
+const EVENT_BATCH_EVENT = "event-batch";
+/**
+ * openmail-cli compose, see ipc.rs
+ */
+const CLI_COMPOSE_EVENT = "cli-compose";
+/**
+ * openmail://message links and the pinned emails
+ * of the tray menu, see deep_link.rs
+ */
+const OPEN_MESSAGE_EVENT = "open-message";
+/**
+ * Timed by the IPC round-trip benchmark, see benchmarks.rs
+ */
+const BENCHMARK_PING_EVENT = "benchmark-ping";
+
+enum BatchedEventName {
+    EmailAdded = "email-added",
+    EmailRemoved = "email-removed",
+    EmailFlagsChanged = "email-flags-changed",
+}
+
+interface EmailChangePayload {
+    account: string;
+    folder: string;
+    change: "added" | "removed" | "flags_changed";
+    uid: string;
+    flags?: string[];
+}
+
+interface BatchedEvent {
+    event: string;
+    payload: any;
+    coalesced: number;
+}
+
+interface EventBatch {
+    seq: number;
+    events: BatchedEvent[];
+    dropped: number;
+}
+
+export class AppEventHandler {
+    private static _listening = false;
+
+    public static async listen(): Promise<void> {
+        if (AppEventHandler._listening) return;
+        AppEventHandler._listening = true;
+
+        await listen<EventBatch>(EVENT_BATCH_EVENT, async (event) => {
+            try {
+                AppEventHandler._handleBatch(event.payload);
+            } finally {
+                await invoke(TauriCommand.ACK_EVENT_BATCH, {
+                    seq: event.payload.seq,
+                });
+            }
+        });
+
+        await listen<ComposeArgs>(CLI_COMPOSE_EVENT, (event) => {
+            showContent(Compose, { composeArgs: event.payload });
+        });
+
+        await listen<MessageLink>(OPEN_MESSAGE_EVENT, (event) => {
+            AppEventHandler._openMessage(event.payload);
+        });
+
+        await listen<number>(BENCHMARK_PING_EVENT, (event) => {
+            invoke(TauriCommand.ACK_BENCHMARK_PING, { seq: event.payload });
+        });
+
+        // The link that started the app, if any.
+        const openedLink = await invoke<OpenedLink | null>(
+            TauriCommand.TAKE_OPENED_LINK,
+        );
+        if (openedLink?.type === "message") {
+            AppEventHandler._openMessage(openedLink);
+        } else if (openedLink?.type === "compose") {
+            showContent(Compose, { composeArgs: openedLink });
+        }
+    }
+
+    private static async _openMessage(link: MessageLink) {
+        const account = SharedStore.accounts.find(
+            (account: Account) => account.email_address === link.account,
+        );
+        if (!account) return;
+
+        const response = await MailboxController.getEmailContent(
+            account,
+            link.folder,
+            link.uid,
+        );
+        if (!response.success || !response.data) {
+            showMessage({
+                title: local.error_get_email_content[DEFAULT_LANGUAGE],
+            });
+            console.error(response.message);
+            return;
+        }
+
+        showContent(Email, { account, email: response.data });
+    }
+
+    private static _handleBatch(batch: EventBatch) {
+        // Accounts whose shown mailbox should be fetched again, either
+        // because an email was added or because events were dropped.
+        const outdated = new Set<string>();
+        if (batch.dropped > 0) {
+            Object.keys(SharedStore.mailboxes).forEach((account) => outdated.add(account));
+        }
+
+        for (const batchedEvent of batch.events) {
+            const payload = batchedEvent.payload as EmailChangePayload;
+            if (!AppEventHandler._isShown(payload)) continue;
+
+            switch (batchedEvent.event) {
+                case BatchedEventName.EmailAdded:
+                    outdated.add(payload.account);
+                    break;
+                case BatchedEventName.EmailRemoved:
+                    AppEventHandler._removeEmail(payload);
+                    break;
+                case BatchedEventName.EmailFlagsChanged:
+                    AppEventHandler._changeFlags(payload);
+                    break;
+            }
+        }
+
+        outdated.forEach((emailAddr) => AppEventHandler._refreshMailbox(emailAddr));
+    }
+
+    private static _isShown(payload: EmailChangePayload): boolean {
+        return (
+            !!payload &&
+            Object.hasOwn(SharedStore.mailboxes, payload.account) &&
+            SharedStore.mailboxes[payload.account].folder === payload.folder
+        );
+    }
+
+    private static _removeEmail(payload: EmailChangePayload) {
+        const emails = SharedStore.mailboxes[payload.account].emails;
+        const length = emails.current.length;
+        emails.current = emails.current.filter((email) => email.uid !== payload.uid);
+        if (emails.current.length < length)
+            SharedStore.mailboxes[payload.account].total -= 1;
+    }
+
+    private static _changeFlags(payload: EmailChangePayload) {
+        const emails = SharedStore.mailboxes[payload.account].emails;
+        for (const page of [emails.prev, emails.current, emails.next]) {
+            const email = page.find((email) => email.uid === payload.uid);
+            if (email) email.flags = payload.flags ?? [];
+        }
+    }
+
+    private static _refreshMailbox(emailAddr: string) {
+        const mailbox = SharedStore.mailboxes[emailAddr];
+        // Only the first page is refreshed, the user shouldn't be sent
+        // back to it while reading the older emails.
+        if (!mailbox || mailbox.emails.prev.length > 0) return;
+
+        const account = SharedStore.accounts.find(
+            (account: Account) => account.email_address === emailAddr,
+        );
+        if (!account) return;
+
+        MailboxController.getMailbox(account, mailbox.folder);
+    }
+}
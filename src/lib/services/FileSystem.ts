@@ -234,14 +234,16 @@ export class FileSystem {
         await this._initialize(this._root, "", true);
     }
 
-    public async download(filename: string, data: string): Promise<void> {
+    public async download(filename: string, data: string | Uint8Array): Promise<void> {
         const file = await create(filename, {
             baseDir: BaseDirectory.Download,
         });
         await file.write(
-            Uint8Array.from(data, (char) =>
-                char.charCodeAt(0),
-            ),
+            data instanceof Uint8Array
+                ? data
+                : Uint8Array.from(data, (char) =>
+                    char.charCodeAt(0),
+                ),
         );
         await file.close();
     }
@@ -3,8 +3,9 @@ import {
     requestPermission,
     sendNotification,
 } from "@tauri-apps/plugin-notification";
+import { invoke } from "@tauri-apps/api/core";
 import { SharedStore } from "$lib/stores/shared.svelte";
-import { type Account, type Email, Folder, type INotificationHandler } from "$lib/types";
+import { type Account, type Email, Folder, type INotificationHandler, TauriCommand } from "$lib/types";
 import { DEFAULT_LANGUAGE } from "$lib/constants";
 import { local } from "$lib/locales";
 import { isStandardFolder } from "$lib/utils";
@@ -95,23 +96,66 @@ export class NotificationHandler implements INotificationHandler {
         this._addMessageIntoOwnerChannel(emailAddr, recentEmails);
     }
 
-    public pushDesktopNotification(title?: string, body?: string) {
+    public async pushDesktopNotification(
+        title?: string,
+        body?: string,
+        recentEmails: Email[] = [],
+    ) {
+        // Accounts are paused outside of their schedule.
+        const pausedAccounts = await invoke<string[]>(
+            TauriCommand.GET_PAUSED_ACCOUNTS,
+        ).catch(() => []);
+        if (pausedAccounts.includes(this.account.email_address))
+            return;
+
         if (this.areNotificationsAllowed()) {
-            sendNotification({
-                title: title || local.new_email_received_title[DEFAULT_LANGUAGE],
-                body: body || local.new_email_received_body[DEFAULT_LANGUAGE],
-            });
+            title = title || local.new_email_received_title[DEFAULT_LANGUAGE];
+            body = body || local.new_email_received_body[DEFAULT_LANGUAGE];
+            const reference = {
+                account: this.account.email_address,
+                folder: Folder.Inbox,
+                uids: recentEmails.map((email) => email.uid),
+            };
+            // Batched delivery shows it once the new emails are released.
+            const isHeld = await invoke<boolean>(TauriCommand.HOLD_NOTIFICATION, {
+                title,
+                body,
+                reference,
+            }).catch(() => false);
+            if (isHeld) return;
+
+            sendNotification({ title, body });
+            invoke(TauriCommand.RECORD_NOTIFICATION, {
+                title,
+                body,
+                reference,
+            }).catch(console.error);
         }
     }
 
+    private async _isMailHeld(): Promise<boolean> {
+        const batchDelivery = await invoke<{ enabled: boolean }>(
+            TauriCommand.GET_BATCH_DELIVERY,
+        ).catch(() => null);
+        return !!batchDelivery?.enabled;
+    }
+
     private _setupWebSocketChannel() {
         this._checkForPermissions();
         SharedStore.recentEmailsChannel[this.account.email_address] = [];
     }
 
-    private _listenForNewMessages(e: MessageEvent<typeof SharedStore.recentEmailsChannel>) {
+    private async _listenForNewMessages(e: MessageEvent<typeof SharedStore.recentEmailsChannel>) {
         const recentMessages = e.data;
-        this.pushDesktopNotification();
+        this.pushDesktopNotification(
+            undefined,
+            undefined,
+            recentMessages[this.account.email_address] ?? [],
+        );
+        // The lists stay as they are while batched delivery holds the new
+        // emails, they are refreshed once the emails are released through
+        // the event batches, see AppEventHandler.ts
+        if (await this._isMailHeld()) return;
         Object.entries(recentMessages).forEach(
             ([ emailAddr, recentEmails ]) => {
                 return this._handleIncomingEmailMessages(
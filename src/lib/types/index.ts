@@ -1,5 +1,122 @@
 export enum TauriCommand {
     GET_SERVER_URL = "get_server_url",
+    QUEUE_EMAIL = "queue_email",
+    GET_OUTBOX = "get_outbox",
+    REMOVE_OUTBOX_ITEM = "remove_outbox_item",
+    RETRY_OUTBOX_ITEM = "retry_outbox_item",
+    GET_SEND_LIMITS = "get_send_limits",
+    SET_SEND_LIMITS = "set_send_limits",
+    CREATE_CAMPAIGN = "create_campaign",
+    GET_CAMPAIGNS = "get_campaigns",
+    GET_CAMPAIGN_PROGRESS = "get_campaign_progress",
+    START_CAMPAIGN = "start_campaign",
+    PAUSE_CAMPAIGN = "pause_campaign",
+    CANCEL_CAMPAIGN = "cancel_campaign",
+    SYNC_NOW = "sync_now",
+    GET_REPLY_TIME_STATS = "get_reply_time_stats",
+    GET_INBOX_PROGRESS = "get_inbox_progress",
+    ENABLE_SETTINGS_SYNC = "enable_settings_sync",
+    DISABLE_SETTINGS_SYNC = "disable_settings_sync",
+    SYNC_SETTINGS_NOW = "sync_settings_now",
+    GET_SETTINGS_SYNC_STATUS = "get_settings_sync_status",
+    EXPORT_ACCOUNT_QR = "export_account_qr",
+    IMPORT_ACCOUNT_QR = "import_account_qr",
+    PARSE_EML_FILE = "parse_eml_file",
+    EXTRACT_PDF_TEXT = "extract_pdf_text",
+    EXTRACT_IMAGE_TEXT = "extract_image_text",
+    ACK_EVENT_BATCH = "ack_event_batch",
+    QUEUE_FLAG_CHANGE = "queue_flag_change",
+    GET_OFFLINE_CHANGES = "get_offline_changes",
+    RESOLVE_OFFLINE_CONFLICT = "resolve_offline_conflict",
+    GET_CONFLICT_POLICY = "get_conflict_policy",
+    SET_CONFLICT_POLICY = "set_conflict_policy",
+    GET_AUDIT_LOG = "get_audit_log",
+    GET_SHARED_MAILBOXES = "get_shared_mailboxes",
+    SET_SHARED_MAILBOX = "set_shared_mailbox",
+    REMOVE_SHARED_MAILBOX = "remove_shared_mailbox",
+    GET_LOCAL_FOLDERS = "get_local_folders",
+    GET_LOCAL_FOLDER_EMAILS = "get_local_folder_emails",
+    CREATE_LOCAL_FOLDER = "create_local_folder",
+    DELETE_LOCAL_FOLDER = "delete_local_folder",
+    ARCHIVE_TO_LOCAL_FOLDER = "archive_to_local_folder",
+    EXPORT_LOCAL_FOLDER_MBOX = "export_local_folder_mbox",
+    SEARCH_LOCAL_FOLDERS = "search_local_folders",
+    GET_DIAGNOSTICS = "get_diagnostics",
+    SEND_FEEDBACK = "send_feedback",
+    RECORD_NOTIFICATION = "record_notification",
+    HOLD_NOTIFICATION = "hold_notification",
+    GET_NOTIFICATION_HISTORY = "get_notification_history",
+    MARK_NOTIFICATIONS_READ = "mark_notifications_read",
+    CLEAR_NOTIFICATION_HISTORY = "clear_notification_history",
+    GET_DOWNLOAD_RULES = "get_download_rules",
+    SET_DOWNLOAD_RULES = "set_download_rules",
+    DOWNLOAD_ATTACHMENTS = "download_attachments",
+    READ_DOWNLOADED_ATTACHMENT = "read_downloaded_attachment",
+    STAGE_IMAGE = "stage_image",
+    IS_ENCRYPTED_ATTACHMENT = "is_encrypted_attachment",
+    EXTRACT_ENCRYPTED_ATTACHMENT = "extract_encrypted_attachment",
+    REMOVE_EXTRACTED_ATTACHMENT = "remove_extracted_attachment",
+    GET_ACCOUNT_SCHEDULES = "get_account_schedules",
+    SET_ACCOUNT_SCHEDULES = "set_account_schedules",
+    GET_PAUSED_ACCOUNTS = "get_paused_accounts",
+    GET_STATUS_EXPORT = "get_status_export",
+    SET_STATUS_EXPORT = "set_status_export",
+    SEARCH_CACHED_EMAILS = "search_cached_emails",
+    GET_SEARCH_SETTINGS = "get_search_settings",
+    SET_SEARCH_LANGUAGE = "set_search_language",
+    GET_EMAIL_LANGUAGE = "get_email_language",
+    PARSE_SEARCH_QUERY = "parse_search_query",
+    GET_SEARCH_STUBS = "get_search_stubs",
+    SET_SEARCH_STUBS = "set_search_stubs",
+    TAKE_OPENED_LINK = "take_opened_link",
+    READ_COMPOSE_ATTACHMENT = "read_compose_attachment",
+    CONFIRM_COMPOSE_ATTACHMENTS = "confirm_compose_attachments",
+    GET_DROP_FOLDER = "get_drop_folder",
+    SET_DROP_FOLDER = "set_drop_folder",
+    GET_CONTEXT_MENU = "get_context_menu",
+    SET_CONTEXT_MENU = "set_context_menu",
+    GET_THREAD_ID = "get_thread_id",
+    EXPORT_THREAD_MARKDOWN = "export_thread_markdown",
+    GET_CLIP_SETTINGS = "get_clip_settings",
+    SET_CLIP_SETTINGS = "set_clip_settings",
+    CLIP_TO_NOTES = "clip_to_notes",
+    GET_FEEDS = "get_feeds",
+    SET_FEED = "set_feed",
+    REMOVE_FEED = "remove_feed",
+    SET_FEED_ENTRIES_SEEN = "set_feed_entries_seen",
+    GET_NEWSGROUPS = "get_newsgroups",
+    SET_NEWSGROUP = "set_newsgroup",
+    REMOVE_NEWSGROUP = "remove_newsgroup",
+    SET_NEWS_ARTICLES_SEEN = "set_news_articles_seen",
+    GET_SHARE_TARGETS = "get_share_targets",
+    SET_SHARE_TARGET = "set_share_target",
+    REMOVE_SHARE_TARGET = "remove_share_target",
+    SHARE_EMAIL = "share_email",
+    SHARE_ATTACHMENT = "share_attachment",
+    GET_BATCH_DELIVERY = "get_batch_delivery",
+    SET_BATCH_DELIVERY = "set_batch_delivery",
+    RELEASE_HELD_MAIL = "release_held_mail",
+    GET_THREAD_SUBSCRIPTIONS = "get_thread_subscriptions",
+    SET_THREAD_SUBSCRIPTIONS_ENABLED = "set_thread_subscriptions_enabled",
+    GET_SUBSCRIBED_THREADS = "get_subscribed_threads",
+    MUTE_THREAD = "mute_thread",
+    GET_CALENDAR_SETTINGS = "get_calendar_settings",
+    SET_CALENDAR_SETTINGS = "set_calendar_settings",
+    SUGGEST_FREE_SLOTS = "suggest_free_slots",
+    GET_SEND_TIME_SETTINGS = "get_send_time_settings",
+    SET_SEND_TIME_SETTINGS = "set_send_time_settings",
+    SUGGEST_SEND_TIME = "suggest_send_time",
+    GET_RECIPIENT_LOCAL_TIME = "get_recipient_local_time",
+    GET_CORRESPONDENT_TIME_ZONES = "get_correspondent_time_zones",
+    GET_PINNED = "get_pinned",
+    PIN_EMAIL = "pin_email",
+    UNPIN_EMAIL = "unpin_email",
+    GET_ACCOUNT_HEALTH = "get_account_health",
+    CHECK_ACCOUNT_HEALTH = "check_account_health",
+    GET_FAULT_INJECTION = "get_fault_injection",
+    SET_FAULT_INJECTION = "set_fault_injection",
+    RUN_BENCHMARKS = "run_benchmarks",
+    ACK_BENCHMARK_PING = "ack_benchmark_ping",
 }
 
 export type OpenmailTaskResults<T> = {
@@ -12,7 +129,7 @@ export interface INotificationHandler {
     terminate(): void;
     reinitialize(): void;
     areNotificationsAllowed(): boolean;
-    pushDesktopNotification(title?: string, body?: string): void;
+    pushDesktopNotification(title?: string, body?: string, recentEmails?: Email[]): Promise<void>;
 }
 
 export interface Account {
@@ -64,6 +181,25 @@ export interface Attachment {
     cid?: string;
 }
 
+/**
+ * Attachment saved by the backend from the source of the
+ * email, with winmail.dat decoded, see downloads.rs
+ */
+export interface SavedAttachment {
+    name: string;
+    type: string;
+    size: number;
+    path: string;
+    encrypted: boolean;
+}
+
+export interface DownloadedAttachments {
+    account: string;
+    folder: string;
+    uid: string;
+    attachments: SavedAttachment[];
+}
+
 export interface Draft {
     sender: string; // Name Surname <namesurname@domain.com> or namesurname@domain.com
     receivers: string | string[];
@@ -128,6 +264,37 @@ export enum Size {
     MB = "MB",
 }
 
+/**
+ * Prefilled composer, opened by openmail-cli or
+ * by the attach links/context menu of the OS.
+ */
+export interface ComposeArgs {
+    to: string;
+    cc?: string | null;
+    bcc?: string | null;
+    subject: string;
+    body: string;
+    attachments: string[]; // paths of the files
+    confirm?: boolean; // attachments of a link, asked to the user first
+}
+
+/**
+ * Email of an openmail://message link, search stubs
+ * of the OS or the pinned emails of the tray menu.
+ */
+export interface MessageLink {
+    account: string;
+    folder: string;
+    uid: string;
+}
+
+/**
+ * The link that started the app, see deep_link.rs
+ */
+export type OpenedLink =
+    | ({ type: "message" } & MessageLink)
+    | ({ type: "compose" } & ComposeArgs);
+
 export interface OriginalMessageContext {
     composeType: "reply" | "forward";
     messageId: string;